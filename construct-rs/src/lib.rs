@@ -1,7 +1,14 @@
+use std::borrow::Cow;
+use std::cell::Cell;
 use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::io::{self, Read, Write, Seek, SeekFrom};
+use pyo3::buffer::PyBuffer;
+use pyo3::marker::Ungil;
 use pyo3::prelude::*;
-use pyo3::types::{PyBytes, PyString, PyDict};
+use pyo3::types::{PyBytes, PyString, PyDict, PyFrozenSet, PyList, PyMapping, PyMemoryView, PyTuple};
 
 /// Error types mirroring `construct.core` exceptions.
 #[derive(Debug)]
@@ -43,6 +50,93 @@ impl std::fmt::Display for ConstructError {
 
 impl std::error::Error for ConstructError {}
 
+/// The `construct.core` exception classes, which construct-rs raises as well, so that
+/// `except construct.StreamError` and the like catch errors of either implementation.
+pub mod exceptions {
+    use pyo3::import_exception;
+    use pyo3::prelude::*;
+
+    macro_rules! construct_exceptions {
+        ($($name:ident),* $(,)?) => {
+            $(import_exception!(construct.core, $name);)*
+
+            /// Add every exception class to the extension module.
+            pub fn register(py: Python, m: &PyModule) -> PyResult<()> {
+                // Raise an ImportError here rather than panic on the first exception raised.
+                py.import("construct.core")?;
+                $(m.add(stringify!($name), py.get_type::<$name>())?;)*
+                Ok(())
+            }
+        };
+    }
+
+    construct_exceptions!(
+        ConstructError,
+        SizeofError,
+        AdaptationError,
+        ValidationError,
+        StreamError,
+        FormatFieldError,
+        IntegerError,
+        StringError,
+        MappingError,
+        RangeError,
+        RepeatError,
+        ConstError,
+        IndexFieldError,
+        CheckError,
+        ExplicitError,
+        NamedTupleError,
+        TimestampError,
+        UnionError,
+        SelectError,
+        SwitchError,
+        StopFieldError,
+        PaddingError,
+        TerminatedError,
+        RawCopyError,
+        RotationError,
+        ChecksumError,
+        CancelParsing,
+    );
+}
+
+impl From<ConstructError> for PyErr {
+    fn from(err: ConstructError) -> PyErr {
+        use exceptions as e;
+        let msg = err.to_string();
+        match err {
+            ConstructError::SizeofError => e::SizeofError::new_err(msg),
+            ConstructError::AdaptationError => e::AdaptationError::new_err(msg),
+            ConstructError::ValidationError => e::ValidationError::new_err(msg),
+            ConstructError::StreamError => e::StreamError::new_err(msg),
+            ConstructError::FormatFieldError => e::FormatFieldError::new_err(msg),
+            ConstructError::IntegerError => e::IntegerError::new_err(msg),
+            ConstructError::StringError => e::StringError::new_err(msg),
+            ConstructError::MappingError => e::MappingError::new_err(msg),
+            ConstructError::RangeError => e::RangeError::new_err(msg),
+            ConstructError::RepeatError => e::RepeatError::new_err(msg),
+            ConstructError::ConstError => e::ConstError::new_err(msg),
+            ConstructError::IndexFieldError => e::IndexFieldError::new_err(msg),
+            ConstructError::CheckError => e::CheckError::new_err(msg),
+            ConstructError::ExplicitError => e::ExplicitError::new_err(msg),
+            ConstructError::NamedTupleError => e::NamedTupleError::new_err(msg),
+            ConstructError::TimestampError => e::TimestampError::new_err(msg),
+            ConstructError::UnionError => e::UnionError::new_err(msg),
+            ConstructError::SelectError => e::SelectError::new_err(msg),
+            ConstructError::SwitchError => e::SwitchError::new_err(msg),
+            ConstructError::StopFieldError => e::StopFieldError::new_err(msg),
+            ConstructError::PaddingError => e::PaddingError::new_err(msg),
+            ConstructError::TerminatedError => e::TerminatedError::new_err(msg),
+            ConstructError::RawCopyError => e::RawCopyError::new_err(msg),
+            ConstructError::RotationError => e::RotationError::new_err(msg),
+            ConstructError::ChecksumError => e::ChecksumError::new_err(msg),
+            ConstructError::CancelParsing => e::CancelParsing::new_err(msg),
+            ConstructError::Other(text) => e::ConstructError::new_err(text),
+        }
+    }
+}
+
 /// Read exactly `length` bytes from a stream.
 pub fn stream_read(stream: &mut impl Read, length: usize) -> Result<Vec<u8>, ConstructError> {
     let mut buf = vec![0u8; length];
@@ -62,21 +156,23 @@ pub fn stream_write(stream: &mut impl Write, data: &[u8]) -> Result<(), Construc
     stream.write_all(data).map_err(|_| ConstructError::StreamError)
 }
 
-/// Seek a stream to `offset` according to `whence`.
+/// Seek a stream to `offset` from the start, the end or the current position, as `whence` tells.
 pub fn stream_seek(stream: &mut impl Seek, offset: i64, whence: SeekFrom) -> Result<u64, ConstructError> {
     stream.seek(match whence {
-        SeekFrom::Start(_) | SeekFrom::End(_) | SeekFrom::Current(_) => whence,
+        SeekFrom::Start(_) => SeekFrom::Start(u64::try_from(offset).map_err(|_| ConstructError::StreamError)?),
+        SeekFrom::End(_) => SeekFrom::End(offset),
+        SeekFrom::Current(_) => SeekFrom::Current(offset),
     }).map_err(|_| ConstructError::StreamError)
 }
 
 /// Get current position of a stream.
 pub fn stream_tell(stream: &mut impl Seek) -> Result<u64, ConstructError> {
-    stream.seek(SeekFrom::Current(0)).map_err(|_| ConstructError::StreamError)
+    stream.stream_position().map_err(|_| ConstructError::StreamError)
 }
 
 /// Return size of stream without changing position.
 pub fn stream_size(stream: &mut impl Seek) -> Result<u64, ConstructError> {
-    let pos = stream.seek(SeekFrom::Current(0)).map_err(|_| ConstructError::StreamError)?;
+    let pos = stream.stream_position().map_err(|_| ConstructError::StreamError)?;
     let end = stream.seek(SeekFrom::End(0)).map_err(|_| ConstructError::StreamError)?;
     stream.seek(SeekFrom::Start(pos)).map_err(|_| ConstructError::StreamError)?;
     Ok(end)
@@ -84,13 +180,305 @@ pub fn stream_size(stream: &mut impl Seek) -> Result<u64, ConstructError> {
 
 /// Check if end of file has been reached without consuming data.
 pub fn stream_iseof(stream: &mut (impl Read + Seek)) -> Result<bool, ConstructError> {
-    let pos = stream.seek(SeekFrom::Current(0)).map_err(|_| ConstructError::StreamError)?;
+    let pos = stream.stream_position().map_err(|_| ConstructError::StreamError)?;
     let mut buf = [0u8; 1];
     let read = stream.read(&mut buf).map_err(|_| ConstructError::StreamError)?;
     stream.seek(SeekFrom::Start(pos)).map_err(|_| ConstructError::StreamError)?;
     Ok(read == 0)
 }
 
+// ========================= Stream adapters ===========================
+
+/// Byte transform used by [`RestreamedBytesIO`] to decode and encode units.
+pub type Transform = Arc<dyn Fn(&[u8]) -> io::Result<Vec<u8>> + Send + Sync>;
+
+/// Size of the reads issued by [`RebufferedBytesIO`] against its substream.
+const REBUFFER_CHUNK: usize = 128 * 1024;
+
+/// Delay before [`RestreamedBytesIO`] and [`RebufferedBytesIO`] retry a read that would
+/// have blocked.
+const RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(1);
+
+/// Stream that decodes data read from, and encodes data written to, a substream
+/// in units of fixed size. Used by `Bitwise` and `Restreamed`.
+///
+/// Seeking is not supported, only telling the amount of data read or written.
+pub struct RestreamedBytesIO<S> {
+    substream: S,
+    decoder: Transform,
+    decoderunit: usize,
+    encoder: Transform,
+    encoderunit: usize,
+    rbuffer: Vec<u8>,
+    wbuffer: Vec<u8>,
+    sincereadwritten: usize,
+}
+
+impl<S: Read + Write> RestreamedBytesIO<S> {
+    pub fn new(substream: S, decoder: Transform, decoderunit: usize, encoder: Transform, encoderunit: usize) -> Self {
+        RestreamedBytesIO {
+            substream,
+            decoder,
+            decoderunit,
+            encoder,
+            encoderunit,
+            rbuffer: Vec::new(),
+            wbuffer: Vec::new(),
+            sincereadwritten: 0,
+        }
+    }
+
+    /// Decode one more unit into the read buffer, returning false at end of substream.
+    /// Short reads are continued until the unit is whole, and reads that would block are
+    /// retried after [`RETRY_DELAY`]. A substream ending within a unit is an error.
+    fn decode_unit(&mut self) -> io::Result<bool> {
+        let mut unit = vec![0u8; self.decoderunit];
+        let mut filled = 0;
+        while filled < unit.len() {
+            match self.substream.read(&mut unit[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::sleep(RETRY_DELAY),
+                Err(e) => return Err(e),
+            }
+        }
+        if filled == 0 {
+            return Ok(false);
+        }
+        if filled < unit.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("substream ended {} bytes into a {} byte unit", filled, self.decoderunit),
+            ));
+        }
+        let decoded = (self.decoder)(&unit)?;
+        self.rbuffer.extend_from_slice(&decoded);
+        Ok(true)
+    }
+
+    /// Read `count` decoded bytes, or everything until the end when `count` is `None`.
+    ///
+    /// Returns an empty buffer when fewer than `count` bytes could be decoded.
+    pub fn read_count(&mut self, count: Option<usize>) -> io::Result<Vec<u8>> {
+        match count {
+            None => {
+                while self.decode_unit()? {}
+                let data = std::mem::take(&mut self.rbuffer);
+                self.sincereadwritten += data.len();
+                Ok(data)
+            }
+            Some(count) => {
+                while self.rbuffer.len() < count {
+                    if !self.decode_unit()? {
+                        return Ok(Vec::new());
+                    }
+                }
+                let data: Vec<u8> = self.rbuffer.drain(..count).collect();
+                self.sincereadwritten += count;
+                Ok(data)
+            }
+        }
+    }
+
+    /// Check that no partially decoded or encoded unit remains buffered.
+    pub fn close(&mut self) -> io::Result<()> {
+        if !self.rbuffer.is_empty() {
+            return Err(io::Error::other(format!(
+                "closing stream but {} unread bytes remain, {} is decoded unit",
+                self.rbuffer.len(),
+                self.decoderunit
+            )));
+        }
+        if !self.wbuffer.is_empty() {
+            return Err(io::Error::other(format!(
+                "closing stream but {} unwritten bytes remain, {} is encoded unit",
+                self.wbuffer.len(),
+                self.encoderunit
+            )));
+        }
+        Ok(())
+    }
+
+    /// Amount of data read or written so far. Correct only on read-only and write-only instances.
+    pub fn tell(&self) -> usize {
+        self.sincereadwritten
+    }
+}
+
+impl<S: Read + Write> Read for RestreamedBytesIO<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.rbuffer.len() < buf.len() && self.decode_unit()? {}
+        let n = buf.len().min(self.rbuffer.len());
+        buf[..n].copy_from_slice(&self.rbuffer[..n]);
+        self.rbuffer.drain(..n);
+        self.sincereadwritten += n;
+        Ok(n)
+    }
+}
+
+impl<S: Read + Write> Write for RestreamedBytesIO<S> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.wbuffer.extend_from_slice(data);
+        while self.wbuffer.len() >= self.encoderunit {
+            let unit: Vec<u8> = self.wbuffer.drain(..self.encoderunit).collect();
+            let encoded = (self.encoder)(&unit)?;
+            self.substream.write_all(&encoded)?;
+        }
+        self.sincereadwritten += data.len();
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.substream.flush()
+    }
+}
+
+impl<S: Read + Write> Seek for RestreamedBytesIO<S> {
+    /// Only `SeekFrom::Current(0)` is supported, which tells the position.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match pos {
+            SeekFrom::Current(0) => Ok(self.sincereadwritten as u64),
+            _ => Err(io::Error::new(io::ErrorKind::Unsupported, "RestreamedBytesIO is not seekable")),
+        }
+    }
+}
+
+/// Stream that caches data read from a non-seekable substream, making it seekable.
+/// Used by `Rebuffered`.
+///
+/// With `tailcutoff` set, cached data further than that many bytes behind the
+/// current offset is discarded and can no longer be read.
+pub struct RebufferedBytesIO<S> {
+    substream: S,
+    offset: usize,
+    rwbuffer: Vec<u8>,
+    moved: usize,
+    tailcutoff: Option<usize>,
+}
+
+impl<S: Read + Write> RebufferedBytesIO<S> {
+    pub fn new(substream: S, tailcutoff: Option<usize>) -> Self {
+        RebufferedBytesIO { substream, offset: 0, rwbuffer: Vec::new(), moved: 0, tailcutoff }
+    }
+
+    /// Read from the substream until `upto` is cached, returning false if it ended first.
+    /// Reads that would block are retried after [`RETRY_DELAY`].
+    fn fill(&mut self, upto: usize) -> io::Result<bool> {
+        let mut chunk = vec![0u8; REBUFFER_CHUNK];
+        while self.cachedto() < upto {
+            match self.substream.read(&mut chunk) {
+                Ok(0) => return Ok(false),
+                Ok(n) => self.rwbuffer.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::sleep(RETRY_DELAY),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(true)
+    }
+
+    fn cut_tail(&mut self) {
+        if let Some(tailcutoff) = self.tailcutoff
+            && self.offset > tailcutoff
+            && self.moved < self.offset - tailcutoff
+        {
+            let removed = (self.offset - tailcutoff - self.moved).min(self.rwbuffer.len());
+            self.moved += removed;
+            self.rwbuffer.drain(..removed);
+        }
+    }
+
+    fn check_tail(&self) -> io::Result<()> {
+        if self.offset < self.moved {
+            return Err(io::Error::other("could not access data because tail was cut off"));
+        }
+        Ok(())
+    }
+
+    /// Read exactly `count` bytes at the current offset.
+    pub fn read_count(&mut self, count: usize) -> io::Result<Vec<u8>> {
+        self.check_tail()?;
+        let endsat = self.offset + count;
+        if !self.fill(endsat)? {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "could not read enough bytes"));
+        }
+        let data = self.rwbuffer[self.offset - self.moved..endsat - self.moved].to_vec();
+        self.offset = endsat;
+        self.cut_tail();
+        Ok(data)
+    }
+
+    /// Offset of the first byte still cached.
+    pub fn cachedfrom(&self) -> usize {
+        self.moved
+    }
+
+    /// Offset one past the last byte cached.
+    pub fn cachedto(&self) -> usize {
+        self.moved + self.rwbuffer.len()
+    }
+
+    pub fn tell(&self) -> usize {
+        self.offset
+    }
+}
+
+impl<S: Read + Write> Read for RebufferedBytesIO<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.check_tail()?;
+        self.fill(self.offset + buf.len())?;
+        if self.offset >= self.cachedto() {
+            return Ok(0);
+        }
+        let n = buf.len().min(self.cachedto() - self.offset);
+        let start = self.offset - self.moved;
+        buf[..n].copy_from_slice(&self.rwbuffer[start..start + n]);
+        self.offset += n;
+        self.cut_tail();
+        Ok(n)
+    }
+}
+
+impl<S: Read + Write> Write for RebufferedBytesIO<S> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.check_tail()?;
+        if !self.fill(self.offset)? {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "could not write past end of data"));
+        }
+        let start = self.offset - self.moved;
+        let end = (start + data.len()).min(self.rwbuffer.len());
+        self.rwbuffer.splice(start..end, data.iter().copied());
+        self.offset += data.len();
+        self.cut_tail();
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<S: Read + Write> Seek for RebufferedBytesIO<S> {
+    /// Seeking relative to the end is not supported.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.offset = match pos {
+            SeekFrom::Start(at) => at as usize,
+            SeekFrom::Current(delta) => self
+                .offset
+                .checked_add_signed(delta as isize)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "negative offset"))?,
+            SeekFrom::End(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "this class seeks only with whence: 0 and 1 (excluded 2)",
+                ))
+            }
+        };
+        Ok(self.offset as u64)
+    }
+}
+
 /// Replace underscores with hyphens in keys of the map.
 pub fn hyphenatedict(input: &HashMap<String, String>) -> HashMap<String, String> {
     input.iter().map(|(k, v)| {
@@ -138,7 +526,7 @@ fn build_possiblestringencodings(py: Python) -> PyObject {
 
 // ========================= BitsInteger ================================
 
-#[pyclass(extends=Construct)]
+#[pyclass(extends=Construct, module = "construct_rs")]
 pub struct BitsInteger {
     length: usize,
     signed: bool,
@@ -159,7 +547,12 @@ impl BitsInteger {
         )
     }
 
-    fn parse<'py>(&self, py: Python<'py>, data: &PyBytes) -> PyResult<PyObject> {
+    fn __getnewargs_ex__(&self, py: Python<'_>) -> NewArgs {
+        newargs(py, (self.length, self.signed, self.swapped))
+    }
+
+    #[pyo3(signature = (data, /, **_contextkw))]
+    fn parse<'py>(&self, py: Python<'py>, data: Input, _contextkw: Option<&PyDict>) -> PyResult<PyObject> {
         let mut bits = data.as_bytes().to_vec();
         if bits.len() != self.length {
             return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
@@ -167,7 +560,7 @@ impl BitsInteger {
             ));
         }
         if self.swapped {
-            if self.length % 8 != 0 {
+            if !self.length.is_multiple_of(8) {
                 return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
                     "little-endianness is only defined for multiples of 8 bits",
                 ));
@@ -178,8 +571,9 @@ impl BitsInteger {
         Ok(val.into_py(py))
     }
 
-    fn build<'py>(&self, py: Python<'py>, obj: &PyAny) -> PyResult<&'py PyBytes> {
-        let mut val: i128 = obj.extract()?;
+    #[pyo3(signature = (obj, /, **_contextkw))]
+    fn build<'py>(&self, py: Python<'py>, obj: &PyAny, _contextkw: Option<&PyDict>) -> PyResult<&'py PyBytes> {
+        let val: i128 = obj.extract()?;
         if val < 0 && !self.signed {
             return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
                 "value is negative, but field is not signed",
@@ -188,7 +582,7 @@ impl BitsInteger {
         let mut bits = integer2bits(val, self.length)
             .map_err(|_| PyErr::new::<pyo3::exceptions::PyValueError, _>("integer error"))?;
         if self.swapped {
-            if self.length % 8 != 0 {
+            if !self.length.is_multiple_of(8) {
                 return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
                     "little-endianness is only defined for multiples of 8 bits",
                 ));
@@ -236,6 +630,19 @@ pub fn bits2integer(data: &[u8], signed: bool) -> i128 {
     }
 }
 
+/// Expand bytes into a bit string, one byte per bit, most significant bit first.
+pub fn bytes2bits(data: &[u8]) -> Vec<u8> {
+    data.iter().flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1)).collect()
+}
+
+/// Pack a bit string, one byte per bit, into bytes. The length must be a multiple of 8.
+pub fn bits2bytes(data: &[u8]) -> Result<Vec<u8>, ConstructError> {
+    if !data.len().is_multiple_of(8) {
+        return Err(ConstructError::IntegerError);
+    }
+    Ok(data.chunks(8).map(|bits| bits.iter().fold(0, |byte, &bit| (byte << 1) | (bit != 0) as u8)).collect())
+}
+
 /// Convert an integer into a big-endian byte string.
 pub fn integer2bytes(mut number: i128, width: usize) -> Result<Vec<u8>, ConstructError> {
     if width > 16 {
@@ -272,9 +679,95 @@ pub fn swapbytes(mut data: Vec<u8>) -> Vec<u8> {
     data
 }
 
+/// Reverse the order of 8-bit groups within a bit string.
+pub fn swapbytesinbits(data: &[u8]) -> Result<Vec<u8>, ConstructError> {
+    if !data.len().is_multiple_of(8) {
+        return Err(ConstructError::Other("data length must be multiple of 8".to_string()));
+    }
+    Ok(data.rchunks(8).flatten().copied().collect())
+}
+
+/// Reverse the bit order within each byte of a byte string.
+pub fn swapbitsinbytes(data: &[u8]) -> Vec<u8> {
+    data.iter().map(|b| b.reverse_bits()).collect()
+}
+
+// ========================= Data transforms ============================
+
+/// Width of the widened key block used by [`xor_bytes`].
+const XOR_BLOCK: usize = 64;
+
+/// Xor `data` in place with `pad`, repeating the pad as needed.
+///
+/// Multi-byte pads are widened into a block of whole repetitions so that the
+/// inner loop has a fixed stride and vectorizes.
+pub fn xor_bytes(data: &mut [u8], pad: &[u8]) {
+    if pad.iter().all(|&p| p == 0) {
+        return;
+    }
+    if pad.len() == 1 {
+        let key = pad[0];
+        for b in data.iter_mut() {
+            *b ^= key;
+        }
+        return;
+    }
+    let reps = XOR_BLOCK.div_ceil(pad.len());
+    let block: Vec<u8> = pad.iter().copied().cycle().take(reps * pad.len()).collect();
+    let mut chunks = data.chunks_exact_mut(block.len());
+    for chunk in &mut chunks {
+        for (b, p) in chunk.iter_mut().zip(&block) {
+            *b ^= p;
+        }
+    }
+    for (b, p) in chunks.into_remainder().iter_mut().zip(&block) {
+        *b ^= p;
+    }
+}
+
+/// Rotate every `group`-byte chunk of `data` left by `amount` bits.
+///
+/// Negative amounts rotate right. Fails with `RotationError` when the group is
+/// less than 1 or does not divide the data length.
+pub fn rotate_left_bytes(data: &[u8], amount: i64, group: i64) -> Result<Vec<u8>, ConstructError> {
+    let bits = match group.checked_mul(8) {
+        Some(bits) if group >= 1 => bits,
+        _ => return Err(ConstructError::RotationError),
+    };
+    let group = usize::try_from(group).map_err(|_| ConstructError::RotationError)?;
+    if !data.len().is_multiple_of(group) {
+        return Err(ConstructError::RotationError);
+    }
+    let amount = amount.rem_euclid(bits) as usize;
+    let amount_bytes = amount / 8;
+    let amount1 = (amount % 8) as u32;
+    let mut out = data.to_vec();
+    if amount == 0 {
+        // nothing to do
+    } else if group == 1 {
+        for b in out.iter_mut() {
+            *b = b.rotate_left(amount1);
+        }
+    } else if amount1 == 0 {
+        for chunk in out.chunks_exact_mut(group) {
+            chunk.rotate_left(amount_bytes);
+        }
+    } else {
+        let amount2 = 8 - amount1;
+        for (dst, src) in out.chunks_exact_mut(group).zip(data.chunks_exact(group)) {
+            for (i, d) in dst.iter_mut().enumerate() {
+                let k1 = (i + amount_bytes) % group;
+                let k2 = (i + 1 + amount_bytes) % group;
+                *d = (src[k1] << amount1) | (src[k2] >> amount2);
+            }
+        }
+    }
+    Ok(out)
+}
+
 // ========================= BytesInteger ================================
 
-#[pyclass(extends=Construct)]
+#[pyclass(extends=Construct, module = "construct_rs")]
 pub struct BytesInteger {
     length: usize,
     signed: bool,
@@ -295,7 +788,12 @@ impl BytesInteger {
         )
     }
 
-    fn parse<'py>(&self, py: Python<'py>, data: &PyBytes) -> PyResult<PyObject> {
+    fn __getnewargs_ex__(&self, py: Python<'_>) -> NewArgs {
+        newargs(py, (self.length, self.signed, self.swapped))
+    }
+
+    #[pyo3(signature = (data, /, **_contextkw))]
+    fn parse<'py>(&self, py: Python<'py>, data: Input, _contextkw: Option<&PyDict>) -> PyResult<PyObject> {
         let mut bytes = data.as_bytes().to_vec();
         if bytes.len() != self.length {
             return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("input length mismatch"));
@@ -307,8 +805,9 @@ impl BytesInteger {
         Ok(val.into_py(py))
     }
 
-    fn build<'py>(&self, py: Python<'py>, obj: &PyAny) -> PyResult<&'py PyBytes> {
-        let mut val: i128 = obj.extract()?;
+    #[pyo3(signature = (obj, /, **_contextkw))]
+    fn build<'py>(&self, py: Python<'py>, obj: &PyAny, _contextkw: Option<&PyDict>) -> PyResult<&'py PyBytes> {
+        let val: i128 = obj.extract()?;
         if val < 0 && !self.signed {
             return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
                 "value is negative, but field is not signed",
@@ -329,7 +828,7 @@ impl BytesInteger {
 
 // ========================= FormatField ================================
 
-#[pyclass(extends=Construct)]
+#[pyclass(extends=Construct, module = "construct_rs")]
 pub struct FormatField {
     endian: char,
     format: char,
@@ -352,7 +851,12 @@ impl FormatField {
         Ok((FormatField { endian: e, format: f, length }, Construct {}))
     }
 
-    fn parse<'py>(&self, py: Python<'py>, data: &PyBytes) -> PyResult<PyObject> {
+    fn __getnewargs_ex__(&self, py: Python<'_>) -> NewArgs {
+        newargs(py, (self.endian.to_string(), self.format.to_string()))
+    }
+
+    #[pyo3(signature = (data, /, **_contextkw))]
+    fn parse<'py>(&self, py: Python<'py>, data: Input, _contextkw: Option<&PyDict>) -> PyResult<PyObject> {
         let buf = data.as_bytes();
         if buf.len() != self.length {
             return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("input length mismatch"));
@@ -451,7 +955,8 @@ impl FormatField {
         Ok(val.into_py(py))
     }
 
-    fn build<'py>(&self, py: Python<'py>, obj: &PyAny) -> PyResult<&'py PyBytes> {
+    #[pyo3(signature = (obj, /, **_contextkw))]
+    fn build<'py>(&self, py: Python<'py>, obj: &PyAny, _contextkw: Option<&PyDict>) -> PyResult<&'py PyBytes> {
         let bytes = match self.format {
             'B' => {
                 let v: u8 = obj.extract()?;
@@ -548,7 +1053,128 @@ impl FormatField {
 
 // ========================= Python bindings ==============================
 
-#[pyclass(subclass)]
+/// Positional and keyword arguments `__new__` is called with when unpickling,
+/// as returned by `__getnewargs_ex__`.
+type NewArgs = (Py<PyTuple>, Py<PyDict>);
+
+fn newargs(py: Python, args: impl IntoPy<Py<PyTuple>>) -> NewArgs {
+    (args.into_py(py), PyDict::new(py).into())
+}
+
+/// Data to parse: `bytes`, or any other object exporting its bytes through the buffer
+/// protocol, like `bytearray`, `memoryview`, `mmap` or numpy arrays, which are read in place.
+pub enum Input<'py> {
+    Bytes(&'py PyBytes),
+    Buffer(&'py PyAny, PyBuffer<u8>),
+}
+
+impl<'py> FromPyObject<'py> for Input<'py> {
+    fn extract(obj: &'py PyAny) -> PyResult<Self> {
+        if let Ok(bytes) = obj.downcast::<PyBytes>() {
+            return Ok(Input::Bytes(bytes));
+        }
+        let (obj, buffer) = byte_buffer(obj)?;
+        Ok(Input::Buffer(obj, buffer))
+    }
+}
+
+/// The contiguous buffer `obj` exports, along with the object exporting it. Buffers of
+/// wider items are taken as their bytes, as `memoryview.cast("B")` takes them.
+fn byte_buffer(obj: &PyAny) -> PyResult<(&PyAny, PyBuffer<u8>)> {
+    let (obj, buffer) = match PyBuffer::<u8>::get(obj) {
+        Ok(buffer) => (obj, buffer),
+        Err(_) => {
+            let obj = PyMemoryView::from(obj)?.call_method1("cast", ("B",))?;
+            (obj, PyBuffer::get(obj)?)
+        }
+    };
+    if !buffer.is_c_contiguous() {
+        return Err(PyErr::new::<pyo3::exceptions::PyBufferError, _>("buffer is not contiguous"));
+    }
+    Ok((obj, buffer))
+}
+
+impl IntoPy<PyObject> for Input<'_> {
+    fn into_py(self, py: Python<'_>) -> PyObject {
+        self.as_any().into_py(py)
+    }
+}
+
+impl<'py> Input<'py> {
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Input::Bytes(bytes) => bytes.as_bytes(),
+            Input::Buffer(_, buffer) if buffer.len_bytes() == 0 => &[],
+            // SAFETY: the buffer is contiguous, and stays exported while `self` is alive.
+            Input::Buffer(_, buffer) => unsafe { std::slice::from_raw_parts(buffer.buf_ptr() as *const u8, buffer.len_bytes()) },
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.as_bytes().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The object the bytes are read from.
+    pub fn as_any(&self) -> &'py PyAny {
+        match self {
+            Input::Bytes(bytes) => bytes,
+            Input::Buffer(obj, _) => obj,
+        }
+    }
+
+    /// `memoryview` of the bytes in `range`, sharing the memory of the input.
+    pub fn view(&self, py: Python<'py>, range: Range<usize>) -> PyResult<&'py PyAny> {
+        let slice = pyo3::types::PySlice::new(py, range.start as isize, range.end as isize, 1);
+        PyMemoryView::from(self.as_any())?.get_item(slice)
+    }
+
+    /// The bytes from `offset` on, as the input itself or a view of it.
+    fn tail(&self, py: Python<'py>, offset: usize) -> PyResult<&'py PyAny> {
+        match offset {
+            0 => Ok(self.as_any()),
+            _ => self.view(py, offset..self.len()),
+        }
+    }
+
+    /// The data a `memoryview` input views, which the outermost parse was given before
+    /// composites passed views of it on. Other inputs are returned as they are.
+    fn outermost(&self) -> PyResult<Input<'py>> {
+        if let Input::Buffer(obj, _) = self
+            && let Ok(view) = obj.downcast::<PyMemoryView>()
+        {
+            let base = view.getattr("obj")?;
+            if !base.is_none() {
+                return Input::extract(base);
+            }
+        }
+        Ok(match self {
+            Input::Bytes(bytes) => Input::Bytes(bytes),
+            Input::Buffer(obj, _) => Input::extract(obj)?,
+        })
+    }
+
+    /// The data as `bytes`, copied unless it already is.
+    pub fn to_bytes(&self, py: Python<'py>) -> &'py PyBytes {
+        match self {
+            Input::Bytes(bytes) => bytes,
+            Input::Buffer(..) => PyBytes::new(py, self.as_bytes()),
+        }
+    }
+
+    /// Run `f` with the GIL released, unless other threads could write to the data meanwhile.
+    fn allow_threads<T: Send>(&self, py: Python, f: impl Ungil + FnOnce() -> T) -> T {
+        match self {
+            Input::Buffer(_, buffer) if !buffer.readonly() => f(),
+            _ => py.allow_threads(f),
+        }
+    }
+}
+
+#[pyclass(subclass, module = "construct_rs")]
 pub struct Construct {}
 
 #[pymethods]
@@ -558,16 +1184,93 @@ impl Construct {
         Construct {}
     }
 
+    /// Pickle module-level singletons like `Int32ub` by name, so they unpickle as the
+    /// same object, and other constructs by their constructor arguments and state.
+    fn __reduce__(slf: &PyCell<Self>, py: Python<'_>) -> PyResult<PyObject> {
+        if let Ok(module) = py.import("construct_rs")
+            && let Some((name, _)) = module.dict().iter().find(|(_, value)| value.is(slf))
+        {
+            return Ok(name.into());
+        }
+        let (args, kwargs): (&PyTuple, &PyDict) = slf.call_method0("__getnewargs_ex__")?.extract()?;
+        let newobj = py.import("copyreg")?.getattr("__newobj_ex__")?;
+        let state = match slf.getattr("__getstate__") {
+            Ok(getstate) => getstate.call0()?,
+            Err(_) => slf.getattr("__dict__").unwrap_or_else(|_| py.None().into_ref(py)),
+        };
+        Ok((newobj, (slf.get_type(), args, kwargs), state).into_py(py))
+    }
+
+    fn __getnewargs_ex__(&self, py: Python<'_>) -> NewArgs {
+        newargs(py, ())
+    }
+
+    /// `"name" / subcon` names the subcon, for a `Struct` or another composite.
+    fn __rtruediv__(slf: &PyCell<Self>, py: Python<'_>, name: String) -> PyResult<Py<Renamed>> {
+        Py::new(py, Renamed::new(slf, Some(name), None, None)?)
+    }
+
+    /// `subcon * "docs"` documents the subcon, and `subcon * hook` calls `hook(obj, context)`
+    /// with every value parsed by it.
+    fn __mul__(slf: &PyCell<Self>, py: Python<'_>, other: &PyAny) -> PyResult<Py<Renamed>> {
+        Renamed::annotated(py, slf, other)
+    }
+
+    fn __rmul__(slf: &PyCell<Self>, py: Python<'_>, other: &PyAny) -> PyResult<Py<Renamed>> {
+        Renamed::annotated(py, slf, other)
+    }
+
+    /// `subcon[count]` is an `Array` of `count` elements, where the count can be a context
+    /// lambda or expression.
+    fn __getitem__(slf: &PyCell<Self>, py: Python<'_>, count: &PyAny) -> PyResult<Py<Array>> {
+        if count.is_instance_of::<pyo3::types::PySlice>() {
+            return Err(ConstructError::Other("subcon[N] syntax can only be used for Arrays, use GreedyRange(subcon) instead?".into()).into());
+        }
+        if !count.is_instance_of::<pyo3::types::PyLong>() && !count.is_callable() {
+            return Err(ConstructError::Other("subcon[N] syntax expects integer or context lambda".into()).into());
+        }
+        Py::new(py, Array::new(count.into(), slf.into()))
+    }
+
+    /// `a >> b` is a `Sequence` of both, with the members of either that is a `Sequence`.
+    fn __rshift__(slf: &PyCell<Self>, py: Python<'_>, other: &PyAny) -> PyResult<Py<Sequence>> {
+        let mut subcons = sequence_members(slf)?;
+        subcons.extend(sequence_members(other)?);
+        Py::new(py, (Sequence { subcons, schema: SchemaCache::default() }, Construct {}))
+    }
+
     /// Parse bytes from memory. Currently returns the data unchanged.
-    fn parse<'py>(&self, py: Python<'py>, data: &PyBytes) -> PyResult<&'py PyBytes> {
+    #[pyo3(signature = (data, /, **_contextkw))]
+    fn parse<'py>(&self, py: Python<'py>, data: Input, _contextkw: Option<&PyDict>) -> PyResult<&'py PyBytes> {
         Ok(PyBytes::new(py, data.as_bytes()))
     }
 
     /// Build an object into bytes. Returns the input bytes.
-    fn build<'py>(&self, py: Python<'py>, obj: &PyBytes) -> PyResult<&'py PyBytes> {
+    #[pyo3(signature = (obj, /, **_contextkw))]
+    fn build<'py>(&self, py: Python<'py>, obj: &PyBytes, _contextkw: Option<&PyDict>) -> PyResult<&'py PyBytes> {
         Ok(PyBytes::new(py, obj.as_bytes()))
     }
 
+    /// Parse the records of an `Array` or `GreedyRange` on `threads` threads, by default
+    /// one per CPU, once it is known where each of them lies. Other constructs, and
+    /// records that call into Python to parse, parse as `parse` does.
+    ///
+    /// Records of a fixed size are located from it, but the others are located one after
+    /// another before the threads start, reading their length fields or, when their size
+    /// depends on more than that, parsing them. Such records gain little from threads.
+    #[pyo3(signature = (data, threads=None, **contextkw))]
+    fn parse_parallel(slf: &PyCell<Self>, py: Python<'_>, data: Input, threads: Option<usize>, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        let threads = match threads {
+            Some(0) => return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("threads must be positive")),
+            Some(threads) => threads,
+            None => std::thread::available_parallelism().map_or(1, |threads| threads.get()),
+        };
+        if let Some(parsed) = parse_parallel_released(py, slf, &data, contextkw, threads)? {
+            return Ok(parsed);
+        }
+        Ok(slf.call_method("parse", (data,), contextkw)?.into())
+    }
+
     /// Parse entire contents of a file.
     fn parse_file<'py>(&self, py: Python<'py>, filename: &str) -> PyResult<&'py PyBytes> {
         let data = std::fs::read(filename).map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
@@ -578,10 +1281,136 @@ impl Construct {
     fn build_file(&self, filename: &str, data: &PyBytes) -> PyResult<()> {
         std::fs::write(filename, data.as_bytes()).map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))
     }
+
+    /// Parse from a file-like object, such as an open file or a socket's `makefile()`.
+    /// The stream is read for as many bytes as the parser can tell the object still
+    /// needs, and no more, so the data following it is left unread. Constructs calling
+    /// into Python are read one byte at a time past what they can tell, until they parse.
+    /// Constructs reading to the end of the data read the rest of the stream, and seekable
+    /// streams are then left positioned after the bytes parsed.
+    #[pyo3(signature = (stream, **contextkw))]
+    fn parse_stream(slf: &PyCell<Self>, py: Python<'_>, stream: Py<PyAny>, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        let seekable = stream.call_method0(py, "seekable").and_then(|seekable| seekable.is_truthy(py)).unwrap_or(false);
+        let mut stream = PyFileLike::new(stream);
+        let mut feeder = Feeder::new(slf, contextkw);
+        let to_end = feeder.reads_to_end();
+        if to_end {
+            feeder.buffer = stream_read_entire(&mut stream)?;
+        }
+        loop {
+            if let Some((obj, consumed)) = feeder.parse_next(py)? {
+                let unused = feeder.buffer.len() - consumed;
+                if seekable && unused > 0 {
+                    stream.seek(SeekFrom::Current(-(unused as i64))).map_err(io_py_error)?;
+                }
+                return Ok(obj);
+            }
+            if to_end {
+                return Err(ConstructError::StreamError.into());
+            }
+            let needed = feeder.needed();
+            feeder.buffer.extend(stream_read(&mut stream, needed)?);
+        }
+    }
+
+    /// Parse from an `asyncio.StreamReader`, to be awaited. The reader is awaited for as
+    /// many bytes as the parser can tell the object still needs, and no more, so the data
+    /// following it is left unread, as `parse_stream` leaves it. Constructs reading to the
+    /// end of the data read the rest of the stream.
+    #[pyo3(signature = (reader, **contextkw))]
+    fn parse_async(slf: &PyCell<Self>, reader: Py<PyAny>, contextkw: Option<&PyDict>) -> AsyncParse {
+        let feeder = Feeder::new(slf, contextkw);
+        let to_end = feeder.reads_to_end();
+        AsyncParse { feeder, reader, reading: None, to_end, eof: false, done: false }
+    }
+
+    /// Build an object into a file-like object.
+    #[pyo3(signature = (obj, stream, **contextkw))]
+    fn build_stream(slf: &PyCell<Self>, py: Python<'_>, obj: &PyAny, stream: Py<PyAny>, contextkw: Option<&PyDict>) -> PyResult<()> {
+        let built = build_in(slf, obj, context_from_kwargs(py, contextkw)?)?;
+        Ok(stream_write(&mut PyFileLike::new(stream), &built)?)
+    }
+
+    /// Build an object into the writable buffer `buffer`, like a `bytearray` or `mmap`,
+    /// starting at `offset`. Returns the number of bytes written.
+    #[pyo3(signature = (obj, buffer, offset=0, **contextkw))]
+    fn build_into(slf: &PyCell<Self>, py: Python<'_>, obj: &PyAny, buffer: &PyAny, offset: usize, contextkw: Option<&PyDict>) -> PyResult<usize> {
+        let built = bytes_like(py, slf.call_method("build", (obj,), contextkw)?)?.as_bytes();
+        let (_, buffer) = byte_buffer(buffer)?;
+        if buffer.readonly() {
+            return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>("buffer is read-only"));
+        }
+        if offset.checked_add(built.len()).is_none_or(|end| end > buffer.len_bytes()) {
+            return Err(ConstructError::StreamError.into());
+        }
+        // SAFETY: the buffer is writable and contiguous, and the range was checked above.
+        unsafe { std::ptr::copy_nonoverlapping(built.as_ptr(), (buffer.buf_ptr() as *mut u8).add(offset), built.len()) };
+        Ok(built.len())
+    }
+
+    /// Generate an arbitrary object this construct builds, for property-based tests of
+    /// round trips. The same `seed` generates the same object.
+    /// Members depending on the context are generated against the keyword arguments and
+    /// the members before them. Only constructs that can be described are supported.
+    #[pyo3(signature = (seed=None, **contextkw))]
+    fn arbitrary(slf: &PyCell<Self>, py: Python<'_>, seed: Option<u64>, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        let schema = Schema::from_construct(slf)?;
+        let mut source = ArbitrarySource::new(seed.unwrap_or_else(ArbitrarySource::random_seed));
+        let empty = Input::Bytes(PyBytes::new(py, b""));
+        let mut failure = None;
+        for _ in 0..ARBITRARY_ATTEMPTS {
+            let mut parser = NativeParser::new(&[]);
+            let generated = parser.scope_from_py(contextkw).and_then(|scope| parser.arbitrary(&schema, &mut source, Some(scope)));
+            let obj = match generated {
+                Ok(value) => value.to_object(py, &empty)?,
+                Err(NativeError::Unsupported) => {
+                    return Err(ConstructError::Other(format!("no arbitrary {} can be generated", schema.type_name())).into());
+                }
+                Err(_) => continue,
+            };
+            match build_in(slf, obj.as_ref(py), context_from_kwargs(py, contextkw)?) {
+                Ok(_) => return Ok(obj),
+                Err(err) if err.is_instance_of::<exceptions::ConstructError>(py) => failure = Some(err),
+                Err(err) => return Err(err),
+            }
+        }
+        Err(failure.unwrap_or_else(|| ConstructError::Other(format!("no arbitrary {} could be generated", schema.type_name())).into()))
+    }
+
+    /// Generate standalone Rust source parsing and building this construct.
+    #[pyo3(signature = (name="Root"))]
+    fn generate_rust(slf: &PyCell<Self>, name: &str) -> PyResult<String> {
+        Ok(generate_rust(&Schema::from_construct(slf)?, name)?)
+    }
+
+    /// Export as a Kaitai Struct (.ksy) document, also written to `filename` if given.
+    #[pyo3(signature = (schemaname="unnamed_schema", filename=None))]
+    fn export_ksy(slf: &PyCell<Self>, schemaname: &str, filename: Option<&str>) -> PyResult<String> {
+        let ksy = export_ksy(&Schema::from_construct(slf)?, schemaname)?;
+        if let Some(filename) = filename {
+            std::fs::write(filename, &ksy).map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+        }
+        Ok(ksy)
+    }
+
+    /// Export as a JSON or YAML schema document, also written to `filename` if given.
+    #[pyo3(signature = (format="json", filename=None))]
+    fn export_schema(slf: &PyCell<Self>, format: &str, filename: Option<&str>) -> PyResult<String> {
+        let schema = Schema::from_construct(slf)?;
+        let document = match format {
+            "json" => schema.to_json(),
+            "yaml" => schema.to_yaml(),
+            other => return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("unknown schema format {:?}", other))),
+        };
+        if let Some(filename) = filename {
+            std::fs::write(filename, &document).map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+        }
+        Ok(document)
+    }
 }
 
 /// A wrapper around another `Construct`-like object.
-#[pyclass(extends=Construct)]
+#[pyclass(extends=Construct, subclass, module = "construct_rs")]
 pub struct Subconstruct {
     subcon: Py<PyAny>,
 }
@@ -593,22 +1422,27 @@ impl Subconstruct {
         (Subconstruct { subcon }, Construct {})
     }
 
+    fn __getnewargs_ex__(&self, py: Python<'_>) -> NewArgs {
+        newargs(py, (self.subcon.clone_ref(py),))
+    }
+
     /// Delegate parsing to the wrapped construct.
-    fn parse<'py>(&self, py: Python<'py>, data: &PyBytes) -> PyResult<&'py PyBytes> {
-        let res = self.subcon.as_ref(py).call_method1("parse", (data,))?;
-        res.extract()
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse<'py>(&self, py: Python<'py>, data: Input, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let (res, _) = parse_prefix_in(py, self.subcon.as_ref(py), &data, 0, context_from_kwargs(py, contextkw)?)?;
+        res.into_ref(py).extract()
     }
 
     /// Delegate building to the wrapped construct.
-    fn build<'py>(&self, py: Python<'py>, obj: &PyBytes) -> PyResult<&'py PyBytes> {
-        let res = self.subcon.as_ref(py).call_method1("build", (obj,))?;
-        res.extract()
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(&self, py: Python<'py>, obj: &PyBytes, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        Ok(PyBytes::new(py, &build_in(self.subcon.as_ref(py), obj, context_from_kwargs(py, contextkw)?)?))
     }
 
     /// Delegate file parsing to the wrapped construct.
     fn parse_file<'py>(&self, py: Python<'py>, filename: &str) -> PyResult<&'py PyBytes> {
-        let res = self.subcon.as_ref(py).call_method1("parse_file", (filename,))?;
-        res.extract()
+        let res = self.subcon.call_method1(py, "parse_file", (filename,))?;
+        res.into_ref(py).extract()
     }
 
     /// Delegate file building to the wrapped construct.
@@ -621,29 +1455,30 @@ impl Subconstruct {
 // ========================= Adapter ==================================
 
 /// Base class for value transforming constructs.
-#[pyclass(extends=Subconstruct)]
+#[pyclass(extends=Subconstruct, subclass, module = "construct_rs")]
 pub struct Adapter {}
 
 #[pymethods]
 impl Adapter {
     #[new]
-    fn new(subcon: Py<PyAny>) -> (Self, Subconstruct) {
-        (Adapter {}, Subconstruct { subcon })
+    fn new(subcon: Py<PyAny>) -> PyClassInitializer<Self> {
+        PyClassInitializer::from(Construct {}).add_subclass(Subconstruct { subcon }).add_subclass(Adapter {})
     }
 
     /// Parse and then decode using `_decode` implemented by subclasses.
-    fn parse<'py>(slf: PyRef<'py, Self>, py: Python<'py>, data: &PyBytes) -> PyResult<PyObject> {
-        let base: PyRef<Subconstruct> = slf.into_super();
-        let intermediate = base.subcon.as_ref(py).call_method1("parse", (data,))?;
-        slf.as_ref().call_method1(py, "_decode", (intermediate,))
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse<'py>(slf: PyRef<'py, Self>, py: Python<'py>, data: Input, contextkw: Option<&'py PyDict>) -> PyResult<PyObject> {
+        let subcon = slf.as_ref().subcon.clone_ref(py);
+        let (intermediate, _) = parse_prefix_in(py, subcon.as_ref(py), &data, 0, context_from_kwargs(py, contextkw)?)?;
+        slf.into_py(py).call_method1(py, "_decode", (intermediate,))
     }
 
     /// Encode with `_encode` implemented by subclasses and build using the wrapped construct.
-    fn build<'py>(slf: PyRef<'py, Self>, py: Python<'py>, obj: &PyAny) -> PyResult<&'py PyBytes> {
-        let encoded = slf.as_ref().call_method1(py, "_encode", (obj,))?;
-        let base: PyRef<Subconstruct> = slf.into_super();
-        let res = base.subcon.as_ref(py).call_method1("build", (encoded,))?;
-        res.extract()
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(slf: PyRef<'py, Self>, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let subcon = slf.as_ref().subcon.clone_ref(py);
+        let encoded = slf.into_py(py).call_method1(py, "_encode", (obj,))?;
+        Ok(PyBytes::new(py, &build_in(subcon.as_ref(py), encoded.as_ref(py), context_from_kwargs(py, contextkw)?)?))
     }
 
     #[pyo3(name = "_decode")]
@@ -660,7 +1495,7 @@ impl Adapter {
 // ========================= StringEncoded =============================
 
 /// Adapter that applies encoding/decoding on byte strings.
-#[pyclass(extends=Adapter)]
+#[pyclass(extends=Adapter, module = "construct_rs")]
 pub struct StringEncoded {
     encoding: String,
 }
@@ -668,14 +1503,22 @@ pub struct StringEncoded {
 #[pymethods]
 impl StringEncoded {
     #[new]
-    fn new(subcon: Py<PyAny>, encoding: &str) -> PyResult<(Self, Adapter, Subconstruct)> {
+    fn new(subcon: Py<PyAny>, encoding: &str) -> PyResult<PyClassInitializer<Self>> {
         encoding_unit(encoding)?;
-        Ok((StringEncoded { encoding: encoding.to_string() }, Adapter {}, Subconstruct { subcon }))
+        Ok(PyClassInitializer::from(Construct {})
+            .add_subclass(Subconstruct { subcon })
+            .add_subclass(Adapter {})
+            .add_subclass(StringEncoded { encoding: encoding.to_string() }))
+    }
+
+    fn __getnewargs_ex__(slf: PyRef<'_, Self>, py: Python<'_>) -> NewArgs {
+        let encoding = slf.encoding.clone();
+        newargs(py, (slf.into_super().into_super().subcon.clone_ref(py), encoding))
     }
 
     #[pyo3(name = "_decode")]
-    fn _decode<'py>(&self, py: Python<'py>, obj: &PyBytes) -> PyResult<PyObject> {
-        obj.call_method1(py, "decode", (self.encoding.as_str(),))
+    fn _decode(&self, obj: &PyBytes) -> PyResult<PyObject> {
+        Ok(obj.call_method1("decode", (self.encoding.as_str(),))?.into())
     }
 
     #[pyo3(name = "_encode")]
@@ -687,36 +1530,176 @@ impl StringEncoded {
     }
 }
 
-// ========================= String Classes ============================
+// ========================= Bytes =====================================
 
-#[pyclass(extends=Construct)]
-pub struct PaddedString {
-    length: usize,
-    encoding: String,
+/// `bytes` built from `obj`, converting other bytes-like objects as `bytes(obj)` does.
+fn bytes_like<'py>(py: Python<'py>, obj: &'py PyAny) -> PyResult<&'py PyBytes> {
+    match obj.downcast::<PyBytes>() {
+        Ok(bytes) => Ok(bytes),
+        Err(_) => Ok(py.get_type::<PyBytes>().call1((obj,))?.downcast()?),
+    }
+}
+
+/// The first `length` bytes of `data`, as `bytes` or, with `zerocopy`, as a `memoryview`
+/// of the input. Composites pass their members a view of their own input, so the view
+/// is of the data given to the outermost `parse`.
+fn bytes_payload(py: Python, data: &Input, length: usize, zerocopy: bool) -> PyResult<PyObject> {
+    match data {
+        _ if zerocopy => Ok(data.view(py, 0..length)?.into()),
+        Input::Bytes(bytes) if length == bytes.as_bytes().len() => Ok(bytes.into_py(py)),
+        _ => Ok(PyBytes::new(py, &data.as_bytes()[..length]).into()),
+    }
+}
+
+/// Field of `length` bytes, parsed as `bytes`, or with `zerocopy` as a `memoryview`
+/// slice of the input. The length can be a context lambda or expression.
+#[pyclass(extends=Construct, module = "construct_rs")]
+pub struct Bytes {
+    length: Py<PyAny>,
+    zerocopy: bool,
+    schema: SchemaCache,
+}
+
+impl Bytes {
+    fn length(&self, py: Python, context: &PyDict) -> PyResult<usize> {
+        let length: i64 = evaluate(self.length.as_ref(py), context)?.extract()?;
+        usize::try_from(length).map_err(|_| ConstructError::StreamError.into())
+    }
 }
 
 #[pymethods]
-impl PaddedString {
+impl Bytes {
     #[new]
-    fn new(length: usize, encoding: &str) -> PyResult<(Self, Construct)> {
-        encoding_unit(encoding)?;
-        Ok((PaddedString { length, encoding: encoding.to_string() }, Construct {}))
+    #[pyo3(signature = (length, zerocopy=false))]
+    fn new(length: Py<PyAny>, zerocopy: bool) -> (Self, Construct) {
+        (Bytes { length, zerocopy, schema: SchemaCache::default() }, Construct {})
     }
 
-    fn parse<'py>(&self, py: Python<'py>, data: &PyBytes) -> PyResult<PyObject> {
-        if data.len() != self.length {
-            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("input length mismatch"));
+    fn __getnewargs_ex__(&self, py: Python<'_>) -> NewArgs {
+        newargs(py, (self.length.clone_ref(py), self.zerocopy))
+    }
+
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(slf: &PyCell<Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        if let Some(parsed) = parse_released(py, slf, &data, contextkw)? {
+            return Ok(parsed);
         }
-        let mut buf = data.as_bytes().to_vec();
-        let pad = encoding_unit(&self.encoding).unwrap();
-        while buf.ends_with(pad) && !buf.is_empty() {
-            let l = pad.len();
-            buf.truncate(buf.len() - l);
+        let this = slf.borrow();
+        let length = this.length(py, context_from_kwargs(py, contextkw)?)?;
+        if length > data.as_bytes().len() {
+            return Err(ConstructError::StreamError.into());
         }
-        PyBytes::new(py, &buf).call_method1("decode", (self.encoding.as_str(),))
+        Ok((bytes_payload(py, &data, length, this.zerocopy)?, length))
     }
 
-    fn build<'py>(&self, py: Python<'py>, obj: &PyAny) -> PyResult<&'py PyBytes> {
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(slf: &PyCell<Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(Self::parse_prefix(slf, py, data, contextkw)?.0)
+    }
+
+    /// Build bytes-like objects of the length, or integers as that many big-endian bytes.
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(&self, py: Python<'py>, obj: &'py PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let length = self.length(py, context_from_kwargs(py, contextkw)?)?;
+        if obj.is_instance_of::<pyo3::types::PyLong>() {
+            let data = integer2bytes(obj.extract()?, length)?;
+            return Ok(PyBytes::new(py, &data));
+        }
+        let data = bytes_like(py, obj)?;
+        if data.as_bytes().len() != length {
+            return Err(ConstructError::StreamError.into());
+        }
+        Ok(data)
+    }
+
+    fn sizeof(&self, py: Python<'_>) -> PyResult<usize> {
+        self.length(py, Container::empty(py)?).map_err(|_| ConstructError::SizeofError.into())
+    }
+}
+
+/// All the remaining bytes, parsed as `bytes`, or with `zerocopy` as a `memoryview`.
+///
+/// The module exposes the instance parsing `bytes` as `GreedyBytes`, which is called
+/// for the others: `GreedyBytes(zerocopy=True)`.
+#[pyclass(extends=Construct, module = "construct_rs")]
+pub struct GreedyBytes {
+    zerocopy: bool,
+}
+
+#[pymethods]
+impl GreedyBytes {
+    #[new]
+    #[pyo3(signature = (zerocopy=false))]
+    fn new(zerocopy: bool) -> (Self, Construct) {
+        (GreedyBytes { zerocopy }, Construct {})
+    }
+
+    #[pyo3(signature = (zerocopy=false))]
+    fn __call__(&self, py: Python<'_>, zerocopy: bool) -> PyResult<Py<GreedyBytes>> {
+        Py::new(py, GreedyBytes::new(zerocopy))
+    }
+
+    /// Pickle as the `GreedyBytes` instance of the module, called for the others, as the
+    /// module has no class of that name to create them from.
+    fn __reduce__(slf: &PyCell<Self>, py: Python<'_>) -> PyResult<PyObject> {
+        let greedybytes = py.import("construct_rs")?.getattr("GreedyBytes")?;
+        if greedybytes.is(slf) {
+            return Ok("GreedyBytes".into_py(py));
+        }
+        Ok((greedybytes, (slf.borrow().zerocopy,)).into_py(py))
+    }
+
+    #[pyo3(signature = (data, /, **_contextkw))]
+    fn parse(&self, py: Python<'_>, data: Input, _contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        bytes_payload(py, &data, data.as_bytes().len(), self.zerocopy)
+    }
+
+    #[pyo3(signature = (obj, /, **_contextkw))]
+    fn build<'py>(&self, py: Python<'py>, obj: &'py PyAny, _contextkw: Option<&PyDict>) -> PyResult<&'py PyBytes> {
+        bytes_like(py, obj)
+    }
+
+    fn sizeof(&self) -> PyResult<usize> {
+        Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("size is dynamic"))
+    }
+}
+
+// ========================= String Classes ============================
+
+#[pyclass(extends=Construct, module = "construct_rs")]
+pub struct PaddedString {
+    length: usize,
+    encoding: String,
+}
+
+#[pymethods]
+impl PaddedString {
+    #[new]
+    fn new(length: usize, encoding: &str) -> PyResult<(Self, Construct)> {
+        encoding_unit(encoding)?;
+        Ok((PaddedString { length, encoding: encoding.to_string() }, Construct {}))
+    }
+
+    fn __getnewargs_ex__(&self, py: Python<'_>) -> NewArgs {
+        newargs(py, (self.length, self.encoding.clone()))
+    }
+
+    #[pyo3(signature = (data, /, **_contextkw))]
+    fn parse<'py>(&self, py: Python<'py>, data: Input, _contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        if data.len() != self.length {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("input length mismatch"));
+        }
+        let mut buf = data.as_bytes().to_vec();
+        let pad = encoding_unit(&self.encoding).unwrap();
+        while buf.ends_with(pad) && !buf.is_empty() {
+            let l = pad.len();
+            buf.truncate(buf.len() - l);
+        }
+        Ok(PyBytes::new(py, &buf).call_method1("decode", (self.encoding.as_str(),))?.into())
+    }
+
+    #[pyo3(signature = (obj, /, **_contextkw))]
+    fn build<'py>(&self, py: Python<'py>, obj: &PyAny, _contextkw: Option<&PyDict>) -> PyResult<&'py PyBytes> {
         let s: &str = obj.extract()?;
         let py_str = PyString::new(py, s);
         let mut data: Vec<u8> = py_str
@@ -739,7 +1722,7 @@ impl PaddedString {
     }
 }
 
-#[pyclass(extends=Construct)]
+#[pyclass(extends=Construct, module = "construct_rs")]
 pub struct PascalString {
     lengthfield: Py<PyAny>,
     encoding: String,
@@ -753,7 +1736,23 @@ impl PascalString {
         Ok((PascalString { lengthfield, encoding: encoding.to_string() }, Construct {}))
     }
 
-    fn parse<'py>(&self, py: Python<'py>, data: &PyBytes) -> PyResult<PyObject> {
+    fn __getnewargs_ex__(&self, py: Python<'_>) -> NewArgs {
+        newargs(py, (self.lengthfield.clone_ref(py), self.encoding.clone()))
+    }
+
+    /// Parse the length prefix and as many bytes of content as it specifies.
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(&self, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        let context = context_from_kwargs(py, contextkw)?;
+        let (length, len_size) = parse_prefix_in(py, self.lengthfield.as_ref(py), &data, 0, context)?;
+        let data = data.as_bytes();
+        let end = len_size + length.extract::<usize>(py)?;
+        let content = data.get(len_size..end).ok_or(ConstructError::StreamError)?;
+        Ok((PyBytes::new(py, content).call_method1("decode", (self.encoding.as_str(),))?.into(), end))
+    }
+
+    #[pyo3(signature = (data, /, **_contextkw))]
+    fn parse<'py>(&self, py: Python<'py>, data: Input, _contextkw: Option<&PyDict>) -> PyResult<PyObject> {
         let len_size: usize = self.lengthfield.as_ref(py).call_method0("sizeof")?.extract()?;
         if data.len() < len_size {
             return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("input length mismatch"));
@@ -765,16 +1764,16 @@ impl PascalString {
             return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("input length mismatch"));
         }
         let content = PyBytes::new(py, &data.as_bytes()[len_size..end]);
-        content.call_method1("decode", (self.encoding.as_str(),))
+        Ok(content.call_method1("decode", (self.encoding.as_str(),))?.into())
     }
 
-    fn build<'py>(&self, py: Python<'py>, obj: &PyAny) -> PyResult<&'py PyBytes> {
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(&self, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
         let s: &str = obj.extract()?;
         let py_str = PyString::new(py, s);
         let data_bytes: &PyBytes = py_str.call_method1("encode", (self.encoding.as_str(),))?.extract()?;
-        let length = data_bytes.len();
-        let len_field: &PyBytes = self.lengthfield.as_ref(py).call_method1("build", (length,))?.extract()?;
-        let mut out = len_field.as_bytes().to_vec();
+        let length = data_bytes.as_bytes().len().into_py(py);
+        let mut out = build_in(self.lengthfield.as_ref(py), length.as_ref(py), context_from_kwargs(py, contextkw)?)?;
         out.extend_from_slice(data_bytes.as_bytes());
         Ok(PyBytes::new(py, &out))
     }
@@ -784,7 +1783,7 @@ impl PascalString {
     }
 }
 
-#[pyclass(extends=Construct)]
+#[pyclass(extends=Construct, module = "construct_rs")]
 pub struct CString {
     encoding: String,
 }
@@ -797,16 +1796,32 @@ impl CString {
         Ok((CString { encoding: encoding.to_string() }, Construct {}))
     }
 
-    fn parse<'py>(&self, py: Python<'py>, data: &PyBytes) -> PyResult<PyObject> {
+    fn __getnewargs_ex__(&self, py: Python<'_>) -> NewArgs {
+        newargs(py, (self.encoding.clone(),))
+    }
+
+    /// Parse up to and including the first terminator, aligned to the encoding unit.
+    #[pyo3(name = "_parseprefix", signature = (data, /, **_contextkw))]
+    fn parse_prefix(&self, py: Python<'_>, data: Input, _contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        let pad = encoding_unit(&self.encoding).unwrap();
+        let data = data.as_bytes();
+        let end = data.chunks(pad.len()).position(|unit| unit == pad).ok_or(ConstructError::StreamError)? * pad.len();
+        let obj = PyBytes::new(py, &data[..end]).call_method1("decode", (self.encoding.as_str(),))?;
+        Ok((obj.into(), end + pad.len()))
+    }
+
+    #[pyo3(signature = (data, /, **_contextkw))]
+    fn parse<'py>(&self, py: Python<'py>, data: Input, _contextkw: Option<&PyDict>) -> PyResult<PyObject> {
         let pad = encoding_unit(&self.encoding).unwrap();
         if !data.as_bytes().ends_with(pad) {
             return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("missing terminator"));
         }
         let slice = &data.as_bytes()[..data.len() - pad.len()];
-        PyBytes::new(py, slice).call_method1("decode", (self.encoding.as_str(),))
+        Ok(PyBytes::new(py, slice).call_method1("decode", (self.encoding.as_str(),))?.into())
     }
 
-    fn build<'py>(&self, py: Python<'py>, obj: &PyAny) -> PyResult<&'py PyBytes> {
+    #[pyo3(signature = (obj, /, **_contextkw))]
+    fn build<'py>(&self, py: Python<'py>, obj: &PyAny, _contextkw: Option<&PyDict>) -> PyResult<&'py PyBytes> {
         let s: &str = obj.extract()?;
         let py_str = PyString::new(py, s);
         let mut data: Vec<u8> = py_str.call_method1("encode", (self.encoding.as_str(),))?.extract::<&PyBytes>()?.as_bytes().to_vec();
@@ -820,7 +1835,7 @@ impl CString {
     }
 }
 
-#[pyclass(extends=Construct)]
+#[pyclass(extends=Construct, module = "construct_rs")]
 pub struct GreedyString {
     encoding: String,
 }
@@ -833,11 +1848,17 @@ impl GreedyString {
         Ok((GreedyString { encoding: encoding.to_string() }, Construct {}))
     }
 
-    fn parse<'py>(&self, py: Python<'py>, data: &PyBytes) -> PyResult<PyObject> {
-        data.call_method1(py, "decode", (self.encoding.as_str(),))
+    fn __getnewargs_ex__(&self, py: Python<'_>) -> NewArgs {
+        newargs(py, (self.encoding.clone(),))
+    }
+
+    #[pyo3(signature = (data, /, **_contextkw))]
+    fn parse<'py>(&self, py: Python<'py>, data: Input, _contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(py.get_type::<PyString>().call1((data, self.encoding.as_str()))?.into())
     }
 
-    fn build<'py>(&self, py: Python<'py>, obj: &PyAny) -> PyResult<&'py PyBytes> {
+    #[pyo3(signature = (obj, /, **_contextkw))]
+    fn build<'py>(&self, py: Python<'py>, obj: &PyAny, _contextkw: Option<&PyDict>) -> PyResult<&'py PyBytes> {
         let s: &str = obj.extract()?;
         let py_str = PyString::new(py, s);
         py_str.call_method1("encode", (self.encoding.as_str(),))?.extract()
@@ -848,110 +1869,10028 @@ impl GreedyString {
     }
 }
 
-#[pymodule]
-fn construct_rs(py: Python, m: &PyModule) -> PyResult<()> {
-    m.add_class::<Construct>()?;
-    m.add_class::<Subconstruct>()?;
-    m.add_class::<Adapter>()?;
-    m.add_class::<StringEncoded>()?;
-    m.add_class::<PaddedString>()?;
-    m.add_class::<PascalString>()?;
-    m.add_class::<CString>()?;
-    m.add_class::<GreedyString>()?;
-    m.add_class::<BitsInteger>()?;
-    m.add_class::<BytesInteger>()?;
-    m.add_class::<FormatField>()?;
+// ========================= Containers ================================
 
-    let bit = Py::new(py, (BitsInteger { length: 1, signed: false, swapped: false }, Construct {}))?;
-    m.add("Bit", bit)?;
-    let nibble = Py::new(py, (BitsInteger { length: 4, signed: false, swapped: false }, Construct {}))?;
-    m.add("Nibble", nibble)?;
-    let octet = Py::new(py, (BitsInteger { length: 8, signed: false, swapped: false }, Construct {}))?;
-    m.add("Octet", octet)?;
+static PRINT_FULL_STRINGS: AtomicBool = AtomicBool::new(false);
+static PRINT_FALSE_FLAGS: AtomicBool = AtomicBool::new(false);
+static PRINT_PRIVATE_ENTRIES: AtomicBool = AtomicBool::new(false);
 
-    m.add("Int8ub", Py::new(py, (FormatField { endian: '>', format: 'B', length: 1 }, Construct {}))?)?;
-    m.add("Int16ub", Py::new(py, (FormatField { endian: '>', format: 'H', length: 2 }, Construct {}))?)?;
-    m.add("Int32ub", Py::new(py, (FormatField { endian: '>', format: 'L', length: 4 }, Construct {}))?)?;
-    m.add("Int64ub", Py::new(py, (FormatField { endian: '>', format: 'Q', length: 8 }, Construct {}))?)?;
-    m.add("Int8sb", Py::new(py, (FormatField { endian: '>', format: 'b', length: 1 }, Construct {}))?)?;
-    m.add("Int16sb", Py::new(py, (FormatField { endian: '>', format: 'h', length: 2 }, Construct {}))?)?;
-    m.add("Int32sb", Py::new(py, (FormatField { endian: '>', format: 'l', length: 4 }, Construct {}))?)?;
-    m.add("Int64sb", Py::new(py, (FormatField { endian: '>', format: 'q', length: 8 }, Construct {}))?)?;
-    m.add("Int8ul", Py::new(py, (FormatField { endian: '<', format: 'B', length: 1 }, Construct {}))?)?;
-    m.add("Int16ul", Py::new(py, (FormatField { endian: '<', format: 'H', length: 2 }, Construct {}))?)?;
-    m.add("Int32ul", Py::new(py, (FormatField { endian: '<', format: 'L', length: 4 }, Construct {}))?)?;
-    m.add("Int64ul", Py::new(py, (FormatField { endian: '<', format: 'Q', length: 8 }, Construct {}))?)?;
-    m.add("Int8sl", Py::new(py, (FormatField { endian: '<', format: 'b', length: 1 }, Construct {}))?)?;
-    m.add("Int16sl", Py::new(py, (FormatField { endian: '<', format: 'h', length: 2 }, Construct {}))?)?;
-    m.add("Int32sl", Py::new(py, (FormatField { endian: '<', format: 'l', length: 4 }, Construct {}))?)?;
-    m.add("Int64sl", Py::new(py, (FormatField { endian: '<', format: 'q', length: 8 }, Construct {}))?)?;
-    m.add("Int8un", Py::new(py, (FormatField { endian: '=', format: 'B', length: 1 }, Construct {}))?)?;
-    m.add("Int16un", Py::new(py, (FormatField { endian: '=', format: 'H', length: 2 }, Construct {}))?)?;
-    m.add("Int32un", Py::new(py, (FormatField { endian: '=', format: 'L', length: 4 }, Construct {}))?)?;
-    m.add("Int64un", Py::new(py, (FormatField { endian: '=', format: 'Q', length: 8 }, Construct {}))?)?;
-    m.add("Int8sn", Py::new(py, (FormatField { endian: '=', format: 'b', length: 1 }, Construct {}))?)?;
-    m.add("Int16sn", Py::new(py, (FormatField { endian: '=', format: 'h', length: 2 }, Construct {}))?)?;
-    m.add("Int32sn", Py::new(py, (FormatField { endian: '=', format: 'l', length: 4 }, Construct {}))?)?;
-    m.add("Int64sn", Py::new(py, (FormatField { endian: '=', format: 'q', length: 8 }, Construct {}))?)?;
+/// When enabled, `Container.__str__` prints bytes and strings in full instead of truncating them.
+#[pyfunction]
+#[pyo3(name = "setGlobalPrintFullStrings", signature = (enabled=false))]
+fn set_global_print_full_strings(enabled: bool) {
+    PRINT_FULL_STRINGS.store(enabled, Ordering::Relaxed);
+}
 
-    m.add("Byte", m.getattr("Int8ub")?)?;
-    m.add("Short", m.getattr("Int16ub")?)?;
-    m.add("Int", m.getattr("Int32ub")?)?;
-    m.add("Long", m.getattr("Int64ub")?)?;
+/// When enabled, `Container.__str__` of a parsed `FlagsEnum` also prints the flags that are not set.
+#[pyfunction]
+#[pyo3(name = "setGlobalPrintFalseFlags", signature = (enabled=false))]
+fn set_global_print_false_flags(enabled: bool) {
+    PRINT_FALSE_FLAGS.store(enabled, Ordering::Relaxed);
+}
 
-    m.add("Float32b", Py::new(py, (FormatField { endian: '>', format: 'f', length: 4 }, Construct {}))?)?;
-    m.add("Float32l", Py::new(py, (FormatField { endian: '<', format: 'f', length: 4 }, Construct {}))?)?;
-    m.add("Float32n", Py::new(py, (FormatField { endian: '=', format: 'f', length: 4 }, Construct {}))?)?;
-    m.add("Float64b", Py::new(py, (FormatField { endian: '>', format: 'd', length: 8 }, Construct {}))?)?;
-    m.add("Float64l", Py::new(py, (FormatField { endian: '<', format: 'd', length: 8 }, Construct {}))?)?;
-    m.add("Float64n", Py::new(py, (FormatField { endian: '=', format: 'd', length: 8 }, Construct {}))?)?;
+/// When enabled, `Container.__str__` shows `_`-prefixed keys. `__repr__` never shows them.
+#[pyfunction]
+#[pyo3(name = "setGlobalPrintPrivateEntries", signature = (enabled=false))]
+fn set_global_print_private_entries(enabled: bool) {
+    PRINT_PRIVATE_ENTRIES.store(enabled, Ordering::Relaxed);
+}
 
-    m.add("Single", m.getattr("Float32b")?)?;
-    m.add("Double", m.getattr("Float64b")?)?;
+/// Returned by `__repr__` and `__str__` when a container (indirectly) contains itself.
+const RECURSION_DETECTED: &str = "<recursion detected>";
 
-    let native_le = cfg!(target_endian = "little");
-    m.add("Int24ub", Py::new(py, (BytesInteger { length: 3, signed: false, swapped: false }, Construct {}))?)?;
-    m.add("Int24ul", Py::new(py, (BytesInteger { length: 3, signed: false, swapped: true }, Construct {}))?)?;
-    m.add("Int24un", Py::new(py, (BytesInteger { length: 3, signed: false, swapped: native_le }, Construct {}))?)?;
-    m.add("Int24sb", Py::new(py, (BytesInteger { length: 3, signed: true, swapped: false }, Construct {}))?)?;
-    m.add("Int24sl", Py::new(py, (BytesInteger { length: 3, signed: true, swapped: true }, Construct {}))?)?;
-    m.add("Int24sn", Py::new(py, (BytesInteger { length: 3, signed: true, swapped: native_le }, Construct {}))?)?;
+/// Held while a container is being printed or searched, so that containers
+/// holding themselves are not visited again.
+struct RecursionLock<'a>(&'a Cell<bool>);
 
-    let poss = build_possiblestringencodings(py);
-    m.add("possiblestringencodings", poss)?;
+impl<'a> RecursionLock<'a> {
+    fn acquire(lock: &'a Cell<bool>) -> Option<Self> {
+        if lock.replace(true) {
+            None
+        } else {
+            Some(RecursionLock(lock))
+        }
+    }
+}
 
-    Ok(())
+impl Drop for RecursionLock<'_> {
+    fn drop(&mut self) {
+        self.0.set(false);
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::Cursor;
-    use pyo3::Python;
-    use pyo3::types::{PyBytes, PyModule};
-    use pyo3::PyAny;
+/// Ensures there is a b- or u- prefix before the repr of a string.
+fn reprstring(value: &PyAny) -> PyResult<String> {
+    let repr = value.repr()?.to_string();
+    Ok(if value.is_instance_of::<PyString>() { format!("u{}", repr) } else { repr })
+}
 
-    #[test]
-    fn test_stream_helpers() {
-        let data = b"abcdef".to_vec();
-        let mut cur = Cursor::new(data.clone());
-        assert_eq!(stream_size(&mut cur).unwrap(), 6);
-        assert!(!stream_iseof(&mut cur).unwrap());
-        assert_eq!(stream_read(&mut cur, 3).unwrap(), b"abc");
-        let mut out = Cursor::new(Vec::new());
-        stream_write(&mut out, b"xyz").unwrap();
-        assert_eq!(out.into_inner(), b"xyz".to_vec());
+/// Whether `key` is a str or bytes key starting with an underscore.
+fn is_private_key(key: &PyAny) -> bool {
+    if let Ok(key) = key.downcast::<PyString>() {
+        return key.to_str().is_ok_and(|key| key.starts_with('_'));
+    }
+    key.downcast::<PyBytes>().is_ok_and(|key| key.as_bytes().starts_with(b"_"))
+}
 
-        let mut cur = Cursor::new(data);
-        let buf = stream_read_entire(&mut cur).unwrap();
-        assert_eq!(buf, b"abcdef");
+/// Equality of two container values, using `numpy.array_equal` for arrays.
+fn values_equal(py: Python, v1: &PyAny, v2: &PyAny) -> PyResult<bool> {
+    if v1.get_type().name()? == "ndarray" || v2.get_type().name()? == "ndarray" {
+        return py.import("numpy")?.call_method1("array_equal", (v1, v2))?.is_truthy();
+    }
+    v1.eq(v2)
+}
+
+/// Indent every line of `str(value)` after the first.
+fn indented_str(value: &PyAny) -> PyResult<String> {
+    Ok(value.str()?.to_str()?.split('\n').collect::<Vec<_>>().join("\n    "))
+}
+
+/// Compile a regex pattern with Python's `re` module.
+fn compile_pattern<'py>(py: Python<'py>, pattern: &PyAny) -> PyResult<&'py PyAny> {
+    py.import("re")?.call_method1("compile", (pattern,))
+}
+
+/// Result of a search: all matches as a list, or the first match or `None`.
+fn search_result(py: Python, items: Vec<PyObject>, search_all: bool) -> PyObject {
+    if search_all {
+        PyList::new(py, items).into()
+    } else {
+        items.into_iter().next().unwrap_or_else(|| py.None())
+    }
+}
+
+static CONTAINER_ATTRIBUTES: pyo3::sync::GILOnceCell<Py<PyFrozenSet>> = pyo3::sync::GILOnceCell::new();
+
+/// Ordered dictionary returned by composite constructs, allowing both key and attribute access.
+///
+/// Equality ignores `_`-prefixed keys, and `__str__` honors the `setGlobalPrint*` settings.
+#[pyclass(extends=PyDict, subclass, module = "construct_rs")]
+#[derive(Default)]
+pub struct Container {
+    locked: Cell<bool>,
+}
+
+impl Container {
+    /// Create an empty container.
+    pub fn empty(py: Python<'_>) -> PyResult<&PyDict> {
+        Ok(Py::new(py, Container::default())?.into_ref(py).downcast::<PyDict>()?)
+    }
+
+    /// Names of the attributes of the class, which take precedence over keys.
+    fn class_attributes(py: Python<'_>) -> PyResult<&PyFrozenSet> {
+        let names = CONTAINER_ATTRIBUTES.get_or_try_init(py, || -> PyResult<Py<PyFrozenSet>> {
+            let names: Vec<PyObject> = py.get_type::<Container>().dir().iter().map(Into::into).collect();
+            Ok(PyFrozenSet::new(py, &names)?.into())
+        })?;
+        Ok(names.as_ref(py))
+    }
+
+    fn search_items(dict: &PyDict, compiled_pattern: &PyAny, search_all: bool) -> Vec<PyObject> {
+        let mut items = Vec::new();
+        for (key, value) in dict.iter() {
+            if value.hasattr("_search").unwrap_or(false) {
+                let Ok(ret) = value.call_method1("_search", (compiled_pattern, search_all)) else { continue };
+                if ret.is_none() {
+                    continue;
+                }
+                if !search_all {
+                    return vec![ret.into()];
+                }
+                if let Ok(found) = ret.extract::<Vec<PyObject>>() {
+                    items.extend(found);
+                }
+            } else if compiled_pattern.call_method1("match", (key,)).is_ok_and(|m| !m.is_none()) {
+                items.push(value.into());
+                if !search_all {
+                    break;
+                }
+            }
+        }
+        items
+    }
+}
+
+#[pymethods]
+impl Container {
+    /// Entries are taken from a dict or a list of pairs, then from the keyword arguments,
+    /// by the inherited `dict.__init__`.
+    #[new]
+    #[pyo3(signature = (*_args, **_entrieskw))]
+    fn new(_args: &PyTuple, _entrieskw: Option<&PyDict>) -> Self {
+        Container::default()
+    }
+
+    fn __reduce__(slf: &PyCell<Self>, py: Python<'_>) -> PyResult<PyObject> {
+        let items = slf.downcast::<PyDict>()?.items();
+        Ok((slf.get_type(), PyTuple::empty(py), py.None(), py.None(), items.as_ref().iter()?).into_py(py))
+    }
+
+    /// Look up keys before the generic lookup, which would raise and catch an
+    /// `AttributeError` for each of them. Attributes of the class still take precedence,
+    /// and those of subclasses, which can define their own, are looked up generically.
+    fn __getattribute__(slf: &PyCell<Self>, name: &PyString) -> PyResult<PyObject> {
+        let py = slf.py();
+        if slf.get_type().is(py.get_type::<Container>())
+            && !Container::class_attributes(py)?.contains(name)?
+            && let Some(value) = slf.downcast::<PyDict>()?.get_item(name)?
+        {
+            return Ok(value.into());
+        }
+        // SAFETY: both objects are alive for the duration of the call.
+        unsafe { PyObject::from_owned_ptr_or_err(py, pyo3::ffi::PyObject_GenericGetAttr(slf.as_ptr(), name.as_ptr())) }
+    }
+
+    fn __getattr__(slf: &PyCell<Self>, name: &str) -> PyResult<PyObject> {
+        match slf.downcast::<PyDict>()?.get_item(name)? {
+            Some(value) => Ok(value.into()),
+            None => Err(PyErr::new::<pyo3::exceptions::PyAttributeError, _>(name.to_string())),
+        }
+    }
+
+    fn __setattr__(slf: &PyCell<Self>, name: &str, value: PyObject) -> PyResult<()> {
+        slf.downcast::<PyDict>()?.set_item(name, value)
+    }
+
+    fn __delattr__(slf: &PyCell<Self>, name: &str) -> PyResult<()> {
+        slf.downcast::<PyDict>()?
+            .del_item(name)
+            .map_err(|_| PyErr::new::<pyo3::exceptions::PyAttributeError, _>(name.to_string()))
+    }
+
+    /// Chain adding new entries to the same container.
+    #[pyo3(signature = (**entrieskw))]
+    fn __call__<'py>(slf: &'py PyCell<Self>, entrieskw: Option<&PyDict>) -> PyResult<&'py PyCell<Self>> {
+        if let Some(entrieskw) = entrieskw {
+            slf.downcast::<PyDict>()?.update(entrieskw.as_mapping())?;
+        }
+        Ok(slf)
+    }
+
+    fn copy<'py>(slf: &'py PyCell<Self>, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let copy = Container::empty(py)?;
+        copy.update(slf.downcast::<PyDict>()?.as_mapping())?;
+        Ok(copy)
+    }
+
+    fn __copy__<'py>(slf: &'py PyCell<Self>, py: Python<'py>) -> PyResult<&'py PyDict> {
+        Self::copy(slf, py)
+    }
+
+    /// For auto completion of attributes based on the keys.
+    fn __dir__(slf: &PyCell<Self>) -> PyResult<Vec<PyObject>> {
+        let mut names: Vec<PyObject> = slf.downcast::<PyDict>()?.keys().iter().map(Into::into).collect();
+        names.extend(slf.get_type().dir().iter().map(Into::into));
+        Ok(names)
+    }
+
+    /// Equality over the keys of this container not starting with an underscore.
+    /// Item order is not checked.
+    fn __eq__(slf: &PyCell<Self>, py: Python<'_>, other: &PyAny) -> PyResult<bool> {
+        if slf.is(other) {
+            return Ok(true);
+        }
+        if !other.is_instance_of::<PyDict>() {
+            return Ok(false);
+        }
+        for (key, value) in slf.downcast::<PyDict>()?.iter() {
+            if is_private_key(key) {
+                continue;
+            }
+            if !other.contains(key)? || !values_equal(py, value, other.get_item(key)?)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn __ne__(slf: &PyCell<Self>, py: Python<'_>, other: &PyAny) -> PyResult<bool> {
+        Ok(!Self::__eq__(slf, py, other)?)
+    }
+
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let this = slf.borrow();
+        let Some(_lock) = RecursionLock::acquire(&this.locked) else {
+            return Ok(RECURSION_DETECTED.to_string());
+        };
+        let mut parts = Vec::new();
+        for (key, value) in slf.downcast::<PyDict>()?.iter() {
+            if key.is_instance_of::<PyString>() && is_private_key(key) {
+                continue;
+            }
+            let text = if value.is_instance_of::<PyString>() || value.is_instance_of::<PyBytes>() {
+                reprstring(value)?
+            } else {
+                value.repr()?.to_string()
+            };
+            parts.push(format!("{}={}", key.str()?, text));
+        }
+        Ok(format!("Container({})", parts.join(", ")))
+    }
+
+    fn __str__(slf: &PyCell<Self>) -> PyResult<String> {
+        let this = slf.borrow();
+        let Some(_lock) = RecursionLock::acquire(&this.locked) else {
+            return Ok(RECURSION_DETECTED.to_string());
+        };
+        let dict = slf.downcast::<PyDict>()?;
+        let isflags = match dict.get_item("_flagsenum")? {
+            Some(flag) => flag.is_truthy()?,
+            None => false,
+        };
+        let mut text = String::from("Container: ");
+        for (key, value) in dict.iter() {
+            if key.is_instance_of::<PyString>() && is_private_key(key) && !PRINT_PRIVATE_ENTRIES.load(Ordering::Relaxed) {
+                continue;
+            }
+            if isflags && !value.is_truthy()? && !PRINT_FALSE_FLAGS.load(Ordering::Relaxed) {
+                continue;
+            }
+            text.push_str(&format!("\n    {} = ", key.str()?));
+            let full = PRINT_FULL_STRINGS.load(Ordering::Relaxed);
+            match &*value.get_type().name()? {
+                "EnumInteger" => text.push_str(&format!("(enum) (unknown) {}", value.str()?)),
+                "EnumIntegerString" => {
+                    text.push_str(&format!("(enum) {} {}", value.str()?, value.getattr("intvalue")?.str()?))
+                }
+                "HexDisplayedBytes" | "HexDumpDisplayedBytes" => text.push_str(&indented_str(value)?),
+                _ if value.is_instance_of::<PyBytes>() || value.is_instance_of::<PyString>() => {
+                    let printingcap = if value.is_instance_of::<PyBytes>() { 16 } else { 32 };
+                    let len = value.len()?;
+                    if len <= printingcap || full {
+                        text.push_str(&format!("{} (total {})", reprstring(value)?, len));
+                    } else {
+                        let head = value.get_item(pyo3::types::PySlice::new(value.py(), 0, printingcap as isize, 1))?;
+                        text.push_str(&format!("{}... (truncated, total {})", reprstring(head)?, len));
+                    }
+                }
+                _ => text.push_str(&indented_str(value)?),
+            }
+        }
+        Ok(text)
+    }
+
+    #[pyo3(name = "_search")]
+    fn search_impl(slf: &PyCell<Self>, py: Python<'_>, compiled_pattern: &PyAny, search_all: bool) -> PyResult<PyObject> {
+        let this = slf.borrow();
+        let Some(_lock) = RecursionLock::acquire(&this.locked) else {
+            return Ok(search_result(py, Vec::new(), search_all));
+        };
+        let items = Container::search_items(slf.downcast::<PyDict>()?, compiled_pattern, search_all);
+        Ok(search_result(py, items, search_all))
+    }
+
+    /// Search the container for the first key matching a regex.
+    fn search(slf: &PyCell<Self>, py: Python<'_>, pattern: &PyAny) -> PyResult<PyObject> {
+        Self::search_impl(slf, py, compile_pattern(py, pattern)?, false)
+    }
+
+    /// Search the container recursively for all keys matching a regex.
+    fn search_all(slf: &PyCell<Self>, py: Python<'_>, pattern: &PyAny) -> PyResult<PyObject> {
+        Self::search_impl(slf, py, compile_pattern(py, pattern)?, true)
+    }
+}
+
+/// Python source of `ListContainer`. It subclasses list, which cannot be extended from
+/// Rust, so that it can be passed wherever lists are expected.
+const LIST_CONTAINER_SOURCE: &str = r#"
+import re
+
+class ListContainer(list):
+    """List returned by repeating and sequence constructs, with pretty-printing and regex searching."""
+    __module__ = "construct_rs"
+
+    def __eq__(self, other):
+        """Equality of the items, using numpy.array_equal for arrays."""
+        try:
+            return list.__eq__(self, other)
+        except ValueError:
+            # Raised by the truth value of comparing numpy arrays, which is ambiguous.
+            pass
+        if len(self) != len(other):
+            return False
+        return all(values_equal(v1, v2) for v1, v2 in zip(self, other))
+
+    def __ne__(self, other):
+        equal = self.__eq__(other)
+        return equal if equal is NotImplemented else not equal
+
+    __hash__ = None
+
+    def copy(self):
+        return ListContainer(self)
+
+    __copy__ = copy
+
+    def __repr__(self):
+        if self.__dict__.get("_locked"):
+            return "<recursion detected>"
+        self._locked = True
+        try:
+            return "ListContainer(%s)" % (list.__repr__(self), )
+        finally:
+            del self._locked
+
+    def __str__(self):
+        if self.__dict__.get("_locked"):
+            return "<recursion detected>"
+        self._locked = True
+        try:
+            return "ListContainer: " + "".join("\n    " + str(item).replace("\n", "\n    ") for item in self)
+        finally:
+            del self._locked
+
+    def _search(self, compiled_pattern, search_all):
+        items = []
+        if self.__dict__.get("_locked"):
+            return items if search_all else None
+        self._locked = True
+        try:
+            for item in self:
+                try:
+                    ret = item._search(compiled_pattern, search_all)
+                except Exception:
+                    continue
+                if ret is not None:
+                    if not search_all:
+                        return ret
+                    items.extend(ret)
+        finally:
+            del self._locked
+        return items if search_all else None
+
+    def search(self, pattern):
+        """Search the items for the first key matching a regex."""
+        return self._search(re.compile(pattern), False)
+
+    def search_all(self, pattern):
+        """Search the items recursively for all keys matching a regex."""
+        return self._search(re.compile(pattern), True)
+"#;
+
+static LIST_CONTAINER: pyo3::sync::GILOnceCell<Py<PyAny>> = pyo3::sync::GILOnceCell::new();
+
+/// Equality of two container values, exposed to `LIST_CONTAINER_SOURCE`.
+#[pyfunction]
+#[pyo3(name = "values_equal")]
+fn py_values_equal(py: Python, v1: &PyAny, v2: &PyAny) -> PyResult<bool> {
+    values_equal(py, v1, v2)
+}
+
+/// Marker for the `ListContainer` class, which is defined in Python on first use.
+pub struct ListContainer;
+
+impl ListContainer {
+    /// The `ListContainer` class.
+    pub fn type_object(py: Python<'_>) -> PyResult<&PyAny> {
+        let class = LIST_CONTAINER.get_or_try_init(py, || -> PyResult<Py<PyAny>> {
+            let module = PyModule::from_code(py, LIST_CONTAINER_SOURCE, "construct_rs/containers.py", "construct_rs.containers")?;
+            module.add_function(wrap_pyfunction!(py_values_equal, module)?)?;
+            Ok(module.getattr("ListContainer")?.into())
+        })?;
+        Ok(class.as_ref(py))
+    }
+
+    /// Create a list container holding `items`.
+    pub fn from_items(py: Python, items: Vec<PyObject>) -> PyResult<PyObject> {
+        Ok(Self::type_object(py)?.call1((PyList::new(py, items),))?.into())
+    }
+}
+
+// ========================= Hex dumps =================================
+
+/// Characters shown for each byte in the text column of a hexdump.
+fn printable(byte: u8) -> char {
+    if (32..128).contains(&byte) { byte as char } else { '.' }
+}
+
+/// Turn bytes into a dump of offsets, hex values and printable characters,
+/// wrapped in a `hexundump("""...""")` call.
+pub fn hexdump(data: &[u8], linesize: usize) -> Result<String, ConstructError> {
+    let digits = if data.len() < 1 << 16 {
+        4
+    } else if (data.len() as u64) < 1 << 32 {
+        8
+    } else {
+        return Err(ConstructError::Other("hexdump cannot process more than 16**8 or 4294967296 bytes".into()));
+    };
+    if linesize == 0 {
+        return Err(ConstructError::Other("hexdump linesize must not be zero".into()));
+    }
+    let width = 3 * linesize - 1;
+    let mut text = String::with_capacity(data.len() * 4 + (data.len() / linesize + 3) * (digits + 8));
+    text.push_str("hexundump(\"\"\"\n");
+    for (i, line) in data.chunks(linesize).enumerate() {
+        let hextext = line.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ");
+        let rawtext: String = line.iter().map(|&b| printable(b)).collect();
+        text.push_str(&format!("{:0digits$X}   {:<width$}   {}\n", i * linesize, hextext, rawtext));
+    }
+    text.push_str("\"\"\")\n");
+    Ok(text)
+}
+
+/// Reverse of [`hexdump`].
+pub fn hexundump(data: &str, linesize: usize) -> Result<Vec<u8>, ConstructError> {
+    let lines: Vec<&str> = data.split('\n').collect();
+    let mut raw = Vec::new();
+    for line in lines.get(1..lines.len().saturating_sub(2)).unwrap_or_default() {
+        let line = match line.find(' ') {
+            Some(pos) => &line[pos..],
+            None => line.char_indices().last().map_or("", |(pos, _)| &line[pos..]),
+        };
+        let line: String = line.trim_start().chars().take(3 * linesize).collect();
+        for value in line.split_whitespace() {
+            let byte = u8::from_str_radix(value, 16)
+                .map_err(|_| ConstructError::Other(format!("invalid hex value in hexdump: {:?}", value)))?;
+            raw.push(byte);
+        }
+    }
+    Ok(raw)
+}
+
+#[pyfunction]
+#[pyo3(name = "hexdump")]
+fn py_hexdump(data: &[u8], linesize: usize) -> PyResult<String> {
+    hexdump(data, linesize).map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))
+}
+
+#[pyfunction]
+#[pyo3(name = "hexundump")]
+fn py_hexundump<'py>(py: Python<'py>, data: &str, linesize: usize) -> PyResult<&'py PyBytes> {
+    let raw = hexundump(data, linesize).map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
+    Ok(PyBytes::new(py, &raw))
+}
+
+/// Python source of the display types returned by `Hex` and `HexDump`. They subclass
+/// int, bytes and dict, which cannot be extended from Rust, and only differ in `__str__`.
+const DISPLAY_TYPES_SOURCE: &str = r#"
+class HexDisplayedInteger(int):
+    """Used internally."""
+    def __str__(self):
+        return "0x" + format(self, self.fmtstr).upper()
+
+    @staticmethod
+    def new(intvalue, fmtstr):
+        obj = HexDisplayedInteger(intvalue)
+        obj.fmtstr = fmtstr
+        return obj
+
+class HexDisplayedBytes(bytes):
+    """Used internally."""
+    def __str__(self):
+        if not hasattr(self, "render"):
+            self.render = "unhexlify('%s')" % (self.hex(), )
+        return self.render
+
+class HexDisplayedDict(dict):
+    """Used internally."""
+    def __str__(self):
+        if not hasattr(self, "render"):
+            self.render = "unhexlify('%s')" % (self["data"].hex(), )
+        return self.render
+
+class HexDumpDisplayedBytes(bytes):
+    """Used internally."""
+    def __str__(self):
+        if not hasattr(self, "render"):
+            self.render = hexdump(self, 16)
+        return self.render
+
+class HexDumpDisplayedDict(dict):
+    """Used internally."""
+    def __str__(self):
+        if not hasattr(self, "render"):
+            self.render = hexdump(self["data"], 16)
+        return self.render
+"#;
+
+static DISPLAY_TYPES: pyo3::sync::GILOnceCell<Py<PyModule>> = pyo3::sync::GILOnceCell::new();
+
+/// Display type of the given name, defined on first use.
+fn display_type<'py>(py: Python<'py>, name: &str) -> PyResult<&'py PyAny> {
+    let module = DISPLAY_TYPES.get_or_try_init(py, || -> PyResult<Py<PyModule>> {
+        let module = PyModule::from_code(py, DISPLAY_TYPES_SOURCE, "construct_rs/hex.py", "construct_rs.hex")?;
+        module.add_function(wrap_pyfunction!(py_hexdump, module)?)?;
+        Ok(module.into())
+    })?;
+    module.as_ref(py).getattr(name)
+}
+
+/// Adapter displaying integers, bytes and RawCopy dictionaries in hexadecimal when printed.
+/// Building and sizeof defer to the subcon.
+#[pyclass(extends=Adapter, module = "construct_rs")]
+pub struct Hex {}
+
+#[pymethods]
+impl Hex {
+    #[new]
+    fn new(subcon: Py<PyAny>) -> PyClassInitializer<Self> {
+        PyClassInitializer::from(Construct {})
+            .add_subclass(Subconstruct { subcon })
+            .add_subclass(Adapter {})
+            .add_subclass(Hex {})
+    }
+
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        let base: PyRef<Subconstruct> = slf.into_super().into_super();
+        let (obj, consumed) = parse_prefix_in(py, base.subcon.as_ref(py), &data, 0, context_from_kwargs(py, contextkw)?)?;
+        Ok((hex_decode(py, base.subcon.as_ref(py), obj.as_ref(py))?, consumed))
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(Self::parse_prefix(slf, py, data, contextkw)?.0)
+    }
+
+    /// Build using the subcon, as the value is displayed differently but not changed.
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(slf: PyRef<'py, Self>, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let base: PyRef<Subconstruct> = slf.into_super().into_super();
+        Ok(PyBytes::new(py, &build_in(base.subcon.as_ref(py), obj, context_from_kwargs(py, contextkw)?)?))
+    }
+
+    #[pyo3(name = "_buildvalue", signature = (obj, /, **contextkw))]
+    fn build_value<'py>(slf: PyRef<'py, Self>, py: Python<'py>, obj: &'py PyAny, contextkw: Option<&'py PyDict>) -> PyResult<(&'py PyBytes, &'py PyAny)> {
+        let base: PyRef<Subconstruct> = slf.into_super().into_super();
+        let (built, value) = build_value_in(base.subcon.clone_ref(py).into_ref(py), obj, context_from_kwargs(py, contextkw)?)?;
+        Ok((PyBytes::new(py, &built), value))
+    }
+
+    #[pyo3(name = "_decode")]
+    fn decode(slf: PyRef<'_, Self>, py: Python<'_>, obj: &PyAny) -> PyResult<PyObject> {
+        let base: PyRef<Subconstruct> = slf.into_super().into_super();
+        hex_decode(py, base.subcon.as_ref(py), obj)
+    }
+
+    #[pyo3(name = "_encode")]
+    fn encode(&self, obj: PyObject) -> PyObject {
+        obj
+    }
+
+    fn sizeof(slf: PyRef<'_, Self>, py: Python<'_>) -> PyResult<usize> {
+        let base: PyRef<Subconstruct> = slf.into_super().into_super();
+        base.subcon.as_ref(py).call_method0("sizeof")?.extract()
+    }
+}
+
+fn hex_decode(py: Python, subcon: &PyAny, obj: &PyAny) -> PyResult<PyObject> {
+    if obj.is_instance_of::<pyo3::types::PyLong>() {
+        let size: usize = subcon.call_method0("sizeof")?.extract()?;
+        let fmtstr = format!("0{}X", 2 * size);
+        return Ok(display_type(py, "HexDisplayedInteger")?.call_method1("new", (obj, fmtstr))?.into());
+    }
+    if obj.is_instance_of::<PyBytes>() {
+        return Ok(display_type(py, "HexDisplayedBytes")?.call1((obj,))?.into());
+    }
+    if obj.is_instance_of::<PyDict>() {
+        return Ok(display_type(py, "HexDisplayedDict")?.call1((obj,))?.into());
+    }
+    Ok(obj.into())
+}
+
+/// Adapter displaying bytes and RawCopy dictionaries as a hexdump when printed.
+/// Building and sizeof defer to the subcon.
+#[pyclass(extends=Adapter, module = "construct_rs")]
+pub struct HexDump {}
+
+#[pymethods]
+impl HexDump {
+    #[new]
+    fn new(subcon: Py<PyAny>) -> PyClassInitializer<Self> {
+        PyClassInitializer::from(Construct {})
+            .add_subclass(Subconstruct { subcon })
+            .add_subclass(Adapter {})
+            .add_subclass(HexDump {})
+    }
+
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        let base: PyRef<Subconstruct> = slf.into_super().into_super();
+        let (obj, consumed) = parse_prefix_in(py, base.subcon.as_ref(py), &data, 0, context_from_kwargs(py, contextkw)?)?;
+        Ok((hexdump_decode(py, obj.as_ref(py))?, consumed))
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(Self::parse_prefix(slf, py, data, contextkw)?.0)
+    }
+
+    /// Build using the subcon, as the value is displayed differently but not changed.
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(slf: PyRef<'py, Self>, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let base: PyRef<Subconstruct> = slf.into_super().into_super();
+        Ok(PyBytes::new(py, &build_in(base.subcon.as_ref(py), obj, context_from_kwargs(py, contextkw)?)?))
+    }
+
+    #[pyo3(name = "_buildvalue", signature = (obj, /, **contextkw))]
+    fn build_value<'py>(slf: PyRef<'py, Self>, py: Python<'py>, obj: &'py PyAny, contextkw: Option<&'py PyDict>) -> PyResult<(&'py PyBytes, &'py PyAny)> {
+        let base: PyRef<Subconstruct> = slf.into_super().into_super();
+        let (built, value) = build_value_in(base.subcon.clone_ref(py).into_ref(py), obj, context_from_kwargs(py, contextkw)?)?;
+        Ok((PyBytes::new(py, &built), value))
+    }
+
+    #[pyo3(name = "_decode")]
+    fn decode(&self, py: Python<'_>, obj: &PyAny) -> PyResult<PyObject> {
+        hexdump_decode(py, obj)
+    }
+
+    #[pyo3(name = "_encode")]
+    fn encode(&self, obj: PyObject) -> PyObject {
+        obj
+    }
+
+    fn sizeof(slf: PyRef<'_, Self>, py: Python<'_>) -> PyResult<usize> {
+        let base: PyRef<Subconstruct> = slf.into_super().into_super();
+        base.subcon.as_ref(py).call_method0("sizeof")?.extract()
+    }
+}
+
+fn hexdump_decode(py: Python, obj: &PyAny) -> PyResult<PyObject> {
+    if obj.is_instance_of::<PyBytes>() {
+        return Ok(display_type(py, "HexDumpDisplayedBytes")?.call1((obj,))?.into());
+    }
+    if obj.is_instance_of::<PyDict>() {
+        return Ok(display_type(py, "HexDumpDisplayedDict")?.call1((obj,))?.into());
+    }
+    Ok(obj.into())
+}
+
+// ========================= Debugging =================================
+
+const PROBE_RULE: &str = "--------------------------------------------------";
+
+/// Print `text` using Python's `print`, so that redirected output is respected.
+fn py_print(py: Python, text: &str) -> PyResult<()> {
+    py.import("builtins")?.call_method1("print", (text,))?;
+    Ok(())
+}
+
+/// Dumps the context, and a hexdump of the data that follows, to aid debugging.
+/// Can be limited to a single context entry using `into`.
+#[pyclass(extends=Construct, module = "construct_rs")]
+pub struct Probe {
+    #[pyo3(get)]
+    into: Option<Py<PyAny>>,
+    #[pyo3(get)]
+    lookahead: Option<usize>,
+}
+
+impl Probe {
+    fn printout(&self, py: Python, data: Option<&[u8]>, context: Option<&PyDict>, path: &str) -> PyResult<()> {
+        let into = match &self.into {
+            Some(into) => into.as_ref(py).repr()?.to_string(),
+            None => "None".to_string(),
+        };
+        let mut lines = vec![PROBE_RULE.to_string(), format!("Probe, path is {}, into is {}", path, into)];
+        if let (Some(lookahead), Some(data)) = (self.lookahead.filter(|&n| n > 0), data) {
+            let datafollows = &data[..lookahead.min(data.len())];
+            if datafollows.is_empty() {
+                lines.push("Stream peek: EOF reached".to_string());
+            } else {
+                lines.push(format!("Stream peek:\n{}", hexdump(datafollows, 16)?.trim_end()));
+            }
+        }
+        if let Some(context) = context {
+            match &self.into {
+                Some(into) => match into.as_ref(py).call1((context,)) {
+                    Ok(subcontext) => lines.push(subcontext.str()?.to_string()),
+                    Err(_) => lines.push(format!("Failed to compute {} on the context {}", into, context.repr()?)),
+                },
+                None => lines.push(context.str()?.to_string()),
+            }
+        }
+        lines.push(PROBE_RULE.to_string());
+        py_print(py, &lines.join("\n"))
+    }
+}
+
+#[pymethods]
+impl Probe {
+    #[new]
+    #[pyo3(signature = (into=None, lookahead=None))]
+    fn new(into: Option<Py<PyAny>>, lookahead: Option<usize>) -> (Self, Construct) {
+        (Probe { into, lookahead }, Construct {})
+    }
+
+    fn __getnewargs_ex__(&self, py: Python<'_>) -> NewArgs {
+        newargs(py, (self.into.as_ref().map(|into| into.clone_ref(py)), self.lookahead))
+    }
+
+    /// Print the context and the data that follows, consuming nothing.
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(&self, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        let context = context_from_kwargs(py, contextkw)?;
+        self.printout(py, Some(data.as_bytes()), Some(context), &context_path("(parsing)", context)?)?;
+        Ok((py.None(), 0))
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(&self, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(self.parse_prefix(py, data, contextkw)?.0)
+    }
+
+    #[pyo3(signature = (obj=None, **contextkw))]
+    fn build<'py>(&self, py: Python<'py>, obj: Option<&PyAny>, contextkw: Option<&PyDict>) -> PyResult<&'py PyBytes> {
+        let _ = obj;
+        let context = context_from_kwargs(py, contextkw)?;
+        self.printout(py, None, Some(context), &context_path("(building)", context)?)?;
+        Ok(PyBytes::new(py, b""))
+    }
+
+    fn sizeof(&self, py: Python<'_>) -> PyResult<usize> {
+        self.printout(py, None, None, "(sizeof)")?;
+        Ok(0)
+    }
+}
+
+/// Hands failures of the subcon to a Python callback, or to `pdb` when there is none.
+///
+/// The callback receives a Container with the `exception`, its `traceback`, the `path`,
+/// the `offset` within `data` at which the failing member began, the `context` and the
+/// `subcon`.
+/// When parsing, a value returned by the callback (or assigned to `retval` from within
+/// `pdb`) is returned instead of raising.
+#[pyclass(extends=Subconstruct, module = "construct_rs")]
+pub struct Debugger {
+    handler: Option<Py<PyAny>>,
+    #[pyo3(get, set)]
+    retval: PyObject,
+}
+
+impl Debugger {
+    fn handle_exc(
+        slf: &PyCell<Self>,
+        py: Python,
+        err: PyErr,
+        path: &str,
+        data: Option<&[u8]>,
+        context: &PyDict,
+    ) -> PyResult<()> {
+        let subcon = slf.borrow().as_ref().subcon.clone_ref(py);
+        let exception = err.value(py);
+        let traceback = err.traceback(py);
+        let handler = slf.borrow().handler.as_ref().map(|handler| handler.clone_ref(py));
+        match handler {
+            Some(handler) => {
+                let info = Container::empty(py)?;
+                info.set_item("exception", exception)?;
+                info.set_item("traceback", traceback)?;
+                info.set_item("path", path)?;
+                info.set_item("offset", failure_offset(py, &err))?;
+                info.set_item("data", data.map(|data| PyBytes::new(py, data)))?;
+                info.set_item("context", context)?;
+                info.set_item("subcon", &subcon)?;
+                let retval = handler.call1(py, (info,))?;
+                if !retval.is_none(py) {
+                    slf.borrow_mut().retval = retval;
+                }
+            }
+            None => {
+                let formatted: Vec<String> =
+                    py.import("traceback")?.call_method1("format_exception", (exception,))?.extract()?;
+                let mut lines = vec![
+                    PROBE_RULE.to_string(),
+                    format!("Debugging exception of {}", subcon.as_ref(py).repr()?),
+                    format!("path is {}", path),
+                    formatted.get(1..).unwrap_or_default().concat(),
+                ];
+                if path.starts_with("(parsing)") {
+                    lines.push("(you can set self.retval, which will be returned from method)".to_string());
+                }
+                py_print(py, &lines.join("\n"))?;
+                py.import("pdb")?.call_method1("post_mortem", (traceback,))?;
+                py_print(py, PROBE_RULE)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl Debugger {
+    #[new]
+    #[pyo3(signature = (subcon, handler=None))]
+    fn new(py: Python<'_>, subcon: Py<PyAny>, handler: Option<Py<PyAny>>) -> PyClassInitializer<Self> {
+        PyClassInitializer::from(Construct {})
+            .add_subclass(Subconstruct { subcon })
+            .add_subclass(Debugger { handler, retval: py.NotImplemented() })
+    }
+
+    fn __getnewargs_ex__(slf: PyRef<'_, Self>, py: Python<'_>) -> NewArgs {
+        newargs(py, (slf.as_ref().subcon.clone_ref(py), slf.handler.as_ref().map(|handler| handler.clone_ref(py))))
+    }
+
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(slf: &PyCell<Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        let subcon = slf.borrow().as_ref().subcon.clone_ref(py);
+        let context = context_from_kwargs(py, contextkw)?;
+        match parse_prefix_in(py, subcon.as_ref(py), &data, 0, context) {
+            Ok(parsed) => Ok(parsed),
+            Err(err) => {
+                slf.borrow_mut().retval = py.NotImplemented();
+                let path = context_path("(parsing)", context)?;
+                Debugger::handle_exc(slf, py, err.clone_ref(py), &path, Some(data.as_bytes()), context)?;
+                let retval = slf.borrow().retval.clone_ref(py);
+                if retval.is(&py.NotImplemented()) {
+                    return Err(err);
+                }
+                Ok((retval, 0))
+            }
+        }
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(slf: &PyCell<Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(Self::parse_prefix(slf, py, data, contextkw)?.0)
+    }
+
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(slf: &PyCell<Self>, py: Python<'py>, obj: &PyAny, contextkw: Option<&PyDict>) -> PyResult<&'py PyBytes> {
+        let subcon = slf.borrow().as_ref().subcon.clone_ref(py);
+        let context = context_from_kwargs(py, contextkw)?;
+        match build_in(subcon.as_ref(py), obj, context) {
+            Ok(built) => Ok(PyBytes::new(py, &built)),
+            Err(err) => {
+                let path = context_path("(building)", context)?;
+                Debugger::handle_exc(slf, py, err.clone_ref(py), &path, None, context)?;
+                Err(err)
+            }
+        }
+    }
+
+    fn sizeof(slf: &PyCell<Self>, py: Python<'_>) -> PyResult<usize> {
+        let subcon = slf.borrow().as_ref().subcon.clone_ref(py);
+        match subcon.call_method0(py, "sizeof").and_then(|size| size.extract(py)) {
+            Ok(size) => Ok(size),
+            Err(err) => {
+                Debugger::handle_exc(slf, py, err.clone_ref(py), "(sizeof)", None, Container::empty(py)?)?;
+                Err(err)
+            }
+        }
+    }
+}
+
+// ========================= Context helpers ===========================
+
+/// Evaluate a constant or a context lambda, mirroring `construct.core.evaluate`.
+fn evaluate<'py>(param: &'py PyAny, context: &'py PyDict) -> PyResult<&'py PyAny> {
+    if param.is_callable() {
+        param.call1((context,))
+    } else {
+        Ok(param)
+    }
+}
+
+/// Keyword argument under which a construct passes its context on to construct-rs
+/// subcons, so that they share it rather than each receiving a copy of its keys.
+const CONTEXT_KWARG: &str = "__context__";
+
+/// Keyword arguments passing `context` on to a construct-rs subcon.
+fn context_kwargs<'py>(py: Python<'py>, context: &'py PyDict) -> PyResult<&'py PyDict> {
+    let kwargs = PyDict::new(py);
+    kwargs.set_item(CONTEXT_KWARG, context)?;
+    Ok(kwargs)
+}
+
+/// Context dictionary built from the `**contextkw` of a parse or build call. Unless it
+/// is passed on by a composite, it is also its own `_params`, for nested contexts to refer
+/// to the parameters of the call by. A context passed on by a composite is used as is.
+fn context_from_kwargs<'py>(py: Python<'py>, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyDict> {
+    if let Some(contextkw) = contextkw
+        && contextkw.len() == 1
+        && let Some(context) = contextkw.get_item(CONTEXT_KWARG)?
+    {
+        return Ok(context.downcast()?);
+    }
+    let context = Container::empty(py)?;
+    if let Some(contextkw) = contextkw {
+        context.update(contextkw.as_mapping())?;
+    }
+    if !context.contains("_params")? {
+        context.set_item("_params", context)?;
+    }
+    Ok(context)
+}
+
+/// Size of a subcon, or `None` when it cannot be computed (dynamic size).
+fn subcon_sizeof(subcon: &PyAny) -> Option<usize> {
+    subcon.call_method0("sizeof").and_then(|size| size.extract()).ok()
+}
+
+/// Context dictionary seen by the members of a composite, nested under `outer`.
+fn nested_context<'py>(py: Python<'py>, outer: &'py PyDict) -> PyResult<&'py PyDict> {
+    let context = Container::empty(py)?;
+    context.set_item("_", outer)?;
+    match outer.get_item("_root")? {
+        Some(root) => context.set_item("_root", root)?,
+        None => context.set_item("_root", context)?,
+    }
+    if let Some(params) = outer.get_item("_params")? {
+        context.set_item("_params", params)?;
+    }
+    Ok(context)
+}
+
+/// Context key holding the name of the member a composite is processing, from which
+/// [`context_path`] tells where a `Probe` or `Debugger` is.
+const MEMBER_KEY: &str = "_member";
+
+/// Path such as `(parsing) -> header -> size`, from the member names recorded in each
+/// level of the nested context.
+fn context_path(action: &str, context: &PyDict) -> PyResult<String> {
+    let mut names = Vec::new();
+    let mut level = Some(context);
+    while let Some(context) = level {
+        if let Some(name) = context.get_item(MEMBER_KEY)?.filter(|name| !name.is_none()) {
+            names.push(name.str()?.to_string());
+        }
+        level = context.get_item("_")?.and_then(|outer| outer.downcast::<PyDict>().ok());
+    }
+    Ok(names.iter().rev().fold(action.to_string(), |path, name| format!("{} -> {}", path, name)))
+}
+
+/// Record on a failure that the member raising it began `offset` bytes into the data
+/// of its composite. Composites add up these offsets as the failure propagates, so
+/// that a `Debugger` can tell where in its data the failure happened.
+fn failed_at(py: Python, err: PyErr, offset: usize) -> PyErr {
+    let value = err.value(py);
+    let _ = value.setattr("_offset", failure_offset(py, &err) + offset);
+    err
+}
+
+/// Offset recorded by [`failed_at`], relative to the data of the outermost composite
+/// the failure has propagated through.
+fn failure_offset(py: Python, err: &PyErr) -> usize {
+    err.value(py).getattr("_offset").and_then(|offset| offset.extract()).unwrap_or(0)
+}
+
+/// A member of a composite construct, optionally named.
+struct Member {
+    name: Option<String>,
+    subcon: Py<PyAny>,
+    build: BuildMethod,
+}
+
+impl Member {
+    fn new(name: Option<String>, subcon: &PyAny) -> Self {
+        Member { name, subcon: subcon.into(), build: BuildMethod::of(subcon) }
+    }
+}
+
+/// Collect members from positional subcons and `name=subcon` keywords.
+///
+/// Positional subcons are named by their `name` attribute when they have one.
+fn collect_members(subcons: &PyTuple, subconskw: Option<&PyDict>) -> PyResult<Vec<Member>> {
+    let mut members = Vec::new();
+    for subcon in subcons.iter() {
+        let name = subcon.getattr("name").ok().and_then(|n| n.extract::<String>().ok());
+        members.push(Member::new(name, subcon));
+    }
+    if let Some(kw) = subconskw {
+        for (name, subcon) in kw.iter() {
+            members.push(Member::new(Some(name.extract()?), subcon));
+        }
+    }
+    Ok(members)
+}
+
+/// Members of `subcon` when it is a `Sequence`, otherwise `subcon` itself, as `>>` joins them.
+fn sequence_members(subcon: &PyAny) -> PyResult<Vec<Member>> {
+    let py = subcon.py();
+    match subcon.downcast::<PyCell<Sequence>>() {
+        Ok(sequence) => Ok(members_from_state(py, members_state(py, &sequence.borrow().subcons))),
+        Err(_) => collect_members(PyTuple::new(py, [subcon]), None),
+    }
+}
+
+/// Look up a named member, for exposing subcons as attributes.
+fn member_getattr(py: Python, members: &[Member], name: &str) -> PyResult<PyObject> {
+    members
+        .iter()
+        .find(|m| m.name.as_deref() == Some(name))
+        .map(|m| m.subcon.clone_ref(py))
+        .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyAttributeError, _>(name.to_string()))
+}
+
+/// Sum of the member sizes, provided every member has a fixed size.
+fn members_sizeof(py: Python, members: &[Member]) -> PyResult<usize> {
+    members.iter().try_fold(0, |size, m| {
+        subcon_sizeof(m.subcon.as_ref(py))
+            .map(|subsize| size + subsize)
+            .ok_or_else(|| ConstructError::SizeofError.into())
+    })
+}
+
+/// Members as `(name, subcon)` pairs, the pickled state of composite constructs.
+fn members_state(py: Python, members: &[Member]) -> Vec<(Option<String>, PyObject)> {
+    members.iter().map(|m| (m.name.clone(), m.subcon.clone_ref(py))).collect()
+}
+
+fn members_from_state(py: Python, state: Vec<(Option<String>, PyObject)>) -> Vec<Member> {
+    state.into_iter().map(|(name, subcon)| Member::new(name, subcon.as_ref(py))).collect()
+}
+
+/// Parse the bytes of `input` from `offset` on, returning the value and the number of
+/// bytes consumed.
+///
+/// Composites report their consumption through `_parseprefix`, and are given a view of
+/// the input rather than a copy. Other constructs consume their fixed size, or all of
+/// the data when their size is dynamic.
+fn parse_prefix(py: Python, subcon: &PyAny, input: &Input, offset: usize) -> PyResult<(PyObject, usize)> {
+    if subcon.hasattr("_parseprefix")? {
+        return subcon.call_method1("_parseprefix", (input.tail(py, offset)?,))?.extract();
+    }
+    let data = &input.as_bytes()[offset..];
+    let size = region_size(subcon, data.len())?;
+    let obj = subcon.call_method1("parse", (PyBytes::new(py, &data[..size]),))?;
+    Ok((obj.into(), size))
+}
+
+/// Number of bytes the subcon takes out of `available`: its fixed size, or all of them
+/// when its size is dynamic.
+fn region_size(subcon: &PyAny, available: usize) -> PyResult<usize> {
+    match subcon_sizeof(subcon) {
+        Some(size) if size > available => Err(ConstructError::StreamError.into()),
+        Some(size) => Ok(size),
+        None => Ok(available),
+    }
+}
+
+/// Whether the subcon's `method` is implemented by construct-rs rather than overridden in
+/// Python, and so accepts the context as keyword arguments.
+fn takes_context(subcon: &PyAny, method: &str) -> bool {
+    subcon
+        .get_type()
+        .getattr(method)
+        .is_ok_and(|method| method.get_type().name().is_ok_and(|name| name == "method_descriptor"))
+}
+
+/// Like [`parse_prefix`], passing `context` on to construct-rs subcons.
+fn parse_prefix_in(py: Python, subcon: &PyAny, input: &Input, offset: usize, context: &PyDict) -> PyResult<(PyObject, usize)> {
+    if takes_context(subcon, "_parseprefix") {
+        return subcon.call_method("_parseprefix", (input.tail(py, offset)?,), Some(context_kwargs(py, context)?))?.extract();
+    }
+    if !subcon.hasattr("_parseprefix")? && takes_context(subcon, "parse") {
+        let data = &input.as_bytes()[offset..];
+        let size = region_size(subcon, data.len())?;
+        let obj = subcon.call_method("parse", (PyBytes::new(py, &data[..size]),), Some(context_kwargs(py, context)?))?;
+        return Ok((obj.into(), size));
+    }
+    parse_prefix(py, subcon, input, offset)
+}
+
+/// Build `obj` using the subcon, passing `context` on to construct-rs subcons.
+fn build_in(subcon: &PyAny, obj: &PyAny, context: &PyDict) -> PyResult<Vec<u8>> {
+    BuildMethod::of(subcon).build(subcon, obj, context_kwargs(subcon.py(), context)?)
+}
+
+/// Like [`build_in`], also returning the value built, for composites to record in the
+/// context. It is `obj` itself, unless the subcon computes its own as `Computed` does.
+fn build_value_in<'py>(subcon: &'py PyAny, obj: &'py PyAny, context: &'py PyDict) -> PyResult<(Vec<u8>, &'py PyAny)> {
+    BuildMethod::of(subcon).build_value(subcon, obj, context_kwargs(subcon.py(), context)?)
+}
+
+/// Which build methods of a subcon construct-rs implements, and so take the context.
+/// Composites find out once, rather than looking the methods up on every call.
+#[derive(Clone, Copy)]
+struct BuildMethod {
+    build: bool,
+    buildvalue: bool,
+}
+
+impl BuildMethod {
+    fn of(subcon: &PyAny) -> Self {
+        BuildMethod { build: takes_context(subcon, "build"), buildvalue: takes_context(subcon, "_buildvalue") }
+    }
+
+    /// Like [`build_in`], given the keyword arguments [`context_kwargs`] makes, which a
+    /// composite makes once for all of its members.
+    fn build(self, subcon: &PyAny, obj: &PyAny, contextkw: &PyDict) -> PyResult<Vec<u8>> {
+        if self.build {
+            let built: &PyBytes = subcon.call_method("build", (obj,), Some(contextkw))?.extract()?;
+            return Ok(built.as_bytes().to_vec());
+        }
+        build_bytes(subcon, obj)
+    }
+
+    /// Like [`build_value_in`], given the keyword arguments [`context_kwargs`] makes.
+    fn build_value<'py>(self, subcon: &'py PyAny, obj: &'py PyAny, contextkw: &'py PyDict) -> PyResult<(Vec<u8>, &'py PyAny)> {
+        if self.buildvalue {
+            let (built, value): (&PyBytes, &PyAny) = subcon.call_method("_buildvalue", (obj,), Some(contextkw))?.extract()?;
+            return Ok((built.as_bytes().to_vec(), value));
+        }
+        Ok((self.build(subcon, obj, contextkw)?, obj))
+    }
+}
+
+// ========================= Expressions ===============================
+
+#[derive(Clone, Debug, PartialEq)]
+enum ExprToken {
+    Int(i128),
+    Float(f64),
+    Str(String),
+    Ident(String),
+    Op(&'static str),
+}
+
+const EXPR_OPERATORS: &[&str] = &[
+    "::", "<<", ">>", "<=", ">=", "==", "!=", "(", ")", "[", "]", ".", ",", "?", ":", "+", "-", "*", "/", "%", "&", "|",
+    "^", "~", "<", ">",
+];
+
+fn expr_tokens(source: &str) -> Result<Vec<ExprToken>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let is_float = i + 1 < chars.len() && chars[i] == '.' && chars[i + 1].is_ascii_digit();
+            if is_float {
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_digit() || "eE".contains(chars[i])) {
+                    i += 1;
+                }
+            }
+            let text: String = chars[start..i].iter().filter(|&&c| c != '_').collect();
+            let token = if is_float {
+                text.parse().map(ExprToken::Float).ok()
+            } else {
+                let (digits, radix) = match text.get(..2) {
+                    Some("0x" | "0X") => (&text[2..], 16),
+                    Some("0b" | "0B") => (&text[2..], 2),
+                    Some("0o" | "0O") => (&text[2..], 8),
+                    _ => (text.as_str(), 10),
+                };
+                i128::from_str_radix(digits, radix).map(ExprToken::Int).ok()
+            };
+            tokens.push(token.ok_or_else(|| format!("invalid number {:?}", text))?);
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(ExprToken::Ident(chars[start..i].iter().collect()));
+        } else if c == '\'' || c == '"' {
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err("unterminated string".to_string()),
+                    Some(&q) if q == c => break,
+                    Some('\\') if c == '"' => {
+                        i += 1;
+                        text.push(match chars.get(i) {
+                            Some('n') => '\n',
+                            Some('t') => '\t',
+                            Some('r') => '\r',
+                            Some('0') => '\0',
+                            Some(&other) => other,
+                            None => return Err("unterminated string".to_string()),
+                        });
+                    }
+                    Some(&other) => text.push(other),
+                }
+                i += 1;
+            }
+            i += 1;
+            tokens.push(ExprToken::Str(text));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let op = EXPR_OPERATORS
+                .iter()
+                .find(|op| rest.starts_with(**op))
+                .ok_or_else(|| format!("unexpected character {:?}", c))?;
+            tokens.push(ExprToken::Op(op));
+            i += op.len();
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parsed Kaitai-style expression.
+#[derive(Clone, Debug, PartialEq)]
+enum ExprNode {
+    Int(i128),
+    Float(f64),
+    Str(String),
+    Bool(bool),
+    List(Vec<ExprNode>),
+    Name(String),
+    EnumRef(String),
+    Attr(Box<ExprNode>, String),
+    Index(Box<ExprNode>, Box<ExprNode>),
+    Unary(&'static str, Box<ExprNode>),
+    Binary(&'static str, Box<ExprNode>, Box<ExprNode>),
+    Ternary(Box<ExprNode>, Box<ExprNode>, Box<ExprNode>),
+}
+
+/// Binary operators from the loosest to the tightest binding level.
+const EXPR_LEVELS: &[&[&str]] = &[
+    &["or"],
+    &["and"],
+    &["not"],
+    &["<", "<=", ">", ">=", "==", "!="],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+struct ExprParser {
+    tokens: Vec<ExprToken>,
+    pos: usize,
+}
+
+impl ExprParser {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(ExprToken::Op(op)) => Some(*op),
+            Some(ExprToken::Ident(word)) if ["and", "or", "not"].contains(&word.as_str()) => {
+                ["and", "or", "not"].into_iter().find(|kw| kw == word)
+            }
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        if self.peek_op() == Some(op) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("expected {:?}", op))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, String> {
+        match self.tokens.get(self.pos) {
+            Some(ExprToken::Ident(name)) => {
+                self.pos += 1;
+                Ok(name.clone())
+            }
+            _ => Err("expected a name".to_string()),
+        }
+    }
+
+    fn ternary(&mut self) -> Result<ExprNode, String> {
+        let cond = self.binary(0)?;
+        if self.peek_op() != Some("?") {
+            return Ok(cond);
+        }
+        self.pos += 1;
+        let then = self.ternary()?;
+        self.expect(":")?;
+        let otherwise = self.ternary()?;
+        Ok(ExprNode::Ternary(Box::new(cond), Box::new(then), Box::new(otherwise)))
+    }
+
+    fn binary(&mut self, level: usize) -> Result<ExprNode, String> {
+        let Some(ops) = EXPR_LEVELS.get(level) else { return self.unary() };
+        if ops[0] == "not" {
+            if self.peek_op() == Some("not") {
+                self.pos += 1;
+                return Ok(ExprNode::Unary("not", Box::new(self.binary(level)?)));
+            }
+            return self.binary(level + 1);
+        }
+        let mut lhs = self.binary(level + 1)?;
+        while let Some(op) = self.peek_op().filter(|op| ops.contains(op)) {
+            self.pos += 1;
+            let rhs = self.binary(level + 1)?;
+            lhs = ExprNode::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<ExprNode, String> {
+        match self.peek_op() {
+            Some(op @ ("-" | "~")) => {
+                self.pos += 1;
+                Ok(ExprNode::Unary(op, Box::new(self.unary()?)))
+            }
+            _ => self.postfix(),
+        }
+    }
+
+    fn postfix(&mut self) -> Result<ExprNode, String> {
+        let mut node = self.primary()?;
+        loop {
+            match self.peek_op() {
+                Some(".") => {
+                    self.pos += 1;
+                    let name = self.ident()?;
+                    if name == "as" || self.peek_op() == Some("(") {
+                        return Err(format!("method call {:?} is not supported", name));
+                    }
+                    node = ExprNode::Attr(Box::new(node), name);
+                }
+                Some("[") => {
+                    self.pos += 1;
+                    let index = self.ternary()?;
+                    self.expect("]")?;
+                    node = ExprNode::Index(Box::new(node), Box::new(index));
+                }
+                _ => return Ok(node),
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<ExprNode, String> {
+        let token = self.tokens.get(self.pos).cloned().ok_or("unexpected end of expression")?;
+        self.pos += 1;
+        match token {
+            ExprToken::Int(i) => Ok(ExprNode::Int(i)),
+            ExprToken::Float(f) => Ok(ExprNode::Float(f)),
+            ExprToken::Str(s) => Ok(ExprNode::Str(s)),
+            ExprToken::Op("(") => {
+                let node = self.ternary()?;
+                self.expect(")")?;
+                Ok(node)
+            }
+            ExprToken::Op("[") => {
+                let mut items = Vec::new();
+                while self.peek_op() != Some("]") {
+                    items.push(self.ternary()?);
+                    if self.peek_op() != Some(",") {
+                        break;
+                    }
+                    self.pos += 1;
+                }
+                self.expect("]")?;
+                Ok(ExprNode::List(items))
+            }
+            ExprToken::Ident(name) => match name.as_str() {
+                "true" => Ok(ExprNode::Bool(true)),
+                "false" => Ok(ExprNode::Bool(false)),
+                "_io" | "sizeof" | "bitsizeof" => Err(format!("{:?} is not supported", name)),
+                _ if self.peek_op() == Some("::") => {
+                    let mut last = name;
+                    while self.peek_op() == Some("::") {
+                        self.pos += 1;
+                        last = self.ident()?;
+                    }
+                    Ok(ExprNode::EnumRef(last))
+                }
+                _ => Ok(ExprNode::Name(name)),
+            },
+            ExprToken::Op(op) => Err(format!("unexpected {:?}", op)),
+        }
+    }
+}
+
+impl ExprNode {
+    fn parse(source: &str) -> Result<ExprNode, String> {
+        let mut parser = ExprParser { tokens: expr_tokens(source)?, pos: 0 };
+        let node = parser.ternary()?;
+        if parser.pos != parser.tokens.len() {
+            return Err("unexpected trailing input".to_string());
+        }
+        Ok(node)
+    }
+
+    fn eval<'py>(&self, py: Python<'py>, context: &'py PyAny, item: Option<&'py PyAny>) -> PyResult<&'py PyAny> {
+        let operator = py.import("operator")?;
+        Ok(match self {
+            ExprNode::Int(i) => i.into_py(py).into_ref(py),
+            ExprNode::Float(f) => f.into_py(py).into_ref(py),
+            ExprNode::Str(s) | ExprNode::EnumRef(s) => PyString::new(py, s),
+            ExprNode::Bool(b) => b.into_py(py).into_ref(py),
+            ExprNode::List(items) => {
+                PyList::new(py, items.iter().map(|node| node.eval(py, context, item)).collect::<PyResult<Vec<_>>>()?)
+            }
+            ExprNode::Name(name) if name == "_" => item.ok_or_else(|| ConstructError::Other("'_' is only defined in repeat-until".into()))?,
+            ExprNode::Name(name) => expr_member(context, name)?,
+            ExprNode::Attr(obj, name) => {
+                let obj = obj.eval(py, context, item)?;
+                if obj.downcast::<PyDict>().is_ok() {
+                    expr_member(obj, name)?
+                } else {
+                    expr_method(py, obj, name)?
+                }
+            }
+            ExprNode::Index(obj, index) => obj.eval(py, context, item)?.get_item(index.eval(py, context, item)?)?,
+            ExprNode::Unary("not", operand) => (!operand.eval(py, context, item)?.is_truthy()?).into_py(py).into_ref(py),
+            ExprNode::Unary(op, operand) => {
+                let func = if *op == "-" { "neg" } else { "invert" };
+                operator.call_method1(func, (operand.eval(py, context, item)?,))?
+            }
+            ExprNode::Binary("and", lhs, rhs) => {
+                let value = lhs.eval(py, context, item)?.is_truthy()? && rhs.eval(py, context, item)?.is_truthy()?;
+                value.into_py(py).into_ref(py)
+            }
+            ExprNode::Binary("or", lhs, rhs) => {
+                let value = lhs.eval(py, context, item)?.is_truthy()? || rhs.eval(py, context, item)?.is_truthy()?;
+                value.into_py(py).into_ref(py)
+            }
+            ExprNode::Binary(op, lhs, rhs) => {
+                let lhs = lhs.eval(py, context, item)?;
+                let rhs = rhs.eval(py, context, item)?;
+                let ints = lhs.is_instance_of::<pyo3::types::PyLong>() && rhs.is_instance_of::<pyo3::types::PyLong>();
+                let func = match *op {
+                    "+" => "add",
+                    "-" => "sub",
+                    "*" => "mul",
+                    "/" if ints => "floordiv",
+                    "/" => "truediv",
+                    "%" => "mod",
+                    "<<" => "lshift",
+                    ">>" => "rshift",
+                    "&" => "and_",
+                    "|" => "or_",
+                    "^" => "xor",
+                    "<" => "lt",
+                    "<=" => "le",
+                    ">" => "gt",
+                    ">=" => "ge",
+                    "==" => "eq",
+                    _ => "ne",
+                };
+                operator.call_method1(func, (lhs, rhs))?
+            }
+            ExprNode::Ternary(cond, then, otherwise) => {
+                if cond.eval(py, context, item)?.is_truthy()? {
+                    then.eval(py, context, item)?
+                } else {
+                    otherwise.eval(py, context, item)?
+                }
+            }
+        })
+    }
+}
+
+/// Member of a context or parsed container, with `_parent` and `_root` referring to the
+/// enclosing and outermost contexts.
+fn expr_member<'py>(obj: &'py PyAny, name: &str) -> PyResult<&'py PyAny> {
+    let key = match name {
+        "_parent" => "_",
+        other => other,
+    };
+    obj.get_item(key).map_err(|_| ConstructError::Other(format!("name {:?} is not defined", name)).into())
+}
+
+/// Kaitai built-in methods on values that are not containers.
+fn expr_method<'py>(py: Python<'py>, obj: &'py PyAny, name: &str) -> PyResult<&'py PyAny> {
+    let builtins = py.import("builtins")?;
+    Ok(match name {
+        "length" | "size" => obj.len()?.into_py(py).into_ref(py),
+        "to_i" => builtins.getattr("int")?.call1((obj,))?,
+        "to_s" => obj.str()?,
+        "first" => obj.get_item(0)?,
+        "last" => obj.get_item(-1)?,
+        "min" | "max" => builtins.getattr(name)?.call1((obj,))?,
+        "reverse" => obj.get_item(pyo3::types::PySlice::new(py, isize::MAX, isize::MIN, -1))?,
+        _ => obj.getattr(name)?,
+    })
+}
+
+/// Context expression written in the Kaitai Struct expression language, such as
+/// `count * 2` or `_parent.kind == animal::cat ? 1 : 2`, usable wherever a context
+/// lambda is accepted.
+///
+/// Names refer to members of the context, `_parent` and `_root` to the enclosing
+/// and outermost ones. Enum references evaluate to the name of the member.
+#[pyclass(module = "construct_rs")]
+pub struct Expr {
+    source: String,
+    node: ExprNode,
+}
+
+impl Expr {
+    /// Compile an expression, returning a description of the syntax error on failure.
+    pub fn compile(source: &str) -> Result<Expr, String> {
+        let node = ExprNode::parse(source)?;
+        Ok(Expr { source: source.to_string(), node })
+    }
+}
+
+#[pymethods]
+impl Expr {
+    #[new]
+    fn new(source: &str) -> PyResult<Self> {
+        Expr::compile(source).map_err(|err| ConstructError::Other(format!("{} in expression {:?}", err, source)).into())
+    }
+
+    fn __reduce__(slf: &PyCell<Self>, py: Python<'_>) -> PyObject {
+        (slf.get_type(), (slf.borrow().source.clone(),)).into_py(py)
+    }
+
+    /// Evaluate against a context. Called as `(obj, list, context)` by `RepeatUntil`,
+    /// in which case `_` refers to the last parsed element.
+    #[pyo3(signature = (*args))]
+    fn __call__<'py>(&self, py: Python<'py>, args: &'py PyTuple) -> PyResult<&'py PyAny> {
+        let context = args.get_item(args.len().saturating_sub(1))?;
+        let item = if args.len() == 3 { Some(args.get_item(0)?) } else { None };
+        self.node.eval(py, context, item).map_err(|err| {
+            ConstructError::Other(format!("{} in expression {:?}", err.value(py), self.source)).into()
+        })
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Expr({:?})", self.source)
+    }
+}
+
+// ========================= Processing ================================
+
+/// Normalize a `ProcessXor` pad into its bytes. Integer pads outside 0..=255 raise
+/// `ValueError`, as xoring with them does in `construct.core`.
+fn xor_pad(pad: &PyAny) -> PyResult<Vec<u8>> {
+    if let Ok(data) = pad.downcast::<PyBytes>() {
+        return Ok(data.as_bytes().to_vec());
+    }
+    if !pad.is_instance_of::<pyo3::types::PyLong>() {
+        return Err(ConstructError::StringError.into());
+    }
+    match pad.extract::<u8>() {
+        Ok(value) => Ok(vec![value]),
+        Err(_) => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("bytes must be in range(0, 256)")),
+    }
+}
+
+/// Xors the data with a single or multi byte pad before handing it to the subcon.
+#[pyclass(extends=Subconstruct, module = "construct_rs")]
+pub struct ProcessXor {
+    padfunc: Py<PyAny>,
+}
+
+#[pymethods]
+impl ProcessXor {
+    #[new]
+    fn new(padfunc: Py<PyAny>, subcon: Py<PyAny>) -> PyClassInitializer<Self> {
+        PyClassInitializer::from(Construct {})
+            .add_subclass(Subconstruct { subcon })
+            .add_subclass(ProcessXor { padfunc })
+    }
+
+    fn __getnewargs_ex__(slf: PyRef<'_, Self>, py: Python<'_>) -> NewArgs {
+        newargs(py, (slf.padfunc.clone_ref(py), slf.as_ref().subcon.clone_ref(py)))
+    }
+
+    /// Xor the subcon's region of the input, all of it when its size is dynamic, with
+    /// the pad and parse it using the subcon. Unlike `construct.core.ProcessXor`, which
+    /// processes the rest of the stream, only the fixed-size region of a subcon is
+    /// processed, and the data following it is left for the next members.
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        let context = context_from_kwargs(py, contextkw)?;
+        let pad = xor_pad(evaluate(slf.padfunc.as_ref(py), context)?)?;
+        let subcon = slf.as_ref().subcon.as_ref(py);
+        let size = region_size(subcon, data.len())?;
+        let mut buf = data.as_bytes()[..size].to_vec();
+        xor_bytes(&mut buf, &pad);
+        let (obj, _) = parse_prefix_in(py, subcon, &Input::Bytes(PyBytes::new(py, &buf)), 0, context)?;
+        Ok((obj, size))
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(Self::parse_prefix(slf, py, data, contextkw)?.0)
+    }
+
+    /// Build using the subcon and xor the result with the pad.
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(slf: PyRef<'py, Self>, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let context = context_from_kwargs(py, contextkw)?;
+        let pad = xor_pad(evaluate(slf.padfunc.as_ref(py), context)?)?;
+        let mut buf = build_in(slf.as_ref().subcon.as_ref(py), obj, context)?;
+        xor_bytes(&mut buf, &pad);
+        Ok(PyBytes::new(py, &buf))
+    }
+
+    fn sizeof(slf: PyRef<'_, Self>, py: Python<'_>) -> PyResult<usize> {
+        slf.as_ref().subcon.as_ref(py).call_method0("sizeof")?.extract()
+    }
+}
+
+/// Rotates the data left by a number of bits within fixed-size groups.
+#[pyclass(extends=Subconstruct, module = "construct_rs")]
+pub struct ProcessRotateLeft {
+    amount: Py<PyAny>,
+    group: Py<PyAny>,
+}
+
+impl ProcessRotateLeft {
+    fn params(&self, py: Python, context: &PyDict) -> PyResult<(i64, i64)> {
+        let amount = evaluate(self.amount.as_ref(py), context)?.extract()?;
+        let group = evaluate(self.group.as_ref(py), context)?.extract()?;
+        Ok((amount, group))
+    }
+}
+
+#[pymethods]
+impl ProcessRotateLeft {
+    #[new]
+    fn new(amount: Py<PyAny>, group: Py<PyAny>, subcon: Py<PyAny>) -> PyClassInitializer<Self> {
+        PyClassInitializer::from(Construct {})
+            .add_subclass(Subconstruct { subcon })
+            .add_subclass(ProcessRotateLeft { amount, group })
+    }
+
+    fn __getnewargs_ex__(slf: PyRef<'_, Self>, py: Python<'_>) -> NewArgs {
+        newargs(py, (slf.amount.clone_ref(py), slf.group.clone_ref(py), slf.as_ref().subcon.clone_ref(py)))
+    }
+
+    /// Rotate the subcon's region of the input, all of it when its size is dynamic, left
+    /// and parse it using the subcon. As with `ProcessXor`, only the fixed-size region of
+    /// a subcon is processed, and the data following it is left for the next members.
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        let context = context_from_kwargs(py, contextkw)?;
+        let (amount, group) = slf.params(py, context)?;
+        let subcon = slf.as_ref().subcon.as_ref(py);
+        let size = region_size(subcon, data.len())?;
+        let buf = rotate_left_bytes(&data.as_bytes()[..size], amount, group)?;
+        let (obj, _) = parse_prefix_in(py, subcon, &Input::Bytes(PyBytes::new(py, &buf)), 0, context)?;
+        Ok((obj, size))
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(Self::parse_prefix(slf, py, data, contextkw)?.0)
+    }
+
+    /// Build using the subcon and rotate the result right.
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(slf: PyRef<'py, Self>, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let context = context_from_kwargs(py, contextkw)?;
+        let (amount, group) = slf.params(py, context)?;
+        let built = build_in(slf.as_ref().subcon.as_ref(py), obj, context)?;
+        let amount = amount.checked_neg().ok_or(ConstructError::RotationError)?;
+        let buf = rotate_left_bytes(&built, amount, group)?;
+        Ok(PyBytes::new(py, &buf))
+    }
+
+    fn sizeof(slf: PyRef<'_, Self>, py: Python<'_>) -> PyResult<usize> {
+        slf.as_ref().subcon.as_ref(py).call_method0("sizeof")?.extract()
+    }
+}
+
+// ========================= Swapping ==================================
+
+/// Apply `swap` to `data`, which must be exactly the subcon's size when it is fixed.
+fn swap_region(subcon: &PyAny, data: &[u8], swap: fn(&[u8]) -> Vec<u8>) -> PyResult<Vec<u8>> {
+    match subcon_sizeof(subcon) {
+        Some(size) if data.len() != size => Err(ConstructError::StreamError.into()),
+        _ => Ok(swap(data)),
+    }
+}
+
+/// Swaps the byte order within the boundaries of the subcon.
+#[pyclass(extends=Subconstruct, module = "construct_rs")]
+pub struct ByteSwapped {}
+
+#[pymethods]
+impl ByteSwapped {
+    #[new]
+    fn new(subcon: Py<PyAny>) -> PyClassInitializer<Self> {
+        PyClassInitializer::from(Construct {})
+            .add_subclass(Subconstruct { subcon })
+            .add_subclass(ByteSwapped {})
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        let subcon = slf.as_ref().subcon.as_ref(py);
+        let buf = swap_region(subcon, data.as_bytes(), |d| swapbytes(d.to_vec()))?;
+        let swapped = Input::Bytes(PyBytes::new(py, &buf));
+        Ok(parse_prefix_in(py, subcon, &swapped, 0, context_from_kwargs(py, contextkw)?)?.0)
+    }
+
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(slf: PyRef<'py, Self>, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let subcon = slf.as_ref().subcon.as_ref(py);
+        let built = build_in(subcon, obj, context_from_kwargs(py, contextkw)?)?;
+        let buf = swap_region(subcon, &built, |d| swapbytes(d.to_vec()))?;
+        Ok(PyBytes::new(py, &buf))
+    }
+
+    fn sizeof(slf: PyRef<'_, Self>, py: Python<'_>) -> PyResult<usize> {
+        slf.as_ref().subcon.as_ref(py).call_method0("sizeof")?.extract()
+    }
+}
+
+/// Swaps the bit order within each byte within the boundaries of the subcon.
+#[pyclass(extends=Subconstruct, module = "construct_rs")]
+pub struct BitsSwapped {}
+
+#[pymethods]
+impl BitsSwapped {
+    #[new]
+    fn new(subcon: Py<PyAny>) -> PyClassInitializer<Self> {
+        PyClassInitializer::from(Construct {})
+            .add_subclass(Subconstruct { subcon })
+            .add_subclass(BitsSwapped {})
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        let subcon = slf.as_ref().subcon.as_ref(py);
+        let buf = swap_region(subcon, data.as_bytes(), swapbitsinbytes)?;
+        let swapped = Input::Bytes(PyBytes::new(py, &buf));
+        Ok(parse_prefix_in(py, subcon, &swapped, 0, context_from_kwargs(py, contextkw)?)?.0)
+    }
+
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(slf: PyRef<'py, Self>, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let subcon = slf.as_ref().subcon.as_ref(py);
+        let built = build_in(subcon, obj, context_from_kwargs(py, contextkw)?)?;
+        let buf = swap_region(subcon, &built, swapbitsinbytes)?;
+        Ok(PyBytes::new(py, &buf))
+    }
+
+    fn sizeof(slf: PyRef<'_, Self>, py: Python<'_>) -> PyResult<usize> {
+        slf.as_ref().subcon.as_ref(py).call_method0("sizeof")?.extract()
+    }
+}
+
+// ========================= Union =====================================
+
+/// Treats the same data as multiple constructs, similar to a C union.
+#[pyclass(extends=Construct, module = "construct_rs")]
+pub struct Union {
+    parsefrom: Py<PyAny>,
+    subcons: Vec<Member>,
+}
+
+#[pymethods]
+impl Union {
+    #[new]
+    #[pyo3(signature = (parsefrom, *subcons, **subconskw))]
+    fn new(parsefrom: &PyAny, subcons: &PyTuple, subconskw: Option<&PyDict>) -> PyResult<(Self, Construct)> {
+        if parsefrom.hasattr("parse")? {
+            return Err(ConstructError::UnionError.into());
+        }
+        let subcons = collect_members(subcons, subconskw)?;
+        Ok((Union { parsefrom: parsefrom.into(), subcons }, Construct {}))
+    }
+
+    fn __getnewargs_ex__(&self, py: Python<'_>) -> NewArgs {
+        newargs(py, (self.parsefrom.clone_ref(py),))
+    }
+
+    fn __getstate__(&self, py: Python<'_>) -> Vec<(Option<String>, PyObject)> {
+        members_state(py, &self.subcons)
+    }
+
+    fn __setstate__(&mut self, py: Python<'_>, state: Vec<(Option<String>, PyObject)>) {
+        self.subcons = members_from_state(py, state);
+    }
+
+    /// Parse every member from the same offset, returning the values and the
+    /// number of bytes selected by `parsefrom`.
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(&self, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        let context = nested_context(py, context_from_kwargs(py, contextkw)?)?;
+        let obj = Container::empty(py)?;
+        let mut forwards = Vec::with_capacity(self.subcons.len());
+        for member in &self.subcons {
+            context.set_item(MEMBER_KEY, &member.name)?;
+            let (subobj, consumed) = parse_prefix_in(py, member.subcon.as_ref(py), &data, 0, context)?;
+            if let Some(name) = &member.name {
+                obj.set_item(name, &subobj)?;
+                context.set_item(name, &subobj)?;
+            }
+            forwards.push(consumed);
+        }
+        let parsefrom = evaluate(self.parsefrom.as_ref(py), context)?;
+        let consumed = if parsefrom.is_none() {
+            0
+        } else if let Ok(index) = parsefrom.extract::<usize>() {
+            *forwards.get(index).ok_or(ConstructError::UnionError)?
+        } else {
+            let name: &str = parsefrom.extract()?;
+            let index = self.subcons.iter().position(|m| m.name.as_deref() == Some(name));
+            forwards[index.ok_or(ConstructError::UnionError)?]
+        };
+        Ok((obj.into(), consumed))
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(&self, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(self.parse_prefix(py, data, contextkw)?.0)
+    }
+
+    /// Build from the first named member present in the given dictionary, which the
+    /// context is updated with.
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(&self, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let context = nested_context(py, context_from_kwargs(py, contextkw)?)?;
+        if let Ok(dict) = obj.downcast::<PyDict>() {
+            context.update(dict.as_mapping())?;
+        }
+        for member in &self.subcons {
+            let Some(name) = &member.name else { continue };
+            if obj.contains(name)? {
+                let subobj = obj.get_item(name)?;
+                context.set_item(MEMBER_KEY, name)?;
+                let built = member.build.build(member.subcon.as_ref(py), subobj, context_kwargs(py, context)?)?;
+                return Ok(PyBytes::new(py, &built));
+            }
+        }
+        Err(ConstructError::UnionError.into())
+    }
+
+    /// Size of the largest member, provided every member has a fixed size.
+    fn sizeof(&self, py: Python<'_>) -> PyResult<usize> {
+        let mut size = 0;
+        for member in &self.subcons {
+            let subsize = subcon_sizeof(member.subcon.as_ref(py)).ok_or(ConstructError::SizeofError)?;
+            size = size.max(subsize);
+        }
+        Ok(size)
+    }
+
+    fn __getattr__(&self, py: Python<'_>, name: &str) -> PyResult<PyObject> {
+        member_getattr(py, &self.subcons, name)
+    }
+}
+
+// ========================= Renamed ===================================
+
+/// Gives the subcon a name, for composites like `Struct` to name their member by, and
+/// optionally docs and a `parsed(obj, context)` hook called with every parsed value.
+/// Parsing, building and sizes are deferred to the subcon.
+///
+/// Made by the `"name" / subcon`, `subcon * "docs"` and `subcon * hook` operators.
+#[pyclass(extends=Subconstruct, module = "construct_rs")]
+pub struct Renamed {
+    #[pyo3(get)]
+    name: Option<String>,
+    #[pyo3(get)]
+    docs: String,
+    #[pyo3(get)]
+    parsed: Option<Py<PyAny>>,
+}
+
+impl Renamed {
+    /// `subcon` documented or hooked by the right operand of `*`.
+    fn annotated(py: Python, subcon: &PyAny, other: &PyAny) -> PyResult<Py<Renamed>> {
+        let renamed = match other.extract::<String>() {
+            Ok(docs) => Renamed::new(subcon, None, Some(docs), None)?,
+            Err(_) if other.is_callable() => Renamed::new(subcon, None, None, Some(other.into()))?,
+            Err(_) => return Err(ConstructError::Other("operator * can only be used with string or lambda".into()).into()),
+        };
+        Py::new(py, renamed)
+    }
+}
+
+#[pymethods]
+impl Renamed {
+    /// Whatever is not given is kept from the subcon, when it is itself renamed.
+    #[new]
+    #[pyo3(signature = (subcon, newname=None, newdocs=None, newparsed=None))]
+    fn new(subcon: &PyAny, newname: Option<String>, newdocs: Option<String>, newparsed: Option<Py<PyAny>>) -> PyResult<PyClassInitializer<Self>> {
+        let inherited = |attr: &str| subcon.getattr(attr).ok().filter(|value| !value.is_none());
+        let name = match newname {
+            Some(name) => Some(name),
+            None => inherited("name").map(|name| name.extract()).transpose()?,
+        };
+        let docs = match newdocs.filter(|docs| !docs.is_empty()) {
+            Some(docs) => docs,
+            None => inherited("docs").map(|docs| docs.extract()).transpose()?.unwrap_or_default(),
+        };
+        let parsed = newparsed.or_else(|| inherited("parsed").map(Into::into));
+        Ok(PyClassInitializer::from(Construct {})
+            .add_subclass(Subconstruct { subcon: subcon.into() })
+            .add_subclass(Renamed { name, docs, parsed }))
+    }
+
+    fn __getnewargs_ex__(slf: PyRef<'_, Self>, py: Python<'_>) -> NewArgs {
+        let parsed = slf.parsed.as_ref().map(|parsed| parsed.clone_ref(py));
+        newargs(py, (slf.as_ref().subcon.clone_ref(py), slf.name.clone(), slf.docs.clone(), parsed))
+    }
+
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        let context = context_from_kwargs(py, contextkw)?;
+        let (obj, consumed) = parse_prefix_in(py, slf.as_ref().subcon.as_ref(py), &data, 0, context)?;
+        if let Some(parsed) = &slf.parsed {
+            parsed.call1(py, (&obj, context))?;
+        }
+        Ok((obj, consumed))
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(Self::parse_prefix(slf, py, data, contextkw)?.0)
+    }
+
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(slf: PyRef<'py, Self>, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let context = context_from_kwargs(py, contextkw)?;
+        Ok(PyBytes::new(py, &build_in(slf.as_ref().subcon.as_ref(py), obj, context)?))
+    }
+
+    #[pyo3(name = "_buildvalue", signature = (obj, /, **contextkw))]
+    fn build_value<'py>(slf: PyRef<'py, Self>, py: Python<'py>, obj: &'py PyAny, contextkw: Option<&'py PyDict>) -> PyResult<(&'py PyBytes, &'py PyAny)> {
+        let (built, value) = build_value_in(slf.as_ref().subcon.clone_ref(py).into_ref(py), obj, context_from_kwargs(py, contextkw)?)?;
+        Ok((PyBytes::new(py, &built), value))
+    }
+
+    fn sizeof(slf: PyRef<'_, Self>, py: Python<'_>) -> PyResult<usize> {
+        slf.as_ref().subcon.as_ref(py).call_method0("sizeof")?.extract()
+    }
+
+    /// Attributes of the subcon, like the members of a renamed `Struct`.
+    fn __getattr__(slf: PyRef<'_, Self>, py: Python<'_>, name: &str) -> PyResult<PyObject> {
+        Ok(slf.as_ref().subcon.as_ref(py).getattr(name)?.into())
+    }
+}
+
+// ========================= Sequences =================================
+
+/// A sequence of unnamed or named fields, parsed into a list.
+#[pyclass(extends=Construct, module = "construct_rs")]
+pub struct Sequence {
+    subcons: Vec<Member>,
+    schema: SchemaCache,
+}
+
+#[pymethods]
+impl Sequence {
+    #[new]
+    #[pyo3(signature = (*subcons, **subconskw))]
+    fn new(subcons: &PyTuple, subconskw: Option<&PyDict>) -> PyResult<(Self, Construct)> {
+        Ok((Sequence { subcons: collect_members(subcons, subconskw)?, schema: SchemaCache::default() }, Construct {}))
+    }
+
+    fn __getstate__(&self, py: Python<'_>) -> Vec<(Option<String>, PyObject)> {
+        members_state(py, &self.subcons)
+    }
+
+    fn __setstate__(&mut self, py: Python<'_>, state: Vec<(Option<String>, PyObject)>) {
+        self.subcons = members_from_state(py, state);
+        self.schema = SchemaCache::default();
+    }
+
+    /// Parse the members one after another, stopping early on `StopFieldError`.
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(slf: &PyCell<Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        if let Some(parsed) = parse_released(py, slf, &data, contextkw)? {
+            return Ok(parsed);
+        }
+        let this = slf.borrow();
+        let context = nested_context(py, context_from_kwargs(py, contextkw)?)?;
+        let mut obj = Vec::with_capacity(this.subcons.len());
+        let mut offset = 0;
+        for member in &this.subcons {
+            context.set_item(MEMBER_KEY, &member.name)?;
+            let (subobj, consumed) = match parse_prefix_in(py, member.subcon.as_ref(py), &data, offset, context) {
+                Ok(parsed) => parsed,
+                Err(err) if err.is_instance_of::<exceptions::StopFieldError>(py) => break,
+                Err(err) => return Err(failed_at(py, err, offset)),
+            };
+            if let Some(name) = &member.name {
+                context.set_item(name, &subobj)?;
+            }
+            obj.push(subobj);
+            offset += consumed;
+        }
+        Ok((ListContainer::from_items(py, obj)?.into_py(py), offset))
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(slf: &PyCell<Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(Self::parse_prefix(slf, py, data, contextkw)?.0)
+    }
+
+    /// Build each member from the matching list item, or from `None` when no list is given.
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(&self, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let context = nested_context(py, context_from_kwargs(py, contextkw)?)?;
+        let contextkw = context_kwargs(py, context)?;
+        let mut out = Vec::new();
+        for (i, member) in self.subcons.iter().enumerate() {
+            let subobj = if obj.is_none() { py.None().into_ref(py) } else { obj.get_item(i)? };
+            if let Some(name) = &member.name {
+                context.set_item(name, subobj)?;
+            }
+            context.set_item(MEMBER_KEY, &member.name)?;
+            let built = match member.build.build(member.subcon.as_ref(py), subobj, contextkw) {
+                Ok(built) => built,
+                Err(err) if err.is_instance_of::<exceptions::StopFieldError>(py) => break,
+                Err(err) => return Err(failed_at(py, err, out.len())),
+            };
+            out.extend_from_slice(&built);
+        }
+        Ok(PyBytes::new(py, &out))
+    }
+
+    fn sizeof(&self, py: Python<'_>) -> PyResult<usize> {
+        members_sizeof(py, &self.subcons)
+    }
+
+    fn __getattr__(&self, py: Python<'_>, name: &str) -> PyResult<PyObject> {
+        member_getattr(py, &self.subcons, name)
+    }
+}
+
+/// A sequence of fields where only the selected one is returned and built from.
+#[pyclass(extends=Construct, module = "construct_rs")]
+pub struct FocusedSeq {
+    parsebuildfrom: Py<PyAny>,
+    subcons: Vec<Member>,
+}
+
+impl FocusedSeq {
+    fn focus(&self, py: Python, context: &PyDict) -> PyResult<String> {
+        evaluate(self.parsebuildfrom.as_ref(py), context)?.extract()
+    }
+}
+
+#[pymethods]
+impl FocusedSeq {
+    #[new]
+    #[pyo3(signature = (parsebuildfrom, *subcons, **subconskw))]
+    fn new(parsebuildfrom: Py<PyAny>, subcons: &PyTuple, subconskw: Option<&PyDict>) -> PyResult<(Self, Construct)> {
+        Ok((FocusedSeq { parsebuildfrom, subcons: collect_members(subcons, subconskw)? }, Construct {}))
+    }
+
+    fn __getnewargs_ex__(&self, py: Python<'_>) -> NewArgs {
+        newargs(py, (self.parsebuildfrom.clone_ref(py),))
+    }
+
+    fn __getstate__(&self, py: Python<'_>) -> Vec<(Option<String>, PyObject)> {
+        members_state(py, &self.subcons)
+    }
+
+    fn __setstate__(&mut self, py: Python<'_>, state: Vec<(Option<String>, PyObject)>) {
+        self.subcons = members_from_state(py, state);
+    }
+
+    /// Parse all members and return the value of the focused one.
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(&self, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        let context = nested_context(py, context_from_kwargs(py, contextkw)?)?;
+        let mut offset = 0;
+        for member in &self.subcons {
+            context.set_item(MEMBER_KEY, &member.name)?;
+            let (subobj, consumed) =
+                parse_prefix_in(py, member.subcon.as_ref(py), &data, offset, context).map_err(|err| failed_at(py, err, offset))?;
+            if let Some(name) = &member.name {
+                context.set_item(name, subobj)?;
+            }
+            offset += consumed;
+        }
+        let focus = self.focus(py, context)?;
+        let result = context.get_item(&focus)?.ok_or_else(|| PyErr::new::<pyo3::exceptions::PyKeyError, _>(focus))?;
+        Ok((result.into(), offset))
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(&self, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(self.parse_prefix(py, data, contextkw)?.0)
+    }
+
+    /// Build the focused member from `obj` and every other member from `None`.
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(&self, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let context = nested_context(py, context_from_kwargs(py, contextkw)?)?;
+        let focus = self.focus(py, context)?;
+        context.set_item(&focus, obj)?;
+        let contextkw = context_kwargs(py, context)?;
+        let mut out = Vec::new();
+        for member in &self.subcons {
+            let subobj = match &member.name {
+                Some(name) => context.get_item(name)?.unwrap_or_else(|| py.None().into_ref(py)),
+                None => py.None().into_ref(py),
+            };
+            context.set_item(MEMBER_KEY, &member.name)?;
+            let built = member.build.build(member.subcon.as_ref(py), subobj, contextkw).map_err(|err| failed_at(py, err, out.len()))?;
+            out.extend_from_slice(&built);
+        }
+        Ok(PyBytes::new(py, &out))
+    }
+
+    fn sizeof(&self, py: Python<'_>) -> PyResult<usize> {
+        members_sizeof(py, &self.subcons)
+    }
+
+    fn __getattr__(&self, py: Python<'_>, name: &str) -> PyResult<PyObject> {
+        member_getattr(py, &self.subcons, name)
+    }
+}
+
+// ========================= Structs and arrays ========================
+
+/// Sequence of fields parsed into a `Container`. Values of the fields parsed or built
+/// so far are visible to the context lambdas and expressions of the following ones.
+#[pyclass(extends=Construct, module = "construct_rs")]
+pub struct Struct {
+    subcons: Vec<Member>,
+    schema: SchemaCache,
+}
+
+/// Members of a `Struct` parsed so far, from which parsing carries on, as a [`Feeder`]
+/// does once more data has been fed.
+struct StructProgress {
+    context: Py<PyDict>,
+    obj: Py<PyDict>,
+    /// Index of the next member to parse, and where it starts.
+    member: usize,
+    offset: usize,
+}
+
+impl Struct {
+    fn start_parse(py: Python, contextkw: Option<&PyDict>) -> PyResult<StructProgress> {
+        let context = nested_context(py, context_from_kwargs(py, contextkw)?)?;
+        Ok(StructProgress { context: context.into(), obj: Container::empty(py)?.into(), member: 0, offset: 0 })
+    }
+
+    /// Parse the members one after another from where `progress` stands, stopping early on
+    /// `StopFieldError`. `data` holds the bytes from `start` on. On failure, `progress`
+    /// is left at the member that failed.
+    fn parse_members(&self, py: Python, data: &Input, start: usize, progress: &mut StructProgress) -> PyResult<()> {
+        let context = progress.context.as_ref(py);
+        let obj = progress.obj.as_ref(py);
+        while let Some(member) = self.subcons.get(progress.member) {
+            context.set_item(MEMBER_KEY, &member.name)?;
+            let (subobj, consumed) = match parse_prefix_in(py, member.subcon.as_ref(py), data, progress.offset - start, context) {
+                Ok(parsed) => parsed,
+                Err(err) if err.is_instance_of::<exceptions::StopFieldError>(py) => break,
+                Err(err) => return Err(failed_at(py, err, progress.offset)),
+            };
+            if let Some(name) = &member.name {
+                obj.set_item(name, &subobj)?;
+                context.set_item(name, &subobj)?;
+            }
+            progress.offset += consumed;
+            progress.member += 1;
+        }
+        progress.member = self.subcons.len();
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl Struct {
+    #[new]
+    #[pyo3(signature = (*subcons, **subconskw))]
+    fn new(subcons: &PyTuple, subconskw: Option<&PyDict>) -> PyResult<(Self, Construct)> {
+        Ok((Struct { subcons: collect_members(subcons, subconskw)?, schema: SchemaCache::default() }, Construct {}))
+    }
+
+    fn __getstate__(&self, py: Python<'_>) -> Vec<(Option<String>, PyObject)> {
+        members_state(py, &self.subcons)
+    }
+
+    fn __setstate__(&mut self, py: Python<'_>, state: Vec<(Option<String>, PyObject)>) {
+        self.subcons = members_from_state(py, state);
+        self.schema = SchemaCache::default();
+    }
+
+    /// Parse the members one after another, stopping early on `StopFieldError`.
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(slf: &PyCell<Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        if let Some(parsed) = parse_released(py, slf, &data, contextkw)? {
+            return Ok(parsed);
+        }
+        let mut progress = Struct::start_parse(py, contextkw)?;
+        slf.borrow().parse_members(py, &data, 0, &mut progress)?;
+        Ok((progress.obj.into_py(py), progress.offset))
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(slf: &PyCell<Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(Self::parse_prefix(slf, py, data, contextkw)?.0)
+    }
+
+    /// Build the named members from the given mapping, and members missing from it
+    /// (including unnamed ones) from `None`.
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(&self, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let context = nested_context(py, context_from_kwargs(py, contextkw)?)?;
+        if !obj.is_none() {
+            context.update(obj.downcast::<PyMapping>()?)?;
+        }
+        let contextkw = context_kwargs(py, context)?;
+        let mut out = Vec::new();
+        for member in &self.subcons {
+            let subcon = member.subcon.as_ref(py);
+            let subobj = match &member.name {
+                Some(name) => context.get_item(name)?.unwrap_or_else(|| py.None().into_ref(py)),
+                None => py.None().into_ref(py),
+            };
+            context.set_item(MEMBER_KEY, &member.name)?;
+            let (built, value) = match member.build.build_value(subcon, subobj, contextkw) {
+                Ok(built) => built,
+                Err(err) if err.is_instance_of::<exceptions::StopFieldError>(py) => break,
+                Err(err) => return Err(failed_at(py, err, out.len())),
+            };
+            if let Some(name) = &member.name {
+                context.set_item(name, value)?;
+            }
+            out.extend_from_slice(&built);
+        }
+        Ok(PyBytes::new(py, &out))
+    }
+
+    fn sizeof(&self, py: Python<'_>) -> PyResult<usize> {
+        members_sizeof(py, &self.subcons)
+    }
+
+    fn __getattr__(&self, py: Python<'_>, name: &str) -> PyResult<PyObject> {
+        member_getattr(py, &self.subcons, name)
+    }
+}
+
+/// Homogeneous array of `count` elements, where the count can be a context lambda or
+/// expression. Elements see the index being processed as `_index` in their context.
+#[pyclass(extends=Subconstruct, module = "construct_rs")]
+pub struct Array {
+    count: Py<PyAny>,
+    schema: SchemaCache,
+}
+
+impl Array {
+    fn count(&self, py: Python, context: &PyDict) -> PyResult<usize> {
+        let count: i64 = evaluate(self.count.as_ref(py), context)?.extract()?;
+        usize::try_from(count).map_err(|_| ConstructError::RangeError.into())
+    }
+}
+
+#[pymethods]
+impl Array {
+    #[new]
+    fn new(count: Py<PyAny>, subcon: Py<PyAny>) -> PyClassInitializer<Self> {
+        PyClassInitializer::from(Construct {})
+            .add_subclass(Subconstruct { subcon })
+            .add_subclass(Array { count, schema: SchemaCache::default() })
+    }
+
+    fn __getnewargs_ex__(slf: PyRef<'_, Self>, py: Python<'_>) -> NewArgs {
+        newargs(py, (slf.count.clone_ref(py), slf.as_ref().subcon.clone_ref(py)))
+    }
+
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(slf: &PyCell<Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        if let Some(parsed) = parse_released(py, slf, &data, contextkw)? {
+            return Ok(parsed);
+        }
+        let slf = slf.borrow();
+        let context = context_from_kwargs(py, contextkw)?;
+        let count = slf.count(py, context)?;
+        let subcon = slf.as_ref().subcon.as_ref(py);
+        let mut items = Vec::with_capacity(count);
+        let mut offset = 0;
+        for i in 0..count {
+            context.set_item("_index", i)?;
+            let (item, consumed) = parse_prefix_in(py, subcon, &data, offset, context).map_err(|err| failed_at(py, err, offset))?;
+            items.push(item);
+            offset += consumed;
+        }
+        Ok((ListContainer::from_items(py, items)?.into_py(py), offset))
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(slf: &PyCell<Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(Self::parse_prefix(slf, py, data, contextkw)?.0)
+    }
+
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(slf: PyRef<'py, Self>, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let context = context_from_kwargs(py, contextkw)?;
+        let count = slf.count(py, context)?;
+        if obj.len()? != count {
+            return Err(ConstructError::RangeError.into());
+        }
+        let subcon = slf.as_ref().subcon.as_ref(py);
+        let (method, contextkw) = (BuildMethod::of(subcon), context_kwargs(py, context)?);
+        let mut out = Vec::new();
+        for (i, item) in obj.iter()?.enumerate() {
+            context.set_item("_index", i)?;
+            out.extend_from_slice(&method.build(subcon, item?, contextkw).map_err(|err| failed_at(py, err, out.len()))?);
+        }
+        Ok(PyBytes::new(py, &out))
+    }
+
+    /// Size of the elements times the count, which must not depend on the context.
+    fn sizeof(slf: PyRef<'_, Self>, py: Python<'_>) -> PyResult<usize> {
+        let count = slf.count(py, Container::empty(py)?).map_err(|_| PyErr::from(ConstructError::SizeofError))?;
+        let size: usize = slf.as_ref().subcon.as_ref(py).call_method0("sizeof")?.extract()?;
+        Ok(size * count)
+    }
+}
+
+/// Array of as many elements as can be parsed, stopping at the end of the data or at
+/// the first element that fails to parse.
+#[pyclass(extends=Subconstruct, module = "construct_rs")]
+pub struct GreedyRange {
+    schema: SchemaCache,
+}
+
+#[pymethods]
+impl GreedyRange {
+    #[new]
+    fn new(subcon: Py<PyAny>) -> PyClassInitializer<Self> {
+        PyClassInitializer::from(Construct {})
+            .add_subclass(Subconstruct { subcon })
+            .add_subclass(GreedyRange { schema: SchemaCache::default() })
+    }
+
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(slf: &PyCell<Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        if let Some(parsed) = parse_released(py, slf, &data, contextkw)? {
+            return Ok(parsed);
+        }
+        let slf = slf.borrow();
+        let context = context_from_kwargs(py, contextkw)?;
+        let subcon = slf.as_ref().subcon.as_ref(py);
+        let mut items = Vec::new();
+        let mut offset = 0;
+        while offset < data.len() {
+            context.set_item("_index", items.len())?;
+            match parse_prefix_in(py, subcon, &data, offset, context) {
+                Ok((_, 0)) => break,
+                Ok((item, consumed)) => {
+                    items.push(item);
+                    offset += consumed;
+                }
+                Err(err) if err.is_instance_of::<exceptions::ExplicitError>(py) => return Err(err),
+                Err(_) => break,
+            }
+        }
+        Ok((ListContainer::from_items(py, items)?.into_py(py), offset))
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(slf: &PyCell<Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(Self::parse_prefix(slf, py, data, contextkw)?.0)
+    }
+
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(slf: PyRef<'py, Self>, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let context = context_from_kwargs(py, contextkw)?;
+        let subcon = slf.as_ref().subcon.as_ref(py);
+        let (method, contextkw) = (BuildMethod::of(subcon), context_kwargs(py, context)?);
+        let mut out = Vec::new();
+        for (i, item) in obj.iter()?.enumerate() {
+            context.set_item("_index", i)?;
+            out.extend_from_slice(&method.build(subcon, item?, contextkw)?);
+        }
+        Ok(PyBytes::new(py, &out))
+    }
+
+    fn sizeof(&self) -> PyResult<usize> {
+        Err(ConstructError::SizeofError.into())
+    }
+}
+
+/// Array of elements parsed until `predicate(obj, lst, context)` holds for the last one,
+/// which is included in the result.
+#[pyclass(extends=Subconstruct, module = "construct_rs")]
+pub struct RepeatUntil {
+    predicate: Py<PyAny>,
+    schema: SchemaCache,
+}
+
+impl RepeatUntil {
+    /// Whether `item`, already appended to `lst`, is the last element. The predicate is
+    /// passed the list being filled, as `construct.core.RepeatUntil` passes it.
+    fn done(&self, py: Python, item: &PyAny, lst: &PyList, context: &PyDict) -> PyResult<bool> {
+        let predicate = self.predicate.as_ref(py);
+        if !predicate.is_callable() {
+            return predicate.is_truthy();
+        }
+        predicate.call1((item, lst, context))?.is_truthy()
+    }
+}
+
+#[pymethods]
+impl RepeatUntil {
+    #[new]
+    fn new(predicate: Py<PyAny>, subcon: Py<PyAny>) -> PyClassInitializer<Self> {
+        PyClassInitializer::from(Construct {})
+            .add_subclass(Subconstruct { subcon })
+            .add_subclass(RepeatUntil { predicate, schema: SchemaCache::default() })
+    }
+
+    fn __getnewargs_ex__(slf: PyRef<'_, Self>, py: Python<'_>) -> NewArgs {
+        newargs(py, (slf.predicate.clone_ref(py), slf.as_ref().subcon.clone_ref(py)))
+    }
+
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(slf: &PyCell<Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        if let Some(parsed) = parse_released(py, slf, &data, contextkw)? {
+            return Ok(parsed);
+        }
+        let slf = slf.borrow();
+        let context = context_from_kwargs(py, contextkw)?;
+        let subcon = slf.as_ref().subcon.as_ref(py);
+        let items = ListContainer::from_items(py, Vec::new())?.into_ref(py).downcast::<PyList>()?;
+        let mut offset = 0;
+        loop {
+            context.set_item("_index", items.len())?;
+            let (item, consumed) = parse_prefix_in(py, subcon, &data, offset, context).map_err(|err| failed_at(py, err, offset))?;
+            offset += consumed;
+            items.append(&item)?;
+            if slf.done(py, item.as_ref(py), items, context)? {
+                break;
+            }
+        }
+        Ok((items.into(), offset))
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(slf: &PyCell<Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(Self::parse_prefix(slf, py, data, contextkw)?.0)
+    }
+
+    /// Build elements until one satisfies the predicate, which one of them must.
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(slf: PyRef<'py, Self>, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let context = context_from_kwargs(py, contextkw)?;
+        let subcon = slf.as_ref().subcon.as_ref(py);
+        let (method, contextkw) = (BuildMethod::of(subcon), context_kwargs(py, context)?);
+        let items = ListContainer::from_items(py, Vec::new())?.into_ref(py).downcast::<PyList>()?;
+        let mut out = Vec::new();
+        for item in obj.iter()? {
+            let item = item?;
+            context.set_item("_index", items.len())?;
+            out.extend_from_slice(&method.build(subcon, item, contextkw).map_err(|err| failed_at(py, err, out.len()))?);
+            items.append(item)?;
+            if slf.done(py, item, items, context)? {
+                return Ok(PyBytes::new(py, &out));
+            }
+        }
+        Err(ConstructError::RepeatError.into())
+    }
+
+    fn sizeof(&self) -> PyResult<usize> {
+        Err(ConstructError::SizeofError.into())
+    }
+}
+
+// ========================= Conditionals ==============================
+
+/// Selects the subcon from `cases` by the value of `keyfunc`, falling back to `default`.
+/// Without a default, unmatched keys parse as `None` and build nothing.
+#[pyclass(extends=Construct, module = "construct_rs")]
+pub struct Switch {
+    keyfunc: Py<PyAny>,
+    cases: Py<PyDict>,
+    default: Option<Py<PyAny>>,
+}
+
+impl Switch {
+    fn select<'py>(&'py self, py: Python<'py>, context: &'py PyDict) -> PyResult<Option<&'py PyAny>> {
+        let key = evaluate(self.keyfunc.as_ref(py), context)?;
+        match self.cases.as_ref(py).get_item(key)? {
+            Some(subcon) => Ok(Some(subcon)),
+            None => Ok(self.default.as_ref().map(|default| default.as_ref(py))),
+        }
+    }
+}
+
+#[pymethods]
+impl Switch {
+    #[new]
+    #[pyo3(signature = (keyfunc, cases, default=None))]
+    fn new(keyfunc: Py<PyAny>, cases: Py<PyDict>, default: Option<Py<PyAny>>) -> (Self, Construct) {
+        (Switch { keyfunc, cases, default }, Construct {})
+    }
+
+    fn __getnewargs_ex__(&self, py: Python<'_>) -> NewArgs {
+        let default = self.default.as_ref().map(|default| default.clone_ref(py));
+        newargs(py, (self.keyfunc.clone_ref(py), self.cases.clone_ref(py), default))
+    }
+
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(&self, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        let context = context_from_kwargs(py, contextkw)?;
+        match self.select(py, context)? {
+            Some(subcon) => parse_prefix_in(py, subcon, &data, 0, context),
+            None => Ok((py.None(), 0)),
+        }
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(&self, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(self.parse_prefix(py, data, contextkw)?.0)
+    }
+
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(&self, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let context = context_from_kwargs(py, contextkw)?;
+        match self.select(py, context)? {
+            Some(subcon) => Ok(PyBytes::new(py, &build_in(subcon, obj, context)?)),
+            None => Ok(PyBytes::new(py, b"")),
+        }
+    }
+
+    /// Size shared by every case and the default, when they all have the same one.
+    fn sizeof(&self, py: Python<'_>) -> PyResult<usize> {
+        let mut sizes = self.cases.as_ref(py).values().iter().map(subcon_sizeof).collect::<Vec<_>>();
+        sizes.push(match &self.default {
+            Some(default) => subcon_sizeof(default.as_ref(py)),
+            None => Some(0),
+        });
+        match sizes.split_first() {
+            Some((Some(size), rest)) if rest.iter().all(|other| *other == Some(*size)) => Ok(*size),
+            _ => Err(ConstructError::SizeofError.into()),
+        }
+    }
+}
+
+/// Parses and builds the subcon only when `condfunc` holds, otherwise parses as `None`
+/// and builds nothing.
+#[pyclass(extends=Subconstruct, module = "construct_rs")]
+pub struct If {
+    condfunc: Py<PyAny>,
+}
+
+#[pymethods]
+impl If {
+    #[new]
+    fn new(condfunc: Py<PyAny>, subcon: Py<PyAny>) -> PyClassInitializer<Self> {
+        PyClassInitializer::from(Construct {})
+            .add_subclass(Subconstruct { subcon })
+            .add_subclass(If { condfunc })
+    }
+
+    fn __getnewargs_ex__(slf: PyRef<'_, Self>, py: Python<'_>) -> NewArgs {
+        newargs(py, (slf.condfunc.clone_ref(py), slf.as_ref().subcon.clone_ref(py)))
+    }
+
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        let context = context_from_kwargs(py, contextkw)?;
+        if !evaluate(slf.condfunc.as_ref(py), context)?.is_truthy()? {
+            return Ok((py.None(), 0));
+        }
+        parse_prefix_in(py, slf.as_ref().subcon.as_ref(py), &data, 0, context)
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(Self::parse_prefix(slf, py, data, contextkw)?.0)
+    }
+
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(slf: PyRef<'py, Self>, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let context = context_from_kwargs(py, contextkw)?;
+        if !evaluate(slf.condfunc.as_ref(py), context)?.is_truthy()? {
+            return Ok(PyBytes::new(py, b""));
+        }
+        Ok(PyBytes::new(py, &build_in(slf.as_ref().subcon.as_ref(py), obj, context)?))
+    }
+
+    /// Size of the subcon or zero, provided the condition does not depend on the context.
+    fn sizeof(slf: PyRef<'_, Self>, py: Python<'_>) -> PyResult<usize> {
+        let cond = evaluate(slf.condfunc.as_ref(py), Container::empty(py)?)
+            .and_then(|cond| cond.is_truthy())
+            .map_err(|_| PyErr::from(ConstructError::SizeofError))?;
+        if !cond {
+            return Ok(0);
+        }
+        slf.as_ref().subcon.as_ref(py).call_method0("sizeof")?.extract()
+    }
+}
+
+/// Field computed from the context, consuming and building no bytes.
+#[pyclass(extends=Construct, module = "construct_rs")]
+pub struct Computed {
+    func: Py<PyAny>,
+}
+
+#[pymethods]
+impl Computed {
+    #[new]
+    fn new(func: Py<PyAny>) -> (Self, Construct) {
+        (Computed { func }, Construct {})
+    }
+
+    fn __getnewargs_ex__(&self, py: Python<'_>) -> NewArgs {
+        newargs(py, (self.func.clone_ref(py),))
+    }
+
+    #[pyo3(name = "_parseprefix", signature = (_data, /, **contextkw))]
+    fn parse_prefix(&self, py: Python<'_>, _data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        let context = context_from_kwargs(py, contextkw)?;
+        Ok((evaluate(self.func.as_ref(py), context)?.into(), 0))
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(&self, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(self.parse_prefix(py, data, contextkw)?.0)
+    }
+
+    #[pyo3(signature = (_obj, **_contextkw))]
+    fn build<'py>(&self, py: Python<'py>, _obj: &PyAny, _contextkw: Option<&PyDict>) -> &'py PyBytes {
+        PyBytes::new(py, b"")
+    }
+
+    /// Build no bytes, and the value computed from the context in place of `obj`.
+    #[pyo3(name = "_buildvalue", signature = (_obj, /, **contextkw))]
+    fn build_value<'py>(&self, py: Python<'py>, _obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<(&'py PyBytes, &'py PyAny)> {
+        let context = context_from_kwargs(py, contextkw)?;
+        Ok((PyBytes::new(py, b""), evaluate(self.func.clone_ref(py).into_ref(py), context)?))
+    }
+
+    fn sizeof(&self) -> usize {
+        0
+    }
+}
+
+/// Restricts the subcon to `length` bytes, padding with zeros when building.
+/// The length can be a context lambda or expression.
+#[pyclass(extends=Subconstruct, module = "construct_rs")]
+pub struct FixedSized {
+    length: Py<PyAny>,
+}
+
+impl FixedSized {
+    fn length(&self, py: Python, context: &PyDict) -> PyResult<usize> {
+        let length: i64 = evaluate(self.length.as_ref(py), context)?.extract()?;
+        usize::try_from(length).map_err(|_| ConstructError::PaddingError.into())
+    }
+}
+
+#[pymethods]
+impl FixedSized {
+    #[new]
+    fn new(length: Py<PyAny>, subcon: Py<PyAny>) -> PyClassInitializer<Self> {
+        PyClassInitializer::from(Construct {})
+            .add_subclass(Subconstruct { subcon })
+            .add_subclass(FixedSized { length })
+    }
+
+    fn __getnewargs_ex__(slf: PyRef<'_, Self>, py: Python<'_>) -> NewArgs {
+        newargs(py, (slf.length.clone_ref(py), slf.as_ref().subcon.clone_ref(py)))
+    }
+
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        let context = context_from_kwargs(py, contextkw)?;
+        let length = slf.length(py, context)?;
+        if length > data.len() {
+            return Err(ConstructError::StreamError.into());
+        }
+        let window = Input::extract(data.view(py, 0..length)?)?;
+        let (obj, _) = parse_prefix_in(py, slf.as_ref().subcon.as_ref(py), &window, 0, context)?;
+        Ok((obj, length))
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(Self::parse_prefix(slf, py, data, contextkw)?.0)
+    }
+
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(slf: PyRef<'py, Self>, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let context = context_from_kwargs(py, contextkw)?;
+        let length = slf.length(py, context)?;
+        let mut built = build_in(slf.as_ref().subcon.as_ref(py), obj, context)?;
+        if built.len() > length {
+            return Err(ConstructError::PaddingError.into());
+        }
+        built.resize(length, 0);
+        Ok(PyBytes::new(py, &built))
+    }
+
+    fn sizeof(slf: PyRef<'_, Self>, py: Python<'_>) -> PyResult<usize> {
+        slf.length(py, Container::empty(py)?).map_err(|_| ConstructError::SizeofError.into())
+    }
+}
+
+/// Parses the subcon at an absolute offset into the data, counted from its end when
+/// negative, without consuming anything where it appears. Offsets count from the start
+/// of the data the outermost parse was given, or of the object a `memoryview` given to
+/// it views, even within a `FixedSized`. Data that `ProcessXor` and the like produce is
+/// new data, which offsets within their subcon count from the start of.
+#[pyclass(extends=Subconstruct, module = "construct_rs")]
+pub struct Pointer {
+    offset: Py<PyAny>,
+}
+
+impl Pointer {
+    fn offset(&self, py: Python, context: &PyDict, length: usize) -> PyResult<usize> {
+        let offset: i64 = evaluate(self.offset.as_ref(py), context)?.extract()?;
+        let position = match offset < 0 {
+            true => i64::try_from(length).ok().and_then(|length| length.checked_add(offset)),
+            false => Some(offset),
+        };
+        position
+            .and_then(|position| usize::try_from(position).ok())
+            .filter(|position| *position <= length)
+            .ok_or_else(|| ConstructError::StreamError.into())
+    }
+}
+
+#[pymethods]
+impl Pointer {
+    #[new]
+    fn new(offset: Py<PyAny>, subcon: Py<PyAny>) -> PyClassInitializer<Self> {
+        PyClassInitializer::from(Construct {})
+            .add_subclass(Subconstruct { subcon })
+            .add_subclass(Pointer { offset })
+    }
+
+    fn __getnewargs_ex__(slf: PyRef<'_, Self>, py: Python<'_>) -> NewArgs {
+        newargs(py, (slf.offset.clone_ref(py), slf.as_ref().subcon.clone_ref(py)))
+    }
+
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        let context = context_from_kwargs(py, contextkw)?;
+        let outermost = data.outermost()?;
+        let offset = slf.offset(py, context, outermost.len())?;
+        let (obj, _) = parse_prefix_in(py, slf.as_ref().subcon.as_ref(py), &outermost, offset, context)?;
+        Ok((obj, 0))
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(Self::parse_prefix(slf, py, data, contextkw)?.0)
+    }
+
+    /// Members are built one after the other into separate bytes, so there is no stream
+    /// to write the subcon into at an offset.
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build(&self, obj: &PyAny, contextkw: Option<&PyDict>) -> PyResult<()> {
+        let _ = (obj, contextkw);
+        Err(ConstructError::Other("Pointer cannot be built by construct-rs, which builds members one after the other".into()).into())
+    }
+
+    fn sizeof(&self) -> usize {
+        0
+    }
+}
+
+// ========================= Enums =====================================
+
+/// Maps integers parsed by the subcon to names, given as keyword arguments or merged
+/// from dictionaries and `enum.IntEnum` classes. Unknown values are returned as is.
+#[pyclass(extends=Subconstruct, module = "construct_rs")]
+pub struct Enum {
+    encmapping: Py<PyDict>,
+    decmapping: Py<PyDict>,
+}
+
+#[pymethods]
+impl Enum {
+    #[new]
+    #[pyo3(signature = (subcon, *merge, **mapping))]
+    fn new(py: Python<'_>, subcon: Py<PyAny>, merge: &PyTuple, mapping: Option<&PyDict>) -> PyResult<PyClassInitializer<Self>> {
+        let encmapping = PyDict::new(py);
+        for source in merge.iter() {
+            let items = match source.downcast::<PyDict>() {
+                Ok(dict) => dict.items(),
+                Err(_) => {
+                    let members = source.getattr("__members__")?.call_method0("items")?;
+                    let mut items = Vec::new();
+                    for item in members.iter()? {
+                        let (name, member): (&PyAny, &PyAny) = item?.extract()?;
+                        items.push((name, member.getattr("value")?));
+                    }
+                    PyList::new(py, items)
+                }
+            };
+            for item in items.iter() {
+                let (name, value): (&PyAny, &PyAny) = item.extract()?;
+                encmapping.set_item(name, value)?;
+            }
+        }
+        if let Some(mapping) = mapping {
+            encmapping.update(mapping.as_mapping())?;
+        }
+        let decmapping = PyDict::new(py);
+        for (name, value) in encmapping.iter() {
+            decmapping.set_item(value, name)?;
+        }
+        Ok(PyClassInitializer::from(Construct {})
+            .add_subclass(Subconstruct { subcon })
+            .add_subclass(Enum { encmapping: encmapping.into(), decmapping: decmapping.into() }))
+    }
+
+    fn __getnewargs_ex__(slf: PyRef<'_, Self>, py: Python<'_>) -> NewArgs {
+        newargs(py, (slf.as_ref().subcon.clone_ref(py), slf.encmapping.clone_ref(py)))
+    }
+
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        let context = context_from_kwargs(py, contextkw)?;
+        let (obj, consumed) = parse_prefix_in(py, slf.as_ref().subcon.as_ref(py), &data, 0, context)?;
+        match slf.decmapping.as_ref(py).get_item(&obj)? {
+            Some(name) => Ok((name.into(), consumed)),
+            None => Ok((obj, consumed)),
+        }
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(Self::parse_prefix(slf, py, data, contextkw)?.0)
+    }
+
+    /// Build a name as its mapped value, and any other value as is.
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(slf: PyRef<'py, Self>, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let context = context_from_kwargs(py, contextkw)?;
+        let value = if obj.is_instance_of::<PyString>() {
+            slf.encmapping.as_ref(py).get_item(obj)?.ok_or(ConstructError::MappingError)?
+        } else {
+            obj
+        };
+        Ok(PyBytes::new(py, &build_in(slf.as_ref().subcon.as_ref(py), value, context)?))
+    }
+
+    fn sizeof(slf: PyRef<'_, Self>, py: Python<'_>) -> PyResult<usize> {
+        slf.as_ref().subcon.as_ref(py).call_method0("sizeof")?.extract()
+    }
+
+    /// Names of the mapping are available as attributes evaluating to themselves.
+    fn __getattr__(&self, py: Python<'_>, name: &str) -> PyResult<PyObject> {
+        match self.encmapping.as_ref(py).contains(name)? {
+            true => Ok(PyString::new(py, name).into()),
+            false => Err(PyErr::new::<pyo3::exceptions::PyAttributeError, _>(name.to_string())),
+        }
+    }
+}
+
+// ========================= Lazy ======================================
+
+/// Parse the member at `offset` of the input, returning the value and its size.
+fn parse_at(py: Python, subcon: &PyAny, data: &Input, offset: usize, context: &PyDict) -> PyResult<(PyObject, usize)> {
+    if offset > data.len() {
+        return Err(ConstructError::StreamError.into());
+    }
+    parse_prefix_in(py, subcon, data, offset, context)
+}
+
+/// Deferred parse returned by [`Lazy`], evaluated when called.
+#[pyclass(module = "construct_rs")]
+pub struct LazyValue {
+    subcon: Py<PyAny>,
+    /// The bytes taken by the value, copied from the input.
+    data: Py<PyBytes>,
+    /// Context the value is parsed in.
+    context: Py<PyDict>,
+    /// Value of a subcon with a dynamic size, which had to be parsed to learn its size.
+    parsed: Option<PyObject>,
+}
+
+#[pymethods]
+impl LazyValue {
+    fn __call__(&self, py: Python<'_>) -> PyResult<PyObject> {
+        if let Some(parsed) = &self.parsed {
+            return Ok(parsed.clone_ref(py));
+        }
+        let data = Input::Bytes(self.data.as_ref(py));
+        Ok(parse_prefix_in(py, self.subcon.as_ref(py), &data, 0, self.context.as_ref(py))?.0)
+    }
+
+    /// Pickle as a deferred parse of the same data.
+    fn __reduce__(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let lazy = Py::new(py, Lazy::new(self.subcon.clone_ref(py)))?;
+        Ok((lazy.getattr(py, "parse")?, (self.data.clone_ref(py),)).into_py(py))
+    }
+}
+
+/// Lazyfies a field, parsing it only when the returned value gets called.
+#[pyclass(extends=Subconstruct, module = "construct_rs")]
+pub struct Lazy {}
+
+#[pymethods]
+impl Lazy {
+    #[new]
+    fn new(subcon: Py<PyAny>) -> PyClassInitializer<Self> {
+        PyClassInitializer::from(Construct {})
+            .add_subclass(Subconstruct { subcon })
+            .add_subclass(Lazy {})
+    }
+
+    /// Skip a fixed-size subcon, deferring its parse. A subcon with a dynamic size is
+    /// parsed right away to learn how many bytes it takes.
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(Py<LazyValue>, usize)> {
+        let context = context_from_kwargs(py, contextkw)?;
+        let subcon = slf.as_ref().subcon.as_ref(py);
+        let (parsed, size) = match subcon_sizeof(subcon) {
+            Some(size) if size > data.len() => return Err(ConstructError::StreamError.into()),
+            Some(size) => (None, size),
+            None => {
+                let (obj, consumed) = parse_prefix_in(py, subcon, &data, 0, context)?;
+                (Some(obj), consumed)
+            }
+        };
+        let lazy = LazyValue {
+            subcon: subcon.into(),
+            data: PyBytes::new(py, &data.as_bytes()[..size]).into(),
+            context: context.into(),
+            parsed,
+        };
+        Ok((Py::new(py, lazy)?, size))
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<Py<LazyValue>> {
+        Ok(Self::parse_prefix(slf, py, data, contextkw)?.0)
+    }
+
+    /// Evaluate the value if it is a deferred parse, then build it using the subcon.
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(slf: PyRef<'py, Self>, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let obj = if obj.is_callable() { obj.call0()? } else { obj };
+        let built = build_in(slf.as_ref().subcon.as_ref(py), obj, context_from_kwargs(py, contextkw)?)?;
+        Ok(PyBytes::new(py, &built))
+    }
+
+    fn sizeof(slf: PyRef<'_, Self>, py: Python<'_>) -> PyResult<usize> {
+        slf.as_ref().subcon.as_ref(py).call_method0("sizeof")?.extract()
+    }
+}
+
+/// Input retained by the results of [`LazyStruct`] and [`LazyArray`], from which the
+/// skipped values are parsed on first access.
+enum LazySource {
+    /// The bytes parsed, copied out of the input unless it is `bytes` holding exactly them,
+    /// as other buffers could change before being parsed.
+    Bytes(Py<PyBytes>),
+    /// Seekable stream and the position the parse started at. Only the values accessed
+    /// are read from it, so the input need not fit in memory.
+    Stream { stream: Py<PyAny>, start: u64 },
+}
+
+impl LazySource {
+    fn from_stream(py: Python, stream: Py<PyAny>) -> PyResult<Self> {
+        let start = stream_tell(&mut PyFileLike::new(stream.clone_ref(py)))?;
+        Ok(LazySource::Stream { stream, start })
+    }
+
+    /// Parse `subcon` at `offset` right away, returning the value and its size.
+    fn parse_prefix(&self, py: Python, subcon: &PyAny, offset: usize, context: &PyDict) -> PyResult<(PyObject, usize)> {
+        match self {
+            LazySource::Bytes(data) => parse_at(py, subcon, &Input::Bytes(data.as_ref(py)), offset, context),
+            LazySource::Stream { stream, start } => {
+                let mut file = PyFileLike::new(stream.clone_ref(py));
+                let at = start + offset as u64;
+                file.seek(SeekFrom::Start(at)).map_err(io_py_error)?;
+                let contextkw = if takes_context(subcon, "parse_stream") { context_kwargs(py, context)? } else { context };
+                let obj = subcon.call_method("parse_stream", (stream,), Some(contextkw))?;
+                Ok((obj.into(), (stream_tell(&mut file)? - at) as usize))
+            }
+        }
+    }
+
+    /// Parse the value skipped at `offset`, leaving a stream positioned where it was.
+    fn parse_at(&self, py: Python, subcon: &PyAny, offset: usize, context: &PyDict) -> PyResult<PyObject> {
+        match self {
+            LazySource::Bytes(_) => Ok(self.parse_prefix(py, subcon, offset, context)?.0),
+            LazySource::Stream { stream, .. } => {
+                let pos = stream_tell(&mut PyFileLike::new(stream.clone_ref(py)))?;
+                let parsed = self.parse_prefix(py, subcon, offset, context);
+                PyFileLike::new(stream.clone_ref(py)).seek(SeekFrom::Start(pos)).map_err(io_py_error)?;
+                Ok(parsed?.0)
+            }
+        }
+    }
+
+    /// Position a stream after the `size` bytes parsed.
+    fn finish(&self, py: Python, size: usize) -> PyResult<()> {
+        if let LazySource::Stream { stream, start } = self {
+            PyFileLike::new(stream.clone_ref(py)).seek(SeekFrom::Start(start + size as u64)).map_err(io_py_error)?;
+        }
+        Ok(())
+    }
+
+    /// The `size` bytes parsed, for pickling.
+    fn to_bytes(&self, py: Python, size: usize) -> PyResult<Py<PyBytes>> {
+        match self {
+            LazySource::Bytes(data) => Ok(data.clone_ref(py)),
+            LazySource::Stream { stream, start } => {
+                let mut file = PyFileLike::new(stream.clone_ref(py));
+                let pos = stream_tell(&mut file)?;
+                file.seek(SeekFrom::Start(*start)).map_err(io_py_error)?;
+                let data = stream_read(&mut file, size);
+                file.seek(SeekFrom::Start(pos)).map_err(io_py_error)?;
+                Ok(PyBytes::new(py, &data?).into())
+            }
+        }
+    }
+}
+
+/// What a lazy parse reads from: the input, of which only the bytes parsed are retained
+/// once their number is known, or a stream.
+enum LazyInput<'a, 'py> {
+    Data(&'a Input<'py>),
+    Stream(LazySource),
+}
+
+impl LazyInput<'_, '_> {
+    fn parse_prefix(&self, py: Python, subcon: &PyAny, offset: usize, context: &PyDict) -> PyResult<(PyObject, usize)> {
+        match self {
+            LazyInput::Data(data) => parse_at(py, subcon, data, offset, context),
+            LazyInput::Stream(source) => source.parse_prefix(py, subcon, offset, context),
+        }
+    }
+
+    fn parse_at(&self, py: Python, subcon: &PyAny, offset: usize, context: &PyDict) -> PyResult<PyObject> {
+        match self {
+            LazyInput::Data(data) => Ok(parse_at(py, subcon, data, offset, context)?.0),
+            LazyInput::Stream(source) => source.parse_at(py, subcon, offset, context),
+        }
+    }
+
+    /// The source retained by the parse result, once it is known to have taken `size` bytes.
+    fn retain(self, py: Python, size: usize) -> PyResult<LazySource> {
+        match self {
+            LazyInput::Data(Input::Bytes(bytes)) if bytes.as_bytes().len() == size => Ok(LazySource::Bytes((*bytes).into())),
+            LazyInput::Data(data) => {
+                let data = data.as_bytes().get(..size).ok_or(ConstructError::StreamError)?;
+                Ok(LazySource::Bytes(PyBytes::new(py, data).into()))
+            }
+            LazyInput::Stream(source) => {
+                source.finish(py, size)?;
+                Ok(source)
+            }
+        }
+    }
+}
+
+/// Parse result of [`LazyStruct`], parsing members on first access.
+///
+/// A mapping rather than a dict, as code reading a dict's storage directly would see
+/// the members not parsed yet. `dict(obj)` parses them all.
+#[pyclass(mapping, module = "construct_rs")]
+pub struct LazyContainer {
+    strukt: Py<LazyStruct>,
+    source: LazySource,
+    offsets: Vec<usize>,
+    values: Vec<Option<PyObject>>,
+    /// Context the skipped members are parsed in.
+    context: Py<PyDict>,
+}
+
+impl LazyContainer {
+    fn index(&self, py: Python, key: &PyAny) -> PyResult<usize> {
+        let strukt = self.strukt.borrow(py);
+        if let Ok(name) = key.extract::<&str>() {
+            return strukt
+                .subcons
+                .iter()
+                .position(|m| m.name.as_deref() == Some(name))
+                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyKeyError, _>(name.to_string()));
+        }
+        let index: usize = key.extract()?;
+        if index >= strukt.subcons.len() {
+            return Err(PyErr::new::<pyo3::exceptions::PyKeyError, _>(index));
+        }
+        Ok(index)
+    }
+
+    fn value(slf: &PyCell<Self>, py: Python, index: usize) -> PyResult<PyObject> {
+        let this = slf.borrow();
+        if let Some(value) = &this.values[index] {
+            return Ok(value.clone_ref(py));
+        }
+        let subcon = this.strukt.borrow(py).subcons[index].subcon.clone_ref(py);
+        let value = this.source.parse_at(py, subcon.as_ref(py), this.offsets[index], this.context.as_ref(py))?;
+        drop(this);
+        slf.borrow_mut().values[index] = Some(value.clone_ref(py));
+        Ok(value)
+    }
+
+    fn names(&self, py: Python) -> Vec<String> {
+        self.strukt.borrow(py).subcons.iter().filter_map(|m| m.name.clone()).collect()
+    }
+}
+
+#[pymethods]
+impl LazyContainer {
+    /// Pickle as a lazy parse of the same data, evaluating no members.
+    fn __reduce__(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let data = self.source.to_bytes(py, self.offsets[self.offsets.len() - 1])?;
+        Ok((self.strukt.getattr(py, "parse")?, (data,)).into_py(py))
+    }
+
+    fn __getitem__(slf: &PyCell<Self>, py: Python<'_>, key: &PyAny) -> PyResult<PyObject> {
+        let index = slf.borrow().index(py, key)?;
+        Self::value(slf, py, index)
+    }
+
+    fn __getattr__(slf: &PyCell<Self>, py: Python<'_>, name: &str) -> PyResult<PyObject> {
+        let index = slf
+            .borrow()
+            .index(py, PyString::new(py, name))
+            .map_err(|_| PyErr::new::<pyo3::exceptions::PyAttributeError, _>(name.to_string()))?;
+        Self::value(slf, py, index)
+    }
+
+    #[pyo3(signature = (key, default=None))]
+    fn get(slf: &PyCell<Self>, py: Python<'_>, key: &PyAny, default: Option<PyObject>) -> PyResult<PyObject> {
+        let index = slf.borrow().index(py, key);
+        match index {
+            Ok(index) => Self::value(slf, py, index),
+            Err(_) => Ok(default.unwrap_or_else(|| py.None())),
+        }
+    }
+
+    fn __contains__(&self, py: Python<'_>, key: &PyAny) -> bool {
+        self.index(py, key).is_ok()
+    }
+
+    fn __len__(&self, py: Python<'_>) -> usize {
+        self.names(py).len()
+    }
+
+    fn keys(&self, py: Python<'_>) -> Vec<String> {
+        self.names(py)
+    }
+
+    fn values(slf: &PyCell<Self>, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        Self::items(slf, py).map(|items| items.into_iter().map(|(_, v)| v).collect())
+    }
+
+    fn items(slf: &PyCell<Self>, py: Python<'_>) -> PyResult<Vec<(String, PyObject)>> {
+        let names = slf.borrow().names(py);
+        names
+            .into_iter()
+            .map(|name| {
+                let index = slf.borrow().index(py, PyString::new(py, &name))?;
+                Ok((name, Self::value(slf, py, index)?))
+            })
+            .collect()
+    }
+
+    fn __iter__(&self, py: Python<'_>) -> PyResult<PyObject> {
+        Ok(PyList::new(py, self.names(py)).call_method0("__iter__")?.into())
+    }
+
+    /// Equality over named members with any mapping, such as a dict or another lazy
+    /// container, ignoring `_`-prefixed keys of the other mapping.
+    fn __eq__(slf: &PyCell<Self>, py: Python<'_>, other: &PyAny) -> PyResult<bool> {
+        if slf.is(other) {
+            return Ok(true);
+        }
+        let Ok(other) = other.downcast::<PyMapping>() else { return Ok(false) };
+        let names = slf.borrow().names(py);
+        let others = other.keys()?.iter()?.filter(|k| k.as_ref().map_or(true, |k| !is_private_key(k))).count();
+        if others != names.len() {
+            return Ok(false);
+        }
+        for (name, value) in Self::items(slf, py)? {
+            match other.get_item(&name) {
+                Ok(theirs) if values_equal(py, value.as_ref(py), theirs)? => {}
+                _ => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+
+    fn __repr__(&self) -> String {
+        let cached = self.values.iter().filter(|v| v.is_some()).count();
+        format!("<LazyContainer: {} items cached, {} subcons>", cached, self.values.len())
+    }
+}
+
+/// Equivalent of a Struct whose fixed-size members are skipped and parsed on first access.
+#[pyclass(extends=Construct, module = "construct_rs")]
+pub struct LazyStruct {
+    subcons: Vec<Member>,
+}
+
+impl LazyStruct {
+    /// Record member offsets, skipping fixed-size members and parsing only the dynamic
+    /// ones. The named members skipped before a dynamic one are parsed along with it,
+    /// so that its context holds every member before it.
+    fn parse_lazy(slf: PyRef<'_, Self>, py: Python<'_>, input: LazyInput, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        let context = nested_context(py, context_from_kwargs(py, contextkw)?)?;
+        let mut offsets = vec![0];
+        let mut values: Vec<Option<PyObject>> = Vec::with_capacity(slf.subcons.len());
+        let mut offset = 0;
+        for member in &slf.subcons {
+            let subcon = member.subcon.as_ref(py);
+            match subcon_sizeof(subcon) {
+                Some(size) => {
+                    offset += size;
+                    values.push(None);
+                }
+                None => {
+                    for (i, skipped) in slf.subcons.iter().enumerate().take(values.len()) {
+                        if let (Some(name), None) = (&skipped.name, &values[i]) {
+                            let value = input.parse_at(py, skipped.subcon.as_ref(py), offsets[i], context)?;
+                            context.set_item(name, &value)?;
+                            values[i] = Some(value);
+                        }
+                    }
+                    context.set_item(MEMBER_KEY, &member.name)?;
+                    let (value, consumed) = input.parse_prefix(py, subcon, offset, context).map_err(|err| failed_at(py, err, offset))?;
+                    if let Some(name) = &member.name {
+                        context.set_item(name, &value)?;
+                    }
+                    offset += consumed;
+                    values.push(Some(value));
+                }
+            }
+            offsets.push(offset);
+        }
+        let source = input.retain(py, offset)?;
+        let container = LazyContainer { strukt: slf.into(), source, offsets, values, context: context.into() };
+        Ok((container.into_py(py), offset))
+    }
+}
+
+#[pymethods]
+impl LazyStruct {
+    #[new]
+    #[pyo3(signature = (*subcons, **subconskw))]
+    fn new(subcons: &PyTuple, subconskw: Option<&PyDict>) -> PyResult<(Self, Construct)> {
+        Ok((LazyStruct { subcons: collect_members(subcons, subconskw)? }, Construct {}))
+    }
+
+    fn __getstate__(&self, py: Python<'_>) -> Vec<(Option<String>, PyObject)> {
+        members_state(py, &self.subcons)
+    }
+
+    fn __setstate__(&mut self, py: Python<'_>, state: Vec<(Option<String>, PyObject)>) {
+        self.subcons = members_from_state(py, state);
+    }
+
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        Self::parse_lazy(slf, py, LazyInput::Data(&data), contextkw)
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(Self::parse_prefix(slf, py, data, contextkw)?.0)
+    }
+
+    /// Parse from a seekable stream, which is retained to parse the skipped members from
+    /// on first access, and left positioned after the struct.
+    #[pyo3(signature = (stream, **contextkw))]
+    fn parse_stream(slf: PyRef<'_, Self>, py: Python<'_>, stream: Py<PyAny>, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(Self::parse_lazy(slf, py, LazyInput::Stream(LazySource::from_stream(py, stream)?), contextkw)?.0)
+    }
+
+    /// Build the named members from the given mapping, as `Struct` does, and the unnamed
+    /// and missing ones from `None`.
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(&self, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let context = nested_context(py, context_from_kwargs(py, contextkw)?)?;
+        if !obj.is_none() {
+            context.update(obj.downcast::<PyMapping>()?)?;
+        }
+        let contextkw = context_kwargs(py, context)?;
+        let mut out = Vec::new();
+        for member in &self.subcons {
+            let subobj = match &member.name {
+                Some(name) => context.get_item(name)?.unwrap_or_else(|| py.None().into_ref(py)),
+                None => py.None().into_ref(py),
+            };
+            context.set_item(MEMBER_KEY, &member.name)?;
+            let built = match member.build.build(member.subcon.as_ref(py), subobj, contextkw) {
+                Ok(built) => built,
+                Err(err) if err.is_instance_of::<exceptions::StopFieldError>(py) => break,
+                Err(err) => return Err(err),
+            };
+            out.extend_from_slice(&built);
+        }
+        Ok(PyBytes::new(py, &out))
+    }
+
+    fn sizeof(&self, py: Python<'_>) -> PyResult<usize> {
+        members_sizeof(py, &self.subcons)
+    }
+
+    fn __getattr__(&self, py: Python<'_>, name: &str) -> PyResult<PyObject> {
+        member_getattr(py, &self.subcons, name)
+    }
+}
+
+/// Parse result of [`LazyArray`], parsing elements on first access.
+#[pyclass(sequence, module = "construct_rs")]
+pub struct LazyListContainer {
+    subcon: Py<PyAny>,
+    source: LazySource,
+    offsets: Vec<usize>,
+    values: Vec<Option<PyObject>>,
+    /// Context the skipped elements are parsed in.
+    context: Py<PyDict>,
+}
+
+impl LazyListContainer {
+    fn value(&mut self, py: Python, index: usize) -> PyResult<PyObject> {
+        if let Some(value) = &self.values[index] {
+            return Ok(value.clone_ref(py));
+        }
+        let value = self.source.parse_at(py, self.subcon.as_ref(py), self.offsets[index], self.context.as_ref(py))?;
+        self.values[index] = Some(value.clone_ref(py));
+        Ok(value)
+    }
+}
+
+#[pymethods]
+impl LazyListContainer {
+    /// Pickle as a lazy parse of the same data, evaluating no items.
+    fn __reduce__(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let array = Py::new(py, LazyArray::new(self.values.len().into_py(py), self.subcon.clone_ref(py)))?;
+        let data = self.source.to_bytes(py, self.offsets[self.offsets.len() - 1])?;
+        Ok((array.getattr(py, "parse")?, (data,)).into_py(py))
+    }
+
+    fn __getitem__(&mut self, py: Python<'_>, index: &PyAny) -> PyResult<PyObject> {
+        let count = self.values.len();
+        if let Ok(slice) = index.downcast::<pyo3::types::PySlice>() {
+            let indices = slice.indices(count as std::os::raw::c_long)?;
+            let mut items = Vec::new();
+            let mut i = indices.start;
+            while (indices.step > 0 && i < indices.stop) || (indices.step < 0 && i > indices.stop) {
+                items.push(self.value(py, i as usize)?);
+                i += indices.step;
+            }
+            return Ok(PyList::new(py, items).into());
+        }
+        let index: isize = index.extract()?;
+        let resolved = if index < 0 { index + count as isize } else { index };
+        if resolved < 0 || resolved as usize >= count {
+            return Err(PyErr::new::<pyo3::exceptions::PyIndexError, _>("list index out of range"));
+        }
+        self.value(py, resolved as usize)
+    }
+
+    fn __len__(&self) -> usize {
+        self.values.len()
+    }
+
+    fn __iter__(&mut self, py: Python<'_>) -> PyResult<PyObject> {
+        let items = (0..self.values.len()).map(|i| self.value(py, i)).collect::<PyResult<Vec<_>>>()?;
+        Ok(PyList::new(py, items).call_method0("__iter__")?.into())
+    }
+
+    fn __eq__(&mut self, py: Python<'_>, other: &PyAny) -> PyResult<bool> {
+        if other.len().ok() != Some(self.values.len()) {
+            return Ok(false);
+        }
+        for i in 0..self.values.len() {
+            if !self.value(py, i)?.as_ref(py).eq(other.get_item(i)?)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn __repr__(&self) -> String {
+        let cached = self.values.iter().filter(|v| v.is_some()).count();
+        format!("<LazyListContainer: {} of {} items cached>", cached, self.values.len())
+    }
+}
+
+/// Equivalent of an Array whose fixed-size elements are skipped and parsed on first access.
+#[pyclass(extends=Subconstruct, module = "construct_rs")]
+pub struct LazyArray {
+    count: Py<PyAny>,
+}
+
+impl LazyArray {
+    fn count(&self, py: Python, context: &PyDict) -> PyResult<usize> {
+        let count: i64 = evaluate(self.count.as_ref(py), context)?.extract()?;
+        usize::try_from(count).map_err(|_| ConstructError::RangeError.into())
+    }
+
+    /// Record element offsets, skipping fixed-size elements and parsing only the dynamic ones.
+    fn parse_lazy(slf: PyRef<'_, Self>, py: Python<'_>, input: LazyInput, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        let context = context_from_kwargs(py, contextkw)?;
+        let count = slf.count(py, context)?;
+        let subcon = slf.as_ref().subcon.clone_ref(py);
+        let mut offsets = vec![0];
+        let mut values = Vec::with_capacity(count);
+        let mut offset = 0;
+        let size = subcon_sizeof(subcon.as_ref(py));
+        for _ in 0..count {
+            match size {
+                Some(size) => {
+                    offset += size;
+                    values.push(None);
+                }
+                None => {
+                    let (value, consumed) = input.parse_prefix(py, subcon.as_ref(py), offset, context)?;
+                    offset += consumed;
+                    values.push(Some(value));
+                }
+            }
+            offsets.push(offset);
+        }
+        let source = input.retain(py, offset)?;
+        let container = LazyListContainer { subcon, source, offsets, values, context: context.into() };
+        Ok((Py::new(py, container)?.into_py(py), offset))
+    }
+}
+
+#[pymethods]
+impl LazyArray {
+    #[new]
+    fn new(count: Py<PyAny>, subcon: Py<PyAny>) -> PyClassInitializer<Self> {
+        PyClassInitializer::from(Construct {})
+            .add_subclass(Subconstruct { subcon })
+            .add_subclass(LazyArray { count })
+    }
+
+    fn __getnewargs_ex__(slf: PyRef<'_, Self>, py: Python<'_>) -> NewArgs {
+        newargs(py, (slf.count.clone_ref(py), slf.as_ref().subcon.clone_ref(py)))
+    }
+
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        Self::parse_lazy(slf, py, LazyInput::Data(&data), contextkw)
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(Self::parse_prefix(slf, py, data, contextkw)?.0)
+    }
+
+    /// Parse from a seekable stream, which is retained to parse the skipped elements from
+    /// on first access, and left positioned after the array.
+    #[pyo3(signature = (stream, **contextkw))]
+    fn parse_stream(slf: PyRef<'_, Self>, py: Python<'_>, stream: Py<PyAny>, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(Self::parse_lazy(slf, py, LazyInput::Stream(LazySource::from_stream(py, stream)?), contextkw)?.0)
+    }
+
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(slf: PyRef<'py, Self>, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let context = context_from_kwargs(py, contextkw)?;
+        let count = slf.count(py, context)?;
+        if obj.len()? != count {
+            return Err(ConstructError::RangeError.into());
+        }
+        let subcon = slf.as_ref().subcon.as_ref(py);
+        let (method, contextkw) = (BuildMethod::of(subcon), context_kwargs(py, context)?);
+        let mut out = Vec::new();
+        for item in obj.iter()? {
+            out.extend_from_slice(&method.build(subcon, item?, contextkw)?);
+        }
+        Ok(PyBytes::new(py, &out))
+    }
+    fn sizeof(slf: PyRef<'_, Self>, py: Python<'_>) -> PyResult<usize> {
+        let count = slf.count(py, PyDict::new(py)).map_err(|_| PyErr::from(ConstructError::SizeofError))?;
+        let size = subcon_sizeof(slf.as_ref().subcon.as_ref(py)).ok_or(ConstructError::SizeofError)?;
+        Ok(count * size)
+    }
+}
+
+// ========================= Python streams ============================
+
+/// Adapts a Python file-like object to `Read`, `Write` and `Seek`.
+///
+/// A `read` returning `None` or raising `BlockingIOError` reports `WouldBlock`.
+pub struct PyFileLike {
+    inner: Py<PyAny>,
+}
+
+impl PyFileLike {
+    pub fn new(inner: Py<PyAny>) -> Self {
+        PyFileLike { inner }
+    }
+}
+
+fn py_io_error(py: Python, err: PyErr) -> io::Error {
+    if err.is_instance_of::<pyo3::exceptions::PyBlockingIOError>(py) {
+        return io::Error::new(io::ErrorKind::WouldBlock, err.to_string());
+    }
+    io::Error::other(err.to_string())
+}
+
+fn io_py_error(err: io::Error) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyIOError, _>(err.to_string())
+}
+
+impl Read for PyFileLike {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Python::with_gil(|py| {
+            let data = self.inner.as_ref(py).call_method1("read", (buf.len(),)).map_err(|e| py_io_error(py, e))?;
+            if data.is_none() {
+                return Err(io::Error::new(io::ErrorKind::WouldBlock, "no data available"));
+            }
+            let data: &[u8] = data.extract().map_err(|e| py_io_error(py, e))?;
+            let n = data.len().min(buf.len());
+            buf[..n].copy_from_slice(&data[..n]);
+            Ok(n)
+        })
+    }
+}
+
+impl Write for PyFileLike {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        Python::with_gil(|py| {
+            let written = self
+                .inner
+                .as_ref(py)
+                .call_method1("write", (PyBytes::new(py, data),))
+                .map_err(|e| py_io_error(py, e))?;
+            Ok(written.extract::<usize>().unwrap_or(data.len()))
+        })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Python::with_gil(|py| {
+            let inner = self.inner.as_ref(py);
+            if inner.hasattr("flush").unwrap_or(false) {
+                inner.call_method0("flush").map_err(|e| py_io_error(py, e))?;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl Seek for PyFileLike {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (offset, whence) = match pos {
+            SeekFrom::Start(at) => (at as i64, 0),
+            SeekFrom::Current(delta) => (delta, 1),
+            SeekFrom::End(delta) => (delta, 2),
+        };
+        Python::with_gil(|py| {
+            let inner = self.inner.as_ref(py);
+            let result = if whence == 1 && offset == 0 {
+                inner.call_method0("tell")
+            } else {
+                inner.call_method1("seek", (offset, whence))
+            };
+            result.and_then(|at| at.extract::<u64>()).map_err(|e| py_io_error(py, e))
+        })
+    }
+}
+
+/// Wrap a Python callable taking and returning bytes as a [`Transform`].
+fn py_transform(func: Py<PyAny>) -> Transform {
+    Arc::new(move |data: &[u8]| {
+        Python::with_gil(|py| {
+            let out = func.as_ref(py).call1((PyBytes::new(py, data),)).map_err(|e| py_io_error(py, e))?;
+            let out: &[u8] = out.extract().map_err(|e| py_io_error(py, e))?;
+            Ok(out.to_vec())
+        })
+    })
+}
+
+/// Python interface of [`RestreamedBytesIO`] over a Python file-like substream.
+#[pyclass(name = "RestreamedBytesIO", module = "construct_rs")]
+pub struct PyRestreamedBytesIO {
+    stream: RestreamedBytesIO<PyFileLike>,
+}
+
+#[pymethods]
+impl PyRestreamedBytesIO {
+    #[new]
+    fn new(substream: Py<PyAny>, decoder: Py<PyAny>, decoderunit: usize, encoder: Py<PyAny>, encoderunit: usize) -> Self {
+        let stream = RestreamedBytesIO::new(
+            PyFileLike::new(substream),
+            py_transform(decoder),
+            decoderunit,
+            py_transform(encoder),
+            encoderunit,
+        );
+        PyRestreamedBytesIO { stream }
+    }
+
+    /// Read `count` decoded bytes, or everything until the end when `count` is `None` or
+    /// negative. The GIL is released while waiting for the substream.
+    #[pyo3(signature = (count=None))]
+    fn read<'py>(&mut self, py: Python<'py>, count: Option<i64>) -> PyResult<&'py PyBytes> {
+        let count = count.and_then(|count| usize::try_from(count).ok());
+        let stream = &mut self.stream;
+        let data = py.allow_threads(|| stream.read_count(count)).map_err(io_py_error)?;
+        Ok(PyBytes::new(py, &data))
+    }
+
+    fn write(&mut self, data: &[u8]) -> PyResult<usize> {
+        self.stream.write(data).map_err(io_py_error)
+    }
+
+    fn close(&mut self) -> PyResult<()> {
+        self.stream.close().map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))
+    }
+
+    #[pyo3(signature = (at, whence=0))]
+    fn seek(&mut self, at: i64, whence: i32) -> PyResult<u64> {
+        let _ = (at, whence);
+        Err(PyErr::new::<pyo3::exceptions::PyIOError, _>("RestreamedBytesIO is not seekable"))
+    }
+
+    fn seekable(&self) -> bool {
+        false
+    }
+
+    /// Correct only on read-only and write-only instances.
+    fn tell(&self) -> usize {
+        self.stream.tell()
+    }
+
+    fn tellable(&self) -> bool {
+        true
+    }
+}
+
+/// Python interface of [`RebufferedBytesIO`] over a Python file-like substream.
+#[pyclass(name = "RebufferedBytesIO", module = "construct_rs")]
+pub struct PyRebufferedBytesIO {
+    stream: RebufferedBytesIO<PyFileLike>,
+}
+
+#[pymethods]
+impl PyRebufferedBytesIO {
+    #[new]
+    #[pyo3(signature = (substream, tailcutoff=None))]
+    fn new(substream: Py<PyAny>, tailcutoff: Option<usize>) -> Self {
+        PyRebufferedBytesIO { stream: RebufferedBytesIO::new(PyFileLike::new(substream), tailcutoff) }
+    }
+
+    /// Read exactly `count` bytes, raising `IOError` if the substream ends first. The GIL
+    /// is released while waiting for the substream, so that other threads can produce the data.
+    #[pyo3(signature = (count=None))]
+    fn read<'py>(&mut self, py: Python<'py>, count: Option<usize>) -> PyResult<&'py PyBytes> {
+        let count = count.ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>("count must be integer, reading until EOF not supported")
+        })?;
+        let stream = &mut self.stream;
+        let data = py.allow_threads(|| stream.read_count(count)).map_err(io_py_error)?;
+        Ok(PyBytes::new(py, &data))
+    }
+
+    fn write(&mut self, py: Python<'_>, data: &[u8]) -> PyResult<usize> {
+        let stream = &mut self.stream;
+        py.allow_threads(|| stream.write(data)).map_err(io_py_error)
+    }
+
+    #[pyo3(signature = (at, whence=0))]
+    fn seek(&mut self, at: i64, whence: i32) -> PyResult<u64> {
+        let pos = match whence {
+            0 if at < 0 => {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("negative seek value {at}")))
+            }
+            0 => SeekFrom::Start(at as u64),
+            1 => SeekFrom::Current(at),
+            _ => {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                    "this class seeks only with whence: 0 and 1 (excluded 2)",
+                ))
+            }
+        };
+        self.stream.seek(pos).map_err(io_py_error)
+    }
+
+    fn seekable(&self) -> bool {
+        true
+    }
+
+    fn tell(&self) -> usize {
+        self.stream.tell()
+    }
+
+    fn tellable(&self) -> bool {
+        true
+    }
+
+    fn cachedfrom(&self) -> usize {
+        self.stream.cachedfrom()
+    }
+
+    fn cachedto(&self) -> usize {
+        self.stream.cachedto()
+    }
+}
+
+// ========================= Tunneling =================================
+
+/// Computes the size of a [`Restreamed`] from the size of its subcon.
+pub type SizeComputer = Arc<dyn Fn(usize) -> PyResult<usize> + Send + Sync>;
+
+/// Python decoder, encoder and size computer a [`Restreamed`] was created from.
+type RestreamedFuncs = (Py<PyAny>, Py<PyAny>, Option<Py<PyAny>>);
+
+/// Wrap a Python callable taking and returning an integer as a [`SizeComputer`].
+fn py_sizecomputer(func: Py<PyAny>) -> SizeComputer {
+    Arc::new(move |size| Python::with_gil(|py| func.as_ref(py).call1((size,))?.extract()))
+}
+
+/// Build `obj` with `subcon` into a vector of bytes.
+fn build_bytes(subcon: &PyAny, obj: &PyAny) -> PyResult<Vec<u8>> {
+    let built: &PyBytes = subcon.call_method1("build", (obj,))?.extract()?;
+    Ok(built.as_bytes().to_vec())
+}
+
+/// Transforms bytes between the underlying data and a fixed-sized subcon.
+///
+/// The functions can be Python callables or, when created from Rust with
+/// [`Transformed::native`], Rust closures.
+#[pyclass(extends=Subconstruct, module = "construct_rs")]
+pub struct Transformed {
+    decodefunc: Transform,
+    decodeamount: Option<usize>,
+    encodefunc: Transform,
+    encodeamount: Option<usize>,
+    /// Python callables the functions were created from, needed for pickling.
+    pyfuncs: Option<(Py<PyAny>, Py<PyAny>)>,
+}
+
+impl Transformed {
+    /// Create a `Transformed` using Rust-side decode and encode functions.
+    pub fn native(
+        subcon: Py<PyAny>,
+        decodefunc: Transform,
+        decodeamount: Option<usize>,
+        encodefunc: Transform,
+        encodeamount: Option<usize>,
+    ) -> PyClassInitializer<Self> {
+        PyClassInitializer::from(Construct {})
+            .add_subclass(Subconstruct { subcon })
+            .add_subclass(Transformed { decodefunc, decodeamount, encodefunc, encodeamount, pyfuncs: None })
+    }
+}
+
+#[pymethods]
+impl Transformed {
+    #[new]
+    #[pyo3(signature = (subcon, decodefunc, decodeamount, encodefunc, encodeamount))]
+    fn new(
+        py: Python<'_>,
+        subcon: Py<PyAny>,
+        decodefunc: Py<PyAny>,
+        decodeamount: Option<usize>,
+        encodefunc: Py<PyAny>,
+        encodeamount: Option<usize>,
+    ) -> PyClassInitializer<Self> {
+        let pyfuncs = Some((decodefunc.clone_ref(py), encodefunc.clone_ref(py)));
+        PyClassInitializer::from(Construct {}).add_subclass(Subconstruct { subcon }).add_subclass(Transformed {
+            decodefunc: py_transform(decodefunc),
+            decodeamount,
+            encodefunc: py_transform(encodefunc),
+            encodeamount,
+            pyfuncs,
+        })
+    }
+
+    fn __getnewargs_ex__(slf: PyRef<'_, Self>, py: Python<'_>) -> PyResult<NewArgs> {
+        let (decodefunc, encodefunc) = slf.pyfuncs.as_ref().ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyTypeError, _>("cannot pickle Transformed using Rust functions")
+        })?;
+        let args = (slf.as_ref().subcon.clone_ref(py), decodefunc.clone_ref(py), slf.decodeamount, encodefunc.clone_ref(py), slf.encodeamount);
+        Ok(newargs(py, args))
+    }
+
+    /// Decode `decodeamount` bytes, or all of the data, and parse the result using the subcon.
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        let context = context_from_kwargs(py, contextkw)?;
+        let bytes = data.as_bytes();
+        let amount = slf.decodeamount.unwrap_or(bytes.len());
+        let raw = bytes.get(..amount).ok_or(ConstructError::StreamError)?;
+        let decoded = Input::Bytes(PyBytes::new(py, &(slf.decodefunc)(raw).map_err(io_py_error)?));
+        let (obj, _) = parse_prefix_in(py, slf.as_ref().subcon.as_ref(py), &decoded, 0, context)?;
+        Ok((obj, amount))
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(Self::parse_prefix(slf, py, data, contextkw)?.0)
+    }
+
+    /// Build using the subcon and encode the result, which must be `encodeamount` bytes long.
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(slf: PyRef<'py, Self>, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let built = build_in(slf.as_ref().subcon.as_ref(py), obj, context_from_kwargs(py, contextkw)?)?;
+        let encoded = (slf.encodefunc)(&built).map_err(io_py_error)?;
+        if slf.encodeamount.is_some_and(|amount| amount != encoded.len()) {
+            return Err(ConstructError::StreamError.into());
+        }
+        Ok(PyBytes::new(py, &encoded))
+    }
+
+    fn sizeof(&self) -> PyResult<usize> {
+        match (self.decodeamount, self.encodeamount) {
+            (Some(decoded), Some(encoded)) if decoded == encoded => Ok(encoded),
+            _ => Err(ConstructError::SizeofError.into()),
+        }
+    }
+}
+
+/// Transforms bytes between the underlying data and a variable-sized subcon,
+/// in units of fixed size, using a [`RestreamedBytesIO`].
+///
+/// The functions can be Python callables or, when created from Rust with
+/// [`Restreamed::native`], Rust closures.
+#[pyclass(extends=Subconstruct, module = "construct_rs")]
+pub struct Restreamed {
+    decoder: Transform,
+    decoderunit: usize,
+    encoder: Transform,
+    encoderunit: usize,
+    sizecomputer: Option<SizeComputer>,
+    /// Python callables the functions were created from, needed for pickling.
+    pyfuncs: Option<RestreamedFuncs>,
+}
+
+impl Restreamed {
+    /// Create a `Restreamed` using Rust-side decoder, encoder and size computer.
+    pub fn native(
+        subcon: Py<PyAny>,
+        decoder: Transform,
+        decoderunit: usize,
+        encoder: Transform,
+        encoderunit: usize,
+        sizecomputer: Option<SizeComputer>,
+    ) -> PyClassInitializer<Self> {
+        PyClassInitializer::from(Construct {})
+            .add_subclass(Subconstruct { subcon })
+            .add_subclass(Restreamed { decoder, decoderunit, encoder, encoderunit, sizecomputer, pyfuncs: None })
+    }
+
+    fn restream<S: Read + Write>(&self, substream: S) -> RestreamedBytesIO<S> {
+        RestreamedBytesIO::new(
+            substream,
+            self.decoder.clone(),
+            self.decoderunit,
+            self.encoder.clone(),
+            self.encoderunit,
+        )
+    }
+}
+
+#[pymethods]
+impl Restreamed {
+    #[new]
+    #[pyo3(signature = (subcon, decoder, decoderunit, encoder, encoderunit, sizecomputer))]
+    fn new(
+        py: Python<'_>,
+        subcon: Py<PyAny>,
+        decoder: Py<PyAny>,
+        decoderunit: usize,
+        encoder: Py<PyAny>,
+        encoderunit: usize,
+        sizecomputer: Option<Py<PyAny>>,
+    ) -> PyClassInitializer<Self> {
+        let pyfuncs = Some((decoder.clone_ref(py), encoder.clone_ref(py), sizecomputer.as_ref().map(|func| func.clone_ref(py))));
+        PyClassInitializer::from(Construct {}).add_subclass(Subconstruct { subcon }).add_subclass(Restreamed {
+            decoder: py_transform(decoder),
+            decoderunit,
+            encoder: py_transform(encoder),
+            encoderunit,
+            sizecomputer: sizecomputer.map(py_sizecomputer),
+            pyfuncs,
+        })
+    }
+
+    fn __getnewargs_ex__(slf: PyRef<'_, Self>, py: Python<'_>) -> PyResult<NewArgs> {
+        let (decoder, encoder, sizecomputer) = slf.pyfuncs.as_ref().ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyTypeError, _>("cannot pickle Restreamed using Rust functions")
+        })?;
+        let args = (
+            slf.as_ref().subcon.clone_ref(py),
+            decoder.clone_ref(py),
+            slf.decoderunit,
+            encoder.clone_ref(py),
+            slf.encoderunit,
+            sizecomputer.as_ref().map(|func| func.clone_ref(py)),
+        );
+        Ok(newargs(py, args))
+    }
+
+    /// Decode as many units as a fixed-sized subcon needs, or all of them, and
+    /// parse the result using the subcon.
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        let context = context_from_kwargs(py, contextkw)?;
+        let subcon = slf.as_ref().subcon.as_ref(py);
+        let mut stream2 = slf.restream(io::Cursor::new(data.as_bytes().to_vec()));
+        let decoded = match subcon_sizeof(subcon) {
+            Some(size) => stream_read(&mut stream2, size)?,
+            None => stream_read_entire(&mut stream2)?,
+        };
+        stream2.close().map_err(io_py_error)?;
+        let (obj, _) = parse_prefix_in(py, subcon, &Input::Bytes(PyBytes::new(py, &decoded)), 0, context)?;
+        Ok((obj, stream2.substream.position() as usize))
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(Self::parse_prefix(slf, py, data, contextkw)?.0)
+    }
+
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(slf: PyRef<'py, Self>, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let built = build_in(slf.as_ref().subcon.as_ref(py), obj, context_from_kwargs(py, contextkw)?)?;
+        let mut stream2 = slf.restream(io::Cursor::new(Vec::new()));
+        stream_write(&mut stream2, &built)?;
+        stream2.close().map_err(io_py_error)?;
+        Ok(PyBytes::new(py, &stream2.substream.into_inner()))
+    }
+
+    fn sizeof(slf: PyRef<'_, Self>, py: Python<'_>) -> PyResult<usize> {
+        let sizecomputer = slf.sizecomputer.as_ref().ok_or(ConstructError::SizeofError)?;
+        sizecomputer(slf.as_ref().subcon.as_ref(py).call_method0("sizeof")?.extract()?)
+    }
+}
+
+/// Parses a field on external data given by `datafunc`, and builds nothing.
+#[pyclass(extends=Subconstruct, module = "construct_rs")]
+pub struct RestreamData {
+    datafunc: Py<PyAny>,
+}
+
+#[pymethods]
+impl RestreamData {
+    #[new]
+    fn new(datafunc: Py<PyAny>, subcon: Py<PyAny>) -> PyClassInitializer<Self> {
+        PyClassInitializer::from(Construct {})
+            .add_subclass(Subconstruct { subcon })
+            .add_subclass(RestreamData { datafunc })
+    }
+
+    fn __getnewargs_ex__(slf: PyRef<'_, Self>, py: Python<'_>) -> NewArgs {
+        newargs(py, (slf.datafunc.clone_ref(py), slf.as_ref().subcon.clone_ref(py)))
+    }
+
+    /// Parse the subcon from bytes, a readable stream, or the bytes a construct
+    /// parses from the main data (which is then consumed).
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        let context = context_from_kwargs(py, contextkw)?;
+        let datafunc = slf.datafunc.as_ref(py);
+        let (external, consumed): (PyObject, usize) = if datafunc.hasattr("parse")? {
+            parse_prefix_in(py, datafunc, &data, 0, context)?
+        } else {
+            let source = evaluate(datafunc, context)?;
+            if source.hasattr("read")? {
+                (source.call_method0("read")?.into(), 0)
+            } else {
+                (source.into(), 0)
+            }
+        };
+        let external = Input::extract(external.as_ref(py))?;
+        let (obj, _) = parse_prefix_in(py, slf.as_ref().subcon.as_ref(py), &external, 0, context)?;
+        Ok((obj, consumed))
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(Self::parse_prefix(slf, py, data, contextkw)?.0)
+    }
+
+    #[pyo3(signature = (_obj, /, **_contextkw))]
+    fn build<'py>(&self, py: Python<'py>, _obj: &PyAny, _contextkw: Option<&PyDict>) -> PyResult<&'py PyBytes> {
+        Ok(PyBytes::new(py, b""))
+    }
+
+    fn sizeof(&self) -> PyResult<usize> {
+        Ok(0)
+    }
+}
+
+/// Caches data read from a non-seekable stream, making it seekable for the subcon.
+///
+/// In-memory parsing and building defer to the subcon; `parse_stream` hands the subcon
+/// a [`RebufferedBytesIO`] over the stream, keeping at most `tailcutoff` bytes cached.
+#[pyclass(extends=Subconstruct, module = "construct_rs")]
+pub struct Rebuffered {
+    tailcutoff: Option<usize>,
+}
+
+#[pymethods]
+impl Rebuffered {
+    #[new]
+    #[pyo3(signature = (subcon, tailcutoff=None))]
+    fn new(subcon: Py<PyAny>, tailcutoff: Option<usize>) -> PyClassInitializer<Self> {
+        PyClassInitializer::from(Construct {})
+            .add_subclass(Subconstruct { subcon })
+            .add_subclass(Rebuffered { tailcutoff })
+    }
+
+    fn __getnewargs_ex__(slf: PyRef<'_, Self>, py: Python<'_>) -> NewArgs {
+        newargs(py, (slf.as_ref().subcon.clone_ref(py), slf.tailcutoff))
+    }
+
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        parse_prefix_in(py, slf.as_ref().subcon.as_ref(py), &data, 0, context_from_kwargs(py, contextkw)?)
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(slf: PyRef<'_, Self>, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(Self::parse_prefix(slf, py, data, contextkw)?.0)
+    }
+
+    /// Parse using the subcon's `parse_stream` from a seekable `RebufferedBytesIO`
+    /// reading from `stream`.
+    #[pyo3(signature = (stream, **contextkw))]
+    fn parse_stream(slf: PyRef<'_, Self>, py: Python<'_>, stream: Py<PyAny>, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        let stream2 = Py::new(py, PyRebufferedBytesIO::new(stream, slf.tailcutoff))?;
+        Ok(slf.as_ref().subcon.as_ref(py).call_method("parse_stream", (stream2,), contextkw)?.into())
+    }
+
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(slf: PyRef<'py, Self>, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let built = build_in(slf.as_ref().subcon.as_ref(py), obj, context_from_kwargs(py, contextkw)?)?;
+        Ok(PyBytes::new(py, &built))
+    }
+
+    fn sizeof(slf: PyRef<'_, Self>, py: Python<'_>) -> PyResult<usize> {
+        slf.as_ref().subcon.as_ref(py).call_method0("sizeof")?.extract()
+    }
+}
+
+// ========================= LazyBound =================================
+
+/// Default limit on nested [`LazyBound`] resolutions.
+const DEFAULT_MAX_RECURSION_DEPTH: usize = 500;
+
+static MAX_RECURSION_DEPTH: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_RECURSION_DEPTH);
+
+thread_local! {
+    static RECURSION_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Set the limit on nested `LazyBound` resolutions for instances without their own limit.
+#[pyfunction]
+#[pyo3(name = "setGlobalMaxRecursionDepth")]
+fn set_global_max_recursion_depth(depth: usize) {
+    MAX_RECURSION_DEPTH.store(depth, Ordering::Relaxed);
+}
+
+/// Current limit on nested `LazyBound` resolutions.
+#[pyfunction]
+#[pyo3(name = "getGlobalMaxRecursionDepth")]
+fn get_global_max_recursion_depth() -> usize {
+    MAX_RECURSION_DEPTH.load(Ordering::Relaxed)
+}
+
+/// Tracks one level of `LazyBound` nesting on the current thread for as long as it lives.
+struct RecursionGuard;
+
+impl RecursionGuard {
+    fn enter(maxdepth: usize) -> PyResult<Self> {
+        RECURSION_DEPTH.with(|depth| {
+            if depth.get() >= maxdepth {
+                return Err(PyErr::new::<pyo3::exceptions::PyRecursionError, _>(format!(
+                    "LazyBound nesting exceeded maximum depth of {}",
+                    maxdepth
+                )));
+            }
+            depth.set(depth.get() + 1);
+            Ok(RecursionGuard)
+        })
+    }
+}
+
+impl Drop for RecursionGuard {
+    fn drop(&mut self) {
+        RECURSION_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+/// Field that binds to its subcon only when first used, for recursive formats.
+#[pyclass(extends=Construct, module = "construct_rs")]
+pub struct LazyBound {
+    subconfunc: Py<PyAny>,
+    subcon: pyo3::sync::GILOnceCell<Py<PyAny>>,
+    maxdepth: Option<usize>,
+    sizing: Cell<bool>,
+}
+
+impl LazyBound {
+    fn subcon<'py>(&self, py: Python<'py>) -> PyResult<&'py PyAny> {
+        let subcon = self.subcon.get_or_try_init(py, || self.subconfunc.as_ref(py).call0().map(Into::into))?;
+        Ok(subcon.clone_ref(py).into_ref(py))
+    }
+
+    fn enter(&self) -> PyResult<RecursionGuard> {
+        RecursionGuard::enter(self.maxdepth.unwrap_or_else(get_global_max_recursion_depth))
+    }
+}
+
+#[pymethods]
+impl LazyBound {
+    #[new]
+    #[pyo3(signature = (subconfunc, maxdepth=None))]
+    fn new(subconfunc: Py<PyAny>, maxdepth: Option<usize>) -> (Self, Construct) {
+        let subcon = pyo3::sync::GILOnceCell::new();
+        (LazyBound { subconfunc, subcon, maxdepth, sizing: Cell::new(false) }, Construct {})
+    }
+
+    fn __getnewargs_ex__(&self, py: Python<'_>) -> NewArgs {
+        newargs(py, (self.subconfunc.clone_ref(py), self.maxdepth))
+    }
+
+    #[pyo3(name = "_parseprefix", signature = (data, /, **contextkw))]
+    fn parse_prefix(&self, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<(PyObject, usize)> {
+        let _guard = self.enter()?;
+        parse_prefix_in(py, self.subcon(py)?, &data, 0, context_from_kwargs(py, contextkw)?)
+    }
+
+    #[pyo3(signature = (data, /, **contextkw))]
+    fn parse(&self, py: Python<'_>, data: Input, contextkw: Option<&PyDict>) -> PyResult<PyObject> {
+        Ok(self.parse_prefix(py, data, contextkw)?.0)
+    }
+
+    #[pyo3(signature = (obj, /, **contextkw))]
+    fn build<'py>(&self, py: Python<'py>, obj: &PyAny, contextkw: Option<&'py PyDict>) -> PyResult<&'py PyBytes> {
+        let _guard = self.enter()?;
+        Ok(PyBytes::new(py, &build_in(self.subcon(py)?, obj, context_from_kwargs(py, contextkw)?)?))
+    }
+
+    /// Size of the bound subcon. A subcon that contains itself has no fixed size.
+    fn sizeof(&self, py: Python<'_>) -> PyResult<usize> {
+        if self.sizing.replace(true) {
+            return Err(ConstructError::SizeofError.into());
+        }
+        let size = self.subcon(py).and_then(|subcon| subcon.call_method0("sizeof")?.extract());
+        self.sizing.set(false);
+        size
+    }
+}
+
+// ========================= Schemas ===================================
+
+/// Description of a construct tree, independent of the Python objects implementing it.
+/// Used to generate code from a schema and to convert it between formats.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Schema {
+    FormatField { endian: char, format: char },
+    BytesInteger { length: usize, signed: bool, swapped: bool },
+    BitsInteger { length: usize, signed: bool, swapped: bool },
+    PaddedString { length: usize, encoding: String },
+    PascalString { lengthfield: Box<Schema>, encoding: String },
+    CString { encoding: String },
+    GreedyString { encoding: String },
+    Bytes { length: SchemaParam, zerocopy: bool },
+    GreedyBytes { zerocopy: bool },
+    Sequence(Vec<SchemaField>),
+    LazyStruct(Vec<SchemaField>),
+    LazyArray { count: usize, subcon: Box<Schema> },
+    Lazy(Box<Schema>),
+    Hex(Box<Schema>),
+    HexDump(Box<Schema>),
+    ByteSwapped(Box<Schema>),
+    BitsSwapped(Box<Schema>),
+    ProcessXor { pad: Vec<u8>, subcon: Box<Schema> },
+    Struct(Vec<SchemaField>),
+    Array { count: SchemaParam, subcon: Box<Schema> },
+    GreedyRange(Box<Schema>),
+    RepeatUntil { predicate: String, subcon: Box<Schema> },
+    Switch { key: String, cases: Vec<(SchemaKey, Schema)>, default: Option<Box<Schema>> },
+    If { condition: SchemaParam, subcon: Box<Schema> },
+    Computed(SchemaParam),
+    FixedSized { length: SchemaParam, subcon: Box<Schema> },
+    Pointer { offset: SchemaParam, subcon: Box<Schema> },
+    Enum { mapping: Vec<(String, i128)>, subcon: Box<Schema> },
+}
+
+/// Parameter given either as a constant or as an expression over the context.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SchemaParam {
+    Const(i128),
+    Expr(String),
+}
+
+/// Value a `Switch` case is selected by.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SchemaKey {
+    Int(i128),
+    Str(String),
+}
+
+/// A member of a composite schema, optionally named.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SchemaField {
+    pub name: Option<String>,
+    pub schema: Schema,
+}
+
+/// Error pointing at the part of a schema that could not be described or processed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SchemaError {
+    pub path: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+impl From<SchemaError> for PyErr {
+    fn from(err: SchemaError) -> PyErr {
+        exceptions::ConstructError::new_err(err.to_string())
+    }
+}
+
+/// Location within a schema, rendered like `(schema) -> header -> [2]`.
+#[derive(Clone)]
+struct SchemaPath(Vec<String>);
+
+impl SchemaPath {
+    fn root() -> Self {
+        SchemaPath(vec!["(schema)".to_string()])
+    }
+
+    fn join(&self, segment: impl Into<String>) -> Self {
+        let mut path = self.0.clone();
+        path.push(segment.into());
+        SchemaPath(path)
+    }
+
+    fn member(&self, index: usize, name: Option<&str>) -> Self {
+        self.join(name.map_or_else(|| format!("[{}]", index), str::to_string))
+    }
+
+    fn error(&self, message: impl Into<String>) -> SchemaError {
+        SchemaError { path: self.0.join(" -> "), message: message.into() }
+    }
+}
+
+impl Schema {
+    /// Describe a tree of construct-rs constructs.
+    pub fn from_construct(construct: &PyAny) -> Result<Schema, SchemaError> {
+        Schema::describe(construct, &SchemaPath::root())
+    }
+
+    fn describe(obj: &PyAny, path: &SchemaPath) -> Result<Schema, SchemaError> {
+        let py = obj.py();
+        let inner = |path: &SchemaPath| -> Result<Box<Schema>, SchemaError> {
+            let subcon = obj.downcast::<PyCell<Subconstruct>>().map_err(|_| path.error("expected a subconstruct"))?;
+            let subcon = subcon.borrow().subcon.clone_ref(py);
+            Ok(Box::new(Schema::describe(subcon.as_ref(py), path)?))
+        };
+        let members = |members: &[Member]| -> Result<Vec<SchemaField>, SchemaError> {
+            members
+                .iter()
+                .enumerate()
+                .map(|(i, m)| {
+                    let schema = Schema::describe(m.subcon.as_ref(py), &path.member(i, m.name.as_deref()))?;
+                    Ok(SchemaField { name: m.name.clone(), schema })
+                })
+                .collect()
+        };
+        if let Ok(c) = obj.downcast::<PyCell<FormatField>>() {
+            let c = c.borrow();
+            return Ok(Schema::FormatField { endian: c.endian, format: c.format });
+        }
+        if let Ok(c) = obj.downcast::<PyCell<BytesInteger>>() {
+            let c = c.borrow();
+            return Ok(Schema::BytesInteger { length: c.length, signed: c.signed, swapped: c.swapped });
+        }
+        if let Ok(c) = obj.downcast::<PyCell<BitsInteger>>() {
+            let c = c.borrow();
+            return Ok(Schema::BitsInteger { length: c.length, signed: c.signed, swapped: c.swapped });
+        }
+        if let Ok(c) = obj.downcast::<PyCell<PaddedString>>() {
+            let c = c.borrow();
+            return Ok(Schema::PaddedString { length: c.length, encoding: c.encoding.clone() });
+        }
+        if let Ok(c) = obj.downcast::<PyCell<PascalString>>() {
+            let c = c.borrow();
+            let lengthfield = Schema::describe(c.lengthfield.as_ref(py), &path.join("lengthfield"))?;
+            return Ok(Schema::PascalString { lengthfield: Box::new(lengthfield), encoding: c.encoding.clone() });
+        }
+        if let Ok(c) = obj.downcast::<PyCell<CString>>() {
+            return Ok(Schema::CString { encoding: c.borrow().encoding.clone() });
+        }
+        if let Ok(c) = obj.downcast::<PyCell<GreedyString>>() {
+            return Ok(Schema::GreedyString { encoding: c.borrow().encoding.clone() });
+        }
+        if let Ok(c) = obj.downcast::<PyCell<Bytes>>() {
+            let c = c.borrow();
+            let length = SchemaParam::describe(c.length.as_ref(py), &path.join("length"))?;
+            return Ok(Schema::Bytes { length, zerocopy: c.zerocopy });
+        }
+        if let Ok(c) = obj.downcast::<PyCell<GreedyBytes>>() {
+            return Ok(Schema::GreedyBytes { zerocopy: c.borrow().zerocopy });
+        }
+        if let Ok(c) = obj.downcast::<PyCell<Renamed>>() {
+            // Names are described by the composite the subcon is a member of.
+            if c.borrow().parsed.is_some() {
+                return Err(path.join("parsed").error("parsed hooks cannot be described"));
+            }
+            return Ok(*inner(path)?);
+        }
+        if let Ok(c) = obj.downcast::<PyCell<Sequence>>() {
+            return Ok(Schema::Sequence(members(&c.borrow().subcons)?));
+        }
+        if let Ok(c) = obj.downcast::<PyCell<LazyStruct>>() {
+            return Ok(Schema::LazyStruct(members(&c.borrow().subcons)?));
+        }
+        if let Ok(c) = obj.downcast::<PyCell<LazyArray>>() {
+            let count = c.borrow().count.clone_ref(py);
+            let count = count
+                .extract::<usize>(py)
+                .map_err(|_| path.join("count").error("only constant counts can be described"))?;
+            return Ok(Schema::LazyArray { count, subcon: inner(path)? });
+        }
+        if let Ok(c) = obj.downcast::<PyCell<ProcessXor>>() {
+            let padfunc = c.borrow().padfunc.clone_ref(py);
+            let pad = match padfunc.as_ref(py) {
+                pad if pad.is_callable() => return Err(path.join("padfunc").error("only constant pads can be described")),
+                pad => xor_pad(pad).map_err(|_| path.join("padfunc").error("expected bytes or an integer"))?,
+            };
+            return Ok(Schema::ProcessXor { pad, subcon: inner(path)? });
+        }
+        if let Ok(c) = obj.downcast::<PyCell<Struct>>() {
+            return Ok(Schema::Struct(members(&c.borrow().subcons)?));
+        }
+        if let Ok(c) = obj.downcast::<PyCell<Array>>() {
+            let count = SchemaParam::describe(c.borrow().count.as_ref(py), &path.join("count"))?;
+            return Ok(Schema::Array { count, subcon: inner(path)? });
+        }
+        if obj.downcast::<PyCell<GreedyRange>>().is_ok() {
+            return Ok(Schema::GreedyRange(inner(path)?));
+        }
+        if let Ok(c) = obj.downcast::<PyCell<RepeatUntil>>() {
+            let predicate = match SchemaParam::describe(c.borrow().predicate.as_ref(py), &path.join("predicate"))? {
+                SchemaParam::Expr(source) => source,
+                SchemaParam::Const(_) => return Err(path.join("predicate").error("expected an expression")),
+            };
+            return Ok(Schema::RepeatUntil { predicate, subcon: inner(path)? });
+        }
+        if let Ok(c) = obj.downcast::<PyCell<Switch>>() {
+            let c = c.borrow();
+            let key = match SchemaParam::describe(c.keyfunc.as_ref(py), &path.join("keyfunc"))? {
+                SchemaParam::Expr(source) => source,
+                SchemaParam::Const(value) => value.to_string(),
+            };
+            let mut cases = Vec::new();
+            for (key, subcon) in c.cases.as_ref(py).iter() {
+                let casepath = path.join("cases").join(key.to_string());
+                let key = match (key.extract::<i128>(), key.extract::<String>()) {
+                    (Ok(value), _) if !key.is_instance_of::<pyo3::types::PyBool>() => SchemaKey::Int(value),
+                    (_, Ok(name)) if ksy_int_key(&name).is_none() => SchemaKey::Str(name),
+                    _ => return Err(casepath.error("case keys must be integers or non-numeric strings")),
+                };
+                cases.push((key, Schema::describe(subcon, &casepath)?));
+            }
+            let default = match &c.default {
+                Some(default) => Some(Box::new(Schema::describe(default.as_ref(py), &path.join("default"))?)),
+                None => None,
+            };
+            return Ok(Schema::Switch { key, cases, default });
+        }
+        if let Ok(c) = obj.downcast::<PyCell<If>>() {
+            let condition = SchemaParam::describe(c.borrow().condfunc.as_ref(py), &path.join("condfunc"))?;
+            return Ok(Schema::If { condition, subcon: inner(path)? });
+        }
+        if let Ok(c) = obj.downcast::<PyCell<Computed>>() {
+            return Ok(Schema::Computed(SchemaParam::describe(c.borrow().func.as_ref(py), &path.join("func"))?));
+        }
+        if let Ok(c) = obj.downcast::<PyCell<FixedSized>>() {
+            let length = SchemaParam::describe(c.borrow().length.as_ref(py), &path.join("length"))?;
+            return Ok(Schema::FixedSized { length, subcon: inner(path)? });
+        }
+        if let Ok(c) = obj.downcast::<PyCell<Pointer>>() {
+            let offset = SchemaParam::describe(c.borrow().offset.as_ref(py), &path.join("offset"))?;
+            return Ok(Schema::Pointer { offset, subcon: inner(path)? });
+        }
+        if let Ok(c) = obj.downcast::<PyCell<Enum>>() {
+            let mut mapping = Vec::new();
+            for (name, value) in c.borrow().encmapping.as_ref(py).iter() {
+                let entry = name.extract::<String>().ok().zip(value.extract::<i128>().ok());
+                mapping.push(entry.ok_or_else(|| path.join("mapping").error("expected names mapped to integers"))?);
+            }
+            return Ok(Schema::Enum { mapping, subcon: inner(path)? });
+        }
+        if obj.downcast::<PyCell<Lazy>>().is_ok() {
+            return Ok(Schema::Lazy(inner(path)?));
+        }
+        if obj.downcast::<PyCell<Hex>>().is_ok() {
+            return Ok(Schema::Hex(inner(path)?));
+        }
+        if obj.downcast::<PyCell<HexDump>>().is_ok() {
+            return Ok(Schema::HexDump(inner(path)?));
+        }
+        if obj.downcast::<PyCell<ByteSwapped>>().is_ok() {
+            return Ok(Schema::ByteSwapped(inner(path)?));
+        }
+        if obj.downcast::<PyCell<BitsSwapped>>().is_ok() {
+            return Ok(Schema::BitsSwapped(inner(path)?));
+        }
+        let name = obj.get_type().name().map(|n| n.to_string()).unwrap_or_default();
+        Err(path.error(format!("{} cannot be described by a schema", name)))
+    }
+
+    /// Size in bytes, or `None` when the size is dynamic.
+    pub fn sizeof(&self) -> Option<usize> {
+        match self {
+            Schema::FormatField { format, .. } => match format {
+                'b' | 'B' => Some(1),
+                'h' | 'H' => Some(2),
+                'l' | 'L' | 'f' => Some(4),
+                _ => Some(8),
+            },
+            Schema::BytesInteger { length, .. } | Schema::BitsInteger { length, .. } => Some(*length),
+            Schema::PaddedString { length, .. } => Some(*length),
+            Schema::PascalString { .. } | Schema::CString { .. } | Schema::GreedyString { .. } => None,
+            Schema::Bytes { length: SchemaParam::Const(length), .. } => usize::try_from(*length).ok(),
+            Schema::Bytes { .. } | Schema::GreedyBytes { .. } => None,
+            Schema::Sequence(fields) | Schema::LazyStruct(fields) | Schema::Struct(fields) => {
+                fields.iter().map(|f| f.schema.sizeof()).sum()
+            }
+            Schema::LazyArray { count, subcon } => subcon.sizeof().map(|size| size * count),
+            Schema::Array { count: SchemaParam::Const(count), subcon } => {
+                subcon.sizeof().zip(usize::try_from(*count).ok()).map(|(size, count)| size * count)
+            }
+            Schema::FixedSized { length: SchemaParam::Const(length), .. } => usize::try_from(*length).ok(),
+            Schema::Computed(_) | Schema::Pointer { .. } => Some(0),
+            Schema::Array { .. }
+            | Schema::FixedSized { .. }
+            | Schema::GreedyRange(_)
+            | Schema::RepeatUntil { .. }
+            | Schema::Switch { .. }
+            | Schema::If { .. } => None,
+            Schema::Lazy(subcon)
+            | Schema::Hex(subcon)
+            | Schema::HexDump(subcon)
+            | Schema::ByteSwapped(subcon)
+            | Schema::BitsSwapped(subcon)
+            | Schema::ProcessXor { subcon, .. }
+            | Schema::Enum { subcon, .. } => subcon.sizeof(),
+        }
+    }
+
+    /// Name of the construct class the schema describes.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Schema::FormatField { .. } => "FormatField",
+            Schema::BytesInteger { .. } => "BytesInteger",
+            Schema::BitsInteger { .. } => "BitsInteger",
+            Schema::PaddedString { .. } => "PaddedString",
+            Schema::PascalString { .. } => "PascalString",
+            Schema::CString { .. } => "CString",
+            Schema::GreedyString { .. } => "GreedyString",
+            Schema::Bytes { .. } => "Bytes",
+            Schema::GreedyBytes { .. } => "GreedyBytes",
+            Schema::Sequence(_) => "Sequence",
+            Schema::LazyStruct(_) => "LazyStruct",
+            Schema::LazyArray { .. } => "LazyArray",
+            Schema::Lazy(_) => "Lazy",
+            Schema::Hex(_) => "Hex",
+            Schema::HexDump(_) => "HexDump",
+            Schema::ByteSwapped(_) => "ByteSwapped",
+            Schema::BitsSwapped(_) => "BitsSwapped",
+            Schema::ProcessXor { .. } => "ProcessXor",
+            Schema::Struct(_) => "Struct",
+            Schema::Array { .. } => "Array",
+            Schema::GreedyRange(_) => "GreedyRange",
+            Schema::RepeatUntil { .. } => "RepeatUntil",
+            Schema::Switch { .. } => "Switch",
+            Schema::If { .. } => "If",
+            Schema::Computed(_) => "Computed",
+            Schema::FixedSized { .. } => "FixedSized",
+            Schema::Pointer { .. } => "Pointer",
+            Schema::Enum { .. } => "Enum",
+        }
+    }
+}
+
+impl SchemaParam {
+    /// Describe a constant integer or an `Expr`; other callables cannot be described.
+    fn describe(param: &PyAny, path: &SchemaPath) -> Result<SchemaParam, SchemaError> {
+        if let Ok(expr) = param.downcast::<PyCell<Expr>>() {
+            return Ok(SchemaParam::Expr(expr.borrow().source.clone()));
+        }
+        if let Ok(value) = param.downcast::<pyo3::types::PyBool>() {
+            return Ok(SchemaParam::Expr(value.is_true().to_string()));
+        }
+        param.extract::<i128>().map(SchemaParam::Const).map_err(|_| path.error("only integers and expressions can be described"))
+    }
+}
+
+// ========================= Native parsing ============================
+
+/// Value parsed from a [`Schema`] without touching Python objects, materialized into
+/// Python values once the GIL is held again.
+#[derive(Clone, Debug, PartialEq)]
+enum NativeValue {
+    None,
+    Bool(bool),
+    Int(i128),
+    Float(f64),
+    Str(String),
+    List(Vec<NativeValue>),
+    ListContainer(Vec<NativeValue>),
+    Container(Vec<(String, NativeValue)>),
+    /// Parsed by `Bytes` or `GreedyBytes`, materialized as a `memoryview` with `zerocopy`.
+    Bytes { data: NativeBytes, zerocopy: bool },
+}
+
+/// Contents of parsed bytes: a range of the input, unless they were parsed from data
+/// processed first.
+#[derive(Clone, Debug, PartialEq)]
+enum NativeBytes {
+    Input(Range<usize>),
+    Owned(Vec<u8>),
+}
+
+impl NativeBytes {
+    fn len(&self) -> usize {
+        match self {
+            NativeBytes::Input(range) => range.len(),
+            NativeBytes::Owned(data) => data.len(),
+        }
+    }
+}
+
+/// Why a native parse gave up. `Failed` is only returned where the Python-facing
+/// implementation raises as well, so `GreedyRange` can stop on it. `Short` fails the
+/// same way, for data ending before the offset into the input it holds. `Unsupported`
+/// means the native parser cannot tell, and the Python-facing implementation has to.
+#[derive(Debug)]
+enum NativeError {
+    Failed,
+    Short(usize),
+    Unsupported,
+}
+
+type NativeResult<T> = Result<T, NativeError>;
+
+/// A context dictionary: a frame, together with the `_index` an array set in its copy.
+#[derive(Clone, Copy)]
+struct NativeScope {
+    frame: usize,
+    index: Option<usize>,
+}
+
+/// The context of a `Struct`, or the one passed as keyword arguments, with `_`, `_root`
+/// and `_params` referring to other frames.
+#[derive(Clone, Default)]
+struct NativeFrame {
+    entries: Vec<(String, NativeValue)>,
+    parent: Option<NativeScope>,
+    root: Option<usize>,
+    params: Option<usize>,
+}
+
+/// Result of an expression, which can also be a context referred to by `_parent` or `_root`.
+enum NativeOperand<'a> {
+    Value(Cow<'a, NativeValue>),
+    Scope(NativeScope),
+}
+
+impl NativeValue {
+    /// Convert a value found in a context, failing for objects whose identity or type
+    /// would not survive being materialized again.
+    fn from_py(obj: &PyAny, depth: usize) -> NativeResult<NativeValue> {
+        if depth > 64 {
+            return Err(NativeError::Unsupported);
+        }
+        if obj.is_none() {
+            return Ok(NativeValue::None);
+        }
+        if obj.is_exact_instance_of::<pyo3::types::PyBool>() {
+            return Ok(NativeValue::Bool(obj.is_truthy().map_err(|_| NativeError::Unsupported)?));
+        }
+        if obj.is_exact_instance_of::<pyo3::types::PyLong>() {
+            return obj.extract().map(NativeValue::Int).map_err(|_| NativeError::Unsupported);
+        }
+        if obj.is_exact_instance_of::<pyo3::types::PyFloat>() {
+            return obj.extract().map(NativeValue::Float).map_err(|_| NativeError::Unsupported);
+        }
+        if obj.is_exact_instance_of::<PyString>() {
+            return obj.extract().map(NativeValue::Str).map_err(|_| NativeError::Unsupported);
+        }
+        if ListContainer::type_object(obj.py()).is_ok_and(|class| obj.get_type().is(class)) {
+            let items = obj.iter().map_err(|_| NativeError::Unsupported)?;
+            return items
+                .map(|item| NativeValue::from_py(item.map_err(|_| NativeError::Unsupported)?, depth + 1))
+                .collect::<NativeResult<_>>()
+                .map(NativeValue::ListContainer);
+        }
+        if obj.is_exact_instance_of::<Container>() {
+            let dict = obj.downcast::<PyDict>().map_err(|_| NativeError::Unsupported)?;
+            let mut entries = Vec::with_capacity(dict.len());
+            for (key, value) in dict.iter() {
+                let key = key.extract::<String>().map_err(|_| NativeError::Unsupported)?;
+                entries.push((key, NativeValue::from_py(value, depth + 1)?));
+            }
+            return Ok(NativeValue::Container(entries));
+        }
+        Err(NativeError::Unsupported)
+    }
+
+    /// Python value, with bytes taken from `input`, the data the value was parsed from.
+    fn to_object(&self, py: Python, input: &Input) -> PyResult<PyObject> {
+        Ok(match self {
+            NativeValue::None => py.None(),
+            NativeValue::Bool(value) => value.into_py(py),
+            NativeValue::Int(value) => value.into_py(py),
+            NativeValue::Float(value) => value.into_py(py),
+            NativeValue::Str(value) => value.into_py(py),
+            NativeValue::List(items) => {
+                let items = items.iter().map(|item| item.to_object(py, input)).collect::<PyResult<Vec<_>>>()?;
+                PyList::new(py, items).into()
+            }
+            NativeValue::ListContainer(items) => {
+                let items = items.iter().map(|item| item.to_object(py, input)).collect::<PyResult<_>>()?;
+                ListContainer::from_items(py, items)?.into_py(py)
+            }
+            NativeValue::Container(entries) => {
+                let obj = Container::empty(py)?;
+                for (name, value) in entries {
+                    obj.set_item(name, value.to_object(py, input)?)?;
+                }
+                obj.into()
+            }
+            NativeValue::Bytes { data: NativeBytes::Input(range), zerocopy: true } => {
+                input.view(py, range.clone())?.into()
+            }
+            NativeValue::Bytes { data, zerocopy } => {
+                let data = match data {
+                    NativeBytes::Input(range) => PyBytes::new(py, &input.as_bytes()[range.clone()]),
+                    NativeBytes::Owned(data) => PyBytes::new(py, data),
+                };
+                match zerocopy {
+                    true => PyMemoryView::from(data)?.into(),
+                    false => data.into(),
+                }
+            }
+        })
+    }
+
+    fn is_true(&self) -> bool {
+        match self {
+            NativeValue::None => false,
+            NativeValue::Bool(value) => *value,
+            NativeValue::Int(value) => *value != 0,
+            NativeValue::Float(value) => *value != 0.0,
+            NativeValue::Str(value) => !value.is_empty(),
+            NativeValue::List(items) | NativeValue::ListContainer(items) => !items.is_empty(),
+            NativeValue::Container(entries) => !entries.is_empty(),
+            NativeValue::Bytes { data, .. } => data.len() != 0,
+        }
+    }
+
+    /// Numeric value, with booleans counting as integers only when `bools` is set.
+    fn number(&self, bools: bool) -> Option<NativeValue> {
+        match self {
+            NativeValue::Int(_) | NativeValue::Float(_) => Some(self.clone()),
+            NativeValue::Bool(value) if bools => Some(NativeValue::Int(*value as i128)),
+            _ => None,
+        }
+    }
+}
+
+/// Set a member the way a dictionary does, keeping the position of an existing key.
+fn native_set(entries: &mut Vec<(String, NativeValue)>, name: &str, value: NativeValue) {
+    match entries.iter_mut().find(|(key, _)| key == name) {
+        Some(entry) => entry.1 = value,
+        None => entries.push((name.to_string(), value)),
+    }
+}
+
+fn native_entry<'a>(entries: &'a [(String, NativeValue)], key: &str) -> NativeResult<&'a NativeValue> {
+    entries.iter().find(|(name, _)| name == key).map(|(_, value)| value).ok_or(NativeError::Unsupported)
+}
+
+/// Member of a parsed container, like [`expr_member`] does.
+fn native_member<'a>(obj: Cow<'a, NativeValue>, key: &str) -> NativeResult<Cow<'a, NativeValue>> {
+    match obj {
+        Cow::Borrowed(NativeValue::Container(entries)) => native_entry(entries, key).map(Cow::Borrowed),
+        Cow::Owned(NativeValue::Container(entries)) => {
+            let (_, value) = entries.into_iter().find(|(name, _)| name == key).ok_or(NativeError::Unsupported)?;
+            Ok(Cow::Owned(value))
+        }
+        _ => Err(NativeError::Unsupported),
+    }
+}
+
+/// Integer as a float, provided the conversion is exact.
+fn native_float(value: i128) -> NativeResult<f64> {
+    match value.unsigned_abs() <= 1 << 53 {
+        true => Ok(value as f64),
+        false => Err(NativeError::Unsupported),
+    }
+}
+
+/// Both operands as floats, when at least one of them is a float.
+fn native_floats(lhs: &NativeValue, rhs: &NativeValue) -> NativeResult<Option<(f64, f64)>> {
+    let float = |value: &NativeValue| match value {
+        NativeValue::Int(i) => native_float(*i),
+        NativeValue::Float(f) => Ok(*f),
+        _ => Err(NativeError::Unsupported),
+    };
+    match (lhs, rhs) {
+        (NativeValue::Int(_), NativeValue::Int(_)) => Ok(None),
+        _ => Ok(Some((float(lhs)?, float(rhs)?))),
+    }
+}
+
+/// Python's `==`, for the values whose equality is known without Python.
+fn native_eq(lhs: &NativeValue, rhs: &NativeValue) -> NativeResult<bool> {
+    if let (Some(lhs), Some(rhs)) = (lhs.number(true), rhs.number(true)) {
+        return Ok(match native_floats(&lhs, &rhs)? {
+            Some((lhs, rhs)) => lhs == rhs,
+            None => lhs == rhs,
+        });
+    }
+    match (lhs, rhs) {
+        (NativeValue::Container(_) | NativeValue::ListContainer(_) | NativeValue::Bytes { .. }, _)
+        | (_, NativeValue::Container(_) | NativeValue::ListContainer(_) | NativeValue::Bytes { .. }) => Err(NativeError::Unsupported),
+        (NativeValue::List(lhs), NativeValue::List(rhs)) => {
+            if lhs.len() != rhs.len() {
+                return Ok(false);
+            }
+            for (lhs, rhs) in lhs.iter().zip(rhs) {
+                if !native_eq(lhs, rhs)? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+        _ => Ok(lhs == rhs),
+    }
+}
+
+/// Python's ordering of numbers and of strings.
+fn native_cmp(lhs: &NativeValue, rhs: &NativeValue) -> NativeResult<Option<std::cmp::Ordering>> {
+    if let (Some(lhs), Some(rhs)) = (lhs.number(true), rhs.number(true)) {
+        return Ok(match (native_floats(&lhs, &rhs)?, lhs, rhs) {
+            (Some((lhs, rhs)), ..) => lhs.partial_cmp(&rhs),
+            (None, NativeValue::Int(lhs), NativeValue::Int(rhs)) => Some(lhs.cmp(&rhs)),
+            _ => None,
+        });
+    }
+    match (lhs, rhs) {
+        (NativeValue::Str(lhs), NativeValue::Str(rhs)) => Ok(Some(lhs.cmp(rhs))),
+        _ => Err(NativeError::Unsupported),
+    }
+}
+
+/// Repeat a string or list like Python's `*`, within a sane size.
+fn native_repeat(value: &NativeValue, count: i128) -> NativeResult<NativeValue> {
+    let count = usize::try_from(count.max(0)).map_err(|_| NativeError::Unsupported)?;
+    let len = match value {
+        NativeValue::Str(s) => s.len(),
+        NativeValue::List(items) => items.len(),
+        _ => return Err(NativeError::Unsupported),
+    };
+    if len.saturating_mul(count) > 1 << 24 {
+        return Err(NativeError::Unsupported);
+    }
+    Ok(match value {
+        NativeValue::Str(s) => NativeValue::Str(s.repeat(count)),
+        NativeValue::List(items) => NativeValue::List(items.iter().cloned().cycle().take(len * count).collect()),
+        _ => unreachable!(),
+    })
+}
+
+/// Python's arithmetic and bitwise operators on integers, floats, strings and lists.
+fn native_binary(op: &str, lhs: &NativeValue, rhs: &NativeValue) -> NativeResult<NativeValue> {
+    use NativeValue::{Float, Int, List, Str};
+    let unsupported = || NativeError::Unsupported;
+    match (op, lhs, rhs) {
+        ("+", Str(lhs), Str(rhs)) => return Ok(Str(format!("{}{}", lhs, rhs))),
+        ("+", List(lhs), List(rhs)) => return Ok(List([lhs.as_slice(), rhs.as_slice()].concat())),
+        ("*", Str(_) | List(_), Int(count)) => return native_repeat(lhs, *count),
+        ("*", Int(count), Str(_) | List(_)) => return native_repeat(rhs, *count),
+        _ => {}
+    }
+    let (Some(_), Some(_)) = (lhs.number(false), rhs.number(false)) else { return Err(unsupported()) };
+    if let Some((a, b)) = native_floats(lhs, rhs)? {
+        return Ok(Float(match op {
+            "+" => a + b,
+            "-" => a - b,
+            "*" => a * b,
+            "/" if b != 0.0 => a / b,
+            "%" if b != 0.0 => {
+                let r = a % b;
+                if r != 0.0 && (r < 0.0) != (b < 0.0) { r + b } else { r.abs().copysign(b) }
+            }
+            _ => return Err(unsupported()),
+        }));
+    }
+    let (Int(a), Int(b)) = (lhs, rhs) else { return Err(unsupported()) };
+    let (a, b) = (*a, *b);
+    let value = match op {
+        "+" => a.checked_add(b),
+        "-" => a.checked_sub(b),
+        "*" => a.checked_mul(b),
+        "/" => a.checked_div(b).map(|q| if a % b != 0 && (a < 0) != (b < 0) { q - 1 } else { q }),
+        "%" => a.checked_rem(b).map(|r| if r != 0 && (r < 0) != (b < 0) { r + b } else { r }),
+        "<<" if b >= 0 => match u32::try_from(b) {
+            Ok(shift) if shift < 127 => a.checked_shl(shift).filter(|value| value >> shift == a),
+            _ => (a == 0).then_some(0),
+        },
+        ">>" if b >= 0 => Some(a >> b.min(127)),
+        "&" => Some(a & b),
+        "|" => Some(a | b),
+        "^" => Some(a ^ b),
+        _ => None,
+    };
+    value.map(Int).ok_or_else(unsupported)
+}
+
+impl ExprNode {
+    /// Evaluate like [`ExprNode::eval`], without Python.
+    fn eval_native<'a>(
+        &self,
+        frames: &'a [NativeFrame],
+        scope: NativeScope,
+        item: Option<&'a NativeValue>,
+    ) -> NativeResult<NativeOperand<'a>> {
+        let value = |node: &ExprNode| node.eval_native(frames, scope, item)?.into_value();
+        let owned = |value: NativeValue| Ok(NativeOperand::Value(Cow::Owned(value)));
+        match self {
+            ExprNode::Int(i) => owned(NativeValue::Int(*i)),
+            ExprNode::Float(f) => owned(NativeValue::Float(*f)),
+            ExprNode::Str(s) | ExprNode::EnumRef(s) => owned(NativeValue::Str(s.clone())),
+            ExprNode::Bool(b) => owned(NativeValue::Bool(*b)),
+            ExprNode::List(items) => {
+                let items = items.iter().map(|node| Ok(value(node)?.into_owned())).collect::<NativeResult<_>>()?;
+                owned(NativeValue::List(items))
+            }
+            ExprNode::Name(name) if name == "_" => Ok(NativeOperand::Value(Cow::Borrowed(item.ok_or(NativeError::Unsupported)?))),
+            ExprNode::Name(name) => native_lookup(frames, scope, if name == "_parent" { "_" } else { name }),
+            ExprNode::Attr(obj, name) => {
+                let key = if name == "_parent" { "_" } else { name.as_str() };
+                match obj.eval_native(frames, scope, item)? {
+                    NativeOperand::Scope(scope) => native_lookup(frames, scope, key),
+                    NativeOperand::Value(obj) if matches!(*obj, NativeValue::Container(_)) => {
+                        Ok(NativeOperand::Value(native_member(obj, key)?))
+                    }
+                    NativeOperand::Value(obj) => owned(native_method(&obj, name)?),
+                }
+            }
+            ExprNode::Index(obj, index) => {
+                let index = value(index)?;
+                match (obj.eval_native(frames, scope, item)?, index.as_ref()) {
+                    (NativeOperand::Scope(scope), NativeValue::Str(key)) => native_lookup(frames, scope, key),
+                    (NativeOperand::Value(obj), NativeValue::Str(key)) => Ok(NativeOperand::Value(native_member(obj, key)?)),
+                    (NativeOperand::Value(obj), NativeValue::Int(index)) => Ok(NativeOperand::Value(native_index(obj, *index)?)),
+                    _ => Err(NativeError::Unsupported),
+                }
+            }
+            ExprNode::Unary("not", operand) => {
+                let operand = operand.eval_native(frames, scope, item)?;
+                owned(NativeValue::Bool(!operand.is_true(frames)))
+            }
+            ExprNode::Unary(op, operand) => match (*op, value(operand)?.as_ref()) {
+                ("-", NativeValue::Int(i)) => owned(NativeValue::Int(i.checked_neg().ok_or(NativeError::Unsupported)?)),
+                ("-", NativeValue::Float(f)) => owned(NativeValue::Float(-f)),
+                ("~", NativeValue::Int(i)) => owned(NativeValue::Int(!i)),
+                _ => Err(NativeError::Unsupported),
+            },
+            ExprNode::Binary("and", lhs, rhs) => {
+                let value = lhs.eval_native(frames, scope, item)?.is_true(frames)
+                    && rhs.eval_native(frames, scope, item)?.is_true(frames);
+                owned(NativeValue::Bool(value))
+            }
+            ExprNode::Binary("or", lhs, rhs) => {
+                let value = lhs.eval_native(frames, scope, item)?.is_true(frames)
+                    || rhs.eval_native(frames, scope, item)?.is_true(frames);
+                owned(NativeValue::Bool(value))
+            }
+            ExprNode::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (value(lhs)?, value(rhs)?);
+                let ordering = |test: fn(std::cmp::Ordering) -> bool| -> NativeResult<NativeValue> {
+                    Ok(NativeValue::Bool(native_cmp(&lhs, &rhs)?.is_some_and(test)))
+                };
+                owned(match *op {
+                    "==" => NativeValue::Bool(native_eq(&lhs, &rhs)?),
+                    "!=" => NativeValue::Bool(!native_eq(&lhs, &rhs)?),
+                    "<" => ordering(|o| o.is_lt())?,
+                    "<=" => ordering(|o| o.is_le())?,
+                    ">" => ordering(|o| o.is_gt())?,
+                    ">=" => ordering(|o| o.is_ge())?,
+                    op => native_binary(op, &lhs, &rhs)?,
+                })
+            }
+            ExprNode::Ternary(cond, then, otherwise) => match cond.eval_native(frames, scope, item)?.is_true(frames) {
+                true => then.eval_native(frames, scope, item),
+                false => otherwise.eval_native(frames, scope, item),
+            },
+        }
+    }
+}
+
+impl<'a> NativeOperand<'a> {
+    fn into_value(self) -> NativeResult<Cow<'a, NativeValue>> {
+        match self {
+            NativeOperand::Value(value) => Ok(value),
+            NativeOperand::Scope(_) => Err(NativeError::Unsupported),
+        }
+    }
+
+    fn is_true(&self, frames: &[NativeFrame]) -> bool {
+        match self {
+            NativeOperand::Value(value) => value.is_true(),
+            NativeOperand::Scope(scope) => {
+                let frame = &frames[scope.frame];
+                !frame.entries.is_empty()
+                    || frame.parent.is_some()
+                    || frame.root.is_some()
+                    || frame.params.is_some()
+                    || scope.index.is_some()
+            }
+        }
+    }
+}
+
+/// Look up a key of a context, like [`expr_member`] does.
+fn native_lookup<'a>(frames: &'a [NativeFrame], scope: NativeScope, key: &str) -> NativeResult<NativeOperand<'a>> {
+    let frame = &frames[scope.frame];
+    match (key, scope.index) {
+        ("_", _) => frame.parent.map(NativeOperand::Scope).ok_or(NativeError::Unsupported),
+        ("_root", _) => {
+            let root = frame.root.ok_or(NativeError::Unsupported)?;
+            Ok(NativeOperand::Scope(NativeScope { frame: root, index: None }))
+        }
+        ("_params", _) => {
+            let params = frame.params.ok_or(NativeError::Unsupported)?;
+            Ok(NativeOperand::Scope(NativeScope { frame: params, index: None }))
+        }
+        ("_index", Some(index)) => Ok(NativeOperand::Value(Cow::Owned(NativeValue::Int(index as i128)))),
+        _ => native_entry(&frame.entries, key).map(|value| NativeOperand::Value(Cow::Borrowed(value))),
+    }
+}
+
+/// Python's indexing of lists and strings.
+fn native_index<'a>(obj: Cow<'a, NativeValue>, index: i128) -> NativeResult<Cow<'a, NativeValue>> {
+    let position = |len: usize| {
+        let index = if index < 0 { index + len as i128 } else { index };
+        usize::try_from(index).ok().filter(|&index| index < len).ok_or(NativeError::Unsupported)
+    };
+    match obj {
+        Cow::Borrowed(NativeValue::List(items) | NativeValue::ListContainer(items)) => Ok(Cow::Borrowed(&items[position(items.len())?])),
+        Cow::Owned(NativeValue::List(mut items) | NativeValue::ListContainer(mut items)) => Ok(Cow::Owned(items.swap_remove(position(items.len())?))),
+        obj => match obj.as_ref() {
+            NativeValue::Str(s) => {
+                let chars: Vec<char> = s.chars().collect();
+                Ok(Cow::Owned(NativeValue::Str(chars[position(chars.len())?].to_string())))
+            }
+            _ => Err(NativeError::Unsupported),
+        },
+    }
+}
+
+/// Kaitai built-in methods, like [`expr_method`] does.
+fn native_method(obj: &NativeValue, name: &str) -> NativeResult<NativeValue> {
+    let unsupported = || NativeError::Unsupported;
+    Ok(match (name, obj) {
+        ("length" | "size", NativeValue::Str(s)) => NativeValue::Int(s.chars().count() as i128),
+        ("length" | "size", NativeValue::List(items) | NativeValue::ListContainer(items)) => NativeValue::Int(items.len() as i128),
+        ("length" | "size", NativeValue::Bytes { data, .. }) => NativeValue::Int(data.len() as i128),
+        ("to_i", NativeValue::Int(_)) => obj.clone(),
+        ("to_i", NativeValue::Bool(b)) => NativeValue::Int(*b as i128),
+        ("to_i", NativeValue::Float(f)) if f.is_finite() && f.abs() < 1e30 => NativeValue::Int(f.trunc() as i128),
+        ("to_i", NativeValue::Str(s)) => {
+            let s = s.trim();
+            let digits = s.strip_prefix(['+', '-']).unwrap_or(s);
+            if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(unsupported());
+            }
+            NativeValue::Int(s.parse().map_err(|_| unsupported())?)
+        }
+        ("to_s", NativeValue::Int(i)) => NativeValue::Str(i.to_string()),
+        ("to_s", NativeValue::Str(_)) => obj.clone(),
+        ("to_s", NativeValue::Bool(b)) => NativeValue::Str(if *b { "True" } else { "False" }.to_string()),
+        ("to_s", NativeValue::None) => NativeValue::Str("None".to_string()),
+        ("first", _) => native_index(Cow::Borrowed(obj), 0)?.into_owned(),
+        ("last", _) => native_index(Cow::Borrowed(obj), -1)?.into_owned(),
+        ("min" | "max", NativeValue::List(items) | NativeValue::ListContainer(items)) => {
+            let same_type = items.windows(2).all(|pair| std::mem::discriminant(&pair[0]) == std::mem::discriminant(&pair[1]));
+            if !same_type || !matches!(items.first(), Some(NativeValue::Int(_) | NativeValue::Str(_))) {
+                return Err(unsupported());
+            }
+            let order = |a: &&NativeValue, b: &&NativeValue| native_cmp(a, b).ok().flatten().unwrap_or(std::cmp::Ordering::Equal);
+            let found = match name {
+                "min" => items.iter().min_by(order),
+                _ => items.iter().max_by(order),
+            };
+            found.cloned().ok_or_else(unsupported)?
+        }
+        ("reverse", NativeValue::List(items)) => NativeValue::List(items.iter().rev().cloned().collect()),
+        ("reverse", NativeValue::ListContainer(items)) => NativeValue::ListContainer(items.iter().rev().cloned().collect()),
+        ("reverse", NativeValue::Str(s)) => NativeValue::Str(s.chars().rev().collect()),
+        _ => return Err(unsupported()),
+    })
+}
+
+/// Decode a string without Python, for the codecs whose behaviour is known exactly.
+fn native_decode(data: &[u8], encoding: &str) -> NativeResult<NativeValue> {
+    let units = |size: usize| match data.len() % size {
+        0 => Ok(data.chunks(size)),
+        _ => Err(NativeError::Failed),
+    };
+    let text = match encoding.replace('-', "_").to_lowercase().as_str() {
+        "ascii" if !data.is_ascii() => return Err(NativeError::Failed),
+        "ascii" | "utf8" | "utf_8" | "u8" => String::from_utf8(data.to_vec()).map_err(|_| NativeError::Failed)?,
+        "utf_16_le" => String::from_utf16(&units(2)?.map(|u| u16::from_le_bytes([u[0], u[1]])).collect::<Vec<_>>()).map_err(|_| NativeError::Failed)?,
+        "utf_16_be" => String::from_utf16(&units(2)?.map(|u| u16::from_be_bytes([u[0], u[1]])).collect::<Vec<_>>()).map_err(|_| NativeError::Failed)?,
+        enc @ ("utf_32_le" | "utf_32_be") => units(4)?
+            .map(|u| {
+                let u = [u[0], u[1], u[2], u[3]];
+                char::from_u32(if enc == "utf_32_le" { u32::from_le_bytes(u) } else { u32::from_be_bytes(u) })
+            })
+            .collect::<Option<String>>()
+            .ok_or(NativeError::Failed)?,
+        _ => return Err(NativeError::Unsupported),
+    };
+    Ok(NativeValue::Str(text))
+}
+
+/// The case of a `Switch` selected by `key`, as the Python-facing implementation
+/// compares them.
+fn native_case<'a>(key: &NativeValue, cases: &'a [(SchemaKey, Schema)], default: Option<&'a Schema>) -> NativeResult<Option<&'a Schema>> {
+    let matches = |case: &SchemaKey| match (case, key) {
+        (SchemaKey::Int(case), NativeValue::Int(_) | NativeValue::Bool(_) | NativeValue::Float(_)) => native_eq(&NativeValue::Int(*case), key),
+        (SchemaKey::Str(case), NativeValue::Str(key)) => Ok(case == key),
+        (_, NativeValue::List(_) | NativeValue::ListContainer(_) | NativeValue::Container(_) | NativeValue::Bytes { .. }) => {
+            Err(NativeError::Unsupported)
+        }
+        _ => Ok(false),
+    };
+    for (case, subcon) in cases {
+        if matches(case)? {
+            return Ok(Some(subcon));
+        }
+    }
+    Ok(default)
+}
+
+/// Value of a `FormatField` of the exact size.
+fn native_formatfield(endian: char, format: char, buf: &[u8]) -> NativeValue {
+    macro_rules! unpack {
+        ($t:ty) => {{
+            let bytes = buf.try_into().unwrap();
+            match endian {
+                '<' => <$t>::from_le_bytes(bytes),
+                '=' => <$t>::from_ne_bytes(bytes),
+                _ => <$t>::from_be_bytes(bytes),
+            }
+        }};
+    }
+    match format {
+        'B' => NativeValue::Int(unpack!(u8) as i128),
+        'b' => NativeValue::Int(unpack!(i8) as i128),
+        'H' => NativeValue::Int(unpack!(u16) as i128),
+        'h' => NativeValue::Int(unpack!(i16) as i128),
+        'L' => NativeValue::Int(unpack!(u32) as i128),
+        'l' => NativeValue::Int(unpack!(i32) as i128),
+        'Q' => NativeValue::Int(unpack!(u64) as i128),
+        'q' => NativeValue::Int(unpack!(i64) as i128),
+        'f' => NativeValue::Float(unpack!(f32) as f64),
+        'd' => NativeValue::Float(unpack!(f64)),
+        _ => NativeValue::Int(0),
+    }
+}
+
+/// Parses a [`Schema`] the way the constructs it describes parse, without Python.
+///
+/// Contexts live in a stack of frames, pushed by `Struct`s and popped once they are
+/// parsed. Expressions are compiled once per parse.
+#[derive(Default)]
+struct NativeParser<'s> {
+    frames: Vec<NativeFrame>,
+    exprs: HashMap<&'s str, ExprNode>,
+    /// Addresses of the data parsed, which parsed bytes are ranges of.
+    input: Range<usize>,
+}
+
+impl<'s> NativeParser<'s> {
+    fn new(input: &[u8]) -> Self {
+        let start = input.as_ptr() as usize;
+        NativeParser { input: start..start + input.len(), ..NativeParser::default() }
+    }
+
+    /// Error for `data` holding fewer than `length` bytes, which is `Short` when `data`
+    /// is part of the input.
+    fn short(&self, data: &[u8], length: usize) -> NativeError {
+        let start = data.as_ptr() as usize;
+        match self.input.start <= start && start <= self.input.end {
+            true => NativeError::Short(start - self.input.start + length),
+            false => NativeError::Failed,
+        }
+    }
+
+    /// The first `length` bytes of `data`.
+    fn take<'d>(&self, data: &'d [u8], length: usize) -> NativeResult<&'d [u8]> {
+        data.get(..length).ok_or_else(|| self.short(data, length))
+    }
+
+    /// Bytes parsed, referring to the input unless `data` was processed first.
+    fn bytes(&self, data: &[u8], zerocopy: bool) -> NativeValue {
+        let start = data.as_ptr() as usize;
+        let data = match self.input.contains(&start) && start + data.len() <= self.input.end {
+            true => NativeBytes::Input(start - self.input.start..start - self.input.start + data.len()),
+            false => NativeBytes::Owned(data.to_vec()),
+        };
+        NativeValue::Bytes { data, zerocopy }
+    }
+
+    /// Frames for a Python context, following `_`, `_root` and `_params` to the enclosing ones.
+    fn frame_from_py(&mut self, context: &PyDict, seen: &mut Vec<(usize, usize)>) -> NativeResult<usize> {
+        let key = context.as_ptr() as usize;
+        if let Some(&(_, frame)) = seen.iter().find(|(ptr, _)| *ptr == key) {
+            return Ok(frame);
+        }
+        let frame = self.frames.len();
+        self.frames.push(NativeFrame::default());
+        seen.push((key, frame));
+        for (name, value) in context.iter() {
+            let name = name.extract::<String>().map_err(|_| NativeError::Unsupported)?;
+            let outer = || value.downcast::<PyDict>().map_err(|_| NativeError::Unsupported);
+            match name.as_str() {
+                "_" => {
+                    let parent = self.frame_from_py(outer()?, seen)?;
+                    self.frames[frame].parent = Some(NativeScope { frame: parent, index: None });
+                }
+                "_root" => self.frames[frame].root = Some(self.frame_from_py(outer()?, seen)?),
+                "_params" => self.frames[frame].params = Some(self.frame_from_py(outer()?, seen)?),
+                _ => {
+                    let value = NativeValue::from_py(value, 0)?;
+                    self.frames[frame].entries.push((name, value));
+                }
+            }
+        }
+        Ok(frame)
+    }
+
+    /// Frames for the context of a parse call, which is its own `_params` unless passed on
+    /// by a composite, as [`context_from_kwargs`] makes it.
+    fn scope_from_py(&mut self, contextkw: Option<&PyDict>) -> NativeResult<NativeScope> {
+        let scope = match contextkw {
+            Some(context) => NativeScope { frame: self.frame_from_py(context, &mut Vec::new())?, index: None },
+            None => self.scope(None),
+        };
+        self.frames[scope.frame].params.get_or_insert(scope.frame);
+        Ok(scope)
+    }
+
+    /// The given context, or a new empty one for constructs parsed without one.
+    fn scope(&mut self, scope: Option<NativeScope>) -> NativeScope {
+        scope.unwrap_or_else(|| {
+            let frame = self.frames.len();
+            self.frames.push(NativeFrame { params: Some(frame), ..NativeFrame::default() });
+            NativeScope { frame, index: None }
+        })
+    }
+
+    /// New frame for the members of a `Struct` or `Sequence`, nested in the context of `scope`.
+    fn member_frame(&mut self, scope: Option<NativeScope>) -> NativeScope {
+        let outer = self.scope(scope);
+        let frame = self.frames.len();
+        let NativeFrame { root, params, .. } = self.frames[outer.frame];
+        self.frames.push(NativeFrame { entries: Vec::new(), parent: Some(outer), root: Some(root.unwrap_or(frame)), params });
+        NativeScope { frame, index: None }
+    }
+
+    fn eval(&mut self, source: &'s str, scope: NativeScope, item: Option<&NativeValue>) -> NativeResult<NativeValue> {
+        if !self.exprs.contains_key(source) {
+            let node = ExprNode::parse(source).map_err(|_| NativeError::Unsupported)?;
+            self.exprs.insert(source, node);
+        }
+        Ok(self.exprs[source].eval_native(&self.frames, scope, item)?.into_value()?.into_owned())
+    }
+
+    fn param(&mut self, param: &'s SchemaParam, scope: NativeScope) -> NativeResult<NativeValue> {
+        match param {
+            SchemaParam::Const(value) => Ok(NativeValue::Int(*value)),
+            SchemaParam::Expr(source) => self.eval(source, scope, None),
+        }
+    }
+
+    /// Integer parameter, as extracted into an `i64` by the Python-facing implementation.
+    fn int_param(&mut self, param: &'s SchemaParam, scope: NativeScope) -> NativeResult<i64> {
+        match self.param(param, scope)? {
+            NativeValue::Int(value) => i64::try_from(value).map_err(|_| NativeError::Unsupported),
+            _ => Err(NativeError::Unsupported),
+        }
+    }
+
+    /// Parse the leading bytes of `data`, like [`parse_prefix`] and [`parse_prefix_in`]
+    /// do, returning the value and the number of bytes consumed.
+    fn parse_prefix(&mut self, schema: &'s Schema, data: &[u8], scope: Option<NativeScope>) -> NativeResult<(NativeValue, usize)> {
+        let depth = self.frames.len();
+        let parsed = self.parse_prefix_frames(schema, data, scope);
+        self.frames.truncate(depth);
+        parsed
+    }
+
+    /// Parse a member of a composite, passing the context on when the member evaluates it.
+    fn parse_member(&mut self, schema: &'s Schema, data: &[u8], scope: NativeScope) -> NativeResult<(NativeValue, usize)> {
+        self.parse_prefix(schema, data, schema.is_contextual().then_some(scope))
+    }
+
+    fn parse_prefix_frames(&mut self, schema: &'s Schema, data: &[u8], scope: Option<NativeScope>) -> NativeResult<(NativeValue, usize)> {
+        match schema {
+            Schema::Struct(fields) => {
+                let scope = self.member_frame(scope);
+                let mut offset = 0;
+                for field in fields {
+                    let (value, consumed) = self.parse_member(&field.schema, &data[offset..], scope)?;
+                    if let Some(name) = &field.name {
+                        native_set(&mut self.frames[scope.frame].entries, name, value);
+                    }
+                    offset += consumed;
+                }
+                Ok((NativeValue::Container(std::mem::take(&mut self.frames[scope.frame].entries)), offset))
+            }
+            Schema::Sequence(fields) => {
+                let scope = self.member_frame(scope);
+                let mut items = Vec::with_capacity(fields.len());
+                let mut offset = 0;
+                for field in fields {
+                    let (value, consumed) = self.parse_member(&field.schema, &data[offset..], scope)?;
+                    if let Some(name) = &field.name {
+                        native_set(&mut self.frames[scope.frame].entries, name, value.clone());
+                    }
+                    items.push(value);
+                    offset += consumed;
+                }
+                Ok((NativeValue::ListContainer(items), offset))
+            }
+            Schema::Array { count, subcon } => {
+                let scope = self.scope(scope);
+                let count = usize::try_from(self.int_param(count, scope)?).map_err(|_| NativeError::Failed)?;
+                let mut items = Vec::with_capacity(count.min(data.len()));
+                let mut offset = 0;
+                for index in 0..count {
+                    let scope = NativeScope { index: Some(index), ..scope };
+                    let (item, consumed) = self.parse_member(subcon, &data[offset..], scope)?;
+                    items.push(item);
+                    offset += consumed;
+                }
+                Ok((NativeValue::ListContainer(items), offset))
+            }
+            Schema::GreedyRange(subcon) => {
+                let scope = self.scope(scope);
+                let mut items = Vec::new();
+                let mut offset = 0;
+                while offset < data.len() {
+                    let scope = NativeScope { index: Some(items.len()), ..scope };
+                    match self.parse_member(subcon, &data[offset..], scope) {
+                        Ok((_, 0)) | Err(NativeError::Failed | NativeError::Short(_)) => break,
+                        Ok((item, consumed)) => {
+                            items.push(item);
+                            offset += consumed;
+                        }
+                        Err(err) => return Err(err),
+                    }
+                }
+                Ok((NativeValue::ListContainer(items), offset))
+            }
+            Schema::RepeatUntil { predicate, subcon } => {
+                let scope = self.scope(scope);
+                let mut items = Vec::new();
+                let mut offset = 0;
+                loop {
+                    let scope = NativeScope { index: Some(items.len()), ..scope };
+                    let (item, consumed) = self.parse_member(subcon, &data[offset..], scope)?;
+                    offset += consumed;
+                    let done = self.eval(predicate, scope, Some(&item))?.is_true();
+                    items.push(item);
+                    if done {
+                        break;
+                    }
+                }
+                Ok((NativeValue::ListContainer(items), offset))
+            }
+            Schema::Switch { key, cases, default } => {
+                let scope = self.scope(scope);
+                let key = self.eval(key, scope, None)?;
+                match native_case(&key, cases, default.as_deref())? {
+                    Some(subcon) => self.parse_member(subcon, data, scope),
+                    None => Ok((NativeValue::None, 0)),
+                }
+            }
+            Schema::If { condition, subcon } => {
+                let scope = self.scope(scope);
+                match self.param(condition, scope)?.is_true() {
+                    true => self.parse_member(subcon, data, scope),
+                    false => Ok((NativeValue::None, 0)),
+                }
+            }
+            Schema::Computed(func) => {
+                let scope = self.scope(scope);
+                Ok((self.param(func, scope)?, 0))
+            }
+            Schema::Bytes { length, zerocopy } => {
+                let scope = self.scope(scope);
+                let length = usize::try_from(self.int_param(length, scope)?).map_err(|_| NativeError::Failed)?;
+                let content = self.take(data, length)?;
+                Ok((self.bytes(content, *zerocopy), length))
+            }
+            Schema::FixedSized { length, subcon } => {
+                let scope = self.scope(scope);
+                let length = usize::try_from(self.int_param(length, scope)?).map_err(|_| NativeError::Failed)?;
+                let window = self.take(data, length)?;
+                let (obj, _) = self.parse_member(subcon, window, scope)?;
+                Ok((obj, length))
+            }
+            Schema::Enum { mapping, subcon } => {
+                let scope = self.scope(scope);
+                let (obj, consumed) = self.parse_member(subcon, data, scope)?;
+                let obj = match obj {
+                    NativeValue::Int(value) => match mapping.iter().rev().find(|(_, mapped)| *mapped == value) {
+                        Some((name, _)) => NativeValue::Str(name.clone()),
+                        None => obj,
+                    },
+                    NativeValue::None | NativeValue::Str(_) => obj,
+                    _ => return Err(NativeError::Unsupported),
+                };
+                Ok((obj, consumed))
+            }
+            Schema::PascalString { lengthfield, encoding } => {
+                let (length, start) = self.parse_prefix(lengthfield, data, None)?;
+                let NativeValue::Int(length) = length else { return Err(NativeError::Unsupported) };
+                let length = usize::try_from(length).map_err(|_| NativeError::Unsupported)?;
+                let end = start.checked_add(length).ok_or(NativeError::Unsupported)?;
+                let content = &self.take(data, end)?[start..];
+                Ok((native_decode(content, encoding)?, end))
+            }
+            Schema::CString { encoding } => {
+                let pad = encoding_unit(encoding).map_err(|_| NativeError::Unsupported)?;
+                let Some(end) = data.chunks(pad.len()).position(|unit| unit == pad) else {
+                    // The terminator is one unit after the last whole one, at the earliest.
+                    return Err(self.short(data, (data.len() / pad.len() + 1) * pad.len()));
+                };
+                let end = end * pad.len();
+                Ok((native_decode(&data[..end], encoding)?, end + pad.len()))
+            }
+            // Reads data outside of the slices composites pass their members.
+            Schema::Pointer { .. } => Err(NativeError::Unsupported),
+            _ => match schema.native_sizeof()? {
+                Some(size) if size > data.len() => Err(self.short(data, size)),
+                Some(size) => Ok((self.parse_whole(schema, &data[..size])?, size)),
+                None => Ok((self.parse_whole(schema, data)?, data.len())),
+            },
+        }
+    }
+
+    /// Number of bytes the record at the start of `data` takes, as parsing it would
+    /// consume, reading only what that may depend on.
+    ///
+    /// Members whose size is known up front are skipped, except integers, which sizes
+    /// and conditions further on usually refer to. Records whose size that leaves
+    /// undetermined are parsed after all.
+    fn measure(&mut self, schema: &'s Schema, data: &[u8], scope: Option<NativeScope>) -> NativeResult<usize> {
+        let depth = self.frames.len();
+        let measured = self.measure_frames(schema, data, scope);
+        self.frames.truncate(depth);
+        match measured {
+            Ok(size) if size <= data.len() => Ok(size),
+            _ => Ok(self.parse_prefix(schema, data, scope)?.1),
+        }
+    }
+
+    fn measure_frames(&mut self, schema: &'s Schema, data: &[u8], scope: Option<NativeScope>) -> NativeResult<usize> {
+        if let Ok(Some(size)) = schema.native_sizeof() {
+            return Ok(size);
+        }
+        match schema {
+            Schema::Struct(fields) => {
+                let scope = self.member_frame(scope);
+                let frame = scope.frame;
+                let mut offset = 0;
+                for field in fields {
+                    let rest = data.get(offset..).ok_or(NativeError::Failed)?;
+                    offset += match &field.schema {
+                        Schema::FormatField { .. } | Schema::BytesInteger { .. } | Schema::BitsInteger { .. } | Schema::Enum { .. } => {
+                            let (value, consumed) = self.parse_member(&field.schema, rest, scope)?;
+                            if let Some(name) = &field.name {
+                                native_set(&mut self.frames[frame].entries, name, value);
+                            }
+                            consumed
+                        }
+                        subcon => self.measure_frames(subcon, rest, subcon.is_contextual().then_some(scope))?,
+                    };
+                }
+                Ok(offset)
+            }
+            Schema::Array { count, subcon } => {
+                let scope = self.scope(scope);
+                let count = usize::try_from(self.int_param(count, scope)?).map_err(|_| NativeError::Failed)?;
+                if let Ok(Some(size)) = subcon.native_sizeof() {
+                    return size.checked_mul(count).ok_or(NativeError::Failed);
+                }
+                let mut offset = 0;
+                for index in 0..count {
+                    let rest = data.get(offset..).ok_or(NativeError::Failed)?;
+                    let scope = NativeScope { index: Some(index), ..scope };
+                    offset += self.measure_frames(subcon, rest, subcon.is_contextual().then_some(scope))?;
+                }
+                Ok(offset)
+            }
+            Schema::If { condition, subcon } => {
+                let scope = self.scope(scope);
+                match self.param(condition, scope)?.is_true() {
+                    true => self.measure_frames(subcon, data, subcon.is_contextual().then_some(scope)),
+                    false => Ok(0),
+                }
+            }
+            Schema::FixedSized { length, .. } => {
+                let scope = self.scope(scope);
+                usize::try_from(self.int_param(length, scope)?).map_err(|_| NativeError::Failed)
+            }
+            Schema::PascalString { lengthfield, .. } => {
+                let (length, start) = self.parse_prefix(lengthfield, data, None)?;
+                let NativeValue::Int(length) = length else { return Err(NativeError::Unsupported) };
+                let length = usize::try_from(length).map_err(|_| NativeError::Unsupported)?;
+                start.checked_add(length).ok_or(NativeError::Unsupported)
+            }
+            Schema::CString { encoding } => {
+                let pad = encoding_unit(encoding).map_err(|_| NativeError::Unsupported)?;
+                let end = data.chunks(pad.len()).position(|unit| unit == pad).ok_or(NativeError::Failed)? * pad.len();
+                Ok(end + pad.len())
+            }
+            _ => Ok(self.parse_prefix(schema, data, scope)?.1),
+        }
+    }
+
+    /// Where each record of an `Array` or `GreedyRange` lies in `data`.
+    ///
+    /// Records of a fixed size are laid out from it. Others are measured one after the
+    /// other on a single thread, as each starts where the previous one ends, so that
+    /// only what [`NativeParser::measure`] leaves to parse is spread over the threads:
+    /// records it cannot tell the size of without parsing them are parsed twice.
+    fn record_bounds(&mut self, schema: &'s Schema, data: &[u8], scope: NativeScope) -> NativeResult<Vec<Range<usize>>> {
+        let mut bounds = Vec::new();
+        let mut offset = 0;
+        match schema {
+            Schema::Array { count, subcon } => {
+                let count = usize::try_from(self.int_param(count, scope)?).map_err(|_| NativeError::Failed)?;
+                if let Ok(Some(size)) = subcon.native_sizeof() {
+                    let total = size.checked_mul(count).ok_or(NativeError::Failed)?;
+                    if total > data.len() {
+                        return Err(NativeError::Short(total - data.len()));
+                    }
+                    return Ok((0..count).map(|index| index * size..(index + 1) * size).collect());
+                }
+                bounds.reserve(count.min(data.len()));
+                for index in 0..count {
+                    let scope = NativeScope { index: Some(index), ..scope };
+                    let size = self.measure(subcon, &data[offset..], subcon.is_contextual().then_some(scope))?;
+                    bounds.push(offset..offset + size);
+                    offset += size;
+                }
+            }
+            Schema::GreedyRange(subcon) => {
+                if let Ok(Some(size)) = subcon.native_sizeof()
+                    && size > 0
+                {
+                    return Ok((0..data.len() / size).map(|index| index * size..(index + 1) * size).collect());
+                }
+                while offset < data.len() {
+                    let scope = NativeScope { index: Some(bounds.len()), ..scope };
+                    match self.measure(subcon, &data[offset..], subcon.is_contextual().then_some(scope)) {
+                        Ok(0) | Err(NativeError::Failed | NativeError::Short(_)) => break,
+                        Ok(size) => {
+                            bounds.push(offset..offset + size);
+                            offset += size;
+                        }
+                        Err(err) => return Err(err),
+                    }
+                }
+            }
+            _ => return Err(NativeError::Unsupported),
+        }
+        Ok(bounds)
+    }
+
+    /// Parse all of `data`, like the `parse` method of the construct does.
+    fn parse_whole(&mut self, schema: &'s Schema, data: &[u8]) -> NativeResult<NativeValue> {
+        let length = |length: usize| match data.len() == length {
+            true => Ok(()),
+            false => Err(NativeError::Failed),
+        };
+        match schema {
+            Schema::FormatField { endian, format } => {
+                length(schema.sizeof().unwrap_or_default())?;
+                Ok(native_formatfield(*endian, *format, data))
+            }
+            Schema::BytesInteger { length: size, signed, swapped } if *size < 16 => {
+                length(*size)?;
+                let mut bytes = data.to_vec();
+                if *swapped {
+                    bytes.reverse();
+                }
+                Ok(NativeValue::Int(bytes2integer(&bytes, *signed)))
+            }
+            Schema::BitsInteger { length: size, signed, swapped } if *size < 127 => {
+                length(*size)?;
+                let mut bits = data.to_vec();
+                if *swapped {
+                    if size % 8 != 0 {
+                        return Err(NativeError::Failed);
+                    }
+                    bits.reverse();
+                }
+                Ok(NativeValue::Int(bits2integer(&bits, *signed)))
+            }
+            Schema::PaddedString { length: size, encoding } => {
+                length(*size)?;
+                let pad = encoding_unit(encoding).map_err(|_| NativeError::Unsupported)?;
+                let mut end = data.len();
+                while end > 0 && data[..end].ends_with(pad) {
+                    end -= pad.len();
+                }
+                native_decode(&data[..end], encoding)
+            }
+            Schema::PascalString { lengthfield, encoding } => {
+                let start = lengthfield.native_sizeof()?.ok_or(NativeError::Failed)?;
+                let prefix = data.get(..start).ok_or(NativeError::Failed)?;
+                let NativeValue::Int(size) = self.parse_whole(lengthfield, prefix)? else { return Err(NativeError::Unsupported) };
+                length(start + usize::try_from(size).map_err(|_| NativeError::Unsupported)?)?;
+                native_decode(&data[start..], encoding)
+            }
+            Schema::CString { encoding } => {
+                let pad = encoding_unit(encoding).map_err(|_| NativeError::Unsupported)?;
+                let content = data.strip_suffix(pad).ok_or(NativeError::Failed)?;
+                native_decode(content, encoding)
+            }
+            Schema::GreedyString { encoding } => native_decode(data, encoding),
+            Schema::GreedyBytes { zerocopy } => Ok(self.bytes(data, *zerocopy)),
+            Schema::ByteSwapped(subcon) | Schema::BitsSwapped(subcon) => {
+                if subcon.native_sizeof()?.is_some_and(|size| size != data.len()) {
+                    return Err(NativeError::Failed);
+                }
+                let swapped = match schema {
+                    Schema::ByteSwapped(_) => swapbytes(data.to_vec()),
+                    _ => swapbitsinbytes(data),
+                };
+                self.parse_whole(subcon, &swapped)
+            }
+            Schema::ProcessXor { pad, subcon } => {
+                let mut buf = data.to_vec();
+                xor_bytes(&mut buf, pad);
+                self.parse_whole(subcon, &buf)
+            }
+            _ if schema.is_contextual() => Ok(self.parse_prefix(schema, data, None)?.0),
+            _ => Err(NativeError::Unsupported),
+        }
+    }
+}
+
+impl Schema {
+    /// Whether the construct evaluates the context, and so is passed the one of its parent.
+    fn is_contextual(&self) -> bool {
+        matches!(
+            self,
+            Schema::Struct(_)
+                | Schema::Sequence(_)
+                | Schema::Bytes { .. }
+                | Schema::Array { .. }
+                | Schema::GreedyRange(_)
+                | Schema::RepeatUntil { .. }
+                | Schema::Switch { .. }
+                | Schema::If { .. }
+                | Schema::Computed(_)
+                | Schema::FixedSized { .. }
+                | Schema::Pointer { .. }
+                | Schema::Enum { .. }
+        )
+    }
+
+    /// Whether parsing takes whatever data follows, so that a stream must be read to its end.
+    fn reads_to_end(&self) -> bool {
+        match self {
+            Schema::GreedyString { .. } | Schema::GreedyBytes { .. } | Schema::GreedyRange(_) => true,
+            Schema::ByteSwapped(_) | Schema::BitsSwapped(_) | Schema::ProcessXor { .. } => self.sizeof().is_none(),
+            Schema::Sequence(fields) | Schema::Struct(fields) | Schema::LazyStruct(fields) => {
+                fields.iter().any(|field| field.schema.reads_to_end())
+            }
+            Schema::Switch { cases, default, .. } => {
+                cases.iter().any(|(_, case)| case.reads_to_end()) || default.as_ref().is_some_and(|default| default.reads_to_end())
+            }
+            Schema::Lazy(subcon) | Schema::Hex(subcon) | Schema::HexDump(subcon) => subcon.reads_to_end(),
+            Schema::LazyArray { subcon, .. }
+            | Schema::Array { subcon, .. }
+            | Schema::RepeatUntil { subcon, .. }
+            | Schema::If { subcon, .. }
+            | Schema::Enum { subcon, .. } => subcon.reads_to_end(),
+            _ => false,
+        }
+    }
+
+    /// Size as reported by the `sizeof` method of the construct, which unlike
+    /// [`Schema::sizeof`] evaluates constant conditions and compares `Switch` cases.
+    fn native_sizeof(&self) -> NativeResult<Option<usize>> {
+        let constant = |param: &SchemaParam| match param {
+            SchemaParam::Const(value) => i64::try_from(*value).map_err(|_| NativeError::Unsupported),
+            SchemaParam::Expr(_) => Err(NativeError::Unsupported),
+        };
+        Ok(match self {
+            Schema::Sequence(fields) | Schema::Struct(fields) => {
+                let mut size = Some(0);
+                for field in fields {
+                    size = size.zip(field.schema.native_sizeof()?).map(|(size, subsize)| size + subsize);
+                }
+                size
+            }
+            Schema::Array { count, subcon } => {
+                let count = usize::try_from(constant(count)?).ok();
+                count.zip(subcon.native_sizeof()?).map(|(count, size)| count * size)
+            }
+            Schema::Switch { cases, default, .. } => {
+                let mut sizes = cases.iter().map(|(_, case)| case.native_sizeof()).collect::<NativeResult<Vec<_>>>()?;
+                sizes.push(match default {
+                    Some(default) => default.native_sizeof()?,
+                    None => Some(0),
+                });
+                match sizes.split_first() {
+                    Some((Some(size), rest)) if rest.iter().all(|other| *other == Some(*size)) => Some(*size),
+                    _ => None,
+                }
+            }
+            Schema::If { condition, subcon } => match constant(condition)? {
+                0 => Some(0),
+                _ => subcon.native_sizeof()?,
+            },
+            Schema::FixedSized { length, .. } | Schema::Bytes { length, .. } => usize::try_from(constant(length)?).ok(),
+            Schema::Enum { subcon, .. } | Schema::ByteSwapped(subcon) | Schema::BitsSwapped(subcon) | Schema::ProcessXor { subcon, .. } => {
+                subcon.native_sizeof()?
+            }
+            Schema::Lazy(_) | Schema::LazyStruct(_) | Schema::LazyArray { .. } | Schema::Hex(_) | Schema::HexDump(_) => {
+                return Err(NativeError::Unsupported);
+            }
+            _ => self.sizeof(),
+        })
+    }
+}
+
+/// Parse the leading bytes of `data` with the GIL released, provided `construct` is a
+/// tree of construct-rs constructs that parses without calling into Python, as context
+/// lambdas and constructs implemented in Python do. Writable buffers are parsed holding
+/// the GIL, so that no other thread writes to them meanwhile.
+///
+/// Returns `None` when the Python-facing implementation has to parse the data instead,
+/// which is also how parse errors get reported exactly as it reports them. The tests
+/// check that both implementations agree on every kind of construct.
+fn parse_released(py: Python, construct: &PyAny, input: &Input, contextkw: Option<&PyDict>) -> PyResult<Option<(PyObject, usize)>> {
+    #[cfg(test)]
+    if !PARSE_RELEASED.get() {
+        return Ok(None);
+    }
+    // Members are parsed along with the outermost construct, unless it has to call into
+    // Python, as one of its members then does, which is not worth describing each time.
+    if let Some(contextkw) = contextkw
+        && contextkw.contains(CONTEXT_KWARG)?
+    {
+        return Ok(None);
+    }
+    let Some(schema) = cached_schema(construct) else { return Ok(None) };
+    let data = input.as_bytes();
+    let mut parser = NativeParser::new(data);
+    let Ok(scope) = parser.scope_from_py(contextkw) else { return Ok(None) };
+    match input.allow_threads(py, || parser.parse_prefix(&schema, data, Some(scope))) {
+        Ok((value, consumed)) => Ok(Some((value.to_object(py, input)?, consumed))),
+        // Parsed again by the Python-facing implementation, to raise the error it raises.
+        Err(NativeError::Failed | NativeError::Short(_)) => Ok(None),
+        Err(NativeError::Unsupported) => Ok(None),
+    }
+}
+
+/// Schema of a construct that parses natively, described when it is first parsed rather
+/// than on every call. Constructs do not change once created, except when unpickled,
+/// which resets it.
+#[derive(Default)]
+struct SchemaCache(pyo3::sync::GILOnceCell<Option<Arc<Schema>>>);
+
+impl SchemaCache {
+    fn get(&self, construct: &PyAny) -> Option<Arc<Schema>> {
+        self.0.get_or_init(construct.py(), || Schema::from_construct(construct).ok().map(Arc::new)).clone()
+    }
+}
+
+/// Schema of `construct`, cached by the constructs that parse natively.
+fn cached_schema(construct: &PyAny) -> Option<Arc<Schema>> {
+    if let Ok(c) = construct.downcast::<PyCell<Struct>>() {
+        return c.borrow().schema.get(construct);
+    }
+    if let Ok(c) = construct.downcast::<PyCell<Sequence>>() {
+        return c.borrow().schema.get(construct);
+    }
+    if let Ok(c) = construct.downcast::<PyCell<Array>>() {
+        return c.borrow().schema.get(construct);
+    }
+    if let Ok(c) = construct.downcast::<PyCell<GreedyRange>>() {
+        return c.borrow().schema.get(construct);
+    }
+    if let Ok(c) = construct.downcast::<PyCell<RepeatUntil>>() {
+        return c.borrow().schema.get(construct);
+    }
+    if let Ok(c) = construct.downcast::<PyCell<Bytes>>() {
+        return c.borrow().schema.get(construct);
+    }
+    Schema::from_construct(construct).ok().map(Arc::new)
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Cleared by the tests to parse with the Python-facing implementation alone.
+    static PARSE_RELEASED: Cell<bool> = const { Cell::new(true) };
+}
+
+/// Parse the records of an `Array` or `GreedyRange` on `threads` threads, with the GIL
+/// released, after finding where each one lies.
+///
+/// Returns `None` when [`parse_released`] would, including for other constructs.
+fn parse_parallel_released(py: Python, construct: &PyAny, input: &Input, contextkw: Option<&PyDict>, threads: usize) -> PyResult<Option<PyObject>> {
+    let Some(schema) = cached_schema(construct) else { return Ok(None) };
+    let schema = &*schema;
+    let (Schema::Array { subcon, .. } | Schema::GreedyRange(subcon)) = schema else { return Ok(None) };
+    let data = input.as_bytes();
+    let mut parser = NativeParser::new(data);
+    let Ok(scope) = parser.scope_from_py(contextkw) else { return Ok(None) };
+    let parsed = input.allow_threads(py, || {
+        let bounds = parser.record_bounds(schema, data, scope)?;
+        let chunk = bounds.len().div_ceil(threads).max(1);
+        let frames = &parser.frames;
+        let results: Vec<NativeResult<NativeValue>> = std::thread::scope(|s| {
+            let workers: Vec<_> = bounds
+                .chunks(chunk)
+                .enumerate()
+                .map(|(n, records)| {
+                    s.spawn(move || {
+                        let mut parser = NativeParser { frames: frames.clone(), ..NativeParser::new(data) };
+                        let results: Vec<_> = records
+                            .iter()
+                            .enumerate()
+                            .map(|(i, record)| {
+                                let scope = NativeScope { index: Some(n * chunk + i), ..scope };
+                                match parser.parse_member(subcon, &data[record.start..], scope)? {
+                                    (value, consumed) if consumed == record.len() => Ok(value),
+                                    _ => Err(NativeError::Unsupported),
+                                }
+                            })
+                            .collect();
+                        results
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+                .collect()
+        });
+        let mut items = Vec::with_capacity(results.len());
+        for result in results {
+            match result {
+                Ok(item) => items.push(item),
+                // A greedy range ends at the first record failing to parse.
+                Err(NativeError::Failed | NativeError::Short(_)) if matches!(schema, Schema::GreedyRange(_)) => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(NativeValue::ListContainer(items))
+    });
+    match parsed {
+        Ok(value) => Ok(Some(value.to_object(py, input)?)),
+        Err(NativeError::Failed | NativeError::Short(_)) => Ok(None),
+        Err(NativeError::Unsupported) => Ok(None),
+    }
+}
+
+// ========================= Incremental parsing =======================
+
+/// Parses objects one after another out of data fed in chunks, like frames received on
+/// a non-blocking socket, returning each object once its last byte has been fed.
+///
+/// When the data buffered runs short, `needed` tells how many more bytes the next
+/// object takes at least, and it is only parsed again once they have been fed, rather
+/// than on every chunk. A `Struct` carries on from the member it ran short at, keeping
+/// the members before it; other constructs are parsed again from their start. Constructs
+/// calling into Python cannot tell how short they are, and are parsed again on every
+/// chunk. Constructs reading to the end of the data, like `GreedyBytes`, take whatever
+/// has been buffered.
+#[pyclass(module = "construct_rs")]
+pub struct Feeder {
+    subcon: Py<PyAny>,
+    schema: Option<Schema>,
+    contextkw: Option<Py<PyDict>>,
+    buffer: Vec<u8>,
+    /// Length the buffer needs before parsing the next object can succeed.
+    wanted: usize,
+    /// Members of the next object parsed so far, when it is a `Struct`.
+    progress: Option<StructProgress>,
+}
+
+impl Feeder {
+    /// Whether the object takes the rest of the data, so that a stream must be read to its
+    /// end. Only a schema tells: objects without one are read as far as they need to parse,
+    /// since a dynamic size alone does not mean the rest of the data is taken.
+    fn reads_to_end(&self) -> bool {
+        self.schema.as_ref().is_some_and(Schema::reads_to_end)
+    }
+
+    /// The next object and its size, or `None` when the buffer is short, noting how short.
+    fn parse_next(&mut self, py: Python) -> PyResult<Option<(PyObject, usize)>> {
+        let contextkw = self.contextkw.as_ref().map(|contextkw| contextkw.as_ref(py));
+        if let Ok(strukt) = self.subcon.as_ref(py).downcast::<PyCell<Struct>>() {
+            let mut progress = match self.progress.take() {
+                Some(progress) => progress,
+                None => Struct::start_parse(py, contextkw)?,
+            };
+            let start = progress.offset;
+            let data = Input::Bytes(PyBytes::new(py, &self.buffer[start..]));
+            return match strukt.borrow().parse_members(py, &data, start, &mut progress) {
+                Ok(()) => Ok(Some((progress.obj.into_py(py), progress.offset))),
+                Err(err) if err.is_instance_of::<exceptions::StreamError>(py) => {
+                    let member = strukt.borrow().subcons[progress.member].subcon.clone_ref(py);
+                    self.wanted = progress.offset + wanted_len(member.as_ref(py), &self.buffer[progress.offset..], progress.context.as_ref(py));
+                    self.progress = Some(progress);
+                    Ok(None)
+                }
+                Err(err) => Err(err),
+            };
+        }
+        if let Some(schema) = &self.schema {
+            let buffer = &self.buffer;
+            let mut parser = NativeParser::new(buffer);
+            if let Ok(scope) = parser.scope_from_py(contextkw) {
+                match py.allow_threads(|| parser.parse_prefix(schema, buffer, Some(scope))) {
+                    Ok((value, consumed)) => {
+                        let record = Input::Bytes(PyBytes::new(py, &buffer[..consumed]));
+                        return Ok(Some((value.to_object(py, &record)?, consumed)));
+                    }
+                    Err(NativeError::Short(end)) => {
+                        self.wanted = end;
+                        return Ok(None);
+                    }
+                    Err(_) => {}
+                }
+            }
+        }
+        // Errors are raised, and other constructs parsed, by the Python-facing implementation.
+        let subcon = self.subcon.as_ref(py);
+        if let Some(size) = subcon_sizeof(subcon)
+            && size > self.buffer.len()
+        {
+            self.wanted = size;
+            return Ok(None);
+        }
+        match parse_prefix_in(py, subcon, &Input::Bytes(PyBytes::new(py, &self.buffer)), 0, context_from_kwargs(py, contextkw)?) {
+            Ok(parsed) => Ok(Some(parsed)),
+            Err(err) if err.is_instance_of::<exceptions::StreamError>(py) => {
+                self.wanted = self.buffer.len() + 1;
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Length of data `subcon` needs to parse, as far as can be told from the `data` it ran
+/// short of: the end the native parser reports or its fixed size, otherwise one more byte.
+fn wanted_len(subcon: &PyAny, data: &[u8], context: &PyDict) -> usize {
+    if let Some(schema) = cached_schema(subcon) {
+        let mut parser = NativeParser::new(data);
+        if let Ok(scope) = parser.scope_from_py(Some(context))
+            && let Err(NativeError::Short(end)) = parser.parse_prefix(&schema, data, Some(scope))
+        {
+            return end;
+        }
+    }
+    match subcon_sizeof(subcon) {
+        Some(size) if size > data.len() => size,
+        _ => data.len() + 1,
+    }
+}
+
+#[pymethods]
+impl Feeder {
+    #[new]
+    #[pyo3(signature = (subcon, **contextkw))]
+    fn new(subcon: &PyAny, contextkw: Option<&PyDict>) -> Self {
+        Feeder {
+            subcon: subcon.into(),
+            schema: Schema::from_construct(subcon).ok(),
+            contextkw: contextkw.map(Into::into),
+            buffer: Vec::new(),
+            wanted: 1,
+            progress: None,
+        }
+    }
+
+    /// Buffer `data`, returning the objects it completes, possibly none.
+    fn feed(&mut self, py: Python<'_>, data: Input) -> PyResult<Vec<PyObject>> {
+        self.buffer.extend_from_slice(data.as_bytes());
+        let mut objs = Vec::new();
+        while !self.buffer.is_empty() && self.buffer.len() >= self.wanted {
+            let Some((obj, consumed)) = self.parse_next(py)? else { break };
+            if consumed == 0 {
+                return Err(ConstructError::Other("constructs parsing no bytes cannot be fed".into()).into());
+            }
+            objs.push(obj);
+            self.buffer.drain(..consumed);
+            self.wanted = 1;
+        }
+        Ok(objs)
+    }
+
+    /// Number of bytes the next object needs fed at least.
+    #[getter]
+    fn needed(&self) -> usize {
+        self.wanted.saturating_sub(self.buffer.len()).max(1)
+    }
+
+    /// Number of bytes fed towards the next object.
+    #[getter]
+    fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+}
+
+/// Awaitable returned by `parse_async`, reading from an `asyncio.StreamReader` the bytes
+/// the next object needs, as far as the parser can tell, and then parsing them.
+#[pyclass(module = "construct_rs")]
+pub struct AsyncParse {
+    feeder: Feeder,
+    reader: Py<PyAny>,
+    /// Iterator of the read being awaited.
+    reading: Option<Py<PyAny>>,
+    /// Whether the object takes the rest of the stream, read in one go.
+    to_end: bool,
+    /// Whether the stream has been read to its end.
+    eof: bool,
+    done: bool,
+}
+
+impl AsyncParse {
+    /// Carry on from the `outcome` of resuming the read being awaited, starting reads
+    /// until the object is parsed. Returns what a read yields to the event loop.
+    fn resume<'py>(&mut self, py: Python<'py>, outcome: Option<PyResult<&'py PyAny>>) -> PyResult<PyObject> {
+        if self.done {
+            return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("cannot reuse already awaited parse"));
+        }
+        match self.step(py, outcome) {
+            Ok(yielded) => Ok(yielded),
+            Err(err) => {
+                self.done = true;
+                self.reading = None;
+                Err(err)
+            }
+        }
+    }
+
+    fn step<'py>(&mut self, py: Python<'py>, mut outcome: Option<PyResult<&'py PyAny>>) -> PyResult<PyObject> {
+        loop {
+            match outcome.take() {
+                Some(Ok(yielded)) => return Ok(yielded.into()),
+                Some(Err(err)) if err.is_instance_of::<pyo3::exceptions::PyStopIteration>(py) => {
+                    self.reading = None;
+                    let data = err.value(py).getattr("value")?;
+                    self.feeder.buffer.extend_from_slice(bytes_like(py, data)?.as_bytes());
+                }
+                Some(Err(err)) if err.is_instance(py, py.import("asyncio")?.getattr("IncompleteReadError")?) => {
+                    return Err(ConstructError::StreamError.into());
+                }
+                Some(Err(err)) => return Err(err),
+                None => {}
+            }
+            if !self.to_end || self.eof {
+                match self.feeder.parse_next(py)? {
+                    Some((obj, _)) => return Err(pyo3::exceptions::PyStopIteration::new_err((obj,))),
+                    None if self.eof => return Err(ConstructError::StreamError.into()),
+                    None => {}
+                }
+            }
+            let reader = self.reader.clone_ref(py).into_ref(py);
+            let read = if self.to_end {
+                self.eof = true;
+                reader.call_method0("read")?
+            } else {
+                reader.call_method1("readexactly", (self.feeder.needed(),))?
+            };
+            let reading = read.call_method0("__await__")?;
+            self.reading = Some(reading.into());
+            outcome = Some(reading.call_method1("send", (py.None(),)));
+        }
+    }
+}
+
+#[pymethods]
+impl AsyncParse {
+    fn __await__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<PyObject> {
+        self.send(py, py.None().into_ref(py))
+    }
+
+    fn send(&mut self, py: Python<'_>, value: &PyAny) -> PyResult<PyObject> {
+        let outcome = self.reading.as_ref().map(|reading| reading.clone_ref(py).into_ref(py).call_method1("send", (value,)));
+        self.resume(py, outcome)
+    }
+
+    #[pyo3(signature = (typ, val=None, tb=None))]
+    fn throw(&mut self, py: Python<'_>, typ: &PyAny, val: Option<&PyAny>, tb: Option<&PyAny>) -> PyResult<PyObject> {
+        let outcome = match (&self.reading, val, tb) {
+            (Some(reading), None, None) => reading.clone_ref(py).into_ref(py).call_method1("throw", (typ,)),
+            (Some(reading), _, _) => reading.clone_ref(py).into_ref(py).call_method1("throw", (typ, val, tb)),
+            (None, None, _) => Err(PyErr::from_value(typ)),
+            (None, Some(val), _) => Err(PyErr::from_value(typ.call1((val,))?)),
+        };
+        self.resume(py, Some(outcome))
+    }
+
+    fn close(&mut self, py: Python<'_>) -> PyResult<()> {
+        self.done = true;
+        match self.reading.take() {
+            Some(reading) => reading.call_method0(py, "close").map(drop),
+            None => Ok(()),
+        }
+    }
+}
+
+// ========================= Arbitrary values ==========================
+
+/// Longest string, byte string or repetition generated where the construct sets no bound.
+const ARBITRARY_MAX_LENGTH: usize = 32;
+
+/// Largest length or count read from another field that is generated.
+const ARBITRARY_MAX_COUNT: i64 = 1 << 16;
+
+/// Values generated before giving up on one the construct builds.
+const ARBITRARY_ATTEMPTS: usize = 100;
+
+/// Pseudo-random source of arbitrary values, reproducible from its seed.
+///
+/// Integers favour their bounds and small values, which sizes and counts usually are,
+/// over uniformly drawn ones.
+struct ArbitrarySource {
+    state: u64,
+}
+
+impl ArbitrarySource {
+    fn new(seed: u64) -> Self {
+        ArbitrarySource { state: seed }
+    }
+
+    /// Seed differing between calls.
+    fn random_seed() -> u64 {
+        use std::hash::BuildHasher;
+        std::collections::hash_map::RandomState::new().hash_one(ARBITRARY_ATTEMPTS)
+    }
+
+    /// SplitMix64.
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Value below `bound`, which must not be zero.
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+
+    fn length(&mut self, max: usize) -> usize {
+        self.below(max as u64 + 1) as usize
+    }
+
+    /// Integer of `bits` bits, the unsigned ones of 128 bits staying within `i128`.
+    fn int(&mut self, bits: usize, signed: bool) -> i128 {
+        if bits == 0 {
+            return 0;
+        }
+        let (min, max) = match (bits >= 128, signed) {
+            (true, true) => (i128::MIN, i128::MAX),
+            (true, false) => (0, i128::MAX),
+            (false, true) => (-(1 << (bits - 1)), (1 << (bits - 1)) - 1),
+            (false, false) => (0, (1 << bits) - 1),
+        };
+        match self.below(4) {
+            0 => [min, max, 0, 1.min(max), (-1).max(min)][self.below(5) as usize],
+            1 => (self.length(ARBITRARY_MAX_LENGTH) as i128).min(max),
+            _ => {
+                let raw = (self.next() as u128) << 64 | self.next() as u128;
+                match max.wrapping_sub(min) as u128 {
+                    u128::MAX => raw as i128,
+                    span => min.wrapping_add((raw % (span + 1)) as i128),
+                }
+            }
+        }
+    }
+
+    /// Character other than NUL, which strings are padded and terminated with.
+    fn char(&mut self, ascii: bool) -> char {
+        let code = match (ascii, self.below(4)) {
+            (true, _) | (false, 0 | 1) => 1 + self.below(0x7f) as u32,
+            (false, 2) => 0x80 + self.below(0x780) as u32,
+            (false, _) => 0x800 + self.below(0x10f800) as u32,
+        };
+        char::from_u32(code).unwrap_or('\u{fffd}')
+    }
+
+    /// Text encoding into at most `max` bytes, byte order mark included.
+    fn text(&mut self, encoding: &str, max: usize) -> NativeResult<String> {
+        let (ascii, bom) = match encoding.replace('-', "_").to_lowercase().as_str() {
+            "ascii" => (true, 0),
+            "utf8" | "utf_8" | "u8" | "utf_16_le" | "utf_16_be" | "utf_32_le" | "utf_32_be" => (false, 0),
+            "utf16" | "utf_16" | "u16" => (false, 2),
+            "utf32" | "utf_32" | "u32" => (false, 4),
+            _ => return Err(NativeError::Unsupported),
+        };
+        let unit = encoding_unit(encoding).map_err(|_| NativeError::Unsupported)?.len();
+        let budget = max.saturating_sub(bom);
+        let mut text = String::new();
+        let mut size = 0;
+        for _ in 0..self.length(ARBITRARY_MAX_LENGTH.min(max)) {
+            let c = self.char(ascii);
+            let width = match unit {
+                1 => c.len_utf8(),
+                2 => 2 * c.len_utf16(),
+                _ => 4,
+            };
+            if size + width > budget {
+                break;
+            }
+            text.push(c);
+            size += width;
+        }
+        Ok(text)
+    }
+}
+
+/// Range of the integers a schema parses, for those bounding the length of another field.
+fn arbitrary_int_max(schema: &Schema) -> Option<i128> {
+    let bits = match schema {
+        Schema::FormatField { format: 'B', .. } => 8,
+        Schema::FormatField { format: 'H', .. } => 16,
+        Schema::FormatField { format: 'L', .. } => 32,
+        Schema::FormatField { format: 'Q', .. } => 64,
+        Schema::FormatField { format: 'b', .. } => 7,
+        Schema::FormatField { format: 'h', .. } => 15,
+        Schema::FormatField { format: 'l', .. } => 31,
+        Schema::FormatField { format: 'q', .. } => 63,
+        Schema::BytesInteger { length, signed, .. } => length * 8 - *signed as usize,
+        Schema::BitsInteger { length, signed, .. } => length - *signed as usize,
+        _ => return None,
+    };
+    Some(if bits >= 127 { i128::MAX } else { (1 << bits) - 1 })
+}
+
+impl<'s> NativeParser<'s> {
+    /// Generate a value that the constructs `schema` describes build, evaluating the
+    /// parameters of members against the members generated before them, like parsing does.
+    fn arbitrary(&mut self, schema: &'s Schema, source: &mut ArbitrarySource, scope: Option<NativeScope>) -> NativeResult<NativeValue> {
+        let depth = self.frames.len();
+        let generated = self.arbitrary_frames(schema, source, scope);
+        self.frames.truncate(depth);
+        generated
+    }
+
+    fn arbitrary_member(&mut self, schema: &'s Schema, source: &mut ArbitrarySource, scope: NativeScope) -> NativeResult<NativeValue> {
+        self.arbitrary(schema, source, schema.is_contextual().then_some(scope))
+    }
+
+    /// Length or count parameter, rejecting those too large to generate.
+    fn arbitrary_count(&mut self, param: &'s SchemaParam, scope: NativeScope) -> NativeResult<usize> {
+        match self.int_param(param, scope)? {
+            count @ 0..=ARBITRARY_MAX_COUNT => Ok(count as usize),
+            _ => Err(NativeError::Failed),
+        }
+    }
+
+    fn arbitrary_frames(&mut self, schema: &'s Schema, source: &mut ArbitrarySource, scope: Option<NativeScope>) -> NativeResult<NativeValue> {
+        Ok(match schema {
+            Schema::FormatField { format, .. } => match format {
+                'B' | 'H' | 'L' | 'Q' | 'b' | 'h' | 'l' | 'q' => {
+                    let signed = format.is_ascii_lowercase();
+                    let bits = match format.to_ascii_uppercase() {
+                        'B' => 8,
+                        'H' => 16,
+                        'L' => 32,
+                        _ => 64,
+                    };
+                    NativeValue::Int(source.int(bits, signed))
+                }
+                'f' => NativeValue::Float(Some(f32::from_bits(source.next() as u32)).filter(|f| f.is_finite()).unwrap_or(0.0) as f64),
+                'd' => NativeValue::Float(Some(f64::from_bits(source.next())).filter(|f| f.is_finite()).unwrap_or(0.0)),
+                _ => return Err(NativeError::Unsupported),
+            },
+            Schema::BytesInteger { length, signed, .. } => NativeValue::Int(source.int(length * 8, *signed)),
+            Schema::BitsInteger { length, signed, .. } => NativeValue::Int(source.int(*length, *signed)),
+            Schema::PaddedString { length, encoding } => NativeValue::Str(source.text(encoding, *length)?),
+            Schema::PascalString { lengthfield, encoding } => {
+                let max = arbitrary_int_max(lengthfield).ok_or(NativeError::Unsupported)?;
+                NativeValue::Str(source.text(encoding, usize::try_from(max).unwrap_or(usize::MAX))?)
+            }
+            Schema::CString { encoding } | Schema::GreedyString { encoding } => NativeValue::Str(source.text(encoding, usize::MAX)?),
+            Schema::Bytes { length, zerocopy } => {
+                let scope = self.scope(scope);
+                let length = self.arbitrary_count(length, scope)?;
+                let data = (0..length).map(|_| source.next() as u8).collect();
+                NativeValue::Bytes { data: NativeBytes::Owned(data), zerocopy: *zerocopy }
+            }
+            Schema::GreedyBytes { zerocopy } => {
+                let data = (0..source.length(ARBITRARY_MAX_LENGTH)).map(|_| source.next() as u8).collect();
+                NativeValue::Bytes { data: NativeBytes::Owned(data), zerocopy: *zerocopy }
+            }
+            Schema::Struct(fields) | Schema::LazyStruct(fields) => {
+                let scope = self.member_frame(scope);
+                for field in fields {
+                    let value = self.arbitrary_member(&field.schema, source, scope)?;
+                    if let Some(name) = &field.name {
+                        native_set(&mut self.frames[scope.frame].entries, name, value);
+                    }
+                }
+                NativeValue::Container(std::mem::take(&mut self.frames[scope.frame].entries))
+            }
+            Schema::Sequence(fields) => {
+                let scope = self.member_frame(scope);
+                let mut items = Vec::with_capacity(fields.len());
+                for field in fields {
+                    let value = self.arbitrary_member(&field.schema, source, scope)?;
+                    if let Some(name) = &field.name {
+                        native_set(&mut self.frames[scope.frame].entries, name, value.clone());
+                    }
+                    items.push(value);
+                }
+                NativeValue::ListContainer(items)
+            }
+            Schema::LazyArray { count, subcon } => {
+                NativeValue::ListContainer((0..*count).map(|_| self.arbitrary(subcon, source, None)).collect::<NativeResult<_>>()?)
+            }
+            Schema::Array { count, subcon } => {
+                let scope = self.scope(scope);
+                let count = self.arbitrary_count(count, scope)?;
+                let items = (0..count).map(|index| self.arbitrary_member(subcon, source, NativeScope { index: Some(index), ..scope }));
+                NativeValue::ListContainer(items.collect::<NativeResult<_>>()?)
+            }
+            Schema::GreedyRange(subcon) => {
+                let scope = self.scope(scope);
+                let count = source.length(ARBITRARY_MAX_LENGTH / 4);
+                let items = (0..count).map(|index| self.arbitrary_member(subcon, source, NativeScope { index: Some(index), ..scope }));
+                NativeValue::ListContainer(items.collect::<NativeResult<_>>()?)
+            }
+            Schema::RepeatUntil { predicate, subcon } => {
+                // Up to `count` items not satisfying the predicate, then one that does.
+                let scope = self.scope(scope);
+                let count = source.length(ARBITRARY_MAX_LENGTH / 4);
+                let mut items = Vec::new();
+                for _ in 0..ARBITRARY_ATTEMPTS {
+                    let scope = NativeScope { index: Some(items.len()), ..scope };
+                    let item = self.arbitrary_member(subcon, source, scope)?;
+                    let done = self.eval(predicate, scope, Some(&item))?.is_true();
+                    if done || items.len() < count {
+                        items.push(item);
+                    }
+                    if done {
+                        return Ok(NativeValue::ListContainer(items));
+                    }
+                }
+                return Err(NativeError::Failed);
+            }
+            Schema::Switch { key, cases, default } => {
+                let scope = self.scope(scope);
+                let key = self.eval(key, scope, None)?;
+                match native_case(&key, cases, default.as_deref())? {
+                    Some(subcon) => self.arbitrary_member(subcon, source, scope)?,
+                    None => NativeValue::None,
+                }
+            }
+            Schema::If { condition, subcon } => {
+                let scope = self.scope(scope);
+                match self.param(condition, scope)?.is_true() {
+                    true => self.arbitrary_member(subcon, source, scope)?,
+                    false => NativeValue::None,
+                }
+            }
+            Schema::Computed(func) => {
+                let scope = self.scope(scope);
+                self.param(func, scope)?
+            }
+            Schema::FixedSized { subcon, .. } => {
+                let scope = self.scope(scope);
+                self.arbitrary_member(subcon, source, scope)?
+            }
+            Schema::Enum { mapping, subcon } => {
+                let scope = self.scope(scope);
+                let value = match mapping.is_empty() || source.below(4) == 0 {
+                    true => self.arbitrary_member(subcon, source, scope)?,
+                    false => NativeValue::Int(mapping[source.below(mapping.len() as u64) as usize].1),
+                };
+                // Values mapped to several names parse as the last of them.
+                match value {
+                    NativeValue::Int(value) => match mapping.iter().rev().find(|(_, mapped)| *mapped == value) {
+                        Some((name, _)) => NativeValue::Str(name.clone()),
+                        None => NativeValue::Int(value),
+                    },
+                    _ => return Err(NativeError::Unsupported),
+                }
+            }
+            Schema::Lazy(subcon)
+            | Schema::Hex(subcon)
+            | Schema::HexDump(subcon)
+            | Schema::ByteSwapped(subcon)
+            | Schema::BitsSwapped(subcon)
+            | Schema::ProcessXor { subcon, .. } => self.arbitrary(subcon, source, None)?,
+            // Built values would have to be written at the offset, which building cannot do.
+            Schema::Pointer { .. } => return Err(NativeError::Unsupported),
+        })
+    }
+}
+
+// ========================= Rust code generation ======================
+
+/// Support code included once in every generated source.
+const RUST_RUNTIME: &str = r#"#[allow(dead_code)]
+mod rt {
+    /// Errors returned by the generated parsers and builders.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Error {
+        /// The data ended before the field did.
+        Stream,
+        /// A value does not fit in its field.
+        Range,
+        /// A string could not be decoded or encoded.
+        String,
+    }
+
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:?}", self)
+        }
+    }
+
+    impl std::error::Error for Error {}
+
+    pub fn take<'a>(data: &'a [u8], offset: &mut usize, length: usize) -> Result<&'a [u8], Error> {
+        let end = offset.checked_add(length).ok_or(Error::Stream)?;
+        let chunk = data.get(*offset..end).ok_or(Error::Stream)?;
+        *offset = end;
+        Ok(chunk)
+    }
+
+    pub fn take_array<const N: usize>(data: &[u8], offset: &mut usize) -> Result<[u8; N], Error> {
+        Ok(take(data, offset, N)?.try_into().unwrap())
+    }
+
+    pub fn take_rest<'a>(data: &'a [u8], offset: &mut usize) -> Result<&'a [u8], Error> {
+        take(data, offset, data.len().saturating_sub(*offset))
+    }
+
+    /// Take the units up to a unit of zeros, consuming the terminator too.
+    pub fn take_terminated<'a>(data: &'a [u8], offset: &mut usize, unit: usize) -> Result<&'a [u8], Error> {
+        let rest = data.get(*offset..).ok_or(Error::Stream)?;
+        let length = rest
+            .chunks(unit)
+            .position(|chunk| chunk.len() == unit && chunk.iter().all(|&b| b == 0))
+            .ok_or(Error::Stream)?
+            * unit;
+        *offset += length + unit;
+        Ok(&rest[..length])
+    }
+
+    pub fn bytes2integer(bytes: &[u8], signed: bool, swapped: bool) -> i128 {
+        let mut value = 0u128;
+        let mut push = |b: u8| value = value << 8 | b as u128;
+        if swapped {
+            bytes.iter().rev().for_each(|&b| push(b));
+        } else {
+            bytes.iter().for_each(|&b| push(b));
+        }
+        sign_extend(value, bytes.len() * 8, signed)
+    }
+
+    pub fn integer2bytes(value: i128, length: usize, signed: bool, swapped: bool) -> Result<Vec<u8>, Error> {
+        check_range(value, length * 8, signed)?;
+        let mut bytes: Vec<u8> = (0..length).rev().map(|i| (value >> (8 * i)) as u8).collect();
+        if swapped {
+            bytes.reverse();
+        }
+        Ok(bytes)
+    }
+
+    /// Integer from a bit string holding one bit per byte.
+    pub fn bits2integer(bits: &[u8], signed: bool, swapped: bool) -> i128 {
+        let mut value = 0u128;
+        let mut push = |b: u8| value = value << 1 | (b != 0) as u128;
+        if swapped {
+            bits.iter().rev().for_each(|&b| push(b));
+        } else {
+            bits.iter().for_each(|&b| push(b));
+        }
+        sign_extend(value, bits.len(), signed)
+    }
+
+    pub fn integer2bits(value: i128, length: usize, signed: bool, swapped: bool) -> Result<Vec<u8>, Error> {
+        check_range(value, length, signed)?;
+        let mut bits: Vec<u8> = (0..length).rev().map(|i| (value >> i) as u8 & 1).collect();
+        if swapped {
+            bits.reverse();
+        }
+        Ok(bits)
+    }
+
+    fn sign_extend(value: u128, bits: usize, signed: bool) -> i128 {
+        if signed && bits < 128 && value >> (bits - 1) & 1 == 1 {
+            value as i128 - (1i128 << bits)
+        } else {
+            value as i128
+        }
+    }
+
+    fn check_range(value: i128, bits: usize, signed: bool) -> Result<(), Error> {
+        if bits >= 128 {
+            return Ok(());
+        }
+        let (min, max) = if signed {
+            (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1)
+        } else {
+            (0, (1i128 << bits) - 1)
+        };
+        if value < min || value > max {
+            return Err(Error::Range);
+        }
+        Ok(())
+    }
+
+    pub fn swap_bytes(data: &[u8]) -> Vec<u8> {
+        data.iter().rev().copied().collect()
+    }
+
+    pub fn swap_bits_in_bytes(data: &[u8]) -> Vec<u8> {
+        data.iter().map(|b| b.reverse_bits()).collect()
+    }
+
+    pub fn xor(data: &[u8], pad: &[u8]) -> Vec<u8> {
+        data.iter().zip(pad.iter().cycle()).map(|(b, p)| b ^ p).collect()
+    }
+
+    /// Strip trailing padding units, as `PaddedString` does.
+    pub fn strip_padding(mut data: &[u8], unit: usize) -> &[u8] {
+        while data.len() >= unit && data[data.len() - unit..].iter().all(|&b| b == 0) && !data.is_empty() {
+            data = &data[..data.len() - unit];
+        }
+        data
+    }
+
+    pub fn pad(mut data: Vec<u8>, length: usize) -> Result<Vec<u8>, Error> {
+        if data.len() > length {
+            return Err(Error::String);
+        }
+        data.resize(length, 0);
+        Ok(data)
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    pub enum Encoding {
+        Ascii,
+        Utf8,
+        Utf16Le,
+        Utf16Be,
+        Utf32Le,
+        Utf32Be,
+    }
+
+    impl Encoding {
+        pub fn unit(self) -> usize {
+            match self {
+                Encoding::Ascii | Encoding::Utf8 => 1,
+                Encoding::Utf16Le | Encoding::Utf16Be => 2,
+                Encoding::Utf32Le | Encoding::Utf32Be => 4,
+            }
+        }
+
+        pub fn decode(self, data: &[u8]) -> Result<String, Error> {
+            match self {
+                Encoding::Ascii if !data.is_ascii() => Err(Error::String),
+                Encoding::Ascii | Encoding::Utf8 => String::from_utf8(data.to_vec()).map_err(|_| Error::String),
+                Encoding::Utf16Le | Encoding::Utf16Be => {
+                    if data.len() % 2 != 0 {
+                        return Err(Error::String);
+                    }
+                    let units = data.chunks(2).map(|c| match self {
+                        Encoding::Utf16Le => u16::from_le_bytes([c[0], c[1]]),
+                        _ => u16::from_be_bytes([c[0], c[1]]),
+                    });
+                    char::decode_utf16(units).collect::<Result<String, _>>().map_err(|_| Error::String)
+                }
+                Encoding::Utf32Le | Encoding::Utf32Be => {
+                    if data.len() % 4 != 0 {
+                        return Err(Error::String);
+                    }
+                    data.chunks(4)
+                        .map(|c| {
+                            let c = [c[0], c[1], c[2], c[3]];
+                            let code = match self {
+                                Encoding::Utf32Le => u32::from_le_bytes(c),
+                                _ => u32::from_be_bytes(c),
+                            };
+                            char::from_u32(code).ok_or(Error::String)
+                        })
+                        .collect()
+                }
+            }
+        }
+
+        pub fn encode(self, text: &str) -> Result<Vec<u8>, Error> {
+            Ok(match self {
+                Encoding::Ascii if !text.is_ascii() => return Err(Error::String),
+                Encoding::Ascii | Encoding::Utf8 => text.as_bytes().to_vec(),
+                Encoding::Utf16Le => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+                Encoding::Utf16Be => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+                Encoding::Utf32Le => text.chars().flat_map(|c| (c as u32).to_le_bytes()).collect(),
+                Encoding::Utf32Be => text.chars().flat_map(|c| (c as u32).to_be_bytes()).collect(),
+            })
+        }
+
+        /// Number of bytes `c` encodes into.
+        pub fn width(self, c: char) -> usize {
+            match self {
+                Encoding::Ascii | Encoding::Utf8 => c.len_utf8(),
+                Encoding::Utf16Le | Encoding::Utf16Be => 2 * c.len_utf16(),
+                Encoding::Utf32Le | Encoding::Utf32Be => 4,
+            }
+        }
+    }
+
+    /// Source of arbitrary values drawing on raw bytes, as fuzzers provide, which reads
+    /// zeros once they are used up.
+    pub struct Unstructured<'a> {
+        data: &'a [u8],
+    }
+
+    impl<'a> Unstructured<'a> {
+        pub fn new(data: &'a [u8]) -> Self {
+            Unstructured { data }
+        }
+
+        /// Whether the raw bytes are used up.
+        pub fn is_empty(&self) -> bool {
+            self.data.is_empty()
+        }
+
+        pub fn bytes(&mut self, length: usize) -> Vec<u8> {
+            let taken = length.min(self.data.len());
+            let mut bytes = self.data[..taken].to_vec();
+            self.data = &self.data[taken..];
+            bytes.resize(length, 0);
+            bytes
+        }
+
+        /// Up to `max` bytes.
+        pub fn bytes_up_to(&mut self, max: usize) -> Vec<u8> {
+            let length = self.length(max);
+            self.bytes(length)
+        }
+
+        /// Length of up to `max`, which is at most 255.
+        pub fn length(&mut self, max: usize) -> usize {
+            self.bytes(1)[0] as usize % (max + 1)
+        }
+
+        /// Integer of `bits` bits, unsigned ones of 128 bits wrapping around.
+        pub fn int(&mut self, bits: usize, signed: bool) -> i128 {
+            let value = self.bytes(bits.div_ceil(8)).iter().fold(0u128, |value, &b| value << 8 | b as u128);
+            let value = if bits < 128 { value & ((1 << bits) - 1) } else { value };
+            sign_extend(value, bits, signed)
+        }
+
+        /// Finite `f32`, which builds and parses back as equal.
+        pub fn float32(&mut self) -> f32 {
+            Some(f32::from_bits(self.int(32, false) as u32)).filter(|f| f.is_finite()).unwrap_or(0.0)
+        }
+
+        /// Finite `f64`, which builds and parses back as equal.
+        pub fn float64(&mut self) -> f64 {
+            Some(f64::from_bits(self.int(64, false) as u64)).filter(|f| f.is_finite()).unwrap_or(0.0)
+        }
+
+        /// Text encoding into at most `max` bytes, without NUL characters, which strings are
+        /// padded and terminated with.
+        pub fn string(&mut self, encoding: Encoding, max: usize) -> String {
+            let mut text = String::new();
+            let mut size = 0;
+            for _ in 0..self.length(max.min(32)) {
+                let code = self.int(24, false) as u32;
+                let code = match (encoding, code % 4) {
+                    (Encoding::Ascii, _) | (_, 0 | 1) => 1 + (code >> 2) % 0x7f,
+                    (_, 2) => 0x80 + (code >> 2) % 0x780,
+                    _ => 0x800 + (code >> 2) % 0x10f800,
+                };
+                let c = char::from_u32(code).unwrap_or('\u{fffd}');
+                if size + encoding.width(c) > max {
+                    break;
+                }
+                text.push(c);
+                size += encoding.width(c);
+            }
+            text
+        }
+    }
+}
+"#;
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false", "fn",
+    "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "static",
+    "struct", "trait", "true", "type", "unsafe", "use", "where", "while", "abstract", "become", "box", "do",
+    "final", "gen", "macro", "override", "priv", "try", "typeof", "unsized", "virtual", "yield",
+];
+
+/// Snake-case identifier for a member name.
+fn rust_field_ident(name: &str) -> String {
+    let mut ident: String = name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    ident = ident.to_lowercase();
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+    if RUST_KEYWORDS.contains(&ident.as_str()) {
+        ident.insert_str(0, "r#");
+    }
+    ident
+}
+
+/// CamelCase type name for a member name.
+fn rust_type_ident(name: &str) -> String {
+    let ident: String = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars.next().map_or_else(String::new, |first| first.to_ascii_uppercase().to_string() + chars.as_str())
+        })
+        .collect();
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("T{}", ident)
+    } else {
+        ident
+    }
+}
+
+/// Smallest Rust integer type holding `bits` bits.
+fn rust_int_type(bits: usize, signed: bool) -> Option<String> {
+    let width = [8, 16, 32, 64, 128].into_iter().find(|&w| bits <= w)?;
+    Some(format!("{}{}", if signed { 'i' } else { 'u' }, width))
+}
+
+/// Variant of the generated `rt::Encoding` for an encoding name.
+fn rust_encoding(encoding: &str, path: &SchemaPath) -> Result<&'static str, SchemaError> {
+    match encoding.replace('-', "_").to_lowercase().as_str() {
+        "ascii" => Ok("rt::Encoding::Ascii"),
+        "utf8" | "utf_8" | "u8" => Ok("rt::Encoding::Utf8"),
+        "utf_16_le" => Ok("rt::Encoding::Utf16Le"),
+        "utf_16_be" => Ok("rt::Encoding::Utf16Be"),
+        "utf_32_le" => Ok("rt::Encoding::Utf32Le"),
+        "utf_32_be" => Ok("rt::Encoding::Utf32Be"),
+        _ => Err(path.error(format!("encoding {:?} is not supported, use an explicit byte order", encoding))),
+    }
+}
+
+/// Bind the value of a multi-line expression to a local.
+fn bind(ident: &str, mut lines: Vec<String>) -> Vec<String> {
+    lines[0] = format!("let {} = {}", ident, lines[0]);
+    if let Some(last) = lines.last_mut() {
+        last.push(';');
+    }
+    lines
+}
+
+fn indent(lines: &[String]) -> Vec<String> {
+    lines.iter().map(|line| if line.is_empty() { line.clone() } else { format!("    {}", line) }).collect()
+}
+
+/// Generated code for one schema node: its type, the lines of an expression parsing it
+/// from `data` at `offset`, the statements building `value` into `out`, and the lines of
+/// an expression generating an arbitrary value from `u`.
+struct RustCode {
+    ty: String,
+    parse: Vec<String>,
+    build: Vec<String>,
+    arbitrary: Vec<String>,
+}
+
+impl RustCode {
+    fn simple(ty: impl Into<String>, parse: String, build: String, arbitrary: String) -> Self {
+        RustCode { ty: ty.into(), parse: vec![parse], build: vec![build], arbitrary: vec![arbitrary] }
+    }
+}
+
+/// Emits Rust types with `parse`/`build` functions for a schema.
+struct RustGenerator {
+    items: Vec<String>,
+}
+
+impl RustGenerator {
+    fn emit(&mut self, schema: &Schema, typename: &str, path: &SchemaPath) -> Result<RustCode, SchemaError> {
+        Ok(match schema {
+            Schema::FormatField { endian, format } => {
+                let ty = match format {
+                    'B' => "u8",
+                    'H' => "u16",
+                    'L' => "u32",
+                    'Q' => "u64",
+                    'b' => "i8",
+                    'h' => "i16",
+                    'l' => "i32",
+                    'q' => "i64",
+                    'f' => "f32",
+                    'd' => "f64",
+                    _ => return Err(path.error(format!("unknown format {:?}", format))),
+                };
+                let order = match endian {
+                    '>' => "be",
+                    '<' => "le",
+                    _ => "ne",
+                };
+                let arbitrary = match ty {
+                    "f32" => "u.float32()".to_string(),
+                    "f64" => "u.float64()".to_string(),
+                    _ => format!("u.int({}, {}) as {}", ty[1..].parse::<usize>().unwrap(), ty.starts_with('i'), ty),
+                };
+                RustCode::simple(
+                    ty,
+                    format!("{}::from_{}_bytes(rt::take_array(data, offset)?)", ty, order),
+                    format!("out.extend_from_slice(&value.to_{}_bytes());", order),
+                    arbitrary,
+                )
+            }
+            Schema::BytesInteger { length, signed, swapped } => {
+                let ty = rust_int_type(length * 8, *signed)
+                    .filter(|_| *length > 0)
+                    .ok_or_else(|| path.error("only integers of 1 to 16 bytes are supported"))?;
+                RustCode::simple(
+                    ty.clone(),
+                    format!("rt::bytes2integer(rt::take(data, offset, {})?, {}, {}) as {}", length, signed, swapped, ty),
+                    format!("out.extend_from_slice(&rt::integer2bytes(*value as i128, {}, {}, {})?);", length, signed, swapped),
+                    format!("u.int({}, {}) as {}", length * 8, signed, ty),
+                )
+            }
+            Schema::BitsInteger { length, signed, swapped } => {
+                if *swapped && !length.is_multiple_of(8) {
+                    return Err(path.error("little-endianness is only defined for multiples of 8 bits"));
+                }
+                let ty = rust_int_type(*length, *signed)
+                    .filter(|_| *length > 0)
+                    .ok_or_else(|| path.error("only integers of 1 to 128 bits are supported"))?;
+                RustCode::simple(
+                    ty.clone(),
+                    format!("rt::bits2integer(rt::take(data, offset, {})?, {}, {}) as {}", length, signed, swapped, ty),
+                    format!("out.extend_from_slice(&rt::integer2bits(*value as i128, {}, {}, {})?);", length, signed, swapped),
+                    format!("u.int({}, {}) as {}", length, signed, ty),
+                )
+            }
+            Schema::PaddedString { length, encoding } => {
+                let enc = rust_encoding(encoding, path)?;
+                RustCode::simple(
+                    "String",
+                    format!("{}.decode(rt::strip_padding(rt::take(data, offset, {})?, {}.unit()))?", enc, length, enc),
+                    format!("out.extend_from_slice(&rt::pad({}.encode(value)?, {})?);", enc, length),
+                    format!("u.string({}, {})", enc, length),
+                )
+            }
+            Schema::PascalString { lengthfield, encoding } => {
+                let enc = rust_encoding(encoding, path)?;
+                let length = self.emit(lengthfield, typename, &path.join("lengthfield"))?;
+                if !length.ty.starts_with(['u', 'i']) {
+                    return Err(path.join("lengthfield").error("expected an integer field"));
+                }
+                let mut parse = vec!["{".to_string()];
+                parse.extend(indent(&bind("length", length.parse)));
+                parse.extend(indent(&[
+                    "let length = usize::try_from(length).map_err(|_| rt::Error::Range)?;".to_string(),
+                    format!("{}.decode(rt::take(data, offset, length)?)?", enc),
+                ]));
+                parse.push("}".to_string());
+                let mut build = vec!["{".to_string()];
+                build.extend(indent(&[
+                    format!("let encoded = {}.encode(value)?;", enc),
+                    format!("let value = &{}::try_from(encoded.len()).map_err(|_| rt::Error::Range)?;", length.ty),
+                ]));
+                build.extend(indent(&length.build));
+                build.extend(indent(&["out.extend_from_slice(&encoded);".to_string()]));
+                build.push("}".to_string());
+                let max = format!("usize::try_from({}::MAX).unwrap_or(usize::MAX)", length.ty);
+                RustCode { ty: "String".to_string(), parse, build, arbitrary: vec![format!("u.string({}, {})", enc, max)] }
+            }
+            Schema::CString { encoding } => {
+                let enc = rust_encoding(encoding, path)?;
+                RustCode {
+                    ty: "String".to_string(),
+                    parse: vec![format!("{}.decode(rt::take_terminated(data, offset, {}.unit())?)?", enc, enc)],
+                    build: vec![
+                        format!("out.extend_from_slice(&{}.encode(value)?);", enc),
+                        format!("out.resize(out.len() + {}.unit(), 0);", enc),
+                    ],
+                    arbitrary: vec![format!("u.string({}, usize::MAX)", enc)],
+                }
+            }
+            Schema::GreedyString { encoding } => {
+                let enc = rust_encoding(encoding, path)?;
+                RustCode::simple(
+                    "String",
+                    format!("{}.decode(rt::take_rest(data, offset)?)?", enc),
+                    format!("out.extend_from_slice(&{}.encode(value)?);", enc),
+                    format!("u.string({}, usize::MAX)", enc),
+                )
+            }
+            Schema::Bytes { length: SchemaParam::Const(length), .. } => RustCode {
+                ty: "Vec<u8>".to_string(),
+                parse: vec![format!("rt::take(data, offset, {})?.to_vec()", length)],
+                build: vec![
+                    format!("if value.len() != {} {{", length),
+                    "    return Err(rt::Error::Range);".to_string(),
+                    "}".to_string(),
+                    "out.extend_from_slice(value);".to_string(),
+                ],
+                arbitrary: vec![format!("u.bytes({})", length)],
+            },
+            Schema::GreedyBytes { .. } => {
+                RustCode::simple(
+                    "Vec<u8>",
+                    "rt::take_rest(data, offset)?.to_vec()".to_string(),
+                    "out.extend_from_slice(value);".to_string(),
+                    "u.bytes_up_to(32)".to_string(),
+                )
+            }
+            Schema::Sequence(fields) | Schema::Struct(fields) => self.emit_struct(fields, typename, path, false)?,
+            Schema::LazyStruct(fields) => self.emit_struct(fields, typename, path, true)?,
+            Schema::LazyArray { count, subcon } => {
+                let item = self.emit(subcon, &format!("{}Item", typename), path)?;
+                // A block collecting `count` items, each the value of `expr`.
+                let repeat = |expr: &[String]| {
+                    let mut lines = vec!["{".to_string()];
+                    let mut body = vec![format!("let mut items = Vec::with_capacity({});", count), format!("for _ in 0..{} {{", count)];
+                    let mut push = expr.to_vec();
+                    push[0] = format!("items.push({}", push[0]);
+                    let last = push.len() - 1;
+                    push[last].push_str(");");
+                    body.extend(indent(&push));
+                    body.push("}".to_string());
+                    body.push("items".to_string());
+                    lines.extend(indent(&body));
+                    lines.push("}".to_string());
+                    lines
+                };
+                let (parse, arbitrary) = (repeat(&item.parse), repeat(&item.arbitrary));
+                let mut build = vec![format!("if value.len() != {} {{", count), "    return Err(rt::Error::Range);".to_string(), "}".to_string()];
+                build.push("for value in value.iter() {".to_string());
+                build.extend(indent(&item.build));
+                build.push("}".to_string());
+                RustCode { ty: format!("Vec<{}>", item.ty), parse, build, arbitrary }
+            }
+            Schema::Lazy(subcon) | Schema::Hex(subcon) | Schema::HexDump(subcon) => self.emit(subcon, typename, path)?,
+            Schema::ByteSwapped(subcon) => self.emit_region(subcon, typename, path, "rt::swap_bytes(&{})", subcon.sizeof())?,
+            Schema::BitsSwapped(subcon) => {
+                self.emit_region(subcon, typename, path, "rt::swap_bits_in_bytes(&{})", subcon.sizeof())?
+            }
+            Schema::ProcessXor { pad, subcon } => {
+                self.emit_region(subcon, typename, path, &format!("rt::xor(&{{}}, &{:?})", pad), None)?
+            }
+            other => return Err(path.error(format!("{} cannot be generated as Rust", other.type_name()))),
+        })
+    }
+
+    /// Parse and build the subcon over a region of the data transformed by `transform`,
+    /// which is either the subcon's fixed `size` or the rest of the data.
+    fn emit_region(
+        &mut self,
+        subcon: &Schema,
+        typename: &str,
+        path: &SchemaPath,
+        transform: &str,
+        size: Option<usize>,
+    ) -> Result<RustCode, SchemaError> {
+        let inner = self.emit(subcon, typename, path)?;
+        let region = match size {
+            Some(size) => format!("rt::take(data, offset, {})?", size),
+            None => "rt::take_rest(data, offset)?".to_string(),
+        };
+        let mut parse = vec!["{".to_string()];
+        let mut body = vec![
+            format!("let region = {};", transform.replace("{}", &region)),
+            "let (data, offset) = (&region[..], &mut 0usize);".to_string(),
+        ];
+        body.extend(inner.parse);
+        parse.extend(indent(&body));
+        parse.push("}".to_string());
+        let mut build = vec!["{".to_string(), "    let mut region = Vec::new();".to_string(), "    {".to_string()];
+        let mut body = vec!["let out = &mut region;".to_string()];
+        body.extend(inner.build);
+        build.extend(indent(&indent(&body)));
+        build.push("    }".to_string());
+        build.push(format!("    out.extend_from_slice(&{});", transform.replace("{}", "region")));
+        build.push("}".to_string());
+        Ok(RustCode { ty: inner.ty, parse, build, arbitrary: inner.arbitrary })
+    }
+
+    /// Emit a struct (named fields) or tuple struct (sequence) type for a composite.
+    fn emit_struct(
+        &mut self,
+        fields: &[SchemaField],
+        typename: &str,
+        path: &SchemaPath,
+        named: bool,
+    ) -> Result<RustCode, SchemaError> {
+        let mut defs = Vec::new();
+        let mut parses = Vec::new();
+        let mut builds = Vec::new();
+        let mut arbitraries = Vec::new();
+        let mut idents = Vec::new();
+        let mut locals = Vec::new();
+        for (i, field) in fields.iter().enumerate() {
+            let fieldpath = path.member(i, field.name.as_deref());
+            let nested = format!("{}{}", typename, field.name.as_deref().map_or_else(|| i.to_string(), rust_type_ident));
+            let code = self.emit(&field.schema, &nested, &fieldpath)?;
+            let ident = match (&field.name, named) {
+                (Some(name), true) => rust_field_ident(name),
+                (None, true) => format!("unnamed_{}", i),
+                (_, false) => format!("field_{}", i),
+            };
+            if idents.contains(&ident) {
+                return Err(fieldpath.error(format!("duplicate field name {:?}", ident)));
+            }
+            // Named fields are prefixed, so that they cannot shadow the parameters and locals
+            // of the generated code.
+            let local = if named { format!("f_{}", ident.trim_start_matches("r#")) } else { ident.clone() };
+            defs.push(if named { format!("pub {}: {},", ident, code.ty) } else { format!("pub {},", code.ty) });
+            parses.extend(bind(&local, code.parse));
+            arbitraries.extend(bind(&local, code.arbitrary));
+            let access = if named { ident.clone() } else { i.to_string() };
+            builds.push("{".to_string());
+            builds.push(format!("    let value = &self.{};", access));
+            builds.extend(indent(&code.build));
+            builds.push("}".to_string());
+            idents.push(ident);
+            locals.push(local);
+        }
+        let mut item = vec!["#[derive(Debug, Clone, PartialEq)]".to_string()];
+        if named {
+            item.push(format!("pub struct {} {{", typename));
+            item.extend(indent(&defs));
+            item.push("}".to_string());
+        } else {
+            item.push(format!("pub struct {}(", typename));
+            item.extend(indent(&defs));
+            item.push(");".to_string());
+        }
+        let construct = if named {
+            let inits: Vec<String> = idents.iter().zip(&locals).map(|(ident, local)| format!("{}: {}", ident, local)).collect();
+            format!("{} {{ {} }}", typename, inits.join(", "))
+        } else {
+            format!("{}({})", typename, locals.join(", "))
+        };
+        item.push(String::new());
+        item.push(format!("impl {} {{", typename));
+        let mut body = vec![
+            "/// Parse from the start of `data`, returning the value and the number of bytes consumed.".to_string(),
+            "pub fn parse(data: &[u8]) -> Result<(Self, usize), rt::Error> {".to_string(),
+            "    let mut offset = 0;".to_string(),
+            "    let value = Self::parse_from(data, &mut offset)?;".to_string(),
+            "    Ok((value, offset))".to_string(),
+            "}".to_string(),
+            String::new(),
+            "/// Parse at `offset` within `data`, advancing it past the parsed bytes.".to_string(),
+            "pub fn parse_from(data: &[u8], offset: &mut usize) -> Result<Self, rt::Error> {".to_string(),
+        ];
+        body.extend(indent(&parses));
+        body.push(format!("    Ok({})", construct));
+        body.extend([
+            "}".to_string(),
+            String::new(),
+            "/// Build into a new vector of bytes.".to_string(),
+            "pub fn build(&self) -> Result<Vec<u8>, rt::Error> {".to_string(),
+            "    let mut out = Vec::new();".to_string(),
+            "    self.build_into(&mut out)?;".to_string(),
+            "    Ok(out)".to_string(),
+            "}".to_string(),
+            String::new(),
+            "/// Build by appending to `out`.".to_string(),
+            "pub fn build_into(&self, out: &mut Vec<u8>) -> Result<(), rt::Error> {".to_string(),
+        ]);
+        body.extend(indent(&builds));
+        body.extend([
+            "    Ok(())".to_string(),
+            "}".to_string(),
+            String::new(),
+            "/// Generate an arbitrary value from the raw bytes `u` draws on, as fuzzers provide.".to_string(),
+            "pub fn arbitrary(u: &mut rt::Unstructured<'_>) -> Self {".to_string(),
+        ]);
+        body.extend(indent(&arbitraries));
+        body.extend([format!("    {}", construct), "}".to_string()]);
+        item.extend(indent(&body));
+        item.push("}".to_string());
+        self.items.push(item.join("\n"));
+        Ok(RustCode::simple(
+            typename,
+            format!("{}::parse_from(data, offset)?", typename),
+            "value.build_into(out)?;".to_string(),
+            format!("{}::arbitrary(u)", typename),
+        ))
+    }
+}
+
+/// Generate standalone Rust source with a type named `name` (and nested types)
+/// for the schema, with `parse`, `build` and `arbitrary` functions.
+///
+/// Non-composite schemas are wrapped in a single-field tuple struct.
+pub fn generate_rust(schema: &Schema, name: &str) -> Result<String, SchemaError> {
+    let mut generator = RustGenerator { items: Vec::new() };
+    let typename = rust_type_ident(name);
+    match schema {
+        Schema::Sequence(_) | Schema::LazyStruct(_) | Schema::Struct(_) => generator.emit(schema, &typename, &SchemaPath::root())?,
+        _ => {
+            let field = SchemaField { name: None, schema: schema.clone() };
+            generator.emit_struct(&[field], &typename, &SchemaPath::root(), false)?
+        }
+    };
+    let mut source = String::from("// Generated by construct-rs from a construct schema.\n\n");
+    source.push_str(RUST_RUNTIME);
+    for item in &generator.items {
+        source.push('\n');
+        source.push_str(item);
+        source.push('\n');
+    }
+    Ok(source)
+}
+
+// ========================= YAML documents ============================
+
+/// Minimal YAML document model, enough for Kaitai Struct files and schema descriptions.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Yaml {
+    Null,
+    Bool(bool),
+    Int(i128),
+    Float(f64),
+    Str(String),
+    List(Vec<Yaml>),
+    Map(Vec<(String, Yaml)>),
+}
+
+impl Yaml {
+    fn str(text: impl Into<String>) -> Yaml {
+        Yaml::Str(text.into())
+    }
+
+    /// Value of `key` in a mapping.
+    pub fn get(&self, key: &str) -> Option<&Yaml> {
+        match self {
+            Yaml::Map(items) => items.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Render as a block-style YAML document.
+    pub fn to_yaml(&self) -> String {
+        let mut out = String::new();
+        match self {
+            Yaml::Map(items) if !items.is_empty() => yaml_map(&mut out, items, 0),
+            Yaml::List(items) if !items.is_empty() => yaml_list(&mut out, items, 0),
+            scalar => {
+                out.push_str(&yaml_scalar(scalar));
+                out.push('\n');
+            }
+        }
+        out
+    }
+}
+
+fn yaml_scalar(value: &Yaml) -> String {
+    match value {
+        Yaml::Null => "null".to_string(),
+        Yaml::Bool(b) => b.to_string(),
+        Yaml::Int(i) => i.to_string(),
+        Yaml::Float(f) => format!("{:?}", f),
+        Yaml::Str(s) => {
+            let plain = !s.is_empty()
+                && s.chars().all(|c| c.is_ascii_alphanumeric() || "_.-/".contains(c))
+                && !s.starts_with(['-', '.'])
+                && !s.starts_with(|c: char| c.is_ascii_digit())
+                && !["true", "false", "null", "yes", "no", "on", "off", "y", "n", "~"].contains(&s.to_lowercase().as_str());
+            if plain { s.clone() } else { format!("'{}'", s.replace('\'', "''")) }
+        }
+        Yaml::List(items) if items.is_empty() => "[]".to_string(),
+        Yaml::Map(items) if items.is_empty() => "{}".to_string(),
+        _ => unreachable!("collections are rendered in block style"),
+    }
+}
+
+fn yaml_map(out: &mut String, items: &[(String, Yaml)], indent: usize) {
+    for (i, (key, value)) in items.iter().enumerate() {
+        if i > 0 {
+            out.push_str(&" ".repeat(indent));
+        }
+        yaml_entry(out, key, value, indent);
+    }
+}
+
+fn yaml_entry(out: &mut String, key: &str, value: &Yaml, indent: usize) {
+    out.push_str(&yaml_scalar(&Yaml::str(key)));
+    out.push(':');
+    match value {
+        Yaml::Map(items) if !items.is_empty() => {
+            out.push('\n');
+            out.push_str(&" ".repeat(indent + 2));
+            yaml_map(out, items, indent + 2);
+        }
+        Yaml::List(items) if !items.is_empty() => {
+            out.push('\n');
+            out.push_str(&" ".repeat(indent + 2));
+            yaml_list(out, items, indent + 2);
+        }
+        scalar => {
+            out.push(' ');
+            out.push_str(&yaml_scalar(scalar));
+            out.push('\n');
+        }
+    }
+}
+
+fn yaml_list(out: &mut String, items: &[Yaml], indent: usize) {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push_str(&" ".repeat(indent));
+        }
+        out.push_str("- ");
+        match item {
+            Yaml::Map(entries) if !entries.is_empty() => yaml_map(out, entries, indent + 2),
+            Yaml::List(entries) if !entries.is_empty() => yaml_list(out, entries, indent + 2),
+            scalar => {
+                out.push_str(&yaml_scalar(scalar));
+                out.push('\n');
+            }
+        }
+    }
+}
+
+/// Line of a YAML document, with comments removed from `text`.
+struct YamlLine {
+    number: usize,
+    indent: usize,
+    text: String,
+    raw: String,
+}
+
+/// Strip a trailing `# comment` that is not inside quotes.
+fn yaml_strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut prev = ' ';
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == '#' && prev.is_whitespace() => return &line[..i],
+            None => {}
+        }
+        prev = c;
+    }
+    line
+}
+
+/// Position of the `:` separating a mapping key from its value, outside of quotes
+/// and flow collections.
+fn yaml_key_end(text: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut depth = 0;
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    for (n, &(i, c)) in chars.iter().enumerate() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '\'' | '"' => quote = Some(c),
+                '[' | '{' => depth += 1,
+                ']' | '}' => depth -= 1,
+                ':' if depth == 0 && chars.get(n + 1).is_none_or(|&(_, next)| next == ' ') => return Some(i),
+                _ => {}
+            },
+        }
+    }
+    None
+}
+
+/// Value of a plain scalar, following the YAML 1.2 core schema.
+fn yaml_plain(text: &str) -> Yaml {
+    match text {
+        "" | "~" | "null" | "Null" | "NULL" => return Yaml::Null,
+        "true" | "True" | "TRUE" => return Yaml::Bool(true),
+        "false" | "False" | "FALSE" => return Yaml::Bool(false),
+        _ => {}
+    }
+    let (sign, digits) = match text.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let int = match digits.get(..2) {
+        Some("0x") => i128::from_str_radix(&digits[2..], 16).ok(),
+        Some("0o") => i128::from_str_radix(&digits[2..], 8).ok(),
+        _ if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) => digits.parse().ok(),
+        _ => None,
+    };
+    if let Some(int) = int {
+        return Yaml::Int(sign * int);
+    }
+    let numeric = text.chars().any(|c| c.is_ascii_digit()) && text.chars().all(|c| c.is_ascii_digit() || "+-.eE".contains(c));
+    match text.parse::<f64>() {
+        Ok(float) if numeric => Yaml::Float(float),
+        _ => Yaml::Str(text.to_string()),
+    }
+}
+
+/// Parser of flow-style values: scalars, `[a, b]` and `{a: b}`.
+struct YamlFlow<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    line: &'a YamlLine,
+}
+
+impl YamlFlow<'_> {
+    fn error(&self, message: impl Into<String>) -> SchemaError {
+        SchemaError { path: format!("line {}", self.line.number), message: message.into() }
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self, nested: bool) -> Result<Yaml, SchemaError> {
+        self.skip_spaces();
+        match self.chars.get(self.pos) {
+            Some('[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                loop {
+                    self.skip_spaces();
+                    if self.chars.get(self.pos) == Some(&']') {
+                        self.pos += 1;
+                        return Ok(Yaml::List(items));
+                    }
+                    items.push(self.value(true)?);
+                    self.skip_spaces();
+                    match self.chars.get(self.pos) {
+                        Some(',') => self.pos += 1,
+                        Some(']') => {}
+                        _ => return Err(self.error("expected ',' or ']' in flow sequence")),
+                    }
+                }
+            }
+            Some('{') => {
+                self.pos += 1;
+                let mut items: Vec<(String, Yaml)> = Vec::new();
+                loop {
+                    self.skip_spaces();
+                    if self.chars.get(self.pos) == Some(&'}') {
+                        self.pos += 1;
+                        return Ok(Yaml::Map(items));
+                    }
+                    let key = match self.value(true)? {
+                        Yaml::Str(key) => key,
+                        other => yaml_scalar(&other),
+                    };
+                    self.skip_spaces();
+                    if self.chars.get(self.pos) != Some(&':') {
+                        return Err(self.error("expected ':' in flow mapping"));
+                    }
+                    self.pos += 1;
+                    items.push((key, self.value(true)?));
+                    self.skip_spaces();
+                    match self.chars.get(self.pos) {
+                        Some(',') => self.pos += 1,
+                        Some('}') => {}
+                        _ => return Err(self.error("expected ',' or '}' in flow mapping")),
+                    }
+                }
+            }
+            Some(&quote @ ('\'' | '"')) => {
+                self.pos += 1;
+                let mut text = String::new();
+                loop {
+                    let c = *self.chars.get(self.pos).ok_or_else(|| self.error("unterminated quoted string"))?;
+                    self.pos += 1;
+                    match c {
+                        '\'' if quote == '\'' && self.chars.get(self.pos) == Some(&'\'') => {
+                            self.pos += 1;
+                            text.push('\'');
+                        }
+                        _ if c == quote => return Ok(Yaml::Str(text)),
+                        '\\' if quote == '"' => {
+                            let escaped = *self.chars.get(self.pos).ok_or_else(|| self.error("unterminated quoted string"))?;
+                            self.pos += 1;
+                            text.push(match escaped {
+                                'n' => '\n',
+                                't' => '\t',
+                                'r' => '\r',
+                                '0' => '\0',
+                                other => other,
+                            });
+                        }
+                        _ => text.push(c),
+                    }
+                }
+            }
+            _ => {
+                let start = self.pos;
+                while let Some(&c) = self.chars.get(self.pos) {
+                    if nested && (c == ',' || c == ']' || c == '}' || (c == ':' && self.chars.get(self.pos + 1).is_none_or(|n| *n == ' '))) {
+                        break;
+                    }
+                    self.pos += 1;
+                }
+                let text: String = self.chars[start..self.pos].iter().collect();
+                Ok(yaml_plain(text.trim()))
+            }
+        }
+    }
+}
+
+/// Block-structure parser over the lines of a document.
+struct YamlParser {
+    lines: Vec<YamlLine>,
+    pos: usize,
+}
+
+impl YamlParser {
+    fn current(&mut self) -> Option<&YamlLine> {
+        while self.lines.get(self.pos).is_some_and(|line| line.text.is_empty()) {
+            self.pos += 1;
+        }
+        self.lines.get(self.pos)
+    }
+
+    fn error(line: &YamlLine, message: impl Into<String>) -> SchemaError {
+        SchemaError { path: format!("line {}", line.number), message: message.into() }
+    }
+
+    fn is_item(text: &str) -> bool {
+        text == "-" || text.starts_with("- ")
+    }
+
+    fn flow(line: &YamlLine, text: &str) -> Result<Yaml, SchemaError> {
+        let mut flow = YamlFlow { chars: text.chars().collect(), pos: 0, line };
+        let value = flow.value(false)?;
+        flow.skip_spaces();
+        if flow.pos != flow.chars.len() {
+            return Err(flow.error("unexpected characters after value"));
+        }
+        Ok(value)
+    }
+
+    fn node(&mut self) -> Result<Yaml, SchemaError> {
+        let Some(line) = self.current() else { return Ok(Yaml::Null) };
+        let indent = line.indent;
+        if YamlParser::is_item(&line.text) {
+            return self.sequence(indent);
+        }
+        if yaml_key_end(&line.text).is_some() {
+            return self.mapping(indent);
+        }
+        let line = &self.lines[self.pos];
+        let value = YamlParser::flow(line, &line.text)?;
+        self.pos += 1;
+        Ok(value)
+    }
+
+    /// Value of a key or item whose content starts on the following lines.
+    fn nested(&mut self, indent: usize) -> Result<Yaml, SchemaError> {
+        match self.current() {
+            Some(line) if line.indent > indent => self.node(),
+            _ => Ok(Yaml::Null),
+        }
+    }
+
+    fn sequence(&mut self, indent: usize) -> Result<Yaml, SchemaError> {
+        let mut items = Vec::new();
+        while let Some(line) = self.current() {
+            if line.indent != indent || !YamlParser::is_item(&line.text) {
+                break;
+            }
+            let rest = line.text[1..].trim_start().to_string();
+            if rest.is_empty() {
+                self.pos += 1;
+                items.push(self.nested(indent)?);
+                continue;
+            }
+            let offset = line.text.len() - rest.len();
+            let line = &mut self.lines[self.pos];
+            line.indent += offset;
+            line.text = rest;
+            items.push(self.node()?);
+        }
+        Ok(Yaml::List(items))
+    }
+
+    fn mapping(&mut self, indent: usize) -> Result<Yaml, SchemaError> {
+        let mut items: Vec<(String, Yaml)> = Vec::new();
+        while let Some(line) = self.current() {
+            if line.indent < indent {
+                break;
+            }
+            if line.indent > indent {
+                return Err(YamlParser::error(line, "unexpected indentation"));
+            }
+            if YamlParser::is_item(&line.text) {
+                break;
+            }
+            let Some(end) = yaml_key_end(&line.text) else {
+                return Err(YamlParser::error(line, "expected a mapping key"));
+            };
+            let key = match YamlParser::flow(line, line.text[..end].trim())? {
+                Yaml::Str(key) => key,
+                other => yaml_scalar(&other),
+            };
+            if items.iter().any(|(existing, _)| *existing == key) {
+                return Err(YamlParser::error(line, format!("duplicate key {:?}", key)));
+            }
+            let value = line.text[end + 1..].trim().to_string();
+            self.pos += 1;
+            let value = if value.is_empty() {
+                match self.current() {
+                    Some(next) if next.indent == indent && YamlParser::is_item(&next.text) => self.sequence(indent)?,
+                    _ => self.nested(indent)?,
+                }
+            } else if value.starts_with(['|', '>']) {
+                self.block_scalar(indent, &value)
+            } else {
+                YamlParser::flow(&self.lines[self.pos - 1], &value)?
+            };
+            items.push((key, value));
+        }
+        Ok(Yaml::Map(items))
+    }
+
+    /// Literal (`|`) or folded (`>`) block scalar following a key.
+    fn block_scalar(&mut self, indent: usize, header: &str) -> Yaml {
+        let mut lines = Vec::new();
+        let mut block_indent = None;
+        while let Some(line) = self.lines.get(self.pos) {
+            if line.raw.trim().is_empty() {
+                lines.push(String::new());
+                self.pos += 1;
+                continue;
+            }
+            let line_indent = line.raw.len() - line.raw.trim_start().len();
+            if line_indent <= indent {
+                break;
+            }
+            let block_indent = *block_indent.get_or_insert(line_indent);
+            lines.push(line.raw.get(block_indent.min(line_indent)..).unwrap_or("").to_string());
+            self.pos += 1;
+        }
+        while lines.last().is_some_and(|line| line.is_empty()) {
+            lines.pop();
+        }
+        let mut text = if header.starts_with('>') { lines.join(" ") } else { lines.join("\n") };
+        if !header.contains('-') {
+            text.push('\n');
+        }
+        Yaml::Str(text)
+    }
+}
+
+impl Yaml {
+    /// Parse a YAML document made of block mappings and sequences, flow collections,
+    /// quoted and plain scalars, and literal or folded block scalars.
+    pub fn parse(text: &str) -> Result<Yaml, SchemaError> {
+        let mut lines = Vec::new();
+        for (i, raw) in text.lines().enumerate() {
+            let content = yaml_strip_comment(raw).trim_end();
+            let trimmed = content.trim_start();
+            let line = YamlLine { number: i + 1, indent: content.len() - trimmed.len(), text: trimmed.to_string(), raw: raw.to_string() };
+            if content[..line.indent].contains('\t') {
+                return Err(YamlParser::error(&line, "tabs are not allowed in indentation"));
+            }
+            let marker = matches!(trimmed, "---" | "...") || trimmed.starts_with('%');
+            lines.push(if marker { YamlLine { text: String::new(), ..line } } else { line });
+        }
+        let mut parser = YamlParser { lines, pos: 0 };
+        let document = parser.node()?;
+        if let Some(line) = parser.current() {
+            return Err(YamlParser::error(line, "unexpected content after the document"));
+        }
+        Ok(document)
+    }
+}
+
+// ========================= JSON documents ============================
+
+/// Parser of JSON text into the `Yaml` document model.
+struct JsonParser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl JsonParser {
+    fn error(&self, message: impl Into<String>) -> SchemaError {
+        SchemaError { path: format!("line {}", self.line), message: message.into() }
+    }
+
+    fn skip_spaces(&mut self) {
+        while let Some(&c) = self.chars.get(self.pos) {
+            if !c.is_whitespace() {
+                break;
+            }
+            if c == '\n' {
+                self.line += 1;
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), SchemaError> {
+        self.skip_spaces();
+        if self.chars.get(self.pos) != Some(&expected) {
+            return Err(self.error(format!("expected {:?}", expected)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// Consume `close`, or a `,` followed by another element, returning whether the
+    /// collection is finished.
+    fn separator(&mut self, close: char) -> Result<bool, SchemaError> {
+        self.skip_spaces();
+        match self.chars.get(self.pos) {
+            Some(&c) if c == close => {
+                self.pos += 1;
+                Ok(true)
+            }
+            Some(',') => {
+                self.pos += 1;
+                Ok(false)
+            }
+            _ => Err(self.error(format!("expected ',' or {:?}", close))),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, SchemaError> {
+        self.expect('"')?;
+        let mut text = String::new();
+        loop {
+            let c = *self.chars.get(self.pos).ok_or_else(|| self.error("unterminated string"))?;
+            self.pos += 1;
+            match c {
+                '"' => return Ok(text),
+                '\n' => return Err(self.error("unterminated string")),
+                '\\' => {
+                    let escaped = *self.chars.get(self.pos).ok_or_else(|| self.error("unterminated string"))?;
+                    self.pos += 1;
+                    text.push(match escaped {
+                        '"' | '\\' | '/' => escaped,
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => {
+                            let hex: String = self.chars.iter().skip(self.pos).take(4).collect();
+                            let code = u32::from_str_radix(&hex, 16).map_err(|_| self.error("invalid \\u escape"))?;
+                            self.pos += 4;
+                            char::from_u32(code).ok_or_else(|| self.error("invalid \\u escape"))?
+                        }
+                        other => return Err(self.error(format!("invalid escape \\{}", other))),
+                    });
+                }
+                _ => text.push(c),
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Yaml, SchemaError> {
+        self.skip_spaces();
+        match self.chars.get(self.pos) {
+            Some('{') => {
+                self.pos += 1;
+                let mut items: Vec<(String, Yaml)> = Vec::new();
+                self.skip_spaces();
+                if self.chars.get(self.pos) == Some(&'}') {
+                    self.pos += 1;
+                    return Ok(Yaml::Map(items));
+                }
+                loop {
+                    self.skip_spaces();
+                    let key = self.string()?;
+                    if items.iter().any(|(existing, _)| *existing == key) {
+                        return Err(self.error(format!("duplicate key {:?}", key)));
+                    }
+                    self.expect(':')?;
+                    items.push((key, self.value()?));
+                    if self.separator('}')? {
+                        return Ok(Yaml::Map(items));
+                    }
+                }
+            }
+            Some('[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.skip_spaces();
+                if self.chars.get(self.pos) == Some(&']') {
+                    self.pos += 1;
+                    return Ok(Yaml::List(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    if self.separator(']')? {
+                        return Ok(Yaml::List(items));
+                    }
+                }
+            }
+            Some('"') => Ok(Yaml::Str(self.string()?)),
+            Some(_) => {
+                let start = self.pos;
+                while self.chars.get(self.pos).is_some_and(|c| c.is_ascii_alphanumeric() || "+-.".contains(*c)) {
+                    self.pos += 1;
+                }
+                let text: String = self.chars[start..self.pos].iter().collect();
+                match text.as_str() {
+                    "null" => Ok(Yaml::Null),
+                    "true" => Ok(Yaml::Bool(true)),
+                    "false" => Ok(Yaml::Bool(false)),
+                    _ => match (text.parse::<i128>(), text.parse::<f64>()) {
+                        (Ok(int), _) => Ok(Yaml::Int(int)),
+                        (_, Ok(float)) if text.starts_with(|c: char| c == '-' || c.is_ascii_digit()) => Ok(Yaml::Float(float)),
+                        _ => Err(self.error(format!("unexpected {:?}", text.chars().next().unwrap_or(self.chars[start])))),
+                    },
+                }
+            }
+            None => Err(self.error("unexpected end of document")),
+        }
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_value(out: &mut String, value: &Yaml, indent: usize) {
+    let pad = " ".repeat(indent + 2);
+    match value {
+        Yaml::Null => out.push_str("null"),
+        Yaml::Bool(b) => out.push_str(&b.to_string()),
+        Yaml::Int(i) => out.push_str(&i.to_string()),
+        Yaml::Float(f) => out.push_str(&format!("{:?}", f)),
+        Yaml::Str(s) => out.push_str(&json_string(s)),
+        Yaml::List(items) if items.is_empty() => out.push_str("[]"),
+        Yaml::Map(items) if items.is_empty() => out.push_str("{}"),
+        Yaml::List(items) => {
+            out.push_str("[\n");
+            for (i, item) in items.iter().enumerate() {
+                out.push_str(&pad);
+                json_value(out, item, indent + 2);
+                out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+            }
+            out.push_str(&" ".repeat(indent));
+            out.push(']');
+        }
+        Yaml::Map(items) => {
+            out.push_str("{\n");
+            for (i, (key, item)) in items.iter().enumerate() {
+                out.push_str(&pad);
+                out.push_str(&json_string(key));
+                out.push_str(": ");
+                json_value(out, item, indent + 2);
+                out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+            }
+            out.push_str(&" ".repeat(indent));
+            out.push('}');
+        }
+    }
+}
+
+impl Yaml {
+    /// Parse a JSON document.
+    pub fn parse_json(text: &str) -> Result<Yaml, SchemaError> {
+        let mut parser = JsonParser { chars: text.chars().collect(), pos: 0, line: 1 };
+        let document = parser.value()?;
+        parser.skip_spaces();
+        if parser.pos != parser.chars.len() {
+            return Err(parser.error("unexpected content after the document"));
+        }
+        Ok(document)
+    }
+
+    /// Render as an indented JSON document.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        json_value(&mut out, self, 0);
+        out.push('\n');
+        out
+    }
+}
+
+// ========================= Kaitai Struct export ======================
+
+/// Kaitai identifier for a member name: lowercase letters, digits and underscores,
+/// starting with a letter.
+fn ksy_ident(name: &str) -> String {
+    let mut ident: String = name.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' }).collect();
+    if !ident.starts_with(|c: char| c.is_ascii_lowercase()) {
+        ident.insert_str(0, "f_");
+    }
+    ident
+}
+
+/// Kaitai name of an encoding.
+fn ksy_encoding(encoding: &str, path: &SchemaPath) -> Result<Yaml, SchemaError> {
+    let name = match encoding.replace('-', "_").to_lowercase().as_str() {
+        "ascii" => "ASCII",
+        "utf8" | "utf_8" | "u8" => "UTF-8",
+        "utf16" | "utf_16" | "u16" => "UTF-16",
+        "utf_16_le" => "UTF-16LE",
+        "utf_16_be" => "UTF-16BE",
+        "utf32" | "utf_32" | "u32" => "UTF-32",
+        "utf_32_le" => "UTF-32LE",
+        "utf_32_be" => "UTF-32BE",
+        _ => return Err(path.error(format!("encoding {:?} has no Kaitai equivalent", encoding))),
+    };
+    Ok(Yaml::str(name))
+}
+
+/// Types, enums and instances declared by the Kaitai type currently being generated.
+#[derive(Default)]
+struct KsyScope {
+    types: Vec<(String, Yaml)>,
+    enums: Vec<(String, Yaml)>,
+    instances: Vec<(String, Yaml)>,
+}
+
+/// Add a declaration named after `hint`, numbered if the name is taken, returning its name.
+fn ksy_declare(declarations: &mut Vec<(String, Yaml)>, hint: &str, spec: Yaml) -> String {
+    let mut name = hint.to_string();
+    let mut n = 1;
+    while declarations.iter().any(|(existing, _)| *existing == name) {
+        n += 1;
+        name = format!("{}_{}", hint, n);
+    }
+    declarations.push((name.clone(), spec));
+    name
+}
+
+impl KsyScope {
+    fn add_type(&mut self, hint: &str, spec: Yaml) -> String {
+        ksy_declare(&mut self.types, hint, spec)
+    }
+
+    fn add_enum(&mut self, hint: &str, spec: Yaml) -> String {
+        ksy_declare(&mut self.enums, hint, spec)
+    }
+}
+
+/// Kaitai value of a constant or expression parameter.
+fn ksy_param(param: &SchemaParam) -> Yaml {
+    match param {
+        SchemaParam::Const(value) => Yaml::Int(*value),
+        SchemaParam::Expr(source) => Yaml::Str(source.clone()),
+    }
+}
+
+type KsyEntry = Vec<(String, Yaml)>;
+
+/// Generates Kaitai Struct declarations for a schema, mirroring `construct.core.KsyGen`.
+struct KsyGenerator {
+    nextid: usize,
+}
+
+impl KsyGenerator {
+    fn allocate_id(&mut self) -> usize {
+        self.nextid += 1;
+        self.nextid
+    }
+
+    /// A user type with its own `seq`, `instances` and nested `types`.
+    fn user_type(&mut self, fields: &[SchemaField], path: &SchemaPath) -> Result<Yaml, SchemaError> {
+        let mut scope = KsyScope::default();
+        let mut seq = Vec::new();
+        for (i, field) in fields.iter().enumerate() {
+            let fieldpath = path.member(i, field.name.as_deref());
+            for entry in self.entries(field.name.as_deref().map(ksy_ident), &field.schema, &mut scope, &fieldpath)? {
+                seq.push(Yaml::Map(entry));
+            }
+        }
+        Ok(KsyGenerator::type_spec(seq, scope))
+    }
+
+    fn type_spec(seq: Vec<Yaml>, scope: KsyScope) -> Yaml {
+        let mut spec = vec![("seq".to_string(), Yaml::List(seq))];
+        if !scope.instances.is_empty() {
+            spec.push(("instances".to_string(), Yaml::Map(scope.instances)));
+        }
+        if !scope.types.is_empty() {
+            spec.push(("types".to_string(), Yaml::Map(scope.types)));
+        }
+        if !scope.enums.is_empty() {
+            spec.push(("enums".to_string(), Yaml::Map(scope.enums)));
+        }
+        Yaml::Map(spec)
+    }
+
+    /// Wrap a schema needing several seq entries into a new user type.
+    fn wrapped(&mut self, schema: &Schema, scope: &mut KsyScope, path: &SchemaPath) -> Result<KsyEntry, SchemaError> {
+        let field = SchemaField { name: Some("value".to_string()), schema: schema.clone() };
+        let spec = self.user_type(&[field], path)?;
+        let name = scope.add_type(&format!("type_{}", self.allocate_id()), spec);
+        Ok(vec![("type".to_string(), Yaml::str(name))])
+    }
+
+    /// Name of a type for the schema, as switch cases need: its own type when a single
+    /// `type` attribute describes it, otherwise a new user type.
+    fn type_name(&mut self, schema: &Schema, scope: &mut KsyScope, path: &SchemaPath) -> Result<Yaml, SchemaError> {
+        let entry = match self.attrs(schema, scope, path)? {
+            entry if matches!(entry.as_slice(), [(key, Yaml::Str(_))] if key == "type") => entry,
+            _ => self.wrapped(schema, scope, path)?,
+        };
+        Ok(entry.into_iter().next().unwrap().1)
+    }
+
+    /// Attributes of a single item of a repeat, wrapped into a new user type when the
+    /// item itself repeats or is conditional.
+    fn item_attrs(&mut self, subcon: &Schema, scope: &mut KsyScope, path: &SchemaPath) -> Result<KsyEntry, SchemaError> {
+        let entry = self.attrs(subcon, scope, path)?;
+        if entry.iter().any(|(key, _)| key == "repeat" || key == "if") {
+            return self.wrapped(subcon, scope, path);
+        }
+        Ok(entry)
+    }
+
+    /// Integer type name like `u4le`, or `None` when Kaitai has no such type.
+    fn int_type(length: usize, signed: bool, swapped: bool) -> Option<String> {
+        match length {
+            1 => Some(format!("{}1", if signed { 's' } else { 'u' })),
+            2 | 4 | 8 => Some(format!("{}{}{}", if signed { 's' } else { 'u' }, length, if swapped { "le" } else { "be" })),
+            _ => None,
+        }
+    }
+
+    /// Attributes of a single seq entry (without `id`) describing the schema.
+    fn attrs(&mut self, schema: &Schema, scope: &mut KsyScope, path: &SchemaPath) -> Result<KsyEntry, SchemaError> {
+        let ty = |name: String| vec![("type".to_string(), Yaml::Str(name))];
+        Ok(match schema {
+            Schema::FormatField { endian, format } => {
+                let swapped = *endian == '<' || (*endian == '=' && cfg!(target_endian = "little"));
+                let order = if swapped { "le" } else { "be" };
+                match format {
+                    'f' => ty(format!("f4{}", order)),
+                    'd' => ty(format!("f8{}", order)),
+                    _ => {
+                        let length = schema.sizeof().unwrap_or(1);
+                        ty(KsyGenerator::int_type(length, format.is_ascii_lowercase(), swapped).unwrap())
+                    }
+                }
+            }
+            Schema::BytesInteger { length, signed, swapped } => match KsyGenerator::int_type(*length, *signed, *swapped) {
+                Some(name) => ty(name),
+                None => self.wrapped(schema, scope, path)?,
+            },
+            // Kaitai bit fields are packed, while BitsInteger reads a byte per bit unless it
+            // is inside a bitwise region, which schemas do not describe.
+            Schema::BitsInteger { .. } => return Err(path.error("BitsInteger is only equivalent to Kaitai bit fields in a bitwise region")),
+            Schema::PaddedString { length, encoding } => vec![
+                ("type".to_string(), Yaml::str("strz")),
+                ("size".to_string(), Yaml::Int(*length as i128)),
+                ("encoding".to_string(), ksy_encoding(encoding, path)?),
+            ],
+            Schema::CString { encoding } => {
+                vec![("type".to_string(), Yaml::str("strz")), ("encoding".to_string(), ksy_encoding(encoding, path)?)]
+            }
+            Schema::GreedyString { encoding } => vec![
+                ("type".to_string(), Yaml::str("str")),
+                ("size-eos".to_string(), Yaml::Bool(true)),
+                ("encoding".to_string(), ksy_encoding(encoding, path)?),
+            ],
+            Schema::PascalString { .. } => self.wrapped(schema, scope, path)?,
+            Schema::Bytes { length: SchemaParam::Const(length), .. } => vec![("size".to_string(), Yaml::Int(*length))],
+            Schema::Bytes { .. } => return Err(path.join("length").error("only constant lengths can be exported")),
+            Schema::GreedyBytes { .. } => vec![("size-eos".to_string(), Yaml::Bool(true))],
+            Schema::Sequence(fields) | Schema::LazyStruct(fields) | Schema::Struct(fields) => {
+                let spec = self.user_type(fields, path)?;
+                let hint = path.0.last().filter(|s| !s.starts_with(['(', '['])).map_or_else(
+                    || format!("type_{}", self.allocate_id()),
+                    |name| ksy_ident(name),
+                );
+                ty(scope.add_type(&hint, spec))
+            }
+            Schema::LazyArray { count, subcon } => {
+                let mut entry = self.item_attrs(subcon, scope, path)?;
+                entry.push(("repeat".to_string(), Yaml::str("expr")));
+                entry.push(("repeat-expr".to_string(), Yaml::Int(*count as i128)));
+                entry
+            }
+            Schema::Array { count, subcon } => {
+                let mut entry = self.item_attrs(subcon, scope, path)?;
+                entry.push(("repeat".to_string(), Yaml::str("expr")));
+                entry.push(("repeat-expr".to_string(), ksy_param(count)));
+                entry
+            }
+            Schema::GreedyRange(subcon) => {
+                let mut entry = self.item_attrs(subcon, scope, path)?;
+                entry.push(("repeat".to_string(), Yaml::str("eos")));
+                entry
+            }
+            Schema::RepeatUntil { predicate, subcon } => {
+                let mut entry = self.item_attrs(subcon, scope, path)?;
+                entry.push(("repeat".to_string(), Yaml::str("until")));
+                entry.push(("repeat-until".to_string(), Yaml::Str(predicate.clone())));
+                entry
+            }
+            Schema::Switch { key, cases, default } => {
+                let mut types = Vec::new();
+                for (case, subcon) in cases {
+                    let (name, literal) = match case {
+                        SchemaKey::Int(value) => (value.to_string(), value.to_string()),
+                        SchemaKey::Str(value) => (value.clone(), format!("{:?}", value)),
+                    };
+                    types.push((literal, self.type_name(subcon, scope, &path.join("cases").join(name))?));
+                }
+                if let Some(default) = default {
+                    types.push(("_".to_string(), self.type_name(default, scope, &path.join("default"))?));
+                }
+                let switch = vec![("switch-on".to_string(), Yaml::Str(key.clone())), ("cases".to_string(), Yaml::Map(types))];
+                vec![("type".to_string(), Yaml::Map(switch))]
+            }
+            Schema::If { condition, subcon } => {
+                let mut entry = self.attrs(subcon, scope, path)?;
+                if entry.iter().any(|(key, _)| key == "if") {
+                    entry = self.wrapped(subcon, scope, path)?;
+                }
+                let condition = match condition {
+                    SchemaParam::Const(value) => Yaml::Bool(*value != 0),
+                    SchemaParam::Expr(source) => Yaml::Str(source.clone()),
+                };
+                entry.push(("if".to_string(), condition));
+                entry
+            }
+            Schema::FixedSized { length, subcon } => {
+                let mut entry = self.attrs(subcon, scope, path)?;
+                entry.retain(|(key, _)| key != "size-eos");
+                if entry.iter().any(|(key, _)| ["size", "repeat", "if"].contains(&key.as_str())) {
+                    entry = self.wrapped(subcon, scope, path)?;
+                }
+                entry.push(("size".to_string(), ksy_param(length)));
+                entry
+            }
+            Schema::Enum { mapping, subcon } => {
+                let mut entry = self.attrs(subcon, scope, path)?;
+                let integer = |ty: &str| ty.starts_with(['u', 's']) && ty.as_bytes().get(1).is_some_and(|c| b"1248".contains(c));
+                if !matches!(entry.as_slice(), [(key, Yaml::Str(ty))] if key == "type" && integer(ty)) {
+                    return Err(path.error("enums are only supported on integers of 1, 2, 4 or 8 bytes"));
+                }
+                let members = mapping.iter().map(|(name, value)| (value.to_string(), Yaml::Str(ksy_ident(name)))).collect();
+                let hint = path.0.last().filter(|s| !s.starts_with(['(', '['])).map_or_else(
+                    || format!("enum_{}", self.allocate_id()),
+                    |name| ksy_ident(name),
+                );
+                entry.push(("enum".to_string(), Yaml::Str(scope.add_enum(&hint, Yaml::Map(members)))));
+                entry
+            }
+            Schema::Lazy(subcon) | Schema::Hex(subcon) | Schema::HexDump(subcon) => self.attrs(subcon, scope, path)?,
+            Schema::ByteSwapped(subcon) => match subcon.as_ref() {
+                Schema::FormatField { endian, format } => {
+                    let endian = match endian {
+                        '<' => '>',
+                        '>' => '<',
+                        _ if cfg!(target_endian = "little") => '>',
+                        _ => '<',
+                    };
+                    self.attrs(&Schema::FormatField { endian, format: *format }, scope, path)?
+                }
+                Schema::BytesInteger { length, signed, swapped } => {
+                    let flipped = Schema::BytesInteger { length: *length, signed: *signed, swapped: !swapped };
+                    self.attrs(&flipped, scope, path)?
+                }
+                _ => return Err(path.error("only integers can be byte-swapped in Kaitai")),
+            },
+            Schema::BitsSwapped(_) => return Err(path.error("BitsSwapped has no Kaitai equivalent")),
+            Schema::ProcessXor { pad, subcon } => {
+                let mut entry = match subcon.as_ref() {
+                    Schema::GreedyString { encoding } => {
+                        vec![("type".to_string(), Yaml::str("str")), ("encoding".to_string(), ksy_encoding(encoding, path)?)]
+                    }
+                    Schema::GreedyBytes { .. } => Vec::new(),
+                    Schema::Sequence(_) | Schema::LazyStruct(_) | Schema::Struct(_) => self.attrs(subcon, scope, path)?,
+                    other => self.wrapped(other, scope, path)?,
+                };
+                let key = match pad.as_slice() {
+                    [byte] => format!("{:#04x}", byte),
+                    bytes => format!("[{}]", bytes.iter().map(|b| format!("{:#04x}", b)).collect::<Vec<_>>().join(", ")),
+                };
+                entry.push(("size-eos".to_string(), Yaml::Bool(true)));
+                entry.push(("process".to_string(), Yaml::Str(format!("xor({})", key))));
+                entry
+            }
+            other => return Err(path.error(format!("{} has no Kaitai equivalent", other.type_name()))),
+        })
+    }
+
+    /// Seq entries for a member, declaring helper fields and instances where Kaitai
+    /// needs them (length prefixes and integers of unusual sizes).
+    fn entries(
+        &mut self,
+        id: Option<String>,
+        schema: &Schema,
+        scope: &mut KsyScope,
+        path: &SchemaPath,
+    ) -> Result<Vec<KsyEntry>, SchemaError> {
+        let with_id = |id: &str, mut entry: KsyEntry| {
+            entry.insert(0, ("id".to_string(), Yaml::str(id)));
+            entry
+        };
+        match schema {
+            Schema::PascalString { lengthfield, encoding } => {
+                let id = id.unwrap_or_else(|| format!("unnamed_{}", self.allocate_id()));
+                let lengthid = format!("len_{}", id);
+                let mut entries = self.entries(Some(lengthid.clone()), lengthfield, scope, &path.join("lengthfield"))?;
+                entries.push(with_id(
+                    &id,
+                    vec![
+                        ("type".to_string(), Yaml::str("str")),
+                        ("size".to_string(), Yaml::Str(lengthid)),
+                        ("encoding".to_string(), ksy_encoding(encoding, path)?),
+                    ],
+                ));
+                Ok(entries)
+            }
+            Schema::BytesInteger { length, signed, swapped } if KsyGenerator::int_type(*length, *signed, *swapped).is_none() => {
+                if *length == 0 || *length > 8 {
+                    return Err(path.error("only integers of up to 8 bytes can be exported"));
+                }
+                let id = id.unwrap_or_else(|| format!("unnamed_{}", self.allocate_id()));
+                // Split into parts Kaitai supports, in storage order, and recombine in an instance.
+                let mut sizes = Vec::new();
+                let mut rest = *length;
+                for size in [4, 2, 1] {
+                    while rest >= size {
+                        sizes.push(size);
+                        rest -= size;
+                    }
+                }
+                let mut shifts = Vec::new();
+                let mut shift = 0;
+                let order: Vec<usize> = if *swapped { (0..sizes.len()).collect() } else { (0..sizes.len()).rev().collect() };
+                shifts.resize(sizes.len(), 0);
+                for &i in &order {
+                    shifts[i] = shift;
+                    shift += sizes[i] * 8;
+                }
+                let names: Vec<String> = if sizes.len() == 2 {
+                    (0..2).map(|i| format!("{}_{}", id, if shifts[i] == 0 { "lo" } else { "hi" })).collect()
+                } else {
+                    (0..sizes.len()).map(|i| format!("{}_p{}", id, i)).collect()
+                };
+                let entries = sizes
+                    .iter()
+                    .zip(&names)
+                    .map(|(&size, name)| {
+                        let ty = KsyGenerator::int_type(size, false, *swapped).unwrap();
+                        with_id(name, vec![("type".to_string(), Yaml::Str(ty))])
+                    })
+                    .collect();
+                let mut terms: Vec<(usize, String)> = names
+                    .iter()
+                    .zip(&shifts)
+                    .map(|(name, &shift)| (shift, if shift == 0 { name.clone() } else { format!("{} << {}", name, shift) }))
+                    .collect();
+                terms.sort_by_key(|term| std::cmp::Reverse(term.0));
+                let mut value = terms.into_iter().map(|(_, term)| term).collect::<Vec<_>>().join(" | ");
+                if *signed {
+                    let sign = 1u64 << (length * 8 - 1);
+                    value = format!("(({}) ^ {}) - {}", value, sign, sign);
+                }
+                scope.instances.push((id, Yaml::Map(vec![("value".to_string(), Yaml::Str(value))])));
+                Ok(entries)
+            }
+            Schema::Lazy(subcon) | Schema::Hex(subcon) | Schema::HexDump(subcon) => self.entries(id, subcon, scope, path),
+            Schema::Computed(value) => {
+                let id = id.ok_or_else(|| path.error("computed values need a name"))?;
+                scope.instances.push((id, Yaml::Map(vec![("value".to_string(), ksy_param(value))])));
+                Ok(Vec::new())
+            }
+            Schema::Pointer { offset, subcon } => {
+                let id = id.ok_or_else(|| path.error("pointers need a name"))?;
+                if matches!(offset, SchemaParam::Const(offset) if *offset < 0) {
+                    return Err(path.join("offset").error("offsets from the end have no Kaitai equivalent"));
+                }
+                let mut instance = vec![("pos".to_string(), ksy_param(offset))];
+                instance.extend(self.attrs(subcon, scope, path)?);
+                scope.instances.push((id, Yaml::Map(instance)));
+                Ok(Vec::new())
+            }
+            _ => {
+                let entry = self.attrs(schema, scope, path)?;
+                Ok(vec![match id {
+                    Some(id) => with_id(&id, entry),
+                    None => entry,
+                }])
+            }
+        }
+    }
+}
+
+/// Export a schema as a Kaitai Struct (.ksy) document.
+///
+/// Composites become user types, repeats `repeat`, `Switch` a `switch-on` type, `Enum`
+/// an `enum` declared in `enums`, `Computed` fields value instances, and `Pointer` fields
+/// positioned instances.
+pub fn export_ksy(schema: &Schema, schemaname: &str) -> Result<String, SchemaError> {
+    let mut generator = KsyGenerator { nextid: 0 };
+    let path = SchemaPath::root();
+    let body = match schema {
+        Schema::Sequence(fields) | Schema::LazyStruct(fields) | Schema::Struct(fields) => generator.user_type(fields, &path)?,
+        _ => {
+            let mut scope = KsyScope::default();
+            let seq = generator.entries(None, schema, &mut scope, &path)?.into_iter().map(Yaml::Map).collect();
+            KsyGenerator::type_spec(seq, scope)
+        }
+    };
+    let Yaml::Map(body) = body else { unreachable!("type specs are mappings") };
+    let mut document = vec![("meta".to_string(), Yaml::Map(vec![("id".to_string(), Yaml::Str(ksy_ident(schemaname)))]))];
+    document.extend(body);
+    Ok(Yaml::Map(document).to_yaml())
+}
+
+// ========================= Kaitai Struct import ======================
+
+/// Callable resolving a Kaitai type by name once it has been imported, letting
+/// `LazyBound` refer to types that contain themselves.
+#[pyclass(module = "construct_rs")]
+struct KsyTypeRef {
+    types: Py<PyDict>,
+    name: String,
+}
+
+#[pymethods]
+impl KsyTypeRef {
+    #[new]
+    fn new(types: Py<PyDict>, name: String) -> Self {
+        KsyTypeRef { types, name }
+    }
+
+    fn __reduce__(slf: &PyCell<Self>, py: Python<'_>) -> PyObject {
+        let this = slf.borrow();
+        (slf.get_type(), (this.types.clone_ref(py), this.name.clone())).into_py(py)
+    }
+
+    fn __call__(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let subcon = self.types.as_ref(py).get_item(&self.name)?;
+        subcon.map(Into::into).ok_or_else(|| PyErr::new::<pyo3::exceptions::PyKeyError, _>(self.name.clone()))
+    }
+}
+
+/// Keys of a mapping, rejecting the ones outside `allowed` that are not documentation.
+fn ksy_check_keys(spec: &Yaml, allowed: &[&str], path: &SchemaPath) -> Result<(), SchemaError> {
+    let Yaml::Map(items) = spec else { return Err(path.error("expected a mapping")) };
+    for (key, _) in items {
+        let documentation = ["doc", "doc-ref", "-orig-id"].contains(&key.as_str()) || key.starts_with("-webide-");
+        if !documentation && !allowed.contains(&key.as_str()) {
+            return Err(path.join(key.as_str()).error(format!("{:?} is not supported", key)));
+        }
+    }
+    Ok(())
+}
+
+fn ksy_str<'a>(spec: &'a Yaml, key: &str, path: &SchemaPath) -> Result<Option<&'a str>, SchemaError> {
+    match spec.get(key) {
+        None => Ok(None),
+        Some(Yaml::Str(value)) => Ok(Some(value)),
+        Some(_) => Err(path.join(key).error("expected a string")),
+    }
+}
+
+/// Integer value of an enum key or switch case written as a YAML key.
+fn ksy_int_key(key: &str) -> Option<i128> {
+    match yaml_plain(key) {
+        Yaml::Int(value) => Some(value),
+        _ => None,
+    }
+}
+
+/// Builds Rust construct trees from a Kaitai Struct document.
+struct KsyImporter<'a> {
+    py: Python<'a>,
+    root: &'a Yaml,
+    types: &'a PyDict,
+    importing: Vec<String>,
+}
+
+impl<'a> KsyImporter<'a> {
+    /// Declaration of the user type at `scope`, the root type for an empty scope.
+    fn spec(&self, scope: &[String]) -> &'a Yaml {
+        scope.iter().fold(self.root, |spec, name| spec.get("types").and_then(|types| types.get(name)).unwrap())
+    }
+
+    fn spec_path(scope: &[String]) -> SchemaPath {
+        scope.iter().fold(SchemaPath(vec!["(ksy)".to_string()]), |path, name| path.join("types").join(name.as_str()))
+    }
+
+    /// Value of a `meta` key, inherited from enclosing types.
+    fn meta(&self, scope: &[String], key: &str) -> Option<&'a Yaml> {
+        (0..=scope.len()).rev().find_map(|depth| self.spec(&scope[..depth]).get("meta").and_then(|meta| meta.get(key)))
+    }
+
+    /// Scope of the user type `name` referenced from `scope`, searching enclosing types
+    /// outwards.
+    fn resolve_type(&self, scope: &[String], name: &str, path: &SchemaPath) -> Result<Vec<String>, SchemaError> {
+        let mut segments = name.split("::");
+        let first = segments.next().unwrap_or_default();
+        for depth in (0..=scope.len()).rev() {
+            let mut found = scope[..depth].to_vec();
+            if self.spec(&found).get("types").and_then(|types| types.get(first)).is_none() {
+                continue;
+            }
+            found.push(first.to_string());
+            for segment in segments {
+                if self.spec(&found).get("types").and_then(|types| types.get(segment)).is_none() {
+                    return Err(path.error(format!("unknown type {:?}", name)));
+                }
+                found.push(segment.to_string());
+            }
+            return Ok(found);
+        }
+        Err(path.error(format!("unknown type {:?}", name)))
+    }
+
+    /// Mapping of the enum `name` referenced from `scope`, from member names to values.
+    fn resolve_enum(&self, scope: &[String], name: &str, path: &SchemaPath) -> Result<&'a PyDict, SchemaError> {
+        let spec = (0..=scope.len())
+            .rev()
+            .find_map(|depth| self.spec(&scope[..depth]).get("enums").and_then(|enums| enums.get(name)))
+            .ok_or_else(|| path.error(format!("unknown enum {:?}", name)))?;
+        let Yaml::Map(members) = spec else { return Err(path.error("expected a mapping of enum values")) };
+        let mapping = PyDict::new(self.py);
+        for (key, member) in members {
+            let value = ksy_int_key(key).ok_or_else(|| path.join(key.as_str()).error("enum keys must be integers"))?;
+            let id = match member {
+                Yaml::Str(id) => id,
+                other => match other.get("id") {
+                    Some(Yaml::Str(id)) => id,
+                    _ => return Err(path.join(key.as_str()).error("expected an enum member name")),
+                },
+            };
+            mapping.set_item(id, value).map_err(|err| path.error(err.to_string()))?;
+        }
+        Ok(mapping)
+    }
+
+    fn expr(&self, source: &str, path: &SchemaPath) -> Result<PyObject, SchemaError> {
+        expr_object(self.py, source, path)
+    }
+
+    /// Constant or expression parameter, like `repeat-expr` or `size`.
+    fn param(&self, value: &Yaml, path: &SchemaPath) -> Result<PyObject, SchemaError> {
+        match value {
+            Yaml::Int(value) => Ok(value.into_py(self.py)),
+            Yaml::Bool(value) => Ok(value.into_py(self.py)),
+            Yaml::Str(source) => self.expr(source, path),
+            _ => Err(path.error("expected an integer or an expression")),
+        }
+    }
+
+    fn new_construct<T: pyo3::PyClass>(&self, init: impl Into<PyClassInitializer<T>>, path: &SchemaPath) -> Result<PyObject, SchemaError> {
+        construct_object(self.py, init, path)
+    }
+
+    /// Construct of a primitive type like `u4le`, `s2` or `f8be`, using the default
+    /// endianness of the scope when none is given.
+    fn primitive(&self, scope: &[String], name: &str, path: &SchemaPath) -> Result<Option<PyObject>, SchemaError> {
+        let (kind, rest) = name.split_at(1.min(name.len()));
+        let (size, order) = match rest.find(|c: char| !c.is_ascii_digit()) {
+            Some(at) => rest.split_at(at),
+            None => (rest, ""),
+        };
+        let format = match (kind, size) {
+            ("u", "1") => 'B',
+            ("s", "1") => 'b',
+            ("u", "2") => 'H',
+            ("s", "2") => 'h',
+            ("u", "4") => 'L',
+            ("s", "4") => 'l',
+            ("u", "8") => 'Q',
+            ("s", "8") => 'q',
+            ("f", "4") => 'f',
+            ("f", "8") => 'd',
+            _ => return Ok(None),
+        };
+        let endian = match order {
+            "le" => '<',
+            "be" => '>',
+            "" if size == "1" => '>',
+            "" => match self.meta(scope, "endian") {
+                Some(Yaml::Str(endian)) if endian == "le" => '<',
+                Some(Yaml::Str(endian)) if endian == "be" => '>',
+                Some(_) => return Err(path.error("only 'le' and 'be' default endianness is supported")),
+                None => return Err(path.error(format!("{:?} needs an endianness, and the schema declares no default", name))),
+            },
+            _ => return Ok(None),
+        };
+        let length = size.parse().unwrap();
+        self.new_construct((FormatField { endian, format, length }, Construct {}), path).map(Some)
+    }
+
+    /// Construct of a `type` value: a primitive, a user type, or a `switch-on`.
+    fn typeref(&mut self, scope: &[String], ty: &Yaml, path: &SchemaPath) -> Result<PyObject, SchemaError> {
+        let name = match ty {
+            Yaml::Str(name) => name,
+            Yaml::Map(_) => return self.switch(scope, ty, path),
+            _ => return Err(path.error("expected a type name")),
+        };
+        if name.contains('(') {
+            return Err(path.error("parametric types are not supported"));
+        }
+        if name.starts_with('b') && name[1..].parse::<usize>().is_ok() {
+            return Err(path.error("bit-sized integers are only supported in types made entirely of them"));
+        }
+        if let Some(primitive) = self.primitive(scope, name, path)? {
+            return Ok(primitive);
+        }
+        let found = self.resolve_type(scope, name, path)?;
+        self.user_type(&found)
+    }
+
+    fn switch(&mut self, scope: &[String], ty: &Yaml, path: &SchemaPath) -> Result<PyObject, SchemaError> {
+        ksy_check_keys(ty, &["switch-on", "cases"], path)?;
+        let on = ksy_str(ty, "switch-on", path)?.ok_or_else(|| path.error("expected switch-on"))?;
+        let keyfunc = self.expr(on, &path.join("switch-on"))?;
+        let Some(Yaml::Map(cases)) = ty.get("cases") else { return Err(path.join("cases").error("expected a mapping of cases")) };
+        let py = self.py;
+        let subcons = PyDict::new(py);
+        let mut default = None;
+        for (key, case) in cases {
+            let casepath = path.join("cases").join(key.as_str());
+            let subcon = self.typeref(scope, case, &casepath)?;
+            if key == "_" {
+                default = Some(subcon);
+                continue;
+            }
+            let expr = Expr::compile(key).map_err(|err| casepath.error(format!("{} in case {:?}", err, key)))?;
+            let value = Container::empty(py)
+                .and_then(|context| expr.node.eval(py, context, None).map(|value| value.to_object(py)))
+                .map_err(|_| casepath.error("cases must be constants"))?;
+            subcons.set_item(value, subcon).map_err(|err| casepath.error(err.to_string()))?;
+        }
+        self.new_construct((Switch { keyfunc, cases: subcons.into(), default }, Construct {}), path)
+    }
+
+    /// Construct of a `seq` entry, without its `id`.
+    fn attribute(&mut self, scope: &[String], spec: &Yaml, path: &SchemaPath) -> Result<PyObject, SchemaError> {
+        let py = self.py;
+        ksy_check_keys(
+            spec,
+            &["id", "type", "size", "size-eos", "encoding", "process", "enum", "repeat", "repeat-expr", "repeat-until", "if"],
+            path,
+        )?;
+        let size = spec.get("size").map(|size| self.param(size, &path.join("size"))).transpose()?;
+        let size_eos = matches!(spec.get("size-eos"), Some(Yaml::Bool(true)));
+        let encoding = || -> Result<String, SchemaError> {
+            let encoding = match spec.get("encoding") {
+                Some(encoding) => Some(encoding),
+                None => self.meta(scope, "encoding"),
+            };
+            match encoding {
+                Some(Yaml::Str(encoding)) => Ok(encoding.to_lowercase().replace('-', "_")),
+                _ => Err(path.error("strings need an encoding")),
+            }
+        };
+        let process = ksy_str(spec, "process", path)?;
+        let mut sized = false;
+        let mut subcon = match spec.get("type") {
+            None if size.is_none() && !size_eos => return Err(path.error("fields without a type need size or size-eos")),
+            // Raw bytes, sized by the `Bytes` itself unless processed first.
+            None => match (&size, process) {
+                (Some(size), None) => {
+                    sized = true;
+                    self.new_construct(Bytes::new(size.clone_ref(py), false), path)?
+                }
+                _ => self.new_construct(GreedyBytes::new(false), path)?,
+            },
+            Some(Yaml::Str(ty)) if ty == "str" => {
+                if size.is_none() && !size_eos {
+                    return Err(path.error("str needs size or size-eos"));
+                }
+                let encoding = encoding()?;
+                self.new_construct(GreedyString::new(&encoding).map_err(|err| path.join("encoding").error(err.to_string()))?, path)?
+            }
+            Some(Yaml::Str(ty)) if ty == "strz" => {
+                let encoding = encoding()?;
+                self.new_construct(CString::new(&encoding).map_err(|err| path.join("encoding").error(err.to_string()))?, path)?
+            }
+            Some(ty) => self.typeref(scope, ty, &path.join("type"))?,
+        };
+        if let Some(process) = process {
+            if size.is_none() && !size_eos {
+                return Err(path.join("process").error("processed fields need size or size-eos"));
+            }
+            subcon = self.process(subcon, process, &path.join("process"))?;
+        }
+        if let Some(size) = size
+            && !sized
+        {
+            subcon = self.new_construct(FixedSized::new(size, subcon), path)?;
+        }
+        if let Some(name) = ksy_str(spec, "enum", path)? {
+            let mapping = self.resolve_enum(scope, name, &path.join("enum"))?;
+            let enum_ = Enum::new(py, subcon, PyTuple::empty(py), Some(mapping)).map_err(|err| path.error(err.to_string()))?;
+            subcon = self.new_construct(enum_, path)?;
+        }
+        subcon = match ksy_str(spec, "repeat", path)? {
+            None => subcon,
+            Some("expr") => {
+                let count = spec.get("repeat-expr").ok_or_else(|| path.error("repeat: expr needs repeat-expr"))?;
+                let count = self.param(count, &path.join("repeat-expr"))?;
+                self.new_construct(Array::new(count, subcon), path)?
+            }
+            Some("eos") => self.new_construct(GreedyRange::new(subcon), path)?,
+            Some("until") => {
+                let until = ksy_str(spec, "repeat-until", path)?.ok_or_else(|| path.error("repeat: until needs repeat-until"))?;
+                let predicate = self.expr(until, &path.join("repeat-until"))?;
+                self.new_construct(RepeatUntil::new(predicate, subcon), path)?
+            }
+            Some(other) => return Err(path.join("repeat").error(format!("unknown repeat {:?}", other))),
+        };
+        if let Some(cond) = spec.get("if") {
+            let cond = self.param(cond, &path.join("if"))?;
+            subcon = self.new_construct(If::new(cond, subcon), path)?;
+        }
+        Ok(subcon)
+    }
+
+    /// `xor(key)` and `rol(amount)` processing with constant arguments.
+    fn process(&self, subcon: PyObject, process: &str, path: &SchemaPath) -> Result<PyObject, SchemaError> {
+        let py = self.py;
+        let (name, args) = process
+            .strip_suffix(')')
+            .and_then(|call| call.split_once('('))
+            .ok_or_else(|| path.error(format!("unsupported process {:?}", process)))?;
+        let arg = Expr::compile(args)
+            .ok()
+            .and_then(|expr| Container::empty(py).and_then(|context| expr.node.eval(py, context, None).map(|v| v.to_object(py))).ok())
+            .ok_or_else(|| path.error("process arguments must be constants"))?;
+        match name {
+            "xor" => {
+                let pad = match arg.as_ref(py).extract::<Vec<u8>>() {
+                    Ok(key) if !arg.as_ref(py).is_instance_of::<pyo3::types::PyLong>() => PyBytes::new(py, &key).into(),
+                    _ => arg,
+                };
+                self.new_construct(ProcessXor::new(pad, subcon), path)
+            }
+            "rol" => self.new_construct(ProcessRotateLeft::new(arg, 1.into_py(py), subcon), path),
+            _ => Err(path.error(format!("unsupported process {:?}", process))),
+        }
+    }
+
+    /// Struct of a user type, built once and shared by every reference to it.
+    fn user_type(&mut self, scope: &[String]) -> Result<PyObject, SchemaError> {
+        let py = self.py;
+        let key = scope.join("::");
+        let path = KsyImporter::spec_path(scope);
+        if let Ok(Some(subcon)) = self.types.get_item(&key) {
+            return Ok(subcon.into());
+        }
+        if self.importing.contains(&key) {
+            let typeref = Py::new(py, KsyTypeRef { types: self.types.into(), name: key }).map_err(|err| path.error(err.to_string()))?;
+            return self.new_construct(LazyBound::new(typeref.into_py(py), None), &path);
+        }
+        self.importing.push(key.clone());
+        let subcon = self.struct_type(scope, &path);
+        self.importing.pop();
+        let subcon = subcon?;
+        self.types.set_item(&key, &subcon).map_err(|err| path.error(err.to_string()))?;
+        Ok(subcon)
+    }
+
+    fn struct_type(&mut self, scope: &[String], path: &SchemaPath) -> Result<PyObject, SchemaError> {
+        let spec = self.spec(scope);
+        ksy_check_keys(spec, &["meta", "seq", "types", "enums", "instances"], path)?;
+        if let Some(meta) = spec.get("meta") {
+            ksy_check_keys(
+                meta,
+                &["id", "title", "application", "file-extension", "xref", "license", "ks-version", "ks-debug", "ks-opaque-types", "endian", "encoding", "bit-endian", "tags"],
+                &path.join("meta"),
+            )?;
+            if matches!(meta.get("bit-endian"), Some(Yaml::Str(order)) if order != "be") {
+                return Err(path.join("meta").join("bit-endian").error("only big-endian bit fields are supported"));
+            }
+        }
+        let seq = match spec.get("seq") {
+            None => &[][..],
+            Some(Yaml::List(seq)) => seq.as_slice(),
+            Some(_) => return Err(path.join("seq").error("expected a list of attributes")),
+        };
+        let is_bits = |entry: &Yaml| matches!(entry.get("type"), Some(Yaml::Str(ty)) if ty.starts_with('b') && ty[1..].parse::<usize>().is_ok());
+        let bitwise = !seq.is_empty() && seq.iter().all(is_bits);
+        let mut subcons = Vec::new();
+        let mut bits = 0;
+        for (i, entry) in seq.iter().enumerate() {
+            let id = ksy_str(entry, "id", &path.join("seq").member(i, None))?;
+            let entrypath = path.join("seq").member(i, id);
+            let subcon = if bitwise {
+                ksy_check_keys(entry, &["id", "type", "enum"], &entrypath)?;
+                let Some(Yaml::Str(ty)) = entry.get("type") else { unreachable!("checked by is_bits") };
+                let length: usize = ty[1..].parse().unwrap();
+                bits += length;
+                let mut subcon = self.new_construct((BitsInteger { length, signed: false, swapped: false }, Construct {}), &entrypath)?;
+                if let Some(name) = ksy_str(entry, "enum", &entrypath)? {
+                    let mapping = self.resolve_enum(scope, name, &entrypath.join("enum"))?;
+                    let enum_ = Enum::new(self.py, subcon, PyTuple::empty(self.py), Some(mapping)).map_err(|err| entrypath.error(err.to_string()))?;
+                    subcon = self.new_construct(enum_, &entrypath)?;
+                }
+                subcon
+            } else {
+                self.attribute(scope, entry, &entrypath)?
+            };
+            subcons.push(Member::new(id.map(str::to_string), subcon.as_ref(self.py)));
+        }
+        if let Some(instances) = spec.get("instances") {
+            let Yaml::Map(instances) = instances else { return Err(path.join("instances").error("expected a mapping")) };
+            for (name, instance) in instances {
+                let instancepath = path.join("instances").join(name.as_str());
+                let subcon = match (instance.get("value"), instance.get("pos")) {
+                    (Some(value), _) => {
+                        ksy_check_keys(instance, &["value"], &instancepath)?;
+                        let func = self.param(value, &instancepath.join("value"))?;
+                        self.new_construct((Computed { func }, Construct {}), &instancepath)?
+                    }
+                    (None, Some(pos)) => {
+                        let offset = self.param(pos, &instancepath.join("pos"))?;
+                        let Yaml::Map(items) = instance else { return Err(instancepath.error("expected a mapping")) };
+                        let attribute = Yaml::Map(items.iter().filter(|(key, _)| key != "pos").cloned().collect());
+                        let subcon = self.attribute(scope, &attribute, &instancepath)?;
+                        self.new_construct(Pointer::new(offset, subcon), &instancepath)?
+                    }
+                    (None, None) => return Err(instancepath.error("only value and positioned instances are supported")),
+                };
+                subcons.push(Member::new(Some(name.clone()), subcon.as_ref(self.py)));
+            }
+        }
+        let strukt = self.new_construct((Struct { subcons, schema: SchemaCache::default() }, Construct {}), path)?;
+        if !bitwise {
+            return Ok(strukt);
+        }
+        let size = bits.div_ceil(8);
+        let decode: Transform = Arc::new(|data: &[u8]| Ok(bytes2bits(data)));
+        let encode: Transform = Arc::new(|data: &[u8]| {
+            let mut data = data.to_vec();
+            data.resize(data.len().div_ceil(8) * 8, 0);
+            bits2bytes(&data).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+        });
+        self.new_construct(Transformed::native(strukt, decode, Some(size), encode, Some(size)), path)
+    }
+}
+
+/// Import a Kaitai Struct (.ksy) document as a tree of construct-rs constructs.
+///
+/// `seq` becomes a `Struct`, repeats become `Array`, `GreedyRange` or `RepeatUntil`,
+/// `switch-on` a `Switch`, enums an `Enum`, value instances `Computed` fields, positioned
+/// instances `Pointer` fields, and expressions are compiled to `Expr`. Types made entirely
+/// of bit fields are parsed bitwise. Unsupported features are reported with their location in the document.
+pub fn import_ksy(py: Python, source: &str) -> Result<PyObject, SchemaError> {
+    let root = Yaml::parse(source)?;
+    if !matches!(root, Yaml::Map(_)) {
+        return Err(SchemaPath(vec!["(ksy)".to_string()]).error("expected a mapping"));
+    }
+    let mut importer = KsyImporter { py, root: &root, types: PyDict::new(py), importing: Vec::new() };
+    importer.user_type(&[])
+}
+
+/// Import a Kaitai Struct (.ksy) document as a construct.
+#[pyfunction]
+#[pyo3(name = "import_ksy")]
+fn py_import_ksy(py: Python<'_>, source: &str) -> PyResult<PyObject> {
+    Ok(import_ksy(py, source)?)
+}
+
+/// Import a Kaitai Struct (.ksy) file as a construct.
+#[pyfunction]
+#[pyo3(name = "import_ksy_file")]
+fn py_import_ksy_file(py: Python<'_>, filename: &str) -> PyResult<PyObject> {
+    let source = std::fs::read_to_string(filename).map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+    Ok(import_ksy(py, &source)?)
+}
+
+// ========================= Schema documents ==========================
+
+/// Name of the `FormatField` singleton with the given byte order and format, like `Int16ub`.
+fn formatfield_name(endian: char, format: char) -> Option<String> {
+    let kind = match format {
+        'B' => "Int8u",
+        'b' => "Int8s",
+        'H' => "Int16u",
+        'h' => "Int16s",
+        'L' => "Int32u",
+        'l' => "Int32s",
+        'Q' => "Int64u",
+        'q' => "Int64s",
+        'f' => "Float32",
+        'd' => "Float64",
+        _ => return None,
+    };
+    let order = match endian {
+        '>' => 'b',
+        '<' => 'l',
+        '=' => 'n',
+        _ => return None,
+    };
+    Some(format!("{}{}", kind, order))
+}
+
+/// Schema of a primitive named like its singleton, e.g. `Int32ul`, `Int24sb` or `Bit`.
+fn schema_primitive(name: &str) -> Option<Schema> {
+    let name = match name {
+        "Byte" => "Int8ub",
+        "Short" => "Int16ub",
+        "Int" => "Int32ub",
+        "Long" => "Int64ub",
+        "Single" => "Float32b",
+        "Double" => "Float64b",
+        other => other,
+    };
+    for endian in ['>', '<', '='] {
+        for format in "BbHhLlQqfd".chars() {
+            if formatfield_name(endian, format).as_deref() == Some(name) {
+                return Some(Schema::FormatField { endian, format });
+            }
+        }
+    }
+    let bits = |length| Schema::BitsInteger { length, signed: false, swapped: false };
+    match name {
+        "Bit" => Some(bits(1)),
+        "Nibble" => Some(bits(4)),
+        "Octet" => Some(bits(8)),
+        "GreedyBytes" => Some(Schema::GreedyBytes { zerocopy: false }),
+        _ => {
+            let rest = name.strip_prefix("Int24")?;
+            let signed = match rest.get(..1)? {
+                "u" => false,
+                "s" => true,
+                _ => return None,
+            };
+            let swapped = match &rest[1..] {
+                "b" => false,
+                "l" => true,
+                "n" => cfg!(target_endian = "little"),
+                _ => return None,
+            };
+            Some(Schema::BytesInteger { length: 3, signed, swapped })
+        }
+    }
+}
+
+/// Singleton name a primitive schema is written as, if it has one.
+fn schema_primitive_name(schema: &Schema) -> Option<String> {
+    match schema {
+        Schema::FormatField { endian, format } => formatfield_name(*endian, *format),
+        Schema::BytesInteger { length: 3, signed, swapped } => {
+            Some(format!("Int24{}{}", if *signed { 's' } else { 'u' }, if *swapped { 'l' } else { 'b' }))
+        }
+        Schema::BitsInteger { length, signed: false, swapped: false } => match length {
+            1 => Some("Bit".to_string()),
+            4 => Some("Nibble".to_string()),
+            8 => Some("Octet".to_string()),
+            _ => None,
+        },
+        Schema::GreedyBytes { zerocopy: false } => Some("GreedyBytes".to_string()),
+        _ => None,
+    }
+}
+
+/// Keys each type of schema node accepts besides `type`.
+fn schema_keys(name: &str) -> Option<&'static [&'static str]> {
+    Some(match name {
+        "FormatField" => &["endian", "format"],
+        "BytesInteger" | "BitsInteger" => &["length", "signed", "swapped"],
+        "PaddedString" => &["length", "encoding"],
+        "PascalString" => &["lengthfield", "encoding"],
+        "CString" | "GreedyString" => &["encoding"],
+        "Bytes" => &["length", "zerocopy"],
+        "GreedyBytes" => &["zerocopy"],
+        "Sequence" | "Struct" | "LazyStruct" => &["fields"],
+        "Array" | "LazyArray" => &["count", "subcon"],
+        "RepeatUntil" => &["predicate", "subcon"],
+        "Switch" => &["keyfunc", "cases", "default"],
+        "If" => &["condfunc", "subcon"],
+        "Computed" => &["func"],
+        "FixedSized" => &["length", "subcon"],
+        "Pointer" => &["offset", "subcon"],
+        "Enum" => &["mapping", "subcon"],
+        "ProcessXor" => &["padfunc", "subcon"],
+        "GreedyRange" | "Lazy" | "Hex" | "HexDump" | "ByteSwapped" | "BitsSwapped" => &["subcon"],
+        _ if schema_primitive(name).is_some() => &[],
+        _ => return None,
+    })
+}
+
+fn construct_object<T: pyo3::PyClass>(py: Python, init: impl Into<PyClassInitializer<T>>, path: &SchemaPath) -> Result<PyObject, SchemaError> {
+    Ok(Py::new(py, init).map_err(|err| path.error(err.to_string()))?.into_py(py))
+}
+
+fn expr_object(py: Python, source: &str, path: &SchemaPath) -> Result<PyObject, SchemaError> {
+    let expr = Expr::compile(source).map_err(|err| path.error(format!("{} in expression {:?}", err, source)))?;
+    construct_object(py, expr, path)
+}
+
+/// Mapping node of a schema document, with its location for error messages.
+struct SchemaNode<'a> {
+    node: &'a Yaml,
+    path: SchemaPath,
+}
+
+impl<'a> SchemaNode<'a> {
+    fn get(&self, key: &str) -> Result<&'a Yaml, SchemaError> {
+        self.node.get(key).ok_or_else(|| self.path.error(format!("missing {:?}", key)))
+    }
+
+    fn size(&self, key: &str) -> Result<usize, SchemaError> {
+        match self.get(key)? {
+            Yaml::Int(value) => usize::try_from(*value).map_err(|_| self.path.join(key).error("expected a non-negative integer")),
+            _ => Err(self.path.join(key).error("expected a non-negative integer")),
+        }
+    }
+
+    fn flag(&self, key: &str) -> Result<bool, SchemaError> {
+        match self.node.get(key) {
+            None => Ok(false),
+            Some(Yaml::Bool(value)) => Ok(*value),
+            Some(_) => Err(self.path.join(key).error("expected true or false")),
+        }
+    }
+
+    fn char(&self, key: &str, allowed: &str) -> Result<char, SchemaError> {
+        match self.get(key)? {
+            Yaml::Str(value) if value.chars().count() == 1 && allowed.contains(value.as_str()) => Ok(value.chars().next().unwrap()),
+            _ => Err(self.path.join(key).error(format!("expected one of {:?}", allowed))),
+        }
+    }
+
+    fn encoding(&self) -> Result<String, SchemaError> {
+        match self.get("encoding")? {
+            Yaml::Str(encoding) => {
+                encoding_unit(encoding).map_err(|_| self.path.join("encoding").error(format!("unknown encoding {:?}", encoding)))?;
+                Ok(encoding.clone())
+            }
+            _ => Err(self.path.join("encoding").error("expected an encoding name")),
+        }
+    }
+
+    fn expr(&self, key: &str) -> Result<String, SchemaError> {
+        match self.get(key)? {
+            Yaml::Str(source) => {
+                Expr::compile(source).map_err(|err| self.path.join(key).error(format!("{} in expression {:?}", err, source)))?;
+                Ok(source.clone())
+            }
+            _ => Err(self.path.join(key).error("expected an expression")),
+        }
+    }
+
+    /// Integer constant, boolean constant, or expression.
+    fn param(&self, key: &str) -> Result<SchemaParam, SchemaError> {
+        match self.get(key)? {
+            Yaml::Int(value) => Ok(SchemaParam::Const(*value)),
+            Yaml::Bool(value) => Ok(SchemaParam::Expr(value.to_string())),
+            Yaml::Str(_) => self.expr(key).map(SchemaParam::Expr),
+            _ => Err(self.path.join(key).error("expected an integer or an expression")),
+        }
+    }
+
+    fn schema(&self, key: &str) -> Result<Box<Schema>, SchemaError> {
+        Ok(Box::new(Schema::read(self.get(key)?, &self.path.join(key), &[])?))
+    }
+
+    fn fields(&self) -> Result<Vec<SchemaField>, SchemaError> {
+        let Yaml::List(items) = self.get("fields")? else { return Err(self.path.join("fields").error("expected a list of fields")) };
+        let mut fields = Vec::new();
+        for (i, item) in items.iter().enumerate() {
+            let name = match item.get("name") {
+                None => None,
+                Some(Yaml::Str(name)) => Some(name.clone()),
+                Some(_) => return Err(self.path.member(i, None).join("name").error("expected a string")),
+            };
+            let schema = Schema::read(item, &self.path.member(i, name.as_deref()), &["name"])?;
+            fields.push(SchemaField { name, schema });
+        }
+        Ok(fields)
+    }
+}
+
+impl SchemaParam {
+    fn to_document(&self) -> Yaml {
+        match self {
+            SchemaParam::Const(value) => Yaml::Int(*value),
+            SchemaParam::Expr(source) => Yaml::Str(source.clone()),
+        }
+    }
+
+    fn to_object(&self, py: Python, path: &SchemaPath) -> Result<PyObject, SchemaError> {
+        match self {
+            SchemaParam::Const(value) => Ok(value.into_py(py)),
+            SchemaParam::Expr(source) => expr_object(py, source, path),
+        }
+    }
+}
+
+impl Schema {
+    /// Read a schema document, either a primitive name like `"Int32ul"` or a mapping
+    /// with a `type` key naming the construct and keys for its parameters:
+    ///
+    /// ```yaml
+    /// type: Struct
+    /// fields:
+    ///   - name: count
+    ///     type: Int8ub
+    ///   - name: items
+    ///     type: Array
+    ///     count: count
+    ///     subcon: Int16ul
+    /// ```
+    ///
+    /// Parameters that may depend on the context take an integer or an expression.
+    /// Switch cases written as integers select integer keys.
+    pub fn from_document(document: &Yaml) -> Result<Schema, SchemaError> {
+        Schema::read(document, &SchemaPath::root(), &[])
+    }
+
+    /// Read a schema from a JSON document.
+    pub fn from_json(text: &str) -> Result<Schema, SchemaError> {
+        Schema::from_document(&Yaml::parse_json(text)?)
+    }
+
+    /// Read a schema from a YAML document.
+    pub fn from_yaml(text: &str) -> Result<Schema, SchemaError> {
+        Schema::from_document(&Yaml::parse(text)?)
+    }
+
+    fn read(node: &Yaml, path: &SchemaPath, extra: &[&str]) -> Result<Schema, SchemaError> {
+        let name = match node {
+            Yaml::Str(name) => return schema_primitive(name).ok_or_else(|| path.error(format!("unknown primitive {:?}", name))),
+            Yaml::Map(_) => match node.get("type") {
+                Some(Yaml::Str(name)) => name.as_str(),
+                Some(_) => return Err(path.join("type").error("expected a type name")),
+                None => return Err(path.error("missing \"type\"")),
+            },
+            _ => return Err(path.error("expected a primitive name or a mapping with a \"type\"")),
+        };
+        let keys = schema_keys(name).ok_or_else(|| path.join("type").error(format!("unknown type {:?}", name)))?;
+        if let Yaml::Map(items) = node {
+            for (key, _) in items {
+                if key != "type" && !keys.contains(&key.as_str()) && !extra.contains(&key.as_str()) {
+                    return Err(path.join(key.as_str()).error(format!("{} has no parameter {:?}", name, key)));
+                }
+            }
+        }
+        let node = SchemaNode { node, path: path.clone() };
+        Ok(match name {
+            "FormatField" => Schema::FormatField { endian: node.char("endian", "<>=")?, format: node.char("format", "BbHhLlQqfd")? },
+            "BytesInteger" => Schema::BytesInteger { length: node.size("length")?, signed: node.flag("signed")?, swapped: node.flag("swapped")? },
+            "BitsInteger" => Schema::BitsInteger { length: node.size("length")?, signed: node.flag("signed")?, swapped: node.flag("swapped")? },
+            "PaddedString" => Schema::PaddedString { length: node.size("length")?, encoding: node.encoding()? },
+            "PascalString" => Schema::PascalString { lengthfield: node.schema("lengthfield")?, encoding: node.encoding()? },
+            "CString" => Schema::CString { encoding: node.encoding()? },
+            "GreedyString" => Schema::GreedyString { encoding: node.encoding()? },
+            "Bytes" => Schema::Bytes { length: node.param("length")?, zerocopy: node.flag("zerocopy")? },
+            "GreedyBytes" => Schema::GreedyBytes { zerocopy: node.flag("zerocopy")? },
+            "Sequence" => Schema::Sequence(node.fields()?),
+            "Struct" => Schema::Struct(node.fields()?),
+            "LazyStruct" => Schema::LazyStruct(node.fields()?),
+            "Array" => Schema::Array { count: node.param("count")?, subcon: node.schema("subcon")? },
+            "LazyArray" => Schema::LazyArray { count: node.size("count")?, subcon: node.schema("subcon")? },
+            "GreedyRange" => Schema::GreedyRange(node.schema("subcon")?),
+            "RepeatUntil" => Schema::RepeatUntil { predicate: node.expr("predicate")?, subcon: node.schema("subcon")? },
+            "Switch" => {
+                let Yaml::Map(items) = node.get("cases")? else { return Err(path.join("cases").error("expected a mapping of cases")) };
+                let mut cases = Vec::new();
+                for (key, case) in items {
+                    let casepath = path.join("cases").join(key.as_str());
+                    let key = ksy_int_key(key).map_or_else(|| SchemaKey::Str(key.clone()), SchemaKey::Int);
+                    cases.push((key, Schema::read(case, &casepath, &[])?));
+                }
+                let default = match node.node.get("default") {
+                    Some(_) => Some(node.schema("default")?),
+                    None => None,
+                };
+                Schema::Switch { key: node.expr("keyfunc")?, cases, default }
+            }
+            "If" => Schema::If { condition: node.param("condfunc")?, subcon: node.schema("subcon")? },
+            "Computed" => Schema::Computed(node.param("func")?),
+            "FixedSized" => Schema::FixedSized { length: node.param("length")?, subcon: node.schema("subcon")? },
+            "Pointer" => Schema::Pointer { offset: node.param("offset")?, subcon: node.schema("subcon")? },
+            "Enum" => {
+                let Yaml::Map(items) = node.get("mapping")? else { return Err(path.join("mapping").error("expected a mapping of names")) };
+                let mut mapping = Vec::new();
+                for (name, value) in items {
+                    let Yaml::Int(value) = value else { return Err(path.join("mapping").join(name.as_str()).error("expected an integer")) };
+                    mapping.push((name.clone(), *value));
+                }
+                Schema::Enum { mapping, subcon: node.schema("subcon")? }
+            }
+            "Lazy" => Schema::Lazy(node.schema("subcon")?),
+            "Hex" => Schema::Hex(node.schema("subcon")?),
+            "HexDump" => Schema::HexDump(node.schema("subcon")?),
+            "ByteSwapped" => Schema::ByteSwapped(node.schema("subcon")?),
+            "BitsSwapped" => Schema::BitsSwapped(node.schema("subcon")?),
+            "ProcessXor" => {
+                let pad = match node.get("padfunc")? {
+                    Yaml::Int(byte) => u8::try_from(*byte).ok().map(|byte| vec![byte]),
+                    Yaml::List(bytes) => bytes.iter().map(|byte| match byte {
+                        Yaml::Int(byte) => u8::try_from(*byte).ok(),
+                        _ => None,
+                    }).collect(),
+                    _ => None,
+                };
+                let pad = pad.ok_or_else(|| path.join("padfunc").error("expected a byte or a list of bytes"))?;
+                Schema::ProcessXor { pad, subcon: node.schema("subcon")? }
+            }
+            primitive => schema_primitive(primitive).unwrap(),
+        })
+    }
+
+    /// Write as a schema document, the inverse of `from_document`.
+    pub fn to_document(&self) -> Yaml {
+        if let Some(name) = schema_primitive_name(self) {
+            return Yaml::Str(name);
+        }
+        let node = |params: Vec<(&str, Yaml)>| {
+            let mut items = vec![("type".to_string(), Yaml::str(self.type_name()))];
+            items.extend(params.into_iter().map(|(key, value)| (key.to_string(), value)));
+            Yaml::Map(items)
+        };
+        let fields = |fields: &[SchemaField]| {
+            let items = fields.iter().map(|field| match (&field.name, field.schema.to_document()) {
+                (None, document) => document,
+                (Some(name), Yaml::Map(mut items)) => {
+                    items.insert(0, ("name".to_string(), Yaml::str(name)));
+                    Yaml::Map(items)
+                }
+                (Some(name), document) => Yaml::Map(vec![("name".to_string(), Yaml::str(name)), ("type".to_string(), document)]),
+            });
+            vec![("fields", Yaml::List(items.collect()))]
+        };
+        match self {
+            Schema::FormatField { endian, format } => {
+                node(vec![("endian", Yaml::Str(endian.to_string())), ("format", Yaml::Str(format.to_string()))])
+            }
+            Schema::BytesInteger { length, signed, swapped } | Schema::BitsInteger { length, signed, swapped } => node(vec![
+                ("length", Yaml::Int(*length as i128)),
+                ("signed", Yaml::Bool(*signed)),
+                ("swapped", Yaml::Bool(*swapped)),
+            ]),
+            Schema::PaddedString { length, encoding } => {
+                node(vec![("length", Yaml::Int(*length as i128)), ("encoding", Yaml::str(encoding))])
+            }
+            Schema::PascalString { lengthfield, encoding } => {
+                node(vec![("lengthfield", lengthfield.to_document()), ("encoding", Yaml::str(encoding))])
+            }
+            Schema::CString { encoding } | Schema::GreedyString { encoding } => node(vec![("encoding", Yaml::str(encoding))]),
+            Schema::Bytes { length, zerocopy } => node(vec![("length", length.to_document()), ("zerocopy", Yaml::Bool(*zerocopy))]),
+            Schema::GreedyBytes { zerocopy } => node(vec![("zerocopy", Yaml::Bool(*zerocopy))]),
+            Schema::Sequence(members) | Schema::LazyStruct(members) | Schema::Struct(members) => node(fields(members)),
+            Schema::LazyArray { count, subcon } => node(vec![("count", Yaml::Int(*count as i128)), ("subcon", subcon.to_document())]),
+            Schema::Array { count, subcon } => node(vec![("count", count.to_document()), ("subcon", subcon.to_document())]),
+            Schema::RepeatUntil { predicate, subcon } => {
+                node(vec![("predicate", Yaml::str(predicate)), ("subcon", subcon.to_document())])
+            }
+            Schema::Switch { key, cases, default } => {
+                let cases = cases.iter().map(|(key, case)| {
+                    let key = match key {
+                        SchemaKey::Int(value) => value.to_string(),
+                        SchemaKey::Str(name) => name.clone(),
+                    };
+                    (key, case.to_document())
+                });
+                let mut params = vec![("keyfunc", Yaml::str(key)), ("cases", Yaml::Map(cases.collect()))];
+                if let Some(default) = default {
+                    params.push(("default", default.to_document()));
+                }
+                node(params)
+            }
+            Schema::If { condition, subcon } => node(vec![("condfunc", condition.to_document()), ("subcon", subcon.to_document())]),
+            Schema::Computed(func) => node(vec![("func", func.to_document())]),
+            Schema::FixedSized { length, subcon } => node(vec![("length", length.to_document()), ("subcon", subcon.to_document())]),
+            Schema::Pointer { offset, subcon } => node(vec![("offset", offset.to_document()), ("subcon", subcon.to_document())]),
+            Schema::Enum { mapping, subcon } => {
+                let mapping = mapping.iter().map(|(name, value)| (name.clone(), Yaml::Int(*value))).collect();
+                node(vec![("mapping", Yaml::Map(mapping)), ("subcon", subcon.to_document())])
+            }
+            Schema::ProcessXor { pad, subcon } => {
+                let pad = pad.iter().map(|&byte| Yaml::Int(byte as i128)).collect();
+                node(vec![("padfunc", Yaml::List(pad)), ("subcon", subcon.to_document())])
+            }
+            Schema::GreedyRange(subcon)
+            | Schema::Lazy(subcon)
+            | Schema::Hex(subcon)
+            | Schema::HexDump(subcon)
+            | Schema::ByteSwapped(subcon)
+            | Schema::BitsSwapped(subcon) => node(vec![("subcon", subcon.to_document())]),
+        }
+    }
+
+    /// Write as an indented JSON document.
+    pub fn to_json(&self) -> String {
+        self.to_document().to_json()
+    }
+
+    /// Write as a YAML document.
+    pub fn to_yaml(&self) -> String {
+        self.to_document().to_yaml()
+    }
+
+    /// Build the tree of construct-rs constructs the schema describes.
+    pub fn to_construct(&self, py: Python) -> Result<PyObject, SchemaError> {
+        self.construct(py, &SchemaPath::root())
+    }
+
+    fn construct(&self, py: Python, path: &SchemaPath) -> Result<PyObject, SchemaError> {
+        let inner = |subcon: &Schema| subcon.construct(py, path);
+        let members = |fields: &[SchemaField]| -> Result<Vec<Member>, SchemaError> {
+            fields
+                .iter()
+                .enumerate()
+                .map(|(i, field)| {
+                    let subcon = field.schema.construct(py, &path.member(i, field.name.as_deref()))?;
+                    Ok(Member::new(field.name.clone(), subcon.as_ref(py)))
+                })
+                .collect()
+        };
+        let encoded = |err: PyErr| path.join("encoding").error(err.to_string());
+        match self {
+            Schema::FormatField { endian, format } => {
+                let init = FormatField::new(&endian.to_string(), &format.to_string()).map_err(|err| path.error(err.to_string()))?;
+                construct_object(py, init, path)
+            }
+            Schema::BytesInteger { length, signed, swapped } => {
+                construct_object(py, BytesInteger::new(*length, Some(*signed), Some(*swapped)), path)
+            }
+            Schema::BitsInteger { length, signed, swapped } => {
+                construct_object(py, BitsInteger::new(*length, Some(*signed), Some(*swapped)), path)
+            }
+            Schema::PaddedString { length, encoding } => construct_object(py, PaddedString::new(*length, encoding).map_err(encoded)?, path),
+            Schema::PascalString { lengthfield, encoding } => {
+                let lengthfield = lengthfield.construct(py, &path.join("lengthfield"))?;
+                construct_object(py, PascalString::new(lengthfield, encoding).map_err(encoded)?, path)
+            }
+            Schema::CString { encoding } => construct_object(py, CString::new(encoding).map_err(encoded)?, path),
+            Schema::GreedyString { encoding } => construct_object(py, GreedyString::new(encoding).map_err(encoded)?, path),
+            Schema::Bytes { length, zerocopy } => {
+                construct_object(py, Bytes::new(length.to_object(py, &path.join("length"))?, *zerocopy), path)
+            }
+            Schema::GreedyBytes { zerocopy: false } => {
+                Ok(py.import("construct_rs").and_then(|m| m.getattr("GreedyBytes")).map_err(|err| path.error(err.to_string()))?.into())
+            }
+            Schema::GreedyBytes { zerocopy: true } => construct_object(py, GreedyBytes::new(true), path),
+            Schema::Sequence(fields) => construct_object(py, (Sequence { subcons: members(fields)?, schema: SchemaCache::default() }, Construct {}), path),
+            Schema::Struct(fields) => construct_object(py, (Struct { subcons: members(fields)?, schema: SchemaCache::default() }, Construct {}), path),
+            Schema::LazyStruct(fields) => construct_object(py, (LazyStruct { subcons: members(fields)? }, Construct {}), path),
+            Schema::LazyArray { count, subcon } => construct_object(py, LazyArray::new(count.into_py(py), inner(subcon)?), path),
+            Schema::Array { count, subcon } => {
+                construct_object(py, Array::new(count.to_object(py, &path.join("count"))?, inner(subcon)?), path)
+            }
+            Schema::GreedyRange(subcon) => construct_object(py, GreedyRange::new(inner(subcon)?), path),
+            Schema::RepeatUntil { predicate, subcon } => {
+                let predicate = expr_object(py, predicate, &path.join("predicate"))?;
+                construct_object(py, RepeatUntil::new(predicate, inner(subcon)?), path)
+            }
+            Schema::Switch { key, cases, default } => {
+                let keyfunc = expr_object(py, key, &path.join("keyfunc"))?;
+                let subcons = PyDict::new(py);
+                for (key, case) in cases {
+                    let (key, casepath) = match key {
+                        SchemaKey::Int(value) => (value.into_py(py), path.join("cases").join(value.to_string())),
+                        SchemaKey::Str(name) => (name.into_py(py), path.join("cases").join(name.as_str())),
+                    };
+                    let subcon = case.construct(py, &casepath)?;
+                    subcons.set_item(key, subcon).map_err(|err| casepath.error(err.to_string()))?;
+                }
+                let default = default.as_ref().map(|default| default.construct(py, &path.join("default"))).transpose()?;
+                construct_object(py, Switch::new(keyfunc, subcons.into(), default), path)
+            }
+            Schema::If { condition, subcon } => {
+                construct_object(py, If::new(condition.to_object(py, &path.join("condfunc"))?, inner(subcon)?), path)
+            }
+            Schema::Computed(func) => construct_object(py, Computed::new(func.to_object(py, &path.join("func"))?), path),
+            Schema::FixedSized { length, subcon } => {
+                construct_object(py, FixedSized::new(length.to_object(py, &path.join("length"))?, inner(subcon)?), path)
+            }
+            Schema::Pointer { offset, subcon } => {
+                construct_object(py, Pointer::new(offset.to_object(py, &path.join("offset"))?, inner(subcon)?), path)
+            }
+            Schema::Enum { mapping, subcon } => {
+                let names = PyDict::new(py);
+                for (name, value) in mapping {
+                    names.set_item(name, value).map_err(|err| path.join("mapping").error(err.to_string()))?;
+                }
+                let init = Enum::new(py, inner(subcon)?, PyTuple::empty(py), Some(names)).map_err(|err| path.error(err.to_string()))?;
+                construct_object(py, init, path)
+            }
+            Schema::Lazy(subcon) => construct_object(py, Lazy::new(inner(subcon)?), path),
+            Schema::Hex(subcon) => construct_object(py, Hex::new(inner(subcon)?), path),
+            Schema::HexDump(subcon) => construct_object(py, HexDump::new(inner(subcon)?), path),
+            Schema::ByteSwapped(subcon) => construct_object(py, ByteSwapped::new(inner(subcon)?), path),
+            Schema::BitsSwapped(subcon) => construct_object(py, BitsSwapped::new(inner(subcon)?), path),
+            Schema::ProcessXor { pad, subcon } => {
+                construct_object(py, ProcessXor::new(PyBytes::new(py, pad).into(), inner(subcon)?), path)
+            }
+        }
+    }
+}
+
+/// Read a schema document, detecting JSON by a leading `{` unless `format` is given.
+fn read_schema(source: &str, format: Option<&str>) -> PyResult<Schema> {
+    let json = match format {
+        None => source.trim_start().starts_with('{'),
+        Some("json") => true,
+        Some("yaml") => false,
+        Some(other) => return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("unknown schema format {:?}", other))),
+    };
+    Ok(if json { Schema::from_json(source)? } else { Schema::from_yaml(source)? })
+}
+
+/// Build a construct from a JSON or YAML schema document.
+#[pyfunction]
+#[pyo3(name = "import_schema", signature = (source, format=None))]
+fn py_import_schema(py: Python<'_>, source: &str, format: Option<&str>) -> PyResult<PyObject> {
+    Ok(read_schema(source, format)?.to_construct(py)?)
+}
+
+/// Build a construct from a JSON or YAML schema file.
+#[pyfunction]
+#[pyo3(name = "import_schema_file", signature = (filename, format=None))]
+fn py_import_schema_file(py: Python<'_>, filename: &str, format: Option<&str>) -> PyResult<PyObject> {
+    let source = std::fs::read_to_string(filename).map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+    Ok(read_schema(&source, format)?.to_construct(py)?)
+}
+
+#[pymodule]
+fn construct_rs(py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<Construct>()?;
+    m.add_class::<Subconstruct>()?;
+    m.add_class::<Adapter>()?;
+    m.add_class::<StringEncoded>()?;
+    m.add_class::<Bytes>()?;
+    m.add("GreedyBytes", Py::new(py, GreedyBytes::new(false))?)?;
+    m.add_class::<PaddedString>()?;
+    m.add_class::<PascalString>()?;
+    m.add_class::<CString>()?;
+    m.add_class::<GreedyString>()?;
+    m.add_class::<BitsInteger>()?;
+    m.add_class::<BytesInteger>()?;
+    m.add_class::<FormatField>()?;
+    m.add_class::<ProcessXor>()?;
+    m.add_class::<ProcessRotateLeft>()?;
+    m.add_class::<ByteSwapped>()?;
+    m.add_class::<BitsSwapped>()?;
+    m.add_class::<Union>()?;
+    m.add_class::<Renamed>()?;
+    m.add_class::<Sequence>()?;
+    m.add_class::<FocusedSeq>()?;
+    m.add_class::<Lazy>()?;
+    m.add_class::<LazyStruct>()?;
+    m.add_class::<LazyArray>()?;
+    m.add_class::<LazyContainer>()?;
+    PyMapping::register::<LazyContainer>(py)?;
+    m.add_class::<LazyListContainer>()?;
+    m.add_class::<LazyBound>()?;
+    m.add_class::<PyRestreamedBytesIO>()?;
+    m.add_class::<PyRebufferedBytesIO>()?;
+    m.add_class::<Transformed>()?;
+    m.add_class::<Restreamed>()?;
+    m.add_class::<RestreamData>()?;
+    m.add_class::<Rebuffered>()?;
+    m.add_function(wrap_pyfunction!(set_global_max_recursion_depth, m)?)?;
+    m.add_function(wrap_pyfunction!(get_global_max_recursion_depth, m)?)?;
+    m.add_class::<Container>()?;
+    m.add("ListContainer", ListContainer::type_object(py)?)?;
+    m.add_function(wrap_pyfunction!(set_global_print_full_strings, m)?)?;
+    m.add_function(wrap_pyfunction!(set_global_print_false_flags, m)?)?;
+    m.add_function(wrap_pyfunction!(set_global_print_private_entries, m)?)?;
+    m.add_function(wrap_pyfunction!(py_hexdump, m)?)?;
+    m.add_function(wrap_pyfunction!(py_hexundump, m)?)?;
+    for name in ["HexDisplayedInteger", "HexDisplayedBytes", "HexDisplayedDict", "HexDumpDisplayedBytes", "HexDumpDisplayedDict"] {
+        m.add(name, display_type(py, name)?)?;
+    }
+    m.add_class::<Hex>()?;
+    m.add_class::<HexDump>()?;
+    m.add_class::<Probe>()?;
+    m.add_class::<Debugger>()?;
+    m.add_class::<Expr>()?;
+    m.add_class::<Struct>()?;
+    m.add_class::<Array>()?;
+    m.add_class::<GreedyRange>()?;
+    m.add_class::<RepeatUntil>()?;
+    m.add_class::<Switch>()?;
+    m.add_class::<If>()?;
+    m.add_class::<Computed>()?;
+    m.add_class::<FixedSized>()?;
+    m.add_class::<Pointer>()?;
+    m.add_class::<Enum>()?;
+    m.add_class::<KsyTypeRef>()?;
+    m.add_class::<Feeder>()?;
+    m.add_class::<AsyncParse>()?;
+    m.add_function(wrap_pyfunction!(py_import_ksy, m)?)?;
+    m.add_function(wrap_pyfunction!(py_import_ksy_file, m)?)?;
+    m.add_function(wrap_pyfunction!(py_import_schema, m)?)?;
+    m.add_function(wrap_pyfunction!(py_import_schema_file, m)?)?;
+    exceptions::register(py, m)?;
+
+    let bit = Py::new(py, (BitsInteger { length: 1, signed: false, swapped: false }, Construct {}))?;
+    m.add("Bit", bit)?;
+    let nibble = Py::new(py, (BitsInteger { length: 4, signed: false, swapped: false }, Construct {}))?;
+    m.add("Nibble", nibble)?;
+    let octet = Py::new(py, (BitsInteger { length: 8, signed: false, swapped: false }, Construct {}))?;
+    m.add("Octet", octet)?;
+
+    m.add("Int8ub", Py::new(py, (FormatField { endian: '>', format: 'B', length: 1 }, Construct {}))?)?;
+    m.add("Int16ub", Py::new(py, (FormatField { endian: '>', format: 'H', length: 2 }, Construct {}))?)?;
+    m.add("Int32ub", Py::new(py, (FormatField { endian: '>', format: 'L', length: 4 }, Construct {}))?)?;
+    m.add("Int64ub", Py::new(py, (FormatField { endian: '>', format: 'Q', length: 8 }, Construct {}))?)?;
+    m.add("Int8sb", Py::new(py, (FormatField { endian: '>', format: 'b', length: 1 }, Construct {}))?)?;
+    m.add("Int16sb", Py::new(py, (FormatField { endian: '>', format: 'h', length: 2 }, Construct {}))?)?;
+    m.add("Int32sb", Py::new(py, (FormatField { endian: '>', format: 'l', length: 4 }, Construct {}))?)?;
+    m.add("Int64sb", Py::new(py, (FormatField { endian: '>', format: 'q', length: 8 }, Construct {}))?)?;
+    m.add("Int8ul", Py::new(py, (FormatField { endian: '<', format: 'B', length: 1 }, Construct {}))?)?;
+    m.add("Int16ul", Py::new(py, (FormatField { endian: '<', format: 'H', length: 2 }, Construct {}))?)?;
+    m.add("Int32ul", Py::new(py, (FormatField { endian: '<', format: 'L', length: 4 }, Construct {}))?)?;
+    m.add("Int64ul", Py::new(py, (FormatField { endian: '<', format: 'Q', length: 8 }, Construct {}))?)?;
+    m.add("Int8sl", Py::new(py, (FormatField { endian: '<', format: 'b', length: 1 }, Construct {}))?)?;
+    m.add("Int16sl", Py::new(py, (FormatField { endian: '<', format: 'h', length: 2 }, Construct {}))?)?;
+    m.add("Int32sl", Py::new(py, (FormatField { endian: '<', format: 'l', length: 4 }, Construct {}))?)?;
+    m.add("Int64sl", Py::new(py, (FormatField { endian: '<', format: 'q', length: 8 }, Construct {}))?)?;
+    m.add("Int8un", Py::new(py, (FormatField { endian: '=', format: 'B', length: 1 }, Construct {}))?)?;
+    m.add("Int16un", Py::new(py, (FormatField { endian: '=', format: 'H', length: 2 }, Construct {}))?)?;
+    m.add("Int32un", Py::new(py, (FormatField { endian: '=', format: 'L', length: 4 }, Construct {}))?)?;
+    m.add("Int64un", Py::new(py, (FormatField { endian: '=', format: 'Q', length: 8 }, Construct {}))?)?;
+    m.add("Int8sn", Py::new(py, (FormatField { endian: '=', format: 'b', length: 1 }, Construct {}))?)?;
+    m.add("Int16sn", Py::new(py, (FormatField { endian: '=', format: 'h', length: 2 }, Construct {}))?)?;
+    m.add("Int32sn", Py::new(py, (FormatField { endian: '=', format: 'l', length: 4 }, Construct {}))?)?;
+    m.add("Int64sn", Py::new(py, (FormatField { endian: '=', format: 'q', length: 8 }, Construct {}))?)?;
+
+    m.add("Byte", m.getattr("Int8ub")?)?;
+    m.add("Short", m.getattr("Int16ub")?)?;
+    m.add("Int", m.getattr("Int32ub")?)?;
+    m.add("Long", m.getattr("Int64ub")?)?;
+
+    m.add("Float32b", Py::new(py, (FormatField { endian: '>', format: 'f', length: 4 }, Construct {}))?)?;
+    m.add("Float32l", Py::new(py, (FormatField { endian: '<', format: 'f', length: 4 }, Construct {}))?)?;
+    m.add("Float32n", Py::new(py, (FormatField { endian: '=', format: 'f', length: 4 }, Construct {}))?)?;
+    m.add("Float64b", Py::new(py, (FormatField { endian: '>', format: 'd', length: 8 }, Construct {}))?)?;
+    m.add("Float64l", Py::new(py, (FormatField { endian: '<', format: 'd', length: 8 }, Construct {}))?)?;
+    m.add("Float64n", Py::new(py, (FormatField { endian: '=', format: 'd', length: 8 }, Construct {}))?)?;
+
+    m.add("Single", m.getattr("Float32b")?)?;
+    m.add("Double", m.getattr("Float64b")?)?;
+
+    let native_le = cfg!(target_endian = "little");
+    m.add("Int24ub", Py::new(py, (BytesInteger { length: 3, signed: false, swapped: false }, Construct {}))?)?;
+    m.add("Int24ul", Py::new(py, (BytesInteger { length: 3, signed: false, swapped: true }, Construct {}))?)?;
+    m.add("Int24un", Py::new(py, (BytesInteger { length: 3, signed: false, swapped: native_le }, Construct {}))?)?;
+    m.add("Int24sb", Py::new(py, (BytesInteger { length: 3, signed: true, swapped: false }, Construct {}))?)?;
+    m.add("Int24sl", Py::new(py, (BytesInteger { length: 3, signed: true, swapped: true }, Construct {}))?)?;
+    m.add("Int24sn", Py::new(py, (BytesInteger { length: 3, signed: true, swapped: native_le }, Construct {}))?)?;
+
+    let poss = build_possiblestringencodings(py);
+    m.add("possiblestringencodings", poss)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use pyo3::Python;
+    use pyo3::types::{PyBytes, PyModule};
+    use pyo3::PyAny;
+
+    #[test]
+    fn test_stream_helpers() {
+        let data = b"abcdef".to_vec();
+        let mut cur = Cursor::new(data.clone());
+        assert_eq!(stream_size(&mut cur).unwrap(), 6);
+        assert!(!stream_iseof(&mut cur).unwrap());
+        assert_eq!(stream_read(&mut cur, 3).unwrap(), b"abc");
+        let mut out = Cursor::new(Vec::new());
+        stream_write(&mut out, b"xyz").unwrap();
+        assert_eq!(out.into_inner(), b"xyz".to_vec());
+
+        let mut cur = Cursor::new(data);
+        let buf = stream_read_entire(&mut cur).unwrap();
+        assert_eq!(buf, b"abcdef");
+    }
+
+    #[test]
+    fn test_xor_bytes() {
+        let mut data = b"\x00\xff".to_vec();
+        xor_bytes(&mut data, b"\xf0");
+        assert_eq!(data, b"\xf0\x0f");
+
+        let mut data = vec![0u8; 130];
+        xor_bytes(&mut data, b"\x01\x02\x03");
+        let expected: Vec<u8> = [1u8, 2, 3].iter().copied().cycle().take(130).collect();
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_rotate_left_bytes() {
+        assert_eq!(rotate_left_bytes(b"\x0f\xf0", 4, 1).unwrap(), b"\xf0\x0f");
+        assert_eq!(rotate_left_bytes(b"\x0f\xf0", 4, 2).unwrap(), b"\xff\x00");
+        assert_eq!(rotate_left_bytes(b"\x01\x02", 8, 2).unwrap(), b"\x02\x01");
+        assert_eq!(rotate_left_bytes(b"\xff\x00", -4, 2).unwrap(), b"\x0f\xf0");
+        assert!(matches!(rotate_left_bytes(b"\x00\x00\x00", 1, 2), Err(ConstructError::RotationError)));
+        assert!(matches!(rotate_left_bytes(b"", 1, 0), Err(ConstructError::RotationError)));
+        assert!(matches!(rotate_left_bytes(b"\x00\x00", 1, i64::MAX), Err(ConstructError::RotationError)));
+        assert!(matches!(rotate_left_bytes(b"\x00\x00", 1, 1 << 61), Err(ConstructError::RotationError)));
+        assert_eq!(rotate_left_bytes(b"\x0f", i64::MIN, 1).unwrap(), b"\x0f");
+    }
+
+    #[test]
+    fn test_process_constructs() {
+        Python::with_gil(|py| {
+            let m = PyModule::new(py, "test").unwrap();
+            construct_rs(py, m).unwrap();
+            let int16: &PyAny = m.getattr("Int16ub").unwrap();
+            let xor = m.getattr("ProcessXor").unwrap().call1((0xf0, int16)).unwrap();
+            let val: i128 = xor.call_method1("parse", (PyBytes::new(py, b"\x00\xff"),)).unwrap().extract().unwrap();
+            assert_eq!(val, 0xf00f);
+            let built: &PyBytes = xor.call_method1("build", (0xf00fi128,)).unwrap().extract().unwrap();
+            assert_eq!(built.as_bytes(), b"\x00\xff");
+
+            let rol = m.getattr("ProcessRotateLeft").unwrap().call1((4, 2, int16)).unwrap();
+            let val: i128 = rol.call_method1("parse", (PyBytes::new(py, b"\x0f\xf0"),)).unwrap().extract().unwrap();
+            assert_eq!(val, 0xff00);
+            let built: &PyBytes = rol.call_method1("build", (0xff00i128,)).unwrap().extract().unwrap();
+            assert_eq!(built.as_bytes(), b"\x0f\xf0");
+            let err = rol.call_method1("parse", (PyBytes::new(py, b"\x0f"),)).unwrap_err();
+            assert!(err.is_instance_of::<exceptions::StreamError>(py));
+            let rol = m.getattr("ProcessRotateLeft").unwrap().call1((4, 2, m.getattr("GreedyBytes").unwrap())).unwrap();
+            let err = rol.call_method1("parse", (PyBytes::new(py, b"\x0f"),)).unwrap_err();
+            assert!(err.is_instance_of::<exceptions::RotationError>(py));
+        });
+    }
+
+    #[test]
+    fn test_swap_helpers() {
+        assert_eq!(swapbitsinbytes(b"\xf0\x01"), b"\x0f\x80");
+        let bits = integer2bits(0x00ff, 16).unwrap();
+        assert_eq!(swapbytesinbits(&bits).unwrap(), integer2bits(0xff00, 16).unwrap());
+        assert!(swapbytesinbits(&[0u8; 7]).is_err());
+    }
+
+    #[test]
+    fn test_swapped_constructs() {
+        Python::with_gil(|py| {
+            let m = PyModule::new(py, "test").unwrap();
+            construct_rs(py, m).unwrap();
+            let int24: &PyAny = m.getattr("Int24ub").unwrap();
+            let swapped = m.getattr("ByteSwapped").unwrap().call1((int24,)).unwrap();
+            let val: i128 = swapped.call_method1("parse", (PyBytes::new(py, b"\x01\x02\x03"),)).unwrap().extract().unwrap();
+            assert_eq!(val, 0x030201);
+            let built: &PyBytes = swapped.call_method1("build", (0x030201i128,)).unwrap().extract().unwrap();
+            assert_eq!(built.as_bytes(), b"\x01\x02\x03");
+            for data in [&b"\x01\x02"[..], b"\x01\x02\x03\x04"] {
+                let err = swapped.call_method1("parse", (PyBytes::new(py, data),)).unwrap_err();
+                assert!(err.is_instance_of::<exceptions::StreamError>(py));
+            }
+
+            let greedy = m.getattr("GreedyString").unwrap().call1(("ascii",)).unwrap();
+            let bitswapped = m.getattr("BitsSwapped").unwrap().call1((greedy,)).unwrap();
+            let val: String = bitswapped.call_method1("parse", (PyBytes::new(py, b"\x82\x42"),)).unwrap().extract().unwrap();
+            assert_eq!(val, "AB");
+        });
+    }
+
+    #[test]
+    fn test_union() {
+        Python::with_gil(|py| {
+            let m = PyModule::new(py, "test").unwrap();
+            construct_rs(py, m).unwrap();
+            let kw = PyDict::new(py);
+            kw.set_item("short", m.getattr("Int16ub").unwrap()).unwrap();
+            kw.set_item("long", m.getattr("Int32ub").unwrap()).unwrap();
+            let union = m.getattr("Union").unwrap().call((0,), Some(kw)).unwrap();
+            assert_eq!(union.call_method0("sizeof").unwrap().extract::<usize>().unwrap(), 4);
+
+            let data = PyBytes::new(py, b"\x01\x02\x03\x04");
+            let obj: &PyDict = union.call_method1("parse", (data,)).unwrap().downcast().unwrap();
+            assert_eq!(obj.get_item("short").unwrap().unwrap().extract::<i128>().unwrap(), 0x0102);
+            assert_eq!(obj.get_item("long").unwrap().unwrap().extract::<i128>().unwrap(), 0x01020304);
+            let (_, consumed): (PyObject, usize) = union.call_method1("_parseprefix", (data,)).unwrap().extract().unwrap();
+            assert_eq!(consumed, 2);
+
+            let obj = PyDict::new(py);
+            obj.set_item("long", 0x01020304).unwrap();
+            let built: &PyBytes = union.call_method1("build", (obj,)).unwrap().extract().unwrap();
+            assert_eq!(built.as_bytes(), b"\x01\x02\x03\x04");
+            let err = union.call_method1("build", (PyDict::new(py),)).unwrap_err();
+            assert!(err.is_instance_of::<exceptions::UnionError>(py));
+        });
+    }
+
+    #[test]
+    fn test_sequences() {
+        Python::with_gil(|py| {
+            let m = PyModule::new(py, "test").unwrap();
+            construct_rs(py, m).unwrap();
+            let int8: &PyAny = m.getattr("Int8ub").unwrap();
+            let int16: &PyAny = m.getattr("Int16ub").unwrap();
+            let seq = m.getattr("Sequence").unwrap().call1((int8, int16)).unwrap();
+            assert_eq!(seq.call_method0("sizeof").unwrap().extract::<usize>().unwrap(), 3);
+            let data = PyBytes::new(py, b"\x01\x00\x02");
+            let obj: Vec<i128> = seq.call_method1("parse", (data,)).unwrap().extract().unwrap();
+            assert_eq!(obj, vec![1, 2]);
+            let built: &PyBytes = seq.call_method1("build", (vec![1, 2],)).unwrap().extract().unwrap();
+            assert_eq!(built.as_bytes(), b"\x01\x00\x02");
+
+            let kw = PyDict::new(py);
+            kw.set_item("tag", int8).unwrap();
+            kw.set_item("value", int16).unwrap();
+            let focused = m.getattr("FocusedSeq").unwrap().call(("value",), Some(kw)).unwrap();
+            let val: i128 = focused.call_method1("parse", (data,)).unwrap().extract().unwrap();
+            assert_eq!(val, 2);
+            assert!(focused.getattr("tag").unwrap().is(int8));
+        });
+    }
+
+    #[test]
+    fn test_lazy_constructs() {
+        Python::with_gil(|py| {
+            let m = PyModule::new(py, "test").unwrap();
+            construct_rs(py, m).unwrap();
+            let int8: &PyAny = m.getattr("Int8ub").unwrap();
+            let int16: &PyAny = m.getattr("Int16ub").unwrap();
+
+            let lazy = m.getattr("Lazy").unwrap().call1((int16,)).unwrap();
+            let thunk = lazy.call_method1("parse", (PyBytes::new(py, b"\x01\x02"),)).unwrap();
+            assert_eq!(thunk.call0().unwrap().extract::<i128>().unwrap(), 0x0102);
+            let built: &PyBytes = lazy.call_method1("build", (thunk,)).unwrap().extract().unwrap();
+            assert_eq!(built.as_bytes(), b"\x01\x02");
+
+            let kw = PyDict::new(py);
+            kw.set_item("a", int8).unwrap();
+            kw.set_item("b", int16).unwrap();
+            let lazystruct = m.getattr("LazyStruct").unwrap().call((), Some(kw)).unwrap();
+            let obj = lazystruct.call_method1("parse", (PyBytes::new(py, b"\x01\x00\x02"),)).unwrap();
+            assert_eq!(obj.repr().unwrap().to_str().unwrap(), "<LazyContainer: 0 items cached, 2 subcons>");
+            assert_eq!(obj.getattr("b").unwrap().extract::<i128>().unwrap(), 2);
+            assert_eq!(obj.get_item("a").unwrap().extract::<i128>().unwrap(), 1);
+            let expected = PyDict::new(py);
+            expected.set_item("a", 1).unwrap();
+            expected.set_item("b", 2).unwrap();
+            assert!(obj.eq(expected).unwrap());
+
+            let lazyarray = m.getattr("LazyArray").unwrap().call1((3, int16)).unwrap();
+            let obj = lazyarray.call_method1("parse", (PyBytes::new(py, b"\x00\x01\x00\x02\x00\x03"),)).unwrap();
+            assert_eq!(obj.len().unwrap(), 3);
+            assert_eq!(obj.get_item(-1).unwrap().extract::<i128>().unwrap(), 3);
+            assert_eq!(obj.repr().unwrap().to_str().unwrap(), "<LazyListContainer: 1 of 3 items cached>");
+            assert!(obj.eq(vec![1, 2, 3]).unwrap());
+            assert_eq!(lazyarray.call_method0("sizeof").unwrap().extract::<usize>().unwrap(), 6);
+        });
+    }
+
+    #[test]
+    fn test_lazybound() {
+        Python::with_gil(|py| {
+            let m = PyModule::new(py, "test").unwrap();
+            construct_rs(py, m).unwrap();
+            let locals = PyDict::new(py);
+            locals.set_item("m", m).unwrap();
+            py.run(
+                "node = m.Sequence(m.Int8ub, m.LazyBound(lambda: node, maxdepth=3))\n\
+                 leaf = m.LazyBound(lambda: m.Int8ub)",
+                Some(locals),
+                None,
+            )
+            .unwrap();
+            let leaf = locals.get_item("leaf").unwrap().unwrap();
+            let val: i128 = leaf.call_method1("parse", (PyBytes::new(py, b"\x05"),)).unwrap().extract().unwrap();
+            assert_eq!(val, 5);
+            assert_eq!(leaf.call_method0("sizeof").unwrap().extract::<usize>().unwrap(), 1);
+
+            let node = locals.get_item("node").unwrap().unwrap();
+            assert!(node.call_method0("sizeof").is_err());
+            let err = node.call_method1("parse", (PyBytes::new(py, &[1u8; 16]),)).unwrap_err();
+            assert!(err.is_instance_of::<pyo3::exceptions::PyRecursionError>(py));
+        });
+    }
+
+    #[test]
+    fn test_restreamed_bytesio() {
+        let decoder: Transform = Arc::new(|data: &[u8]| Ok(swapbitsinbytes(data)));
+        let encoder: Transform = Arc::new(|data: &[u8]| Ok(swapbitsinbytes(data)));
+        let mut stream = RestreamedBytesIO::new(Cursor::new(b"\x80\x01\xf0".to_vec()), decoder, 1, encoder, 1);
+        assert_eq!(stream.read_count(Some(2)).unwrap(), b"\x01\x80");
+        assert_eq!(stream_tell(&mut stream).unwrap(), 2);
+        assert_eq!(stream_read_entire(&mut stream).unwrap(), b"\x0f");
+        assert!(stream.read_count(Some(1)).unwrap().is_empty());
+        stream.close().unwrap();
+
+        // Substream returning one byte per read, and blocking before each.
+        struct Trickle(Vec<u8>, bool);
+        impl Read for Trickle {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.1 = !self.1;
+                if self.1 {
+                    return Err(io::Error::new(io::ErrorKind::WouldBlock, "no data available"));
+                }
+                let n = buf.len().min(self.0.len()).min(1);
+                buf[..n].copy_from_slice(&self.0[..n]);
+                self.0.drain(..n);
+                Ok(n)
+            }
+        }
+        impl Write for Trickle {
+            fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+                Ok(data.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let swap: Transform = Arc::new(|data: &[u8]| Ok(data.iter().rev().copied().collect()));
+        let mut stream = RestreamedBytesIO::new(Trickle(b"abcde".to_vec(), false), swap.clone(), 2, swap, 2);
+        assert_eq!(stream.read_count(Some(4)).unwrap(), b"badc");
+        assert_eq!(stream.read_count(None).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        let decoder: Transform = Arc::new(|data: &[u8]| Ok(data.to_vec()));
+        let encoder: Transform = Arc::new(|data: &[u8]| Ok(bits2integer(data, false).to_be_bytes()[15..].to_vec()));
+        let mut stream = RestreamedBytesIO::new(Cursor::new(Vec::new()), decoder, 1, encoder, 8);
+        stream_write(&mut stream, &integer2bits(0xa5, 8).unwrap()[..4]).unwrap();
+        assert!(stream.close().is_err());
+        stream_write(&mut stream, &integer2bits(0xa5, 8).unwrap()[4..]).unwrap();
+        stream.close().unwrap();
+        assert_eq!(stream.substream.into_inner(), b"\xa5");
+    }
+
+    #[test]
+    fn test_rebuffered_bytesio() {
+        let mut stream = RebufferedBytesIO::new(Cursor::new(b"abcdefgh".to_vec()), Some(4));
+        assert_eq!(stream.read_count(6).unwrap(), b"abcdef");
+        assert_eq!((stream.cachedfrom(), stream.cachedto()), (2, 8));
+        stream_seek(&mut stream, -3, SeekFrom::Current(-3)).unwrap();
+        assert_eq!(stream_read(&mut stream, 3).unwrap(), b"def");
+        stream_seek(&mut stream, 0, SeekFrom::Start(0)).unwrap();
+        assert!(stream.read_count(1).is_err());
+        stream_seek(&mut stream, 6, SeekFrom::Start(6)).unwrap();
+        stream_write(&mut stream, b"XYZ").unwrap();
+        assert_eq!(stream.tell(), 9);
+        stream_seek(&mut stream, 6, SeekFrom::Start(6)).unwrap();
+        assert_eq!(stream.read_count(3).unwrap(), b"XYZ");
+        assert!(stream.read_count(1).is_err());
+        stream_seek(&mut stream, 20, SeekFrom::Start(20)).unwrap();
+        assert_eq!(stream.read(&mut [0u8; 4]).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_tunneling() {
+        Python::with_gil(|py| {
+            let m = PyModule::new(py, "test").unwrap();
+            construct_rs(py, m).unwrap();
+            let int16: &PyAny = m.getattr("Int16ub").unwrap();
+            let reverse: Transform = Arc::new(|data: &[u8]| Ok(swapbytes(data.to_vec())));
+            let transformed = Py::new(py, Transformed::native(int16.into(), reverse.clone(), Some(2), reverse, Some(2))).unwrap();
+            let val: i128 = transformed.call_method1(py, "parse", (PyBytes::new(py, b"\x01\x02"),)).unwrap().extract(py).unwrap();
+            assert_eq!(val, 0x0201);
+            let built: Vec<u8> = transformed.call_method1(py, "build", (0x0201,)).unwrap().extract(py).unwrap();
+            assert_eq!(built, b"\x01\x02");
+            assert_eq!(transformed.call_method0(py, "sizeof").unwrap().extract::<usize>(py).unwrap(), 2);
+
+            let bits = m.getattr("BitsInteger").unwrap().call1((16,)).unwrap();
+            let bytes2bits: Transform = Arc::new(|data: &[u8]| Ok(integer2bits(bytes2integer(data, false), 8).unwrap()));
+            let bits2bytes: Transform = Arc::new(|data: &[u8]| Ok(vec![bits2integer(data, false) as u8]));
+            let bitwise = Py::new(
+                py,
+                Restreamed::native(bits.into(), bytes2bits, 1, bits2bytes, 8, Some(Arc::new(|n| Ok(n / 8)))),
+            )
+            .unwrap();
+            let val: i128 = bitwise.call_method1(py, "parse", (PyBytes::new(py, b"\x01\x02"),)).unwrap().extract(py).unwrap();
+            assert_eq!(val, 0x0102);
+            let built: Vec<u8> = bitwise.call_method1(py, "build", (0x0102,)).unwrap().extract(py).unwrap();
+            assert_eq!(built, b"\x01\x02");
+            assert_eq!(bitwise.call_method0(py, "sizeof").unwrap().extract::<usize>(py).unwrap(), 2);
+
+            let restream = m.getattr("RestreamData").unwrap().call1((PyBytes::new(py, b"\x00\x07"), int16)).unwrap();
+            let val: i128 = restream.call_method1("parse", (PyBytes::new(py, b""),)).unwrap().extract().unwrap();
+            assert_eq!(val, 7);
+            assert_eq!(restream.call_method1("build", (7,)).unwrap().extract::<Vec<u8>>().unwrap(), b"");
+        });
+    }
+
+    #[test]
+    fn test_hexdump() {
+        let data: Vec<u8> = (0..=255u8).chain(b"0".repeat(100)).collect();
+        let dump = hexdump(&data, 16).unwrap();
+        let lines: Vec<&str> = dump.split('\n').collect();
+        assert_eq!(lines[0], "hexundump(\"\"\"");
+        assert_eq!(lines[3], "0020   20 21 22 23 24 25 26 27 28 29 2A 2B 2C 2D 2E 2F    !\"#$%&'()*+,-./");
+        assert_eq!(lines[23], "0160   30 30 30 30                                       0000");
+        assert_eq!(lines[24..], ["\"\"\")", ""]);
+        assert_eq!(hexundump(&dump, 16).unwrap(), data);
+        assert_eq!(hexdump(b"", 16).unwrap(), "hexundump(\"\"\"\n\"\"\")\n");
+        assert!(hexdump(b"abc", 0).is_err());
+    }
+
+    #[test]
+    fn test_generate_rust() {
+        Python::with_gil(|py| {
+            let m = PyModule::new(py, "test").unwrap();
+            construct_rs(py, m).unwrap();
+            let locals = PyDict::new(py);
+            locals.set_item("m", m).unwrap();
+            let packet = py
+                .eval(
+                    r#"m.LazyStruct(
+                        length=m.Int16ul,
+                        name=m.PascalString(m.Int8ub, "utf8"),
+                        items=m.LazyArray(3, m.Sequence(m.Int8ub, m.BytesInteger(3, True, False))),
+                        type=m.Hex(m.Int32ub),
+                    )"#,
+                    Some(locals),
+                    None,
+                )
+                .unwrap();
+            let schema = Schema::from_construct(packet).unwrap();
+            assert_eq!(schema.sizeof(), None);
+            let source = generate_rust(&schema, "packet").unwrap();
+            assert!(source.contains("pub struct Packet {\n    pub length: u16,\n    pub name: String,\n    pub items: Vec<PacketItemsItem>,\n    pub r#type: u32,\n}"));
+            assert!(source.contains("pub struct PacketItemsItem(\n    pub u8,\n    pub i32,\n);"));
+            assert!(source.contains("let f_length = u16::from_le_bytes(rt::take_array(data, offset)?);"));
+            let generated: String = packet.call_method1("generate_rust", ("packet",)).unwrap().extract().unwrap();
+            assert_eq!(generated, source);
+
+            let err = Schema::from_construct(py.eval("m.LazyStruct(a=m.LazyArray(lambda ctx: 1, m.Byte))", Some(locals), None).unwrap())
+                .unwrap_err();
+            assert_eq!(err.to_string(), "(schema) -> a -> count: only constant counts can be described");
+            let err = generate_rust(&Schema::CString { encoding: "utf16".into() }, "name").unwrap_err();
+            assert_eq!(err.path, "(schema) -> [0]");
+        });
+    }
+
+    #[test]
+    fn test_generate_rust_compiles() {
+        Python::with_gil(|py| {
+            let m = PyModule::new(py, "test").unwrap();
+            construct_rs(py, m).unwrap();
+            let locals = PyDict::new(py);
+            locals.set_item("m", m).unwrap();
+            // Field names matching the parameters and locals of the generated code.
+            let fixture = py
+                .eval(
+                    r#"m.LazyStruct(
+                        data=m.Int16ub,
+                        offset=m.Int8sb,
+                        value=m.PascalString(m.Int8ub, "utf8"),
+                        encoded=m.CString("utf8"),
+                        length=m.ByteSwapped(m.Int32ub),
+                        items=m.LazyArray(2, m.Sequence(m.Int8ub, m.BytesInteger(3, True, False))),
+                        out=m.BitsSwapped(m.Bytes(2)),
+                        u=m.Int8ub,
+                        region=m.ProcessXor(b"\x55", m.GreedyBytes),
+                    )"#,
+                    Some(locals),
+                    None,
+                )
+                .unwrap();
+            let mut source = generate_rust(&Schema::from_construct(fixture).unwrap(), "fixture").unwrap();
+            source.push_str(
+                r#"
+fn main() {
+    let data = b"\x01\x02\xff\x03abcxyz\x00\x04\x03\x02\x01\x05\xff\xff\xfe\x06\x00\x00\x01\x80\x01\x07\x55\x54";
+    let (value, size) = Fixture::parse(data).unwrap();
+    assert_eq!(size, data.len());
+    assert_eq!(value.build().unwrap(), data);
+    println!("{:?}", value);
+    for seed in [&b""[..], b"\x00\x01\x02\x03", &[0xa5; 64]] {
+        let value = Fixture::arbitrary(&mut rt::Unstructured::new(seed));
+        assert_eq!(Fixture::parse(&value.build().unwrap()).unwrap().0, value);
+    }
+}
+"#,
+            );
+            let dir = std::env::temp_dir().join(format!("construct-rs-generated-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("fixture.rs"), &source).unwrap();
+            let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+            let compiled = std::process::Command::new(rustc)
+                .args(["--edition", "2021", "-o"])
+                .arg(dir.join("fixture"))
+                .arg(dir.join("fixture.rs"))
+                .output()
+                .unwrap();
+            assert!(compiled.status.success(), "{}", String::from_utf8_lossy(&compiled.stderr));
+            let run = std::process::Command::new(dir.join("fixture")).output().unwrap();
+            std::fs::remove_dir_all(&dir).unwrap();
+            assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+            assert_eq!(
+                String::from_utf8_lossy(&run.stdout),
+                "Fixture { data: 258, offset: -1, value: \"abc\", encoded: \"xyz\", length: 16909060, \
+                 items: [FixtureItemsItem(5, -2), FixtureItemsItem(6, 1)], out: [1, 128], u: 7, region: [0, 1] }\n"
+            );
+        });
+    }
+
+    #[test]
+    fn test_export_ksy() {
+        Python::with_gil(|py| {
+            let m = PyModule::new(py, "test").unwrap();
+            construct_rs(py, m).unwrap();
+            let locals = PyDict::new(py);
+            locals.set_item("m", m).unwrap();
+            // Mirrors tests/kaitai_comparisons/comparison_1_kaitai.ksy, except for its bit
+            // fields, which schemas cannot describe.
+            let source = include_str!("../../tests/kaitai_comparisons/comparison_1_kaitai.ksy");
+            let (source, _) = source.split_once("    types:\n      flags:\n").unwrap();
+            let source = source.replace("      - id: flags\n        type: flags\n", "");
+            let d = py
+                .eval(
+                    r#"m.Struct(
+                        count=m.Int32ul,
+                        items=m.Array(m.Expr("count"), m.Struct(
+                            num1=m.Int8ul,
+                            num2=m.Int24ul,
+                            fixedarray1=m.Array(3, m.Int8ul),
+                            name1=m.CString("utf8"),
+                            name2=m.PascalString(m.Int8ul, "utf8"),
+                        )),
+                    )"#,
+                    Some(locals),
+                    None,
+                )
+                .unwrap();
+            let ksy = export_ksy(&Schema::from_construct(d).unwrap(), "comparison_1_kaitai").unwrap();
+            assert!(ksy.starts_with(
+                "meta:\n  id: comparison_1_kaitai\nseq:\n  - id: count\n    type: u4le\n  - id: items\n    type: items\n    repeat: expr\n    repeat-expr: count\n"
+            ));
+            let exported: String = d.call_method1("export_ksy", ("comparison_1_kaitai",)).unwrap().extract().unwrap();
+            assert_eq!(exported, ksy);
+            // Same members and instances as the file's `item` type, with explicit endianness and encodings.
+            let (exported, expected) = (Yaml::parse(&ksy).unwrap(), Yaml::parse(&source).unwrap());
+            let item = |doc: &Yaml, name: &str| doc.get("types").unwrap().get(name).unwrap().clone();
+            let ids = |item: &Yaml| match item.get("seq") {
+                Some(Yaml::List(seq)) => seq.iter().map(|entry| entry.get("id").unwrap().to_yaml()).collect::<Vec<_>>(),
+                _ => panic!("expected a seq"),
+            };
+            assert_eq!(ids(&item(&exported, "items")), ids(&item(&expected, "item")));
+            assert_eq!(item(&exported, "items").get("instances"), item(&expected, "item").get("instances"));
+
+            let d = py.eval("m.ProcessXor(b'\\x01\\x02', m.BytesInteger(3, True, False))", Some(locals), None).unwrap();
+            let ksy = export_ksy(&Schema::from_construct(d).unwrap(), "x").unwrap();
+            assert!(ksy.contains("process: 'xor([0x01, 0x02])'"));
+            assert!(ksy.contains("value: '((value_hi << 8 | value_lo) ^ 8388608) - 8388608'"));
+            let err = export_ksy(&Schema::BitsInteger { length: 3, signed: false, swapped: false }, "x").unwrap_err();
+            assert_eq!(err.to_string(), "(schema): BitsInteger is only equivalent to Kaitai bit fields in a bitwise region");
+            let pointer = Schema::Pointer { offset: SchemaParam::Const(2), subcon: Box::new(Schema::FormatField { endian: '>', format: 'B' }) };
+            assert_eq!(export_ksy(&pointer, "x").unwrap_err().to_string(), "(schema): pointers need a name");
+        });
+    }
+
+    #[test]
+    fn test_import_ksy() {
+        let doc = Yaml::parse("a: [1, 'x y', {b: ~}]\nc:\n  - d: 0x10 # comment\n    e: |\n      text\n").unwrap();
+        assert_eq!(doc.get("a").unwrap().to_yaml(), "- 1\n- 'x y'\n- b: null\n");
+        let Some(Yaml::List(items)) = doc.get("c") else { panic!("expected a list") };
+        assert!(matches!(items[0].get("d"), Some(Yaml::Int(16))));
+        assert!(matches!(items[0].get("e"), Some(Yaml::Str(text)) if text == "text\n"));
+        assert_eq!(Yaml::parse("a: 1\n  b: 2\n").unwrap_err().to_string(), "line 2: unexpected indentation");
+        let expr = Expr::compile("num2_hi << 16 | num2_lo").unwrap();
+        assert!(matches!(expr.node, ExprNode::Binary(..)));
+        assert!(Expr::compile("a +").is_err());
+
+        Python::with_gil(|py| {
+            let m = PyModule::new(py, "construct_rs").unwrap();
+            construct_rs(py, m).unwrap();
+            let ksy = "meta: {id: t, endian: be}\nseq:\n  - id: n\n    type: u1\n  - id: v\n    type: u2\n    repeat: expr\n    repeat-expr: n\n";
+            let d = m.getattr("import_ksy").unwrap().call1((ksy,)).unwrap();
+            let data = PyBytes::new(py, b"\x02\x00\x01\x00\x02");
+            let obj = d.call_method1("parse", (data,)).unwrap();
+            assert_eq!(obj.get_item("v").unwrap().extract::<Vec<u16>>().unwrap(), vec![1, 2]);
+            assert!(d.call_method1("build", (obj,)).unwrap().eq(data).unwrap());
+        });
+    }
+
+    #[test]
+    fn test_schema_documents() {
+        let document = r#"{"type": "Struct", "fields": [{"name": "n", "type": "Int8ub"}, {"name": "v", "type": "Array", "count": "n", "subcon": "Int16ul"}]}"#;
+        let schema = Schema::from_json(document).unwrap();
+        let expected = Schema::Struct(vec![
+            SchemaField { name: Some("n".into()), schema: Schema::FormatField { endian: '>', format: 'B' } },
+            SchemaField {
+                name: Some("v".into()),
+                schema: Schema::Array { count: SchemaParam::Expr("n".into()), subcon: Box::new(Schema::FormatField { endian: '<', format: 'H' }) },
+            },
+        ]);
+        assert_eq!(schema, expected);
+        assert_eq!(Schema::from_json(&schema.to_json()).unwrap(), expected);
+        assert_eq!(Schema::from_yaml(&schema.to_yaml()).unwrap(), expected);
+        assert_eq!(Yaml::parse_json("[1, -2.5, \"a\\n\", null, true]").unwrap().to_json(), "[\n  1,\n  -2.5,\n  \"a\\n\",\n  null,\n  true\n]\n");
+        assert_eq!(Yaml::parse_json("{\"a\": 1,\n \"a\": 2}").unwrap_err().to_string(), "line 2: duplicate key \"a\"");
+
+        let err = Schema::from_yaml("type: Struct\nfields:\n  - name: a\n    type: Array\n    count: 'a +'\n    subcon: Int8ub\n").unwrap_err();
+        assert_eq!(err.path, "(schema) -> a -> count");
+        let err = Schema::from_json(r#"{"type": "Switch", "keyfunc": "k", "cases": {"1": {"type": "If", "subcon": "Int8ub"}}}"#).unwrap_err();
+        assert_eq!(err.to_string(), "(schema) -> cases -> 1: missing \"condfunc\"");
+        let err = Schema::from_json(r#"{"type": "CString", "encoding": "utf8", "length": 3}"#).unwrap_err();
+        assert_eq!(err.to_string(), "(schema) -> length: CString has no parameter \"length\"");
+    }
+
+    /// Parse `data` with the native parser and with the Python-facing implementation,
+    /// checking that they agree. Returns whether the native parser supports `construct`.
+    fn parse_both_ways(py: Python, construct: &PyAny, data: &[u8], contextkw: Option<&PyDict>) -> bool {
+        let schema = Schema::from_construct(construct).unwrap();
+        let input = Input::Bytes(PyBytes::new(py, data));
+        let mut parser = NativeParser::new(data);
+        let native = parser.scope_from_py(contextkw).and_then(|scope| parser.parse_prefix(&schema, data, Some(scope)));
+        PARSE_RELEASED.set(false);
+        let python = parse_prefix_in(py, construct, &input, 0, context_from_kwargs(py, contextkw).unwrap());
+        PARSE_RELEASED.set(true);
+        match (native, python) {
+            (Ok((value, consumed)), Ok((obj, expected))) => {
+                assert_eq!(consumed, expected, "{} parsing {:?}", construct, data);
+                let value = value.to_object(py, &input).unwrap();
+                assert!(value.as_ref(py).eq(&obj).unwrap(), "{} parsing {:?}: {} != {}", construct, data, value, obj);
+                true
+            }
+            (Err(NativeError::Failed | NativeError::Short(_)), Err(_)) => true,
+            (Err(NativeError::Unsupported), _) => false,
+            (Ok(_), Err(err)) => panic!("{} parsing {:?}: only the native parser succeeds, not raising {}", construct, data, err),
+            (Err(_), Ok((obj, _))) => panic!("{} parsing {:?}: only the Python-facing parser succeeds, parsing {}", construct, data, obj),
+        }
+    }
+
+    #[test]
+    fn test_native_parser_agrees() {
+        Python::with_gil(|py| {
+            let m = PyModule::new(py, "construct_rs").unwrap();
+            construct_rs(py, m).unwrap();
+            let locals = PyDict::new(py);
+            locals.set_item("m", m).unwrap();
+            let kw = PyDict::new(py);
+            kw.set_item("n", 2).unwrap();
+            kw.set_item("k", 1).unwrap();
+            // Every kind of schema, whether the native parser supports it, and inputs to parse.
+            let cases: &[(&str, bool, &[&[u8]])] = &[
+                ("m.Int16ub", true, &[b"\x01\x02", b"\x01"]),
+                ("m.Float32l", true, &[b"\x00\x00\x80\x3f", b""]),
+                ("m.Int24sl", true, &[b"\xff\xff\xff", b"\x01\x02"]),
+                ("m.BitsInteger(4)", true, &[b"\x01\x00\x01\x01", b"\x01"]),
+                (r#"m.PaddedString(4, "utf8")"#, true, &[b"ab\x00\x00", b"abc"]),
+                (r#"m.PascalString(m.Int8ub, "utf8")"#, true, &[b"\x02hi", b"\x05hi", b""]),
+                (r#"m.CString("utf8")"#, true, &[b"abc\x00rest", b"abc"]),
+                (r#"m.GreedyString("utf8")"#, true, &[b"abc", b""]),
+                (r#"m.Bytes(m.Expr("_params.n"))"#, true, &[b"abcd", b"a"]),
+                ("m.GreedyBytes", true, &[b"abc"]),
+                ("m.Sequence(m.Int8ub, m.Int16ub)", true, &[b"\x01\x00\x02", b"\x01"]),
+                ("m.LazyStruct(a=m.Int8ub)", false, &[b"\x01"]),
+                ("m.LazyArray(2, m.Int8ub)", false, &[b"\x01\x02"]),
+                ("m.Lazy(m.Int8ub)", false, &[b"\x01"]),
+                ("m.Hex(m.Int16ub)", false, &[b"\x01\x02"]),
+                ("m.HexDump(m.Bytes(2))", false, &[b"ab"]),
+                ("m.ByteSwapped(m.Int16ub)", true, &[b"\x01\x02", b"\x01"]),
+                ("m.BitsSwapped(m.Bytes(1))", true, &[b"\x01", b""]),
+                ("m.ProcessXor(1, m.Bytes(2))", true, &[b"ab", b"a"]),
+                (r#"m.Struct(n=m.Int8ub, data=m.Bytes(m.Expr("n")), k=m.Computed(m.Expr("_params.k")))"#, true, &[b"\x02ab", b"\x02a"]),
+                (r#"m.Array(m.Expr("_params.n"), m.Int8ub)"#, true, &[b"\x01\x02", b"\x01"]),
+                ("m.GreedyRange(m.Int16ub)", true, &[b"\x00\x01\x00\x02\x03", b""]),
+                (r#"m.RepeatUntil(m.Expr("_ == 0"), m.Int8ub)"#, true, &[b"\x01\x00\x02", b"\x01"]),
+                (r#"m.Switch(m.Expr("_params.k"), {1: m.Int8ub, 2: m.Int16ub})"#, true, &[b"\x01", b""]),
+                (r#"m.If(m.Expr("_params.k"), m.Int8ub)"#, true, &[b"\x01", b""]),
+                (r#"m.Computed(m.Expr("_params.k * 2"))"#, true, &[b""]),
+                (r#"m.FixedSized(3, m.CString("utf8"))"#, true, &[b"ab\x00", b"abc", b"a"]),
+                ("m.Struct(a=m.Int8ub, b=m.Pointer(-1, m.Int8ub))", false, &[b"\x01\x02"]),
+                ("m.Enum(m.Int8ub, a=1, b=2)", true, &[b"\x01", b"\x03", b""]),
+            ];
+            for (source, supported, inputs) in cases {
+                let construct = py.eval(source, Some(locals), None).unwrap();
+                for data in *inputs {
+                    assert_eq!(parse_both_ways(py, construct, data, Some(kw)), *supported, "{} parsing {:?}", source, data);
+                }
+            }
+            for (source, data) in [("m.GreedyRange(m.Int16ub)", &b"\x00\x01\x00\x02\x03"[..]), (r#"m.Array(m.Expr("_params.n"), m.Int8ub)"#, b"\x01")] {
+                let construct = py.eval(source, Some(locals), None).unwrap();
+                let input = Input::Bytes(PyBytes::new(py, data));
+                let parallel = parse_parallel_released(py, construct, &input, Some(kw), 2).unwrap();
+                PARSE_RELEASED.set(false);
+                let python = parse_prefix_in(py, construct, &input, 0, context_from_kwargs(py, Some(kw)).unwrap());
+                PARSE_RELEASED.set(true);
+                match (parallel, python) {
+                    (Some(value), Ok((obj, _))) => assert!(value.as_ref(py).eq(obj).unwrap(), "{} parsing {:?} in parallel", source, data),
+                    (None, Err(_)) => {}
+                    (parallel, python) => panic!("{} parsing {:?} in parallel: {:?} but {:?}", source, data, parallel, python),
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn test_release_gil() {
+        Python::with_gil(|py| {
+            let m = PyModule::new(py, "construct_rs").unwrap();
+            construct_rs(py, m).unwrap();
+            let locals = PyDict::new(py);
+            locals.set_item("m", m).unwrap();
+            let pure = py
+                .eval(r#"m.Struct(n=m.Int8ub, xs=m.Array(m.Expr("n + _parent.k"), m.Int16ub), s=m.PascalString(m.Byte, "utf8"))"#, Some(locals), None)
+                .unwrap();
+            let mixed = py.eval("m.Struct(n=m.Int8ub, c=m.Computed(lambda ctx: ctx.n * 2))", Some(locals), None).unwrap();
+            let kw = PyDict::new(py);
+            kw.set_item("k", 1).unwrap();
+            let (obj, consumed) = parse_released(py, pure, &Input::Bytes(PyBytes::new(py, b"\x01\x00\x02\x00\x03\x02hi!")), Some(kw)).unwrap().unwrap();
+            assert_eq!(consumed, 8);
+            let xs: Vec<i64> = obj.as_ref(py).get_item("xs").unwrap().extract().unwrap();
+            assert_eq!(xs, [2, 3]);
+            assert!(parse_released(py, mixed, &Input::Bytes(PyBytes::new(py, b"\x01")), None).unwrap().is_none());
+            // Errors are left for the Python-facing implementation to raise.
+            assert!(parse_released(py, pure, &Input::Bytes(PyBytes::new(py, b"\x01\x00")), Some(kw)).unwrap().is_none());
+            // The schema is described once, and only the outermost parse tries it.
+            assert!(Arc::ptr_eq(&cached_schema(pure).unwrap(), &cached_schema(pure).unwrap()));
+            let contextkw = context_kwargs(py, context_from_kwargs(py, Some(kw)).unwrap()).unwrap();
+            assert!(parse_released(py, pure, &Input::Bytes(PyBytes::new(py, b"\x01\x00\x02\x00\x03\x02hi!")), Some(contextkw)).unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn test_streams() {
+        Python::with_gil(|py| {
+            let m = PyModule::new(py, "construct_rs").unwrap();
+            construct_rs(py, m).unwrap();
+            let locals = PyDict::new(py);
+            locals.set_item("m", m).unwrap();
+            let nested = py.eval(r#"m.Struct("a" / m.Struct("x" / m.Computed(m.Expr("_params.k"))))"#, None, Some(locals)).unwrap();
+            let kw = PyDict::new(py);
+            kw.set_item("k", 3).unwrap();
+            let (obj, _) = parse_released(py, nested, &Input::Bytes(PyBytes::new(py, b"")), Some(kw)).unwrap().unwrap();
+            let x: i64 = obj.as_ref(py).get_item("a").unwrap().get_item("x").unwrap().extract().unwrap();
+            assert_eq!(x, 3);
+        });
+    }
+
+    #[test]
+    fn test_arbitrary() {
+        let schema = Schema::Struct(vec![
+            SchemaField { name: Some("n".into()), schema: Schema::BytesInteger { length: 2, signed: true, swapped: false } },
+            SchemaField { name: Some("s".into()), schema: Schema::PaddedString { length: 4, encoding: "ascii".into() } },
+        ]);
+        let source = generate_rust(&schema, "record").unwrap();
+        assert!(source.contains("pub struct Unstructured<'a>"));
+        assert!(source.contains(
+            "pub fn arbitrary(u: &mut rt::Unstructured<'_>) -> Self {\n        let field_0 = u.int(16, true) as i16;\n        let field_1 = u.string(rt::Encoding::Ascii, 4);\n        Record(field_0, field_1)\n"
+        ));
     }
 
     #[test]
     fn test_subconstruct_delegation() {
         Python::with_gil(|py| {
             let inner = Py::new(py, Construct {}).unwrap();
-            let sub = Py::new(py, (Subconstruct { subcon: inner.clone_ref(py) }, Construct {})).unwrap();
+            let sub = Py::new(py, (Subconstruct { subcon: inner.clone_ref(py).into_py(py) }, Construct {})).unwrap();
             let data = PyBytes::new(py, b"abc");
             let res: &PyBytes = sub.call_method1(py, "parse", (data,)).unwrap().extract(py).unwrap();
             assert_eq!(res.as_bytes(), b"abc");
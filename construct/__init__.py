@@ -21,8 +21,19 @@ Hands-on example:
 
 import os
 from construct.core import *
+from construct.expr import *
+from construct.debug import *
+from construct.version import *
+from construct import lib
+
+# construct.debug re-exports construct.lib and construct.core, so the Rust names
+# must be bound after it, or they would be replaced by the Python ones again.
 if os.getenv("CONSTRUCT_USE_RUST"):
     try:
+        import construct_rs
+    except ImportError:
+        construct_rs = None
+    if construct_rs is not None:
         from construct_rs import Construct as Construct
         from construct_rs import Subconstruct as Subconstruct
         from construct_rs import BitsInteger as BitsInteger
@@ -34,6 +45,8 @@ if os.getenv("CONSTRUCT_USE_RUST"):
         from construct_rs import PascalString as PascalString
         from construct_rs import CString as CString
         from construct_rs import GreedyString as GreedyString
+        from construct_rs import Bytes as Bytes
+        from construct_rs import GreedyBytes as GreedyBytes
         from construct_rs import possiblestringencodings as possiblestringencodings
         from construct_rs import Bit as Bit
         from construct_rs import Nibble as Nibble
@@ -50,12 +63,56 @@ if os.getenv("CONSTRUCT_USE_RUST"):
         from construct_rs import Single, Double
         from construct_rs import Int24ub, Int24ul, Int24un
         from construct_rs import Int24sb, Int24sl, Int24sn
-    except Exception:
-        pass
-from construct.expr import *
-from construct.debug import *
-from construct.version import *
-from construct import lib
+        from construct_rs import ProcessXor as ProcessXor
+        from construct_rs import ProcessRotateLeft as ProcessRotateLeft
+        from construct_rs import ByteSwapped as ByteSwapped
+        from construct_rs import BitsSwapped as BitsSwapped
+        from construct_rs import Union as Union
+        from construct_rs import Renamed as Renamed
+        from construct_rs import Sequence as Sequence
+        from construct_rs import FocusedSeq as FocusedSeq
+        from construct_rs import Lazy as Lazy
+        from construct_rs import LazyStruct as LazyStruct
+        from construct_rs import LazyArray as LazyArray
+        from construct_rs import LazyContainer as LazyContainer
+        from construct_rs import LazyListContainer as LazyListContainer
+        from construct_rs import LazyBound as LazyBound
+        from construct_rs import RestreamedBytesIO as RestreamedBytesIO
+        from construct_rs import RebufferedBytesIO as RebufferedBytesIO
+        from construct_rs import Transformed as Transformed
+        from construct_rs import Restreamed as Restreamed
+        from construct_rs import RestreamData as RestreamData
+        from construct_rs import Rebuffered as Rebuffered
+        from construct_rs import Struct as Struct
+        from construct_rs import Array as Array
+        from construct_rs import GreedyRange as GreedyRange
+        from construct_rs import RepeatUntil as RepeatUntil
+        from construct_rs import Switch as Switch
+        from construct_rs import If as If
+        from construct_rs import Computed as Computed
+        from construct_rs import FixedSized as FixedSized
+        from construct_rs import Pointer as Pointer
+        from construct_rs import Enum as Enum
+        from construct_rs import Container as Container
+        from construct_rs import ListContainer as ListContainer
+        from construct_rs import setGlobalPrintFullStrings as setGlobalPrintFullStrings
+        from construct_rs import setGlobalPrintFalseFlags as setGlobalPrintFalseFlags
+        from construct_rs import setGlobalPrintPrivateEntries as setGlobalPrintPrivateEntries
+        from construct_rs import hexdump as hexdump
+        from construct_rs import hexundump as hexundump
+        from construct_rs import HexDisplayedInteger as HexDisplayedInteger
+        from construct_rs import HexDisplayedBytes as HexDisplayedBytes
+        from construct_rs import HexDisplayedDict as HexDisplayedDict
+        from construct_rs import HexDumpDisplayedBytes as HexDumpDisplayedBytes
+        from construct_rs import HexDumpDisplayedDict as HexDumpDisplayedDict
+        from construct_rs import Hex as Hex
+        from construct_rs import HexDump as HexDump
+        from construct_rs import Probe as Probe
+        from construct_rs import Debugger as Debugger
+        from construct_rs import import_ksy as import_ksy
+        from construct_rs import import_ksy_file as import_ksy_file
+        from construct_rs import import_schema as import_schema
+        from construct_rs import import_schema_file as import_schema_file
 
 
 #===============================================================================
@@ -823,3 +823,18 @@ def test_overall_build_compiled(benchmark):
     d = example.compile()
     obj = example.parse(exampledata)
     benchmark(d.build, obj)
+
+def test_rust_listcontainer_getitem(benchmark):
+    rs = pytest.importorskip("construct_rs")
+    l = rs.ListContainer(range(100))
+    benchmark(l.__getitem__, 50)
+
+def test_rust_listcontainer_eq(benchmark):
+    rs = pytest.importorskip("construct_rs")
+    l = rs.ListContainer(range(100))
+    benchmark(l.__eq__, list(range(100)))
+
+def test_rust_container_getattr(benchmark):
+    rs = pytest.importorskip("construct_rs")
+    c = rs.Container(a=1, b=2)
+    benchmark(getattr, c, "a")
@@ -0,0 +1,939 @@
+# -*- coding: utf-8 -*-
+
+from declarativeunittest import *
+import json
+
+rs = pytest.importorskip("construct_rs")
+
+kaitai_comparisons = os.path.join(os.path.dirname(__file__), "kaitai_comparisons")
+
+
+def test_union():
+    d = rs.Union(0, n=rs.Int8ub, x=rs.ProcessXor(lambda this: this["n"], rs.Int8ub))
+    assert d.parse(b"\x05") == dict(n=5, x=0)
+    d = rs.Union(0, n=rs.Int8ub, u=rs.Union(0, x=rs.ProcessXor(lambda this: this["_"]["n"], rs.Int8ub)))
+    assert d.parse(b"\x03") == dict(n=3, u=dict(x=0))
+    assert rs.Union(0, x=rs.ProcessXor(lambda this: this["k"], rs.Int8ub), k=rs.Int8ub).build(dict(x=1, k=3)) == b"\x02"
+    assert rs.Union(0, x=rs.ProcessXor(lambda this: this["_params"]["k"], rs.Int8ub)).build(dict(x=1), k=3) == b"\x02"
+
+def test_sequences():
+    d = rs.Sequence("n" / rs.Int8ub, rs.Bytes(rs.Expr("n")))
+    assert d.parse(b"\x02ab") == [2, b"ab"]
+    assert d.build([3, b"xyz"]) == b"\x03xyz"
+    d = rs.Struct("size" / rs.Int8ub, "s" / rs.Sequence(rs.Bytes(rs.Expr("_parent.size"))))
+    assert d.parse(b"\x01ab") == dict(size=1, s=[b"a"])
+    d = rs.FocusedSeq("data", "n" / rs.Int8ub, "data" / rs.Bytes(rs.Expr("n")))
+    assert d.parse(b"\x02abc") == b"ab"
+    d = rs.FocusedSeq("n", "n" / rs.Int8ub, "x" / rs.Switch(rs.Expr("n"), {}))
+    assert d.build(5) == b"\x05"
+
+def test_repeatuntil():
+    seen = []
+    def predicate(obj, lst, ctx):
+        seen.append((lst, len(lst)))
+        return obj == 0
+    d = rs.RepeatUntil(predicate, rs.Int8ub)
+    lst = d.parse(b"\x01\x02\x00\x03")
+    assert lst == [1, 2, 0] and type(lst) is rs.ListContainer
+    # The list being filled is passed, holding the elements up to the current one.
+    assert all(l is lst for l, _ in seen) and [n for _, n in seen] == [1, 2, 3]
+    seen.clear()
+    assert d.build([1, 0]) == b"\x01\x00"
+    assert seen[0][0] is seen[1][0] and [n for _, n in seen] == [1, 2]
+
+def test_members_share_input():
+    class Recorder(rs.Construct):
+        def __init__(self):
+            self.seen = []
+        def _parseprefix(self, data):
+            self.seen.append(data)
+            return None, 1
+
+    data = b"\x01abc"
+    r = Recorder()
+    assert rs.Sequence(rs.Int8ub, r, r).parse(data) == [1, None, None]
+    assert r.seen[1].obj is data
+    assert bytes(r.seen[1]) == b"bc"
+    r = Recorder()
+    rs.Struct("a" / rs.Int8ub, "s" / rs.Struct("b" / rs.Int8ub, "r" / r)).parse(data)
+    assert r.seen[0].obj is data
+    assert bytes(r.seen[0]) == b"bc"
+
+def test_processing_context():
+    d = rs.Struct("key" / rs.Int8ub, "data" / rs.ProcessXor(rs.Expr("key"), rs.Bytes(4)), "tail" / rs.Int8ub)
+    assert d.parse(b"\x01\x00\x03\x62\x63\x09") == dict(key=1, data=b"\x01\x02cb", tail=9)
+    assert d.build(dict(key=1, data=b"\x01\x02cb", tail=9)) == b"\x01\x00\x03\x62\x63\x09"
+    d = rs.Struct("key" / rs.Int8ub, "data" / rs.ProcessXor(lambda ctx: ctx.key, rs.Bytes(rs.Expr("key"))))
+    assert d.parse(b"\x02\x03\x03") == dict(key=2, data=b"\x01\x01")
+    d = rs.Struct("n" / rs.Int8ub, "data" / rs.ProcessRotateLeft(rs.Expr("n"), 1, rs.Bytes(2)), "tail" / rs.Int8ub)
+    assert d.parse(b"\x04\x12\x34\x09") == dict(n=4, data=b"\x21\x43", tail=9)
+    assert d.build(dict(n=4, data=b"\x21\x43", tail=9)) == b"\x04\x12\x34\x09"
+    d = rs.Struct("n" / rs.Int8ub, "data" / rs.Hex(rs.Bytes(rs.Expr("n"))), "tail" / rs.Int8ub)
+    assert d.parse(b"\x02ab\x09") == dict(n=2, data=b"ab", tail=9)
+    assert d.build(dict(n=2, data=b"ab", tail=9)) == b"\x02ab\x09"
+    d = rs.Struct("n" / rs.Int8ub, "data" / rs.HexDump(rs.Bytes(rs.Expr("n"))))
+    assert d.build(dict(n=1, data=b"z")) == b"\x01z"
+    # Only the region of a fixed-size subcon is processed, and the trailing data left as is.
+    assert rs.ProcessXor(1, rs.Bytes(2))._parseprefix(b"\x00\x01rest") == (b"\x01\x00", 2)
+    assert rs.ProcessRotateLeft(4, 1, rs.Bytes(1))._parseprefix(b"\x12rest") == (b"\x21", 1)
+    assert rs.Sequence(rs.ProcessXor(0xff, rs.Bytes(1)), rs.GreedyBytes).parse(b"\x0f\x0f") == [b"\xf0", b"\x0f"]
+    assert raises(rs.ProcessXor(1, rs.Bytes(4)).parse, b"ab") == rs.StreamError
+    assert raises(rs.ProcessXor(256, rs.Bytes(2)).parse, b"ab") == ValueError
+    assert raises(rs.ProcessXor(-1, rs.Bytes(2)).build, b"ab") == ValueError
+    assert raises(rs.ProcessXor("a", rs.Bytes(2)).parse, b"ab") == rs.StringError
+    assert raises(rs.ProcessRotateLeft(1, 2**61, rs.GreedyBytes).parse, b"ab") == rs.RotationError
+    assert raises(rs.ProcessRotateLeft(-2**63, 1, rs.GreedyBytes).build, b"ab") == rs.RotationError
+
+def test_lazy_constructs():
+    d = rs.LazyStruct("n" / rs.Int8ub, rs.Int8ub, "data" / rs.Bytes(rs.Expr("n")), "k" / rs.Int16ub)
+    obj = d.parse(b"\x02\xffab\x00\x07")
+    assert len(obj) == 3
+    assert obj.get("k") == 7
+    assert obj.get("missing", 0) == 0
+    assert dict(obj) == dict(n=2, data=b"ab", k=7)
+    assert dict(n=2, data=b"ab", k=7) == obj
+    obj = rs.LazyStruct(a=rs.Int8ub, b=rs.Int16ub).parse(b"\x01\x00\x02")
+    assert isinstance(obj, collections.abc.Mapping)
+    assert not isinstance(obj, dict)
+    assert json.loads(json.dumps(dict(obj))) == dict(a=1, b=2)
+    assert {**rs.LazyStruct(a=rs.Int8ub, b=rs.Int16ub).parse(b"\x01\x00\x02")} == dict(a=1, b=2)
+    assert rs.LazyStruct(a=rs.Int8ub, b=rs.Int16ub).build(obj) == b"\x01\x00\x02"
+    data = b"\x01\x02ab\x03"
+    obj = rs.LazyStruct("a" / rs.Int8ub, "b" / rs.Bytes(2, zerocopy=True), "c" / rs.Bytes(rs.Expr("a"), zerocopy=True)).parse(data)
+    assert obj.b.obj is data
+    assert obj.c.obj is data
+    assert obj.b == b"\x02a"
+
+def test_lazy_context():
+    d = rs.LazyStruct("n" / rs.Int8ub, "k" / rs.Computed(rs.Expr("_params.k")))
+    obj = d.parse(b"\x01", k=4)
+    assert obj.k == 4
+    assert obj == d.parse(b"\x01", k=4)
+    assert obj != d.parse(b"\x02", k=4)
+    assert rs.LazyStruct(a=rs.Int8ub, b=rs.Computed(7)).build(dict(a=1)) == b"\x01"
+    assert rs.LazyArray(2, rs.Computed(rs.Expr("_params.k"))).parse(b"", k=3)[1] == 3
+    assert rs.LazyArray(2, rs.Bytes(rs.Expr("_params.k"))).parse(b"abcd", k=2)[1] == b"cd"
+    assert rs.LazyArray(2, rs.Bytes(rs.Expr("_params.k"))).build([b"ab", b"cd"], k=2) == b"abcd"
+    obj = rs.Struct("x" / rs.LazyStruct(a=rs.Int8ub), "rest" / rs.GreedyBytes).parse(bytearray(b"\x01" + b"z" * 100))
+    assert obj.x.__reduce__()[1] == (b"\x01", )
+
+def test_lazy():
+    d = rs.Struct("a" / rs.Lazy(rs.PascalString(rs.Int8ub, "utf8")), "b" / rs.Int8ub)
+    obj = d.parse(b"\x02hi\x07")
+    assert obj.a() == "hi"
+    assert obj.b == 7
+    d = rs.Struct("a" / rs.Lazy(rs.Int16ub), "b" / rs.Int8ub)
+    obj = d.parse(b"\x00\x01\x07")
+    assert obj.a() == 1
+    assert obj.b == 7
+    assert d.build(dict(a=obj.a, b=7)) == b"\x00\x01\x07"
+    d = rs.Struct("n" / rs.Int8ub, "data" / rs.Lazy(rs.Bytes(rs.Expr("n"))), "tail" / rs.Int8ub)
+    obj = d.parse(b"\x02ab\x09")
+    assert obj.data() == b"ab"
+    assert obj.tail == 9
+    assert d.build(dict(n=2, data=b"ab", tail=9)) == b"\x02ab\x09"
+    assert raises(rs.Lazy(rs.Int16ub).parse, b"\x01") == rs.StreamError
+
+def test_lazybound():
+    d = rs.Struct("n" / rs.Int8ub, "data" / rs.LazyBound(lambda: rs.Bytes(rs.Expr("n"))), "tail" / rs.Int8ub)
+    assert d.parse(b"\x02ab\x09") == dict(n=2, data=b"ab", tail=9)
+    assert d.build(dict(n=2, data=b"ab", tail=9)) == b"\x02ab\x09"
+    node = rs.Struct("n" / rs.Int8ub, "data" / rs.Bytes(rs.Expr("n")), "next" / rs.If(rs.Expr("n"), rs.LazyBound(lambda: node)))
+    obj = node.parse(b"\x01a\x02bc\x00")
+    assert obj.data == b"a"
+    assert obj.next.data == b"bc"
+    assert obj.next.next.n == 0
+    assert node.build(obj) == b"\x01a\x02bc\x00"
+
+def test_lazy_parse_stream():
+    stream = io.BytesIO(b"\x01\x00\x02\x03rest")
+    obj = rs.LazyStruct(a=rs.Int8ub, b=rs.Int16ub, c=rs.Int8ub).parse_stream(stream)
+    assert stream.tell() == 4
+    assert repr(obj) == "<LazyContainer: 0 items cached, 3 subcons>"
+    assert obj.b == 2
+    assert stream.tell() == 4
+    assert obj == dict(a=1, b=2, c=3)
+    stream = io.BytesIO(b"\x00\x01\x00\x02")
+    obj = rs.LazyArray(2, rs.Int16ub).parse_stream(stream)
+    assert stream.tell() == 4
+    assert obj[1] == 2
+    assert stream.tell() == 4
+
+def test_restreamed_bytesio():
+    reverse = lambda data: data[::-1]
+    stream = rs.RestreamedBytesIO(io.BytesIO(b"abcdef"), reverse, 2, reverse, 2)
+    assert stream.read(2) == b"ba"
+    assert stream.read(-1) == b"dcfe"
+    assert stream.tell() == 6
+    stream = rs.RestreamedBytesIO(io.BytesIO(b"abc"), reverse, 2, reverse, 2)
+    assert raises(stream.read) == IOError
+
+def test_rebuffered_bytesio():
+    import threading
+
+    class Pipe:
+        def __init__(self):
+            self.data = b""
+        def read(self, count):
+            data, self.data = self.data[:count], self.data[count:]
+            return data or None
+
+    pipe = Pipe()
+    stream = rs.RebufferedBytesIO(pipe)
+    producer = threading.Timer(0.05, lambda: setattr(pipe, "data", b"abc"))
+    producer.start()
+    assert stream.read(3) == b"abc"
+    producer.join()
+    assert raises(stream.seek, -1) == ValueError
+    assert stream.tell() == 3
+    stream = rs.RebufferedBytesIO(io.BytesIO(b"abc"))
+    assert raises(stream.read, 4) == IOError
+    assert stream.seek(10) == 10
+    try:
+        stream.read(1)
+        assert False
+    except IOError as e:
+        assert str(e) == "could not read enough bytes"
+
+class Socket(io.RawIOBase):
+    """Readable stream that returns a single byte per read, and cannot seek."""
+    def __init__(self, data):
+        self.data = data
+    def readable(self):
+        return True
+    def read(self, count=-1):
+        data, self.data = self.data[:1], self.data[1:]
+        return data
+
+def test_rebuffered():
+    class Seeking(rs.Construct):
+        def parse_stream(self, stream):
+            assert type(stream) is rs.RebufferedBytesIO
+            first = stream.read(2)
+            stream.seek(0)
+            return first + stream.read(2)
+
+    assert rs.Rebuffered(Seeking()).parse_stream(Socket(b"abcdef")) == b"abab"
+    d = rs.Rebuffered(rs.Struct("n" / rs.Int8ub, "data" / rs.Bytes(rs.Expr("n"))), tailcutoff=1)
+    assert d.parse_stream(Socket(b"\x02ab")) == dict(n=2, data=b"ab")
+    d = rs.Rebuffered(rs.Struct("data" / rs.Bytes(rs.Expr("_params.n"))))
+    assert d.parse_stream(Socket(b"xyz"), n=3) == dict(data=b"xyz")
+
+def test_restreaming_context():
+    d = rs.Struct("n" / rs.Int8ub, "data" / rs.Transformed(rs.Bytes(rs.Expr("n")), lambda b: b[::-1], 2, lambda b: b[::-1], 2), "tail" / rs.Int8ub)
+    assert d.parse(b"\x02ab\x09") == dict(n=2, data=b"ba", tail=9)
+    assert d.build(dict(n=2, data=b"ba", tail=9)) == b"\x02ab\x09"
+    d = rs.Struct("n" / rs.Int8ub, "data" / rs.Restreamed(rs.Bytes(rs.Expr("n")), bytes.upper, 1, bytes.lower, 1, lambda n: n))
+    assert d.parse(b"\x02ab") == dict(n=2, data=b"AB")
+    assert d.build(dict(n=2, data=b"AB")) == b"\x02ab"
+    d = rs.Struct("n" / rs.Int8ub, "data" / rs.RestreamData(b"\x05\x06\x07", rs.Array(rs.Expr("n"), rs.Int8ub)), "tail" / rs.Int8ub)
+    assert d.parse(b"\x02\x09") == dict(n=2, data=[5, 6], tail=9)
+    assert d.build(dict(n=2, data=[5, 6], tail=9)) == b"\x02\x09"
+    d = rs.Struct("n" / rs.Int8ub, "data" / rs.Rebuffered(rs.Bytes(rs.Expr("n"))), "tail" / rs.Int8ub)
+    assert d.parse(b"\x02ab\x09") == dict(n=2, data=b"ab", tail=9)
+    assert d.build(dict(n=2, data=b"ab", tail=9)) == b"\x02ab\x09"
+
+def test_containers():
+    c = rs.Container(a=1, b=b"\x00" * 20)
+    c.c = rs.ListContainer([rs.Container(x=5), rs.Container(x=6)])
+    c._io = None
+    assert list(c.keys()) == ["a", "b", "c", "_io"]
+    assert c.a == 1
+    assert c["c"][1].x == 6
+    assert c == {"a": 1, "b": b"\x00" * 20, "c": [{"x": 5}, {"x": 6}]}
+    assert c != {"a": 2}
+    assert c.c == [{"x": 5}, {"x": 6}]
+    assert c.c != [{"x": 5}]
+    assert repr(c.c[0]) == "Container(x=5)"
+    assert repr(c.c) == "ListContainer([Container(x=5), Container(x=6)])"
+
+def test_containers_str():
+    c = rs.Container(a=1, b=b"\x00" * 20, _io=None)
+    assert str(c).splitlines()[2] == "    b = " + repr(b"\x00" * 16) + "... (truncated, total 20)"
+    assert "_io" not in str(c)
+    rs.setGlobalPrintPrivateEntries(True)
+    rs.setGlobalPrintFullStrings(True)
+    try:
+        assert "_io = None" in str(c)
+        assert "(total 20)" in str(c)
+        assert "truncated" not in str(c)
+    finally:
+        rs.setGlobalPrintPrivateEntries(False)
+        rs.setGlobalPrintFullStrings(False)
+
+def test_containers_recursion_and_search():
+    c = rs.Container(a=1, c=rs.ListContainer([rs.Container(x=5), rs.Container(x=6)]))
+    c.c.append(c)
+    assert "<recursion detected>" in repr(c)
+    assert c.search("x") == 5
+    assert c.search_all("x") == [5, 6]
+    assert c.search("y") is None
+    del c.c[-1]
+    assert len(c.c) == 2
+    assert c.c[::-1][0].x == 6
+
+def test_listcontainer():
+    l = rs.ListContainer([3, 1, 2])
+    assert isinstance(l, list)
+    assert json.dumps(l) == "[3, 1, 2]"
+    assert l + [4] == [3, 1, 2, 4]
+    l.sort()
+    assert l == [1, 2, 3]
+    assert [1, 2, 3] == l
+    assert l != [1, 2]
+    l.reverse()
+    l[1:] = [5]
+    assert l == [3, 5]
+    assert 5 in l
+    assert list(iter(l)) == [3, 5]
+    assert type(l[:1]) is list
+
+def test_nested_context():
+    seen = []
+    inner = rs.Struct("y" / rs.Computed(lambda this: seen.append(this._)))
+    d = rs.Struct("x" / rs.Int8ub, "s" / rs.Array(2, inner), "z" / rs.Computed(lambda this: seen.append(this)))
+    d.parse(b"\x01")
+    assert seen[0] is seen[1] is seen[2]
+    assert seen[0].x == 1
+
+def test_hexdump():
+    obj = rs.Hex(rs.Int32ub).parse(b"\x00\x00\x01\x02")
+    assert obj == 258
+    assert str(obj) == "0x00000102"
+    assert isinstance(obj, rs.HexDisplayedInteger)
+    obj = rs.HexDump(rs.Int32ub).build(258)
+    assert obj == b"\x00\x00\x01\x02"
+    assert str(rs.HexDisplayedBytes(obj)) == "unhexlify('00000102')"
+    assert str(rs.HexDumpDisplayedBytes(obj)) == rs.hexdump(obj, 16)
+    assert rs.hexundump(rs.hexdump(obj, 8), 8) == obj
+    assert rs.Sequence(rs.Hex(rs.Int8ub), rs.Int8ub).parse(b"\x01\x02") == [1, 2]
+
+def test_probe():
+    import contextlib
+    out = io.StringIO()
+    with contextlib.redirect_stdout(out):
+        assert rs.Sequence(rs.Int8ub, rs.Probe(lookahead=4)).parse(b"\x01\x02\x03") == [1, None]
+        rs.Probe(lambda ctx: ctx.count).parse(b"", count=7)
+    lines = out.getvalue().splitlines()
+    assert lines[1] == "Probe, path is (parsing), into is None"
+    assert lines[4] == "0000   02 03                                             .."
+    assert lines[-2] == "7"
+
+    out = io.StringIO()
+    with contextlib.redirect_stdout(out):
+        rs.Struct("a" / rs.Int8ub, "b" / rs.Struct("p" / rs.Probe())).parse(b"\x01")
+    lines = out.getvalue().splitlines()
+    assert lines[1] == "Probe, path is (parsing) -> b -> p, into is None"
+
+def test_debugger():
+    failures = []
+    def handler(info):
+        failures.append(info)
+        return 42
+    d = rs.Debugger(rs.Int16ub, handler)
+    assert d.parse(b"\x01", x=1) == 42
+    assert isinstance(failures[0].exception, rs.StreamError)
+    assert failures[0].context.x == 1
+    assert failures[0].path == "(parsing)"
+    assert failures[0].data == b"\x01"
+    assert raises(d.build, None) == TypeError
+    assert isinstance(failures[1].exception, TypeError)
+    assert failures[1].path == "(building)"
+
+    d = rs.Struct("n" / rs.Int8ub, "d" / rs.Debugger(rs.Struct("x" / rs.Int8ub, "y" / rs.Array(2, rs.Int16ub)), handler))
+    assert d.parse(b"\x07\x01\x00\x02\x00") == dict(n=7, d=42)
+    assert failures[2].path == "(parsing) -> d"
+    assert failures[2].offset == 3
+    assert failures[2].context.n == 7
+
+def comparison_1_struct():
+    return rs.Struct(
+        count=rs.Int32ul,
+        items=rs.Array(rs.Expr("count"), rs.Struct(
+            num1=rs.Int8ul,
+            num2=rs.Int24ul,
+            fixedarray1=rs.Array(3, rs.Int8ul),
+            name1=rs.CString("utf8"),
+            name2=rs.PascalString(rs.Int8ul, "utf8"),
+        )),
+    )
+
+def test_export_ksy():
+    # Same as comparison_1_kaitai.ksy, except for its bit fields, which schemas cannot describe.
+    with open(os.path.join(kaitai_comparisons, "comparison_1_kaitai.ksy")) as f:
+        expected = f.read()
+    expected = expected.split("    types:\n      flags:\n")[0].replace("      - id: flags\n        type: flags\n", "")
+    exported = comparison_1_struct().export_ksy("comparison_1_kaitai")
+    item = b"\x01\x02\x02\x03\x01\x02\x03ab\x00\x03xyz"
+    data = b"\x02\x00\x00\x00" + item + item
+    o = rs.import_ksy(exported).parse(data)
+    assert o == rs.import_ksy(expected).parse(data)
+    assert o["items"][1].num2 == 0x030202
+    assert rs.import_ksy(exported).build(o) == data
+
+def test_export_ksy_switch():
+    d = rs.Struct(
+        kind=rs.Int8ub,
+        color=rs.Enum(rs.Int8ub, red=1, green=2),
+        body=rs.Switch(rs.Expr("kind"), {1: rs.Int16ub, 2: rs.Struct(a=rs.Int8ub, b=rs.Int8ub), 3: rs.CString("utf8")}, rs.Int8ub),
+        tail=rs.GreedyRange(rs.Int16ub),
+    )
+    ksy = d.export_ksy("switched")
+    assert "enum: color\n" in ksy
+    assert "enums:\n  color:\n    '1': red\n    '2': green\n" in ksy
+    assert "switch-on: kind\n      cases:\n        '1': u2be\n        '2': f_2\n        '3': type_1\n        _: u1\n" in ksy
+    assert "repeat: eos\n" in ksy
+    # Strings need their own type to be a case, so are wrapped into a member.
+    assert rs.import_ksy(ksy).parse(b"\x03\x02ab\x00").body == {"value": "ab"}
+    for data in [b"\x01\x02\x00\x07\x00\x08", b"\x02\x01\x05\x06\x00\x08", b"\x09\x01\x05"]:
+        assert rs.import_ksy(ksy).parse(data) == d.parse(data)
+
+def test_export_ksy_pointer():
+    d = rs.Struct(offset=rs.Int8ub, magic=rs.Bytes(2), value=rs.Pointer(rs.Expr("offset"), rs.Int16ub))
+    ksy = d.export_ksy("pointed")
+    assert "instances:\n  value:\n    pos: offset\n    type: u2be\n" in ksy
+    data = b"\x03AB\x00\x07"
+    assert d.parse(data) == dict(offset=3, magic=b"AB", value=7)
+    assert rs.import_ksy(ksy).parse(data) == d.parse(data)
+    # Offsets count from the start of the outermost data, and from its end when negative.
+    assert rs.Struct(skip=rs.Bytes(1), inner=rs.Struct(value=rs.Pointer(0, rs.Int8ub))).parse(b"\x05").inner.value == 5
+    assert rs.Pointer(-2, rs.Int16ub).parse(b"xx\x00\x01") == 1
+    assert raises(rs.Pointer(5, rs.Int8ub).parse, b"\x00") == rs.StreamError
+    assert raises(d.build, dict(offset=3, magic=b"AB", value=7)) == rs.ConstructError
+
+def test_import_ksy():
+    with open(os.path.join(kaitai_comparisons, "comparison_1_kaitai.ksy")) as f:
+        d = rs.import_ksy(f.read())
+    item = b"\x01\x02\x02\x03\xb0\x01\x02\x03ab\x00\x03xyz"
+    data = b"\x01\x00\x00\x00" + item
+    o = d.parse(data)
+    assert o.count == 1
+    assert o["items"][0].num2 == 0x030202
+    assert o["items"][0].flags.num4 == 3
+    assert o["items"][0].fixedarray1 == [1, 2, 3]
+    assert (o["items"][0].name1, o["items"][0].name2) == ("ab", "xyz")
+    assert d.build(o) == data
+
+def test_import_ksy_switch():
+    d = rs.import_ksy("""
+meta:
+  endian: be
+seq:
+  - id: kind
+    type: u1
+    enum: animal
+  - id: body
+    type:
+      switch-on: kind
+      cases:
+        animal::cat: u2
+        _: u1
+  - id: tail
+    type: u1
+    repeat: until
+    repeat-until: _ == 0
+    if: kind != animal::cat
+enums:
+  animal:
+    1: cat
+    2: dog
+""")
+    assert d.parse(b"\x01\x00\x05") == dict(kind="cat", body=5, tail=None)
+    assert d.parse(b"\x02\x07\x08\x00") == dict(kind="dog", body=7, tail=[8, 0])
+    assert d.build(dict(kind="dog", body=7, tail=[8, 0])) == b"\x02\x07\x08\x00"
+
+def test_import_ksy_unsupported():
+    try:
+        rs.import_ksy("instances:\n  a:\n    pos: 3\n    io: _root._io\n    type: u1\n")
+        assert False
+    except rs.ConstructError as e:
+        assert str(e) == '(ksy) -> instances -> a -> io: "io" is not supported'
+
+def test_struct_build_computed():
+    d = rs.Struct("a" / rs.Int8ub, "b" / rs.Hex(rs.Computed(rs.Expr("a * 2"))), "c" / rs.Bytes(rs.Expr("b")))
+    assert d.build(dict(a=1, c=b"xy")) == b"\x01xy"
+    d = rs.Struct("a" / rs.Int8ub, rs.Renamed(rs.Computed(rs.Expr("a + 1")), "b"), "c" / rs.Bytes(rs.Expr("b")))
+    assert d.build(dict(a=1, c=b"xy")) == b"\x01xy"
+    obj = rs.LazyStruct(a=rs.Int8ub, b=rs.Int16ub).parse(b"\x01\x00\x02")
+    assert rs.Struct(a=rs.Int8ub, b=rs.Int16ub).build(obj) == b"\x01\x00\x02"
+
+def test_schema_documents():
+    d = rs.import_schema("""
+type: Struct
+fields:
+  - name: kind
+    type: Enum
+    subcon: Int8ub
+    mapping: {cat: 1, dog: 2}
+  - name: count
+    type: Int16ul
+  - name: body
+    type: Switch
+    keyfunc: kind
+    cases:
+      cat: Int24sb
+      dog: {type: PascalString, lengthfield: Byte, encoding: ascii}
+  - name: extra
+    type: If
+    condfunc: count > 1
+    subcon: {type: FixedSized, length: 2, subcon: Int8ub}
+  - name: total
+    type: Computed
+    func: count * 2
+  - name: tail
+    type: RepeatUntil
+    predicate: _ == 0
+    subcon: Int8ub
+""")
+    data = b"\x02\x02\x00\x03abc\x07\x00\x05\x00"
+    o = d.parse(data)
+    assert o == dict(kind="dog", count=2, body="abc", extra=7, total=4, tail=[5, 0])
+    assert d.build(o) == data
+    exported = d.export_schema()
+    assert rs.import_schema(exported).export_schema() == exported
+    assert rs.import_schema(d.export_schema("yaml")).parse(data) == o
+
+    try:
+        rs.Struct(a=rs.Computed(lambda ctx: 1)).export_schema()
+        assert False
+    except rs.ConstructError as e:
+        assert str(e) == "(schema) -> a -> func: only integers and expressions can be described"
+
+def test_pickle():
+    import copy, pickle
+    roundtrip = lambda o: pickle.loads(pickle.dumps(o))
+    assert roundtrip(rs.Int32ub) is rs.Int32ub
+    assert copy.deepcopy(rs.Byte) is rs.Byte
+
+    d = rs.Struct(
+        kind=rs.Enum(rs.Int8ub, cat=1, dog=2),
+        count=rs.Int16ul,
+        items=rs.Array(rs.Expr("count"), rs.Int8ub),
+        body=rs.Switch(rs.Expr("kind"), {"cat": rs.Int24sb, "dog": rs.PascalString(rs.Byte, "ascii")}),
+        pad=rs.FixedSized(2, rs.Int8ub),
+    )
+    data = b"\x02\x02\x00\x01\x02\x03abc\x07\x00"
+    d2 = roundtrip(d)
+    assert type(d2) is rs.Struct
+    o = d2.parse(data)
+    assert o == d.parse(data)
+    assert d2.build(o) == data
+    assert roundtrip(o) == o
+    assert type(roundtrip(o)) is rs.Container
+    assert roundtrip(o["items"]) == [1, 2]
+    assert type(roundtrip(o["items"])) is rs.ListContainer
+
+    lazy = rs.LazyStruct(a=rs.Int8ub, b=rs.Int16ub).parse(b"\x01\x00\x02")
+    assert roundtrip(lazy)["b"] == 2
+
+def test_pickle_native_functions():
+    import pickle
+    native = rs.import_ksy("""
+meta: {id: bits, bit-endian: be}
+seq:
+  - id: a
+    type: b4
+""")
+    try:
+        pickle.dumps(native)
+        assert False
+    except TypeError as e:
+        assert str(e) == "cannot pickle Transformed using Rust functions"
+
+def test_release_gil():
+    from concurrent.futures import ThreadPoolExecutor
+    pure = rs.Struct(n=rs.Int8ub, xs=rs.Array(rs.Expr("n + _parent.k"), rs.Int16ub), s=rs.PascalString(rs.Byte, "utf8"))
+    mixed = rs.Struct(n=rs.Int8ub, c=rs.Computed(lambda ctx: ctx.n * 2))
+    data = [bytes([i % 7]) + bytes(range(2 * (i % 7 + 1))) + b"\x02ok" for i in range(64)]
+    with ThreadPoolExecutor(4) as pool:
+        results = list(pool.map(lambda d: pure.parse(d, k=1), data))
+    for d, r in zip(data, results):
+        assert r.n == d[0]
+        assert len(r.xs) == d[0] + 1
+        assert r.s == "ok"
+        assert type(r) is rs.Container
+        assert type(r.xs) is rs.ListContainer
+    assert mixed.parse(b"\x03").c == 6
+    assert raises(pure.parse, b"\x01\x00", k=1) == rs.StreamError
+
+def test_parse_parallel():
+    record = rs.Struct(
+        length=rs.Int8ub,
+        kind=rs.Enum(rs.Int8ub, text=1, words=2),
+        body=rs.Switch(rs.Expr("kind"), {"text": rs.PascalString(rs.Int8ub, "utf8"), "words": rs.Array(rs.Expr("length"), rs.Int16ub)}),
+        index=rs.Computed(rs.Expr("_parent._index")),
+    )
+    data = b"".join(
+        bytes([i % 5, 1, 2]) + b"hi" if i % 2 else bytes([i % 5, 2]) + bytes(2 * (i % 5))
+        for i in range(1000)
+    )
+    greedy = rs.GreedyRange(record)
+    assert greedy.parse_parallel(data, threads=3) == greedy.parse(data)
+    assert greedy.parse_parallel(data + b"\x04\x02\x00") == greedy.parse(data)
+    assert type(greedy.parse_parallel(data)) is rs.ListContainer
+    assert [r.index for r in greedy.parse_parallel(data, threads=7)] == list(range(1000))
+
+    array = rs.Array(rs.Expr("count"), rs.Struct(a=rs.Int16ub, b=rs.BytesInteger(3)))
+    fixed = bytes(range(250))
+    assert array.parse_parallel(fixed, threads=4, count=50) == array.parse(fixed, count=50)
+    assert raises(array.parse_parallel, fixed, count=51) == rs.StreamError
+    assert rs.GreedyRange(rs.Int16ub).parse_parallel(b"\x00\x01\x00\x02\x03", threads=2) == [1, 2]
+    assert raises(array.parse_parallel, fixed, threads=0, count=1) == ValueError
+
+    mixed = rs.GreedyRange(rs.Struct(a=rs.Int8ub, b=rs.Computed(lambda ctx: ctx.a + 1)))
+    assert [r.b for r in mixed.parse_parallel(b"\x01\x02")] == [2, 3]
+    assert rs.Struct(a=rs.Int8ub).parse_parallel(b"\x05").a == 5
+
+def test_bytes():
+    data = b"\x03abcdefgh"
+    d = rs.Struct(count=rs.Int8ub, payload=rs.Bytes(rs.Expr("count")), rest=rs.GreedyBytes)
+    o = d.parse(data)
+    assert o.payload == b"abc"
+    assert type(o.payload) is bytes
+    assert o.rest == b"defgh"
+    assert d.build(o) == data
+    assert d.build(dict(count=3, payload=bytearray(b"abc"), rest=memoryview(b"defgh"))) == data
+    assert rs.Bytes(2).build(0x0102) == b"\x01\x02"
+    assert rs.Bytes(3).sizeof() == 3
+    assert raises(d.parse, b"\x05ab") == rs.StreamError
+    assert raises(rs.Bytes(2).build, b"abc") == rs.StreamError
+
+def test_bytes_zerocopy():
+    import pickle
+    data = b"\x03abcdefgh"
+    views = rs.Struct(count=rs.Int8ub, payload=rs.Bytes(rs.Expr("count"), zerocopy=True), rest=rs.GreedyBytes(zerocopy=True))
+    o = views.parse(data)
+    assert type(o.payload) is memoryview
+    assert o.payload.obj is data
+    assert o.payload == b"abc"
+    assert o.rest.obj is data
+    assert o.rest == b"defgh"
+    records = rs.GreedyRange(rs.Bytes(2, zerocopy=True)).parse(data)
+    assert [bytes(r) for r in records] == [b"\x03a", b"bc", b"de", b"fg"]
+    assert all(r.obj is data for r in records)
+    fallback = rs.Struct(count=rs.Int8ub, payload=rs.Bytes(lambda ctx: ctx.count, zerocopy=True))
+    assert fallback.parse(data).payload == b"abc"
+    assert fallback.parse(data).payload.obj is data
+    records = rs.Array(2, rs.Bytes(lambda ctx: 2, zerocopy=True)).parse(data)
+    assert [bytes(r) for r in records] == [b"\x03a", b"bc"]
+    assert all(r.obj is data for r in records)
+    assert pickle.loads(pickle.dumps(views)).parse(data).payload == b"abc"
+
+def test_bytes_schemas():
+    import pickle
+    d = rs.Struct(count=rs.Int8ub, payload=rs.Bytes(rs.Expr("count")), rest=rs.GreedyBytes)
+    views = rs.Struct(count=rs.Int8ub, payload=rs.Bytes(rs.Expr("count"), zerocopy=True), rest=rs.GreedyBytes(zerocopy=True))
+    assert pickle.loads(pickle.dumps(rs.GreedyBytes)) is rs.GreedyBytes
+    assert rs.import_schema(views.export_schema()).export_schema() == views.export_schema()
+    assert '"type": "GreedyBytes"' in d.export_schema()
+
+    ksy = rs.import_ksy("""
+meta: {id: raw}
+seq:
+  - id: count
+    type: u1
+  - id: payload
+    size: count
+  - id: masked
+    size: 2
+    process: xor(0xff)
+  - id: rest
+    size-eos: true
+""")
+    assert ksy.parse(b"\x02ab\x00\x01zz") == dict(count=2, payload=b"ab", masked=b"\xff\xfe", rest=b"zz")
+    assert "size-eos: true" in rs.Struct(a=rs.Bytes(2), b=rs.GreedyBytes).export_ksy("raw")
+
+def test_buffer_input():
+    import array
+    raw = b"\x03abcdefgh"
+    d = rs.Struct(count=rs.Int8ub, payload=rs.Bytes(rs.Expr("count"), zerocopy=True), rest=rs.GreedyBytes)
+    for data in (bytearray(raw), memoryview(raw), memoryview(b"xx" + raw)[2:], array.array("B", raw)):
+        o = d.parse(data)
+        assert o.count == 3
+        assert o.payload == b"abc"
+        assert o.rest == b"defgh"
+    o = d.parse(bytearray(raw))
+    assert type(o.payload.obj) is bytearray
+    assert rs.Int16ul.parse(array.array("H", [513])) == 513
+    assert rs.GreedyRange(rs.Int8ub).parse(memoryview(b"\x01\x02")) == [1, 2]
+    assert rs.PascalString(rs.Int8ub, "utf8").parse(bytearray(b"\x02hi")) == "hi"
+    assert rs.Struct(n=rs.Int8ub, b=rs.Bytes(lambda ctx: ctx.n)).parse(bytearray(b"\x02ab")).b == b"ab"
+    assert rs.Lazy(rs.Int8ub).parse(bytearray(b"\x07"))() == 7
+    assert rs.Array(2, rs.Int8ub).parse_parallel(bytearray(b"\x01\x02")) == [1, 2]
+    assert raises(rs.Int8ub.parse, "x") == TypeError
+    assert raises(rs.Int8ub.parse, memoryview(b"abcd")[::2]) == BufferError
+
+def test_build_into():
+    import mmap
+    buffer = bytearray(6)
+    assert rs.Int16ub.build_into(258, buffer, 2) == 2
+    assert buffer == b"\x00\x00\x01\x02\x00\x00"
+    d = rs.Struct(count=rs.Int8ub, payload=rs.Bytes(rs.Expr("count")), rest=rs.GreedyBytes)
+    mapped = mmap.mmap(-1, 4)
+    assert d.build_into(dict(count=1, payload=b"z", rest=b"q"), mapped) == 3
+    assert mapped[:] == b"\x01zq\x00"
+    assert raises(rs.Int16ub.build_into, 1, buffer, 5) == rs.StreamError
+    assert raises(rs.Int16ub.build_into, 1, b"abcd") == TypeError
+
+def test_operators():
+    seen = []
+    d = rs.Struct(
+        "count" / rs.Int8ub,
+        "items" / rs.Int16ub[rs.Expr("count")] * "the items",
+        "tail" / rs.Int8ub * (lambda obj, ctx: seen.append((obj, ctx.count))),
+    )
+    o = d.parse(b"\x02\x00\x01\x00\x02\x09")
+    assert o == dict(count=2, items=[1, 2], tail=9)
+    assert seen == [(9, 2)]
+    assert d.build(o) == b"\x02\x00\x01\x00\x02\x09"
+    assert d.items.name == "items"
+    assert d.items.docs == "the items"
+    assert ("a" / (rs.Int8ub * "docs")).docs == "docs"
+    assert ("a" / rs.Int8ub).sizeof() == 1
+    assert rs.Int8ub[3].parse(b"abc") == [97, 98, 99]
+    assert rs.Int8ub[lambda ctx: 2].parse(b"ab") == [97, 98]
+    seq = rs.Int8ub >> rs.Int16ub >> ("z" / rs.Int8ub)
+    assert seq.parse(b"\x01\x00\x02\x03") == [1, 2, 3]
+    assert seq.build([1, 2, 3]) == b"\x01\x00\x02\x03"
+    assert len(seq.__getstate__()) == 3
+    assert seq.z.name == "z"
+    assert raises(lambda: rs.Int8ub[1:2]) == rs.ConstructError
+    assert raises(lambda: rs.Int8ub["x"]) == rs.ConstructError
+    assert raises(lambda: rs.Int8ub * 3) == rs.ConstructError
+    assert raises(lambda: 3 / rs.Int8ub) == TypeError
+
+    named = rs.Struct("a" / rs.Int8ub, "b" / rs.Bytes(rs.Expr("a")) * "payload")
+    assert named.parse(b"\x02xy") == dict(a=2, b=b"xy")
+    assert rs.import_schema(named.export_schema()).parse(b"\x01z") == dict(a=1, b=b"z")
+    assert raises(d.export_schema) == rs.ConstructError
+
+def test_parse_stream():
+    d = rs.Struct("n" / rs.Int8ub, "data" / rs.Bytes(rs.Expr("n")), "k" / rs.Computed(rs.Expr("_params.k")))
+    stream = io.BytesIO(b"\x02ab\x01c rest")
+    assert d.parse_stream(stream, k=5) == dict(n=2, data=b"ab", k=5)
+    assert stream.tell() == 3
+    assert d.parse_stream(stream, k=6) == dict(n=1, data=b"c", k=6)
+    assert stream.read() == b" rest"
+    out = io.BytesIO()
+    assert d.build_stream(dict(n=1, data=b"z"), out, k=1) is None
+    assert out.getvalue() == b"\x01z"
+    assert raises(rs.Int32ub.parse_stream, io.BytesIO(b"\x01")) == rs.StreamError
+    stream = Socket(b"\x02ab\x01c")
+    assert d.parse_stream(stream, k=0) == dict(n=2, data=b"ab", k=0)
+    assert d.parse_stream(stream, k=0) == dict(n=1, data=b"c", k=0)
+    d = rs.Struct("n" / rs.Int8ub, "s" / rs.PascalString(rs.Int8ub, "utf8"))
+    stream = Socket(b"\x01\x02hi rest")
+    assert d.parse_stream(stream) == dict(n=1, s="hi")
+    assert stream.read() == b" "
+    assert rs.ByteSwapped(rs.Bytes(rs.Expr("_params.n"))).parse_stream(io.BytesIO(b"ab"), n=2) == b"ba"
+    assert rs.Hex(rs.Bytes(rs.Expr("_params.n"))).parse_stream(io.BytesIO(b"abc"), n=2) == b"ab"
+    # Constructs calling into Python are read as far as they parse, not to the end.
+    stream = Socket(b"\x02abNEXT")
+    assert rs.Struct(n=rs.Int8ub, data=rs.Bytes(lambda ctx: ctx.n)).parse_stream(stream) == dict(n=2, data=b"ab")
+    assert stream.data == b"NEXT"
+
+def test_parse_stream_socket():
+    import socket
+    d = rs.Struct("n" / rs.Int8ub, "data" / rs.Bytes(rs.Expr("n")), "k" / rs.Computed(rs.Expr("_params.k")))
+    a, b = socket.socketpair()
+    writer, reader = a.makefile("wb"), b.makefile("rb")
+    rs.Int32ub.build_stream(7, writer)
+    writer.flush()
+    assert rs.Int32ub.parse_stream(reader) == 7
+    b.settimeout(5)
+    writer.write(b"\x02xy\x01z")
+    writer.flush()
+    assert d.parse_stream(reader, k=0) == dict(n=2, data=b"xy", k=0)
+    assert d.parse_stream(reader, k=1) == dict(n=1, data=b"z", k=1)
+    writer.write(b"\x01xy")
+    writer.close()
+    a.close()
+    assert d.parse_stream(reader, k=0) == dict(n=1, data=b"x", k=0)
+    reader.close()
+    b.close()
+
+def test_params():
+    inner = rs.Struct("x" / rs.Computed(rs.Expr("_params.k * 2")))
+    assert rs.Struct("a" / inner, "y" / rs.Computed(lambda ctx: ctx._params.k)).parse(b"", k=4) == dict(a=dict(x=8), y=4)
+    assert rs.Struct("v" / rs.If(lambda ctx: ctx._params.on, rs.Int8ub)).build(dict(v=3), on=True) == b"\x03"
+
+def test_params_python_constructs():
+    class Plain(rs.Construct):
+        def parse(self, data):
+            return bytes(data[:1])
+        def build(self, obj):
+            return obj
+        def sizeof(self):
+            return 1
+
+    d = rs.Struct("a" / Plain(), "b" / rs.Int8ub)
+    assert d.parse(b"x\x01") == dict(a=b"x", b=1)
+    assert d.build(dict(a=b"x", b=1)) == b"x\x01"
+    assert d.parse_stream(io.BytesIO(b"x\x01")) == dict(a=b"x", b=1)
+
+def test_feeder():
+    frame = rs.Struct("n" / rs.Int16ub, "payload" / rs.Bytes(rs.Expr("n")), "tag" / rs.CString("utf8"))
+    frames = [dict(n=5, payload=b"hello", tag="a"), dict(n=0, payload=b"", tag="bb"), dict(n=30, payload=b"xyz" * 10, tag="")]
+    stream = b"".join(frame.build(obj) for obj in frames)
+    feeder = rs.Feeder(frame)
+    parsed = []
+    for i in range(0, len(stream), 3):
+        parsed += feeder.feed(stream[i:i + 3])
+    assert parsed == frames
+    assert feeder.buffered == 0
+
+    feeder = rs.Feeder(frame)
+    assert feeder.feed(b"\x00") == []
+    assert feeder.needed == 1
+    assert feeder.feed(b"\x05he") == []
+    assert feeder.needed == 3
+    assert feeder.buffered == 4
+    assert feeder.feed(bytearray(b"llo")) == []
+    assert feeder.needed == 1
+    assert feeder.feed(memoryview(b"a\x00\x00\x00")) == [frames[0]]
+    assert feeder.needed == 1
+    assert feeder.feed(b"bb\x00") == [frames[1]]
+
+    parses = []
+    frame = rs.Struct("n" / rs.Int8ub * (lambda obj, ctx: parses.append(obj)), "data" / rs.Bytes(lambda ctx: ctx.n))
+    feeder = rs.Feeder(frame)
+    parsed = []
+    for byte in b"\x04abcd\x01z":
+        parsed += feeder.feed(bytes([byte]))
+    assert parsed == [dict(n=4, data=b"abcd"), dict(n=1, data=b"z")]
+    assert parses == [4, 1]
+
+def test_feeder_fallback():
+    feeder = rs.Feeder(rs.Int32ub)
+    assert feeder.feed(b"\x00\x00") == []
+    assert feeder.needed == 2
+    assert feeder.feed(b"\x00\x01\x00") == [1]
+    assert feeder.needed == 3
+
+    feeder = rs.Feeder(rs.Struct("n" / rs.Int8ub, "s" / rs.Bytes(lambda ctx: ctx.n)))
+    assert feeder.feed(b"\x03a") == []
+    assert feeder.needed == 1
+    assert feeder.feed(b"bc\x01z") == [dict(n=3, s=b"abc"), dict(n=1, s=b"z")]
+
+    feeder = rs.Feeder(rs.Struct("a" / rs.Int8ub, "b" / rs.Bytes(rs.Expr("_params.size"))), size=2)
+    assert feeder.feed(b"\x01xy\x02") == [dict(a=1, b=b"xy")]
+    assert feeder.needed == 2
+    assert raises(rs.Feeder(rs.Computed(1)).feed, b"x") == rs.ConstructError
+
+def async_reader(data, eof=True):
+    import asyncio
+
+    class Reader(asyncio.StreamReader):
+        def __init__(self):
+            super().__init__()
+            self.asked = []
+        async def readexactly(self, n):
+            self.asked.append(n)
+            return await super().readexactly(n)
+
+    reader = Reader()
+    reader.feed_data(data)
+    if eof:
+        reader.feed_eof()
+    return reader
+
+def test_parse_async():
+    import asyncio
+
+    async def main():
+        frame = rs.Struct("n" / rs.Int8ub, "data" / rs.Bytes(rs.Expr("n")), "crc" / rs.Int16ub)
+        reader = async_reader(b"\x03abc\x00\x07rest", eof=False)
+        assert await frame.parse_async(reader) == dict(n=3, data=b"abc", crc=7)
+        assert reader.asked == [1, 3, 2]
+        assert await reader.readexactly(4) == b"rest"
+
+        reader = async_reader(b"\x02x", eof=False)
+        task = asyncio.ensure_future(frame.parse_async(reader))
+        await asyncio.sleep(0)
+        assert not task.done()
+        reader.feed_data(b"y\x00\x01")
+        assert await task == dict(n=2, data=b"xy", crc=1)
+
+        reader = async_reader(b"\x01abc")
+        assert await rs.Struct("a" / rs.Int8ub, "b" / rs.GreedyBytes).parse_async(reader) == dict(a=1, b=b"abc")
+        assert await rs.Bytes(rs.Expr("_params.k")).parse_async(async_reader(b"xyz"), k=2) == b"xy"
+        assert await rs.Struct("n" / rs.Int8ub, "s" / rs.Bytes(lambda ctx: ctx.n)).parse_async(async_reader(b"\x02xy")) == dict(n=2, s=b"xy")
+        # Constructs calling into Python are awaited as far as they parse, not to the end.
+        reader = async_reader(b"\x02abNEXT", eof=False)
+        d = rs.Struct(n=rs.Int8ub, data=rs.Bytes(lambda ctx: ctx.n))
+        assert await asyncio.wait_for(d.parse_async(reader), 5) == dict(n=2, data=b"ab")
+        assert await reader.readexactly(4) == b"NEXT"
+        try:
+            await frame.parse_async(async_reader(b"\x05ab"))
+            assert False
+        except rs.StreamError:
+            pass
+
+        task = asyncio.ensure_future(frame.parse_async(async_reader(b"", eof=False)))
+        await asyncio.sleep(0)
+        task.cancel()
+        try:
+            await task
+            assert False
+        except asyncio.CancelledError:
+            pass
+
+    asyncio.run(main())
+
+def test_arbitrary():
+    record = rs.Struct(
+        "kind" / rs.Enum(rs.Int8ub, small=1, large=2),
+        "n" / rs.Int16ul,
+        "payload" / rs.Bytes(rs.Expr("n")),
+        "value" / rs.Switch(rs.Expr("n"), {0: rs.Float32b, 1: rs.BytesInteger(3, True, False)}, default=rs.BitsInteger(5)),
+        "name" / rs.PaddedString(8, "utf16"),
+        "label" / rs.PascalString(rs.Int8ub, "utf8"),
+        "tail" / rs.RepeatUntil(rs.Expr("_ == 0"), rs.Int8ub),
+        "rest" / rs.GreedyRange(rs.CString("utf_32_le")),
+    )
+    for seed in range(300):
+        obj = record.arbitrary(seed)
+        assert record.parse(record.build(obj)) == obj
+        assert len(obj.payload) == obj.n
+        assert obj.kind not in (1, 2)
+        assert obj.tail[-1] == 0
+        assert 0 not in obj.tail[:-1]
+    assert record.arbitrary(7) == record.arbitrary(7)
+    assert len({rs.Int64ub.arbitrary() for _ in range(20)}) > 1
+    assert all(-8 <= rs.BitsInteger(4, signed=True).arbitrary(seed) < 8 for seed in range(100))
+    assert len(rs.Bytes(rs.Expr("_params.size")).arbitrary(1, size=5)) == 5
+    assert raises(rs.Struct("n" / rs.Int8ub, "f" / rs.Bytes(lambda ctx: ctx.n)).arbitrary) == rs.ConstructError
+
+def imported_with_rust(*names):
+    """Checks which names the construct package binds to construct_rs when CONSTRUCT_USE_RUST is set."""
+    import subprocess, sys
+    code = "import construct, construct_rs; print([getattr(construct, n) is getattr(construct_rs, n) for n in %r])" % (names,)
+    env = dict(os.environ, CONSTRUCT_USE_RUST="1", PYTHONPATH=os.pathsep.join(sys.path))
+    output = subprocess.check_output([sys.executable, "-c", code], env=env)
+    return eval(output)
+
+def test_package_containers():
+    names = ("Container", "ListContainer", "setGlobalPrintFullStrings", "setGlobalPrintFalseFlags", "setGlobalPrintPrivateEntries")
+    assert all(imported_with_rust(*names))
+
+def test_package_hex():
+    names = ("hexdump", "hexundump", "HexDisplayedInteger", "HexDisplayedBytes", "HexDisplayedDict", "HexDumpDisplayedBytes", "HexDumpDisplayedDict", "Hex", "HexDump")
+    assert all(imported_with_rust(*names))
+
+def test_package_probe_and_debugger():
+    assert all(imported_with_rust("Probe", "Debugger"))
+
+def test_package_composites():
+    names = ("Struct", "Sequence", "FocusedSeq", "Union", "Array", "GreedyRange", "RepeatUntil", "Switch", "If", "Computed", "FixedSized", "Pointer", "Enum")
+    assert all(imported_with_rust(*names))
+    import subprocess, sys
+    code = "import construct; print(construct.Struct('a' / construct.Int8ub, 'b' / construct.Array(2, construct.Int8ub)).parse(b'\\x01\\x02\\x03'))"
+    env = dict(os.environ, CONSTRUCT_USE_RUST="1", PYTHONPATH=os.pathsep.join(sys.path))
+    output = subprocess.check_output([sys.executable, "-c", code], env=env)
+    assert output.split() == b"Container: a = 1 b = ListContainer: 2 3".split()
+
+def test_package_exceptions():
+    names = ("ConstructError", "StreamError", "SizeofError", "UnionError", "RotationError", "StopFieldError", "CancelParsing")
+    assert all(imported_with_rust(*names))
+    assert raises(rs.ProcessXor(1, rs.Bytes(3)).parse, b"") == StreamError
+    assert issubclass(rs.StreamError, ConstructError)